@@ -0,0 +1,27 @@
+//! Preparing a full width receipt image for printing
+//!
+//! Run with `cargo bench --bench img`, add `--features rayon` to compare
+//! the parallel path.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use image::{DynamicImage, RgbImage};
+use posify::img::Image;
+
+/// 80mm paper width with 2000 rows of gradient
+fn photo() -> Image {
+    let buf = RgbImage::from_fn(576, 2000, |x, y| {
+        let value = ((x + y) % 256) as u8;
+        image::Rgb([value, value / 2, 255 - value])
+    });
+    Image::from(DynamicImage::ImageRgb8(buf))
+}
+
+fn bench(c: &mut Criterion) {
+    let image = photo();
+    let dithered = image.dither();
+    c.bench_function("dither 576x2000", |b| b.iter(|| image.dither()));
+    c.bench_function("raster 576x2000", |b| b.iter(|| dithered.get_raster()));
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
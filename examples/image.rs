@@ -1,5 +1,5 @@
 use posify::img;
-use posify::printer::{Printer, SupportedPrinters};
+use posify::printer::{CutMode, Printer, SupportedPrinters};
 
 fn main() -> Result<(), posify::printer::Error> {
     let logo = image::open("rust.png").expect("File not found!").resize(
@@ -19,7 +19,7 @@ fn main() -> Result<(), posify::printer::Error> {
         .chain_align("ct")?
         .chain_raster(&logo, None)?
         .chain_feed(1)?
-        .chain_partial_cut()?
+        .chain_cut(CutMode::Partial, None)?
         .flush();
     Ok(())
 }
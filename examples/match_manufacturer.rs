@@ -1,5 +1,5 @@
 use posify::barcode::{BarcodeType, Font, TextPosition};
-use posify::printer::{self, Printer};
+use posify::printer::{self, CutMode, Printer};
 
 fn main() -> Result<(), printer::Error> {
     let (mfg, vid, pid) = Printer::get_mfg_info().unwrap();
@@ -24,7 +24,7 @@ fn main() -> Result<(), printer::Error> {
             0x40,
         )?
         .chain_feed(5)?
-        .chain_partial_cut()?
+        .chain_cut(CutMode::Partial, None)?
         .flush();
 
     Ok(())
@@ -1,7 +1,7 @@
 use std::error::Error;
 
 use posify::barcode::{BarcodeType, Font, TextPosition};
-use posify::printer::{Printer, SupportedPrinters};
+use posify::printer::{CutMode, Printer, SupportedPrinters};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let vid: u16 = 0x154f;
@@ -25,7 +25,7 @@ fn main() -> Result<(), Box<dyn Error>> {
             0x40,
         )?
         .chain_feed(5)?
-        .chain_partial_cut()?
+        .chain_cut(CutMode::Partial, None)?
         .flush();
 
     Ok(())
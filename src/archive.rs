@@ -0,0 +1,86 @@
+//! Copies of every printed job for audits
+//!
+//! [Printer::set_archive](crate::printer::Printer::set_archive) hands each
+//! job the printer printed to an [Archive], rendered as plain text and/or
+//! a PNG by the [preview](crate::preview) that also draws print previews,
+//! so the archived copy can't drift from what a preview shows.
+//!
+//! ```no_run
+//! use posify::archive::{ArchiveOptions, ArchivedJob};
+//! use posify::printer::{Printer, SupportedPrinters};
+//!
+//! let mut printer = Printer::new(None, None, SupportedPrinters::SNBC, 0x0483, 0x5743)?;
+//! let options = ArchiveOptions {
+//!     text: true,
+//!     png: true,
+//! };
+//! printer.set_archive(options, |copy: &ArchivedJob| {
+//!     if let Some(png) = &copy.png {
+//!         let _ = std::fs::write("receipt.png", png);
+//!     }
+//! });
+//! # Ok::<(), posify::printer::Error>(())
+//! ```
+
+use std::time::SystemTime;
+
+use crate::barcode::Font;
+use crate::job::Job;
+use crate::profile::Profile;
+
+/// Which copies are made of each job
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct ArchiveOptions {
+    /// See [preview::to_text](crate::preview::to_text)
+    pub text: bool,
+    /// See [preview::to_png](crate::preview::to_png)
+    pub png: bool,
+}
+
+/// A job that was printed, with the header and footer it was printed with
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchivedJob {
+    pub job: Job,
+    pub printed: SystemTime,
+    pub text: Option<String>,
+    pub png: Option<Vec<u8>>,
+}
+
+impl ArchivedJob {
+    /// Renders the copies of job options asks for on the paper of profile,
+    /// [Profile::dots_per_line] or 576 dots wide
+    pub fn new(job: Job, options: ArchiveOptions, profile: &Profile) -> ArchivedJob {
+        let width = profile.dots_per_line.unwrap_or(576) as u32;
+        let columns = profile.chars_per_line(Font::FontA, 1);
+        let text = options.text.then(|| crate::preview::to_text(&job, columns));
+        let png = if options.png {
+            crate::preview::to_png(&job, width)
+                .map_err(|e| log::error!("Rendering the archive copy failed: {}", e))
+                .ok()
+        } else {
+            None
+        };
+        ArchivedJob {
+            job,
+            printed: SystemTime::now(),
+            text,
+            png,
+        }
+    }
+}
+
+/// Receives the printed jobs, closures taking an [ArchivedJob] implement it
+pub trait Archive: Send {
+    fn archive(&mut self, copy: &ArchivedJob);
+}
+
+impl<F: FnMut(&ArchivedJob) + Send> Archive for F {
+    fn archive(&mut self, copy: &ArchivedJob) {
+        self(copy)
+    }
+}
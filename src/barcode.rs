@@ -1,7 +1,16 @@
+use crate::img::Image;
 use crate::printer::SupportedPrinters;
+use image::{DynamicImage, GrayImage, Luma};
 use std::io;
 
-#[derive(Clone, Copy, PartialEq)]
+/// Digits of Code 32 in base 32, without vowels
+const CODE32_ALPHABET: &[u8; 32] = b"0123456789BCDFGHJKLMNPQRSTUVWXYZ";
+
+/// Data characters of Code 39 in the order of their mod 43 values
+const CODE39_CHARS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ-. $/+%";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BarcodeType {
     UPCA = 0,   // or 65?
     UPCE = 1,   // or 66?
@@ -18,6 +27,8 @@ pub enum BarcodeType {
     GS1 = 13,      // or 78?
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TextPosition {
     Off = 0x00,
     Above = 0x01,
@@ -25,6 +36,8 @@ pub enum TextPosition {
     Both = 0x03,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Font {
     Standard,   // As defined in SNBC printer docs
     Compressed, // As defined in SNBC printer docs
@@ -32,6 +45,36 @@ pub enum Font {
     FontB,      // As defined in P3 printer docs
 }
 
+/// Narrow to wide bar ratio of Code 39, see [Code39Options]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Code39Ratio {
+    /// 1:2
+    Two,
+    /// 1:2.5
+    TwoAndAHalf,
+    /// 1:3
+    Three,
+}
+
+/// How [code39_data] prepares Code 39 data
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Code39Options {
+    /// Adds the mod 43 check character
+    pub check_digit: bool,
+    /// Sends the `*` start and stop characters, for printers that don't add
+    /// them themselves
+    pub start_stop: bool,
+    /// None keeps the printer's ratio, only Star Line Mode printers can
+    /// select one
+    pub ratio: Option<Code39Ratio>,
+}
+
 #[derive(thiserror::Error, Debug, PartialEq)]
 pub enum CodeCError {
     #[error("Not a Number")]
@@ -40,6 +83,255 @@ pub enum CodeCError {
     InvalidLength,
 }
 
+/// Why barcode data can't be encoded, see [BarcodeType::validate]
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+pub enum BarcodeError {
+    #[error("Invalid character {0:?} for this barcode type")]
+    InvalidCharacter(char),
+    #[error("Invalid length {0} for this barcode type")]
+    InvalidLength(usize),
+    #[error("Barcode type not supported")]
+    Unsupported,
+    #[error("Wrong check digit, expected {0}")]
+    InvalidCheckDigit(char),
+    #[error("Data is {len} bytes, at most {max} fit this barcode type")]
+    TooLong { len: usize, max: usize },
+}
+
+/// Bars of a one track Pharmacode for n, left to right, true for a wide
+/// bar. n is 3 to 131070.
+pub fn pharmacode_bars(n: u32) -> Result<Vec<bool>, BarcodeError> {
+    if !(3..=131070).contains(&n) {
+        return Err(BarcodeError::InvalidLength(n as usize));
+    }
+    let mut bars = Vec::new();
+    let mut n = n;
+    // Built from the right, a narrow bar is worth 2^i and a wide one 2^(i+1)
+    while n > 0 {
+        let wide = n.is_multiple_of(2);
+        bars.push(wide);
+        n = if wide { (n - 2) / 2 } else { (n - 1) / 2 };
+    }
+    bars.reverse();
+    Ok(bars)
+}
+
+/// Pharmacode n drawn height dots high at 8 dots per mm: narrow bars 0.5
+/// mm, wide bars 1.5 mm and 1 mm between them. Printer firmware doesn't
+/// know the symbology.
+pub fn pharmacode_image(n: u32, height: u32) -> Result<Image, BarcodeError> {
+    const NARROW: u32 = 4;
+    const WIDE: u32 = 12;
+    const SPACE: u32 = 8;
+    let bars = pharmacode_bars(n)?;
+    let width = bars
+        .iter()
+        .map(|wide| if *wide { WIDE } else { NARROW })
+        .sum::<u32>()
+        + SPACE * (bars.len() as u32 - 1);
+    let mut image = GrayImage::from_pixel(width, height.max(1), Luma([0xFF]));
+    let mut x = 0;
+    for wide in bars {
+        let bar = if wide { WIDE } else { NARROW };
+        for px in x..x + bar {
+            for py in 0..height.max(1) {
+                image.put_pixel(px, py, Luma([0]));
+            }
+        }
+        x += bar + SPACE;
+    }
+    Ok(Image::from(DynamicImage::ImageLuma8(image)))
+}
+
+/// The 9 digits of an Italian pharmaceutical (AIC) code from 8 digits,
+/// adding the check digit, or from 9 digits with a correct one
+pub fn code32_digits(code: &str) -> Result<String, BarcodeError> {
+    if let Some(c) = code.chars().find(|c| !c.is_ascii_digit()) {
+        return Err(BarcodeError::InvalidCharacter(c));
+    }
+    let check = |digits: &str| {
+        let sum: u32 = digits
+            .chars()
+            .filter_map(|c| c.to_digit(10))
+            .enumerate()
+            .map(|(i, d)| {
+                if i % 2 == 1 {
+                    (2 * d) / 10 + (2 * d) % 10
+                } else {
+                    d
+                }
+            })
+            .sum();
+        char::from_digit(sum % 10, 10).expect("below 10")
+    };
+    match code.len() {
+        8 => Ok(format!("{}{}", code, check(code))),
+        9 => {
+            let expected = check(&code[..8]);
+            if !code.ends_with(expected) {
+                return Err(BarcodeError::InvalidCheckDigit(expected));
+            }
+            Ok(code.to_string())
+        }
+        len => Err(BarcodeError::InvalidLength(len)),
+    }
+}
+
+/// Code 39 data of a Code 32: the 9 digits of [code32_digits] as six base
+/// 32 digits
+pub fn code32_data(code: &str) -> Result<String, BarcodeError> {
+    let mut value: u32 = code32_digits(code)?.parse().expect("9 digits");
+    let mut data = [b'0'; 6];
+    for digit in data.iter_mut().rev() {
+        *digit = CODE32_ALPHABET[(value % 32) as usize];
+        value /= 32;
+    }
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+/// Mod 43 check character of Code 39 data, None if data has a character
+/// Code 39 can't encode
+pub fn code39_check_char(data: &str) -> Option<char> {
+    let mut sum = 0;
+    for c in data.chars() {
+        sum += CODE39_CHARS.find(c)?;
+    }
+    CODE39_CHARS.chars().nth(sum % 43)
+}
+
+/// Code 39 data of code as options ask for it
+///
+/// `*` start and stop characters around code are removed, anywhere else
+/// it's [BarcodeError::InvalidCharacter] like lower case letters.
+pub fn code39_data(code: &str, options: &Code39Options) -> Result<String, BarcodeError> {
+    let code = code
+        .strip_prefix('*')
+        .and_then(|code| code.strip_suffix('*'))
+        .unwrap_or(code);
+    if let Some(c) = code.chars().find(|c| !CODE39_CHARS.contains(*c)) {
+        return Err(BarcodeError::InvalidCharacter(c));
+    }
+    if code.is_empty() {
+        return Err(BarcodeError::InvalidLength(0));
+    }
+    let mut data = String::with_capacity(code.len() + 3);
+    if options.start_stop {
+        data.push('*');
+    }
+    data.push_str(code);
+    if options.check_digit {
+        data.push(code39_check_char(code).expect("validated"));
+    }
+    if options.start_stop {
+        data.push('*');
+    }
+    BarcodeType::CODE39.check_len(&data)?;
+    Ok(data)
+}
+
+/// GS1 mod 10 check digit of digits (GTIN, SSCC, ITF-14), weighting the
+/// last digit by 3. None if digits has anything but ASCII digits.
+pub fn gs1_check_digit(digits: &str) -> Option<char> {
+    let mut sum = 0;
+    for (i, c) in digits.chars().rev().enumerate() {
+        let digit = c.to_digit(10)?;
+        sum += if i % 2 == 0 { 3 * digit } else { digit };
+    }
+    char::from_digit((10 - sum % 10) % 10, 10)
+}
+
+/// The 14 digits of an ITF-14 carton code from 13 digits, adding the check
+/// digit, or from 14 digits with a correct check digit
+pub fn itf14_code(code: &str) -> Result<String, BarcodeError> {
+    if let Some(c) = code.chars().find(|c| !c.is_ascii_digit()) {
+        return Err(BarcodeError::InvalidCharacter(c));
+    }
+    match code.len() {
+        13 => Ok(format!(
+            "{}{}",
+            code,
+            gs1_check_digit(code).expect("only digits")
+        )),
+        14 => {
+            let expected = gs1_check_digit(&code[..13]).expect("only digits");
+            if !code.ends_with(expected) {
+                return Err(BarcodeError::InvalidCheckDigit(expected));
+            }
+            Ok(code.to_string())
+        }
+        len => Err(BarcodeError::InvalidLength(len)),
+    }
+}
+
+impl BarcodeType {
+    /// Longest data GS k function B can send, its length is one byte
+    ///
+    /// Code 128 loses two bytes to the code set selection and ITF needs an
+    /// even length. Some printers hang until power cycled when sent more.
+    pub fn max_len(&self) -> usize {
+        match self {
+            BarcodeType::Code128 => 253,
+            BarcodeType::ITF => 254,
+            _ => 255,
+        }
+    }
+
+    /// [BarcodeError::TooLong] when code is longer than [BarcodeType::max_len]
+    pub fn check_len(&self, code: &str) -> Result<(), BarcodeError> {
+        match code.len() {
+            len if len > self.max_len() => Err(BarcodeError::TooLong {
+                len,
+                max: self.max_len(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that code only uses characters the symbology can encode and has
+    /// a length it accepts (check digits are optional for UPC/EAN)
+    pub fn validate(&self, code: &str) -> Result<(), BarcodeError> {
+        let digits = |lengths: &[usize]| {
+            if let Some(c) = code.chars().find(|c| !c.is_ascii_digit()) {
+                return Err(BarcodeError::InvalidCharacter(c));
+            }
+            if !lengths.contains(&code.len()) {
+                return Err(BarcodeError::InvalidLength(code.len()));
+            }
+            Ok(())
+        };
+        let charset = |valid: &dyn Fn(char) -> bool| {
+            if let Some(c) = code.chars().find(|c| !valid(*c)) {
+                return Err(BarcodeError::InvalidCharacter(c));
+            }
+            if code.is_empty() {
+                return Err(BarcodeError::InvalidLength(0));
+            }
+            Ok(())
+        };
+        match self {
+            BarcodeType::UPCA => digits(&[11, 12]),
+            BarcodeType::UPCE => digits(&[6, 7, 8]),
+            BarcodeType::EAN13 => digits(&[12, 13]),
+            BarcodeType::EAN8 => digits(&[7, 8]),
+            BarcodeType::CODE39 => code39_data(code, &Code39Options::default()).map(|_| ()),
+            BarcodeType::ITF => {
+                charset(&|c| c.is_ascii_digit())?;
+                if !code.len().is_multiple_of(2) {
+                    return Err(BarcodeError::InvalidLength(code.len()));
+                }
+                Ok(())
+            }
+            BarcodeType::Codabar => {
+                charset(&|c| c.is_ascii_digit() || "ABCDabcd$+-./:".contains(c))
+            }
+            BarcodeType::Code93 | BarcodeType::Code128 => charset(&|c| c.is_ascii()),
+            _ => Err(BarcodeError::Unsupported),
+        }?;
+        self.check_len(code)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Barcode {
     pub printer: SupportedPrinters,
     pub width: u8,  // 2 <= n <= 6
@@ -148,7 +440,7 @@ impl Barcode {
         }
         // Length must be divisible by 2
         // alternatively we might be able to just prepend a 0?
-        if barcode.len() % 2 != 0 {
+        if !barcode.len().is_multiple_of(2) {
             return Err(CodeCError::InvalidLength);
         }
 
@@ -191,4 +483,104 @@ mod tests {
         let resp = Barcode::to_codeset_c("1234".to_string()).unwrap();
         assert_eq!(resp, vec![0x0c_u8, 0x22]);
     }
+
+    #[test]
+    fn validate_tests() {
+        assert_eq!(BarcodeType::EAN13.validate("590123412345"), Ok(()));
+        assert_eq!(
+            BarcodeType::EAN13.validate("59012341234"),
+            Err(BarcodeError::InvalidLength(11))
+        );
+        assert_eq!(
+            BarcodeType::CODE39.validate("abc"),
+            Err(BarcodeError::InvalidCharacter('a'))
+        );
+        assert_eq!(
+            BarcodeType::ITF.validate("123"),
+            Err(BarcodeError::InvalidLength(3))
+        );
+        assert_eq!(BarcodeType::Code128.validate("Hello 123"), Ok(()));
+
+        assert_eq!(itf14_code("1540014128876").unwrap(), "15400141288763");
+        assert_eq!(itf14_code("15400141288763").unwrap(), "15400141288763");
+        assert_eq!(
+            itf14_code("15400141288760"),
+            Err(BarcodeError::InvalidCheckDigit('3'))
+        );
+        assert_eq!(itf14_code("154"), Err(BarcodeError::InvalidLength(3)));
+    }
+
+    #[test]
+    fn pharmacy_codes() {
+        assert_eq!(pharmacode_bars(3), Ok(vec![false, false]));
+        // 2 + 2
+        assert_eq!(pharmacode_bars(4), Ok(vec![false, true]));
+        assert_eq!(pharmacode_bars(131070), Ok(vec![true; 16]));
+        assert_eq!(pharmacode_bars(2), Err(BarcodeError::InvalidLength(2)));
+        let image = pharmacode_image(4, 40).unwrap();
+        assert_eq!((image.width, image.height), (4 + 8 + 12, 40));
+
+        assert_eq!(code32_digits("01234567").unwrap(), "012345676");
+        assert_eq!(
+            code32_digits("012345670"),
+            Err(BarcodeError::InvalidCheckDigit('6'))
+        );
+        // 12345676 is 0, 11, 24, 24, 10 and 12 in base 32
+        assert_eq!(code32_data("012345676").unwrap(), "0CSSBD");
+    }
+
+    #[test]
+    fn code39_options() {
+        // Wikipedia's example, CODE39 checks with W
+        assert_eq!(code39_check_char("CODE39"), Some('W'));
+        assert_eq!(code39_check_char("abc"), None);
+        let options = Code39Options {
+            check_digit: true,
+            start_stop: true,
+            ratio: None,
+        };
+        assert_eq!(code39_data("*CODE39*", &options).unwrap(), "*CODE39W*");
+        assert_eq!(
+            code39_data("CODE39", &Code39Options::default()).unwrap(),
+            "CODE39"
+        );
+        assert_eq!(
+            code39_data("A*B", &options),
+            Err(BarcodeError::InvalidCharacter('*'))
+        );
+        assert_eq!(
+            code39_data("**", &options),
+            Err(BarcodeError::InvalidLength(0))
+        );
+        assert_eq!(BarcodeType::CODE39.validate("*AB-1*"), Ok(()));
+        assert_eq!(
+            BarcodeType::CODE39.validate("AB*1"),
+            Err(BarcodeError::InvalidCharacter('*'))
+        );
+    }
+
+    #[test]
+    fn limits_length() {
+        let long = "A".repeat(254);
+        assert_eq!(
+            BarcodeType::Code128.validate(&long),
+            Err(BarcodeError::TooLong { len: 254, max: 253 })
+        );
+        assert_eq!(BarcodeType::CODE39.validate(&long), Ok(()));
+        // Refused before anything is encoded, whatever the dialect
+        let bytes = crate::job::barcode(
+            SupportedPrinters::SNBC,
+            &SupportedPrinters::SNBC.quirks(),
+            &long,
+            BarcodeType::Code128,
+            TextPosition::Below,
+            Font::FontA,
+            2,
+            50,
+        );
+        assert!(matches!(
+            bytes,
+            Err(crate::printer::Error::Barcode(BarcodeError::TooLong { .. }))
+        ));
+    }
 }
@@ -0,0 +1,293 @@
+//! posify - print on receipt printers from the command line
+//!
+//! Needs the `cli` feature: `cargo install posify --features cli`.
+
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use posify::barcode::BarcodeType;
+use posify::device::Network;
+use posify::document::{Alignment, Document, TextStyle};
+use posify::img::Image;
+use posify::job::{Encoder, Job};
+use posify::printer::{DrawerPin, OfflineStatus, Printer, SupportedPrinters};
+
+const USAGE: &str = "\
+Usage: posify [OPTIONS] <COMMAND>
+
+Commands:
+  text                 Print text read from stdin
+  image <PATH>         Print an image file
+  barcode <DATA>       Print a barcode, see --type
+  qr <DATA>            Print a QR code
+  test-page            Print a page exercising text, barcodes and QR codes
+  drawer               Open the cash drawer
+  status               Show the printer's off-line status
+
+Options:
+  -t, --target <TARGET>    usb:<vid>:<pid> (hex), tcp:<host>[:<port>] or
+                           file:<path> [default: usb:0483:5743]
+  -p, --printer <NAME>     snbc, p3, epic, star, star-escpos, bixolon,
+                           citizen or generic [default: generic]
+      --type <KIND>        Barcode type: upca, upce, ean13, ean8, code39,
+                           itf, codabar, code93 or code128 [default: code128]
+      --no-cut             Don't cut after printing
+  -h, --help               Show this help
+";
+
+enum Target {
+    Usb { vid: u16, pid: u16 },
+    Tcp { host: String, port: u16 },
+    File(PathBuf),
+}
+
+enum Action {
+    Print(Document),
+    Drawer,
+    Status,
+}
+
+struct Options {
+    target: Target,
+    printer: SupportedPrinters,
+    action: Action,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let result = parse(&args).and_then(|options| run(options).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("posify: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse(args: &[String]) -> Result<Options, String> {
+    let mut target = Target::Usb {
+        vid: 0x0483,
+        pid: 0x5743,
+    };
+    let mut printer = SupportedPrinters::Generic;
+    let mut kind = BarcodeType::Code128;
+    let mut cut = true;
+    let mut positional = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-t" | "--target" => target = parse_target(value()?)?,
+            "-p" | "--printer" => printer = parse_printer(value()?)?,
+            "--type" => kind = parse_barcode_type(value()?)?,
+            "--no-cut" => cut = false,
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    let mut doc = Document::new();
+    let action = match positional.as_slice() {
+        ["text"] => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| e.to_string())?;
+            for line in text.lines() {
+                doc.text(line);
+            }
+            Action::Print(doc)
+        }
+        ["image", path] => {
+            let image = Image::new(path.to_string()).map_err(|e| e.to_string())?;
+            doc.image(image);
+            Action::Print(doc)
+        }
+        ["barcode", data] => {
+            kind.validate(data).map_err(|e| e.to_string())?;
+            doc.barcode(data, kind, 80);
+            Action::Print(doc)
+        }
+        ["qr", data] => {
+            doc.qr(data, 6);
+            Action::Print(doc)
+        }
+        ["test-page"] => {
+            test_page(&mut doc, printer);
+            Action::Print(doc)
+        }
+        ["drawer"] => Action::Drawer,
+        ["status"] => Action::Status,
+        [] => return Err("missing command, see --help".to_string()),
+        other => return Err(format!("unknown command {}, see --help", other.join(" "))),
+    };
+    let action = match action {
+        Action::Print(mut doc) => {
+            doc.feed(3);
+            if cut {
+                doc.cut();
+            }
+            Action::Print(doc)
+        }
+        action => action,
+    };
+    Ok(Options {
+        target,
+        printer,
+        action,
+    })
+}
+
+fn parse_target(target: &str) -> Result<Target, String> {
+    let invalid = || format!("invalid target {}, see --help", target);
+    match target.split_once(':') {
+        Some(("usb", ids)) => {
+            let (vid, pid) = ids.split_once(':').ok_or_else(invalid)?;
+            let hex = |id: &str| u16::from_str_radix(id.trim_start_matches("0x"), 16);
+            Ok(Target::Usb {
+                vid: hex(vid).map_err(|_| invalid())?,
+                pid: hex(pid).map_err(|_| invalid())?,
+            })
+        }
+        Some(("tcp", address)) => {
+            let (host, port) = match address.rsplit_once(':') {
+                Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+                // The raw printing port most network printers listen on
+                None => (address, 9100),
+            };
+            Ok(Target::Tcp {
+                host: host.to_string(),
+                port,
+            })
+        }
+        Some(("file", path)) => Ok(Target::File(PathBuf::from(path))),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_printer(name: &str) -> Result<SupportedPrinters, String> {
+    Ok(match name.to_lowercase().as_str() {
+        "snbc" => SupportedPrinters::SNBC,
+        "p3" => SupportedPrinters::P3,
+        "epic" => SupportedPrinters::Epic,
+        "star" => SupportedPrinters::Star,
+        "star-escpos" => SupportedPrinters::StarEscPos,
+        "bixolon" => SupportedPrinters::Bixolon,
+        "citizen" => SupportedPrinters::Citizen,
+        "generic" => SupportedPrinters::Generic,
+        _ => return Err(format!("unknown printer {}, see --help", name)),
+    })
+}
+
+fn parse_barcode_type(name: &str) -> Result<BarcodeType, String> {
+    Ok(match name.to_lowercase().as_str() {
+        "upca" => BarcodeType::UPCA,
+        "upce" => BarcodeType::UPCE,
+        "ean13" => BarcodeType::EAN13,
+        "ean8" => BarcodeType::EAN8,
+        "code39" => BarcodeType::CODE39,
+        "itf" => BarcodeType::ITF,
+        "codabar" => BarcodeType::Codabar,
+        "code93" => BarcodeType::Code93,
+        "code128" => BarcodeType::Code128,
+        _ => return Err(format!("unknown barcode type {}, see --help", name)),
+    })
+}
+
+fn test_page(doc: &mut Document, printer: SupportedPrinters) {
+    let centered = |bold, size| TextStyle {
+        align: Alignment::Center,
+        bold,
+        width: size,
+        height: size,
+        ..TextStyle::default()
+    };
+    doc.styled("POSIFY", centered(true, 2))
+        .styled(&format!("Test page for {:?}", printer), centered(false, 1))
+        .text("")
+        .text("Normal text")
+        .styled(
+            "Bold text",
+            TextStyle {
+                bold: true,
+                ..TextStyle::default()
+            },
+        )
+        .styled(
+            "Underlined text",
+            TextStyle {
+                underline: true,
+                ..TextStyle::default()
+            },
+        )
+        .styled(
+            "Right aligned",
+            TextStyle {
+                align: Alignment::Right,
+                ..TextStyle::default()
+            },
+        )
+        .text("0123456789 ABCDEFGHIJKLMNOPQRSTUVWXYZ")
+        .barcode("POSIFY-123", BarcodeType::Code128, 80)
+        .qr("https://github.com/flynnguy/posify", 6);
+}
+
+fn run(options: Options) -> Result<(), Box<dyn std::error::Error>> {
+    match options.target {
+        Target::Usb { vid, pid } => {
+            let mut printer = Printer::new(None, None, options.printer, vid, pid)?;
+            match options.action {
+                Action::Print(doc) => {
+                    printer.print_document(&doc)?;
+                }
+                Action::Drawer => {
+                    printer.cashdraw(DrawerPin::Pin2, 100, 200)?;
+                }
+                Action::Status => println!("{}", printer.status_offline()?),
+            }
+        }
+        Target::Tcp { host, port } => {
+            let mut network = Network::new(&host, port)?;
+            match options.action {
+                Action::Status => {
+                    network.set_read_timeout(Some(Duration::from_secs(2)))?;
+                    // DLE EOT 2 - Transmit off-line status
+                    network.write_all(&[0x10, 0x04, 0x02])?;
+                    let mut status = [0; 1];
+                    network.read_exact(&mut status)?;
+                    println!("{}", OfflineStatus::from(status[0]));
+                }
+                action => network.write_all(&encode(action, options.printer)?)?,
+            }
+        }
+        Target::File(path) => match options.action {
+            Action::Status => return Err("status needs a usb or tcp target".into()),
+            action => {
+                let mut file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+                file.write_all(&encode(action, options.printer)?)?;
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Bytes for targets without a [Printer]
+fn encode(action: Action, printer: SupportedPrinters) -> Result<Vec<u8>, posify::printer::Error> {
+    match action {
+        Action::Print(doc) => Encoder::new(printer).encode(&Job::from(&doc)),
+        // The pulse in the printer's dialect, the device is never opened
+        _ => Printer::lazy(None, None, printer, 0, 0)
+            .capture(|p| p.cashdraw(DrawerPin::Pin2, 100, 200)),
+    }
+}
@@ -0,0 +1,188 @@
+//! Card payment slips with tip and signature lines
+//!
+//! A [CardSlip] prints the amount authorised, a tip and a total line and,
+//! on the merchant copy, a rule to sign on. Lines the customer fills in by
+//! hand are blank rules with a free line above them, a pen needs more room
+//! than the printer font. Print one slip of each [SlipCopy] per payment.
+//!
+//! ```
+//! use posify::card_slip::{CardSlip, SlipCopy};
+//!
+//! let mut slip = CardSlip::new(4250, 32);
+//! slip.card("VISA **** 1234").authorization("A1B2C3");
+//! let merchant = slip.document(SlipCopy::Merchant);
+//! // The tip was written on the merchant copy, the customer gets the total
+//! slip.tip(600);
+//! let customer = slip.document(SlipCopy::Customer);
+//! ```
+
+use crate::barcode::Font;
+use crate::document::{Alignment, Document, TextStyle};
+use crate::format;
+use crate::locale::Locale;
+use crate::profile::Profile;
+
+/// Who keeps the slip
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SlipCopy {
+    /// Signed by the customer and kept by the merchant
+    Merchant,
+    Customer,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CardSlip {
+    /// Characters per printed line
+    pub width: usize,
+    /// Authorised amount in minor units
+    pub amount: i64,
+    /// None prints blank tip and total lines to write on
+    pub tip: Option<i64>,
+    /// Masked card number, e.g. `VISA **** 1234`
+    pub card: Option<String>,
+    pub authorization: Option<String>,
+    /// Labels and amounts, English by default
+    pub locale: Locale,
+}
+
+impl CardSlip {
+    /// Slip as wide as font A fits on the paper of profile, see
+    /// [Profile::chars_per_line]
+    pub fn for_profile(amount: i64, profile: &Profile) -> CardSlip {
+        CardSlip::new(amount, profile.chars_per_line(Font::FontA, 1))
+    }
+
+    pub fn new(amount: i64, width: usize) -> CardSlip {
+        CardSlip {
+            width,
+            amount,
+            tip: None,
+            card: None,
+            authorization: None,
+            locale: Locale::default(),
+        }
+    }
+
+    pub fn tip(&mut self, tip: i64) -> &mut Self {
+        self.tip = Some(tip);
+        self
+    }
+
+    pub fn card(&mut self, card: &str) -> &mut Self {
+        self.card = Some(card.to_string());
+        self
+    }
+
+    pub fn authorization(&mut self, code: &str) -> &mut Self {
+        self.authorization = Some(code.to_string());
+        self
+    }
+
+    pub fn document(&self, copy: SlipCopy) -> Document {
+        let money = &self.locale.money;
+        let center = TextStyle {
+            align: Alignment::Center,
+            ..TextStyle::default()
+        };
+        let bold = TextStyle {
+            bold: true,
+            ..TextStyle::default()
+        };
+        // As wide as the widest amount is likely to be written
+        let rule = "_".repeat((self.width / 2).max(1));
+
+        let mut doc = Document::new();
+        let title = match copy {
+            SlipCopy::Merchant => self.locale.get("merchant_copy"),
+            SlipCopy::Customer => self.locale.get("customer_copy"),
+        };
+        doc.styled(
+            title,
+            TextStyle {
+                bold: true,
+                ..center
+            },
+        );
+        if let Some(card) = &self.card {
+            doc.styled(card, center);
+        }
+        if let Some(code) = &self.authorization {
+            doc.styled(&self.locale.text("auth", &[("code", code)]), center);
+        }
+        doc.feed(1);
+
+        let amount = money.format(self.amount);
+        doc.text(&format::columns(
+            self.locale.get("amount"),
+            &amount,
+            self.width,
+        ));
+        match self.tip {
+            Some(tip) => {
+                let total = money.format(self.amount.saturating_add(tip));
+                let tip = money.format(tip);
+                doc.text(&format::columns(self.locale.get("tip"), &tip, self.width));
+                doc.styled(
+                    &format::columns(self.locale.get("total"), &total, self.width),
+                    bold,
+                );
+            }
+            None => {
+                // Room above each rule for handwriting
+                doc.feed(1);
+                doc.text(&format::columns(self.locale.get("tip"), &rule, self.width));
+                doc.feed(1);
+                doc.styled(
+                    &format::columns(self.locale.get("total"), &rule, self.width),
+                    bold,
+                );
+            }
+        }
+
+        if copy == SlipCopy::Merchant {
+            doc.feed(3);
+            doc.text(&format!("X{}", "_".repeat(self.width.saturating_sub(1))));
+            doc.styled(self.locale.get("signature"), center);
+        }
+        doc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Element;
+
+    fn lines(doc: &Document) -> Vec<String> {
+        doc.elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Text { content, .. } => Some(content.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prints_copies() {
+        let mut slip = CardSlip::new(1000, 20);
+        let merchant = lines(&slip.document(SlipCopy::Merchant));
+        assert_eq!(merchant[0], "MERCHANT COPY");
+        assert_eq!(merchant[2], "Tip       __________");
+        assert_eq!(merchant[3], "TOTAL     __________");
+        assert_eq!(merchant.last().unwrap(), "Signature");
+
+        slip.locale = Locale::de_de();
+        slip.tip(150);
+        let customer = lines(&slip.document(SlipCopy::Customer));
+        assert_eq!(customer[0], "KUNDENBELEG");
+        assert_eq!(customer[2], "Trinkgeld     1,50 €");
+        assert_eq!(customer.last().unwrap(), "SUMME        11,50 €");
+
+        slip.authorization("A1B2C3");
+        let customer = lines(&slip.document(SlipCopy::Customer));
+        assert_eq!(customer[1], "Genehmigung A1B2C3");
+    }
+}
@@ -1,4 +1,4 @@
-/**
+/*!
  * ESC/POS Commands (Constants)
  */
 
@@ -20,7 +20,9 @@ pub const LS_SET: &[u8] = b"\x1b\x33";
  * [`CASH_DRAWER` Cash Drawer]
  */
 // .CASH_DRAWER
+#[deprecated(note = "missing the pulse times, use Printer::cashdraw")]
 pub const CD_KICK_2: &[u8] = b"\x1b\x70\x00"; // Sends a pulse to pin 2 []
+#[deprecated(note = "missing the pulse times, use Printer::cashdraw")]
 pub const CD_KICK_5: &[u8] = b"\x1b\x70\x01"; // Sends a pulse to pin 5 []
 
 /**
@@ -36,6 +38,8 @@ pub const TXT_UNDERL_ON: &[u8] = b"\x1b\x2d\x01"; // Underline font 1-dot ON
 pub const TXT_UNDERL2_ON: &[u8] = b"\x1b\x2d\x02"; // Underline font 2-dot ON
 pub const TXT_BOLD_OFF: &[u8] = b"\x1b\x45\x00"; // Bold font OFF
 pub const TXT_BOLD_ON: &[u8] = b"\x1b\x45\x01"; // Bold font ON
+pub const TXT_REVERSE_OFF: &[u8] = b"\x1d\x42\x00"; // White/black reverse OFF
+pub const TXT_REVERSE_ON: &[u8] = b"\x1d\x42\x01"; // White/black reverse ON
 
 pub const TXT_FONT_A: &[u8] = b"\x1b\x4d\x00"; // Font type A
 pub const TXT_FONT_B: &[u8] = b"\x1b\x4d\x01"; // Font type B
@@ -49,7 +53,6 @@ pub const TXT_ALIGN_RT: &[u8] = b"\x1b\x61\x02"; // Right justification
  * [`BARCODE_FORMAT` Barcode format]
  */
 // .BARCODE_FORMAT
-
 pub const BARCODE_FONT_A: &[u8] = b"\x1d\x66\x00"; // Font type A for HRI barcode chars
 pub const BARCODE_FONT_B: &[u8] = b"\x1d\x66\x01"; // Font type B for HRI barcode chars
 
@@ -85,3 +88,23 @@ pub const GSV0_NORMAL: &[u8] = b"\x1d\x76\x30\x00";
 pub const GSV0_DW: &[u8] = b"\x1d\x76\x30\x01";
 pub const GSV0_DH: &[u8] = b"\x1d\x76\x30\x02";
 pub const GSV0_DWDH: &[u8] = b"\x1d\x76\x30\x03";
+
+/**
+ * [`STAR_LINE_MODE` Star Line Mode]
+ */
+// .STAR_LINE_MODE
+pub const STAR_BOLD_ON: &[u8] = b"\x1b\x45"; // Select emphasized printing
+pub const STAR_BOLD_OFF: &[u8] = b"\x1b\x46"; // Cancel emphasized printing
+pub const STAR_REVERSE_ON: &[u8] = b"\x1b\x34"; // Select highlight printing
+pub const STAR_REVERSE_OFF: &[u8] = b"\x1b\x35"; // Cancel highlight printing
+pub const STAR_ALIGN_LT: &[u8] = b"\x1b\x1d\x61\x00"; // Left alignment
+pub const STAR_ALIGN_CT: &[u8] = b"\x1b\x1d\x61\x01"; // Center alignment
+pub const STAR_ALIGN_RT: &[u8] = b"\x1b\x1d\x61\x02"; // Right alignment
+pub const STAR_FONT_A: &[u8] = b"\x1b\x1e\x46\x00"; // Font A (12 x 24)
+pub const STAR_FONT_B: &[u8] = b"\x1b\x1e\x46\x01"; // Font B (9 x 24)
+pub const STAR_FULL_CUT: &[u8] = b"\x1b\x64\x02"; // Feed to cutting position and full cut
+pub const STAR_PARTIAL_CUT: &[u8] = b"\x1b\x64\x03"; // Feed to cutting position and partial cut
+pub const STAR_RASTER_START: &[u8] = b"\x1b\x2a\x72\x41"; // Enter raster mode
+pub const STAR_RASTER_END: &[u8] = b"\x1b\x2a\x72\x42"; // Quit raster mode
+pub const STAR_DRAWER_1: &[u8] = b"\x07"; // Open drawer 1 (pin 2)
+pub const STAR_DRAWER_2: &[u8] = b"\x1a"; // Open drawer 2 (pin 5)
@@ -0,0 +1,199 @@
+//! Coupons printed below a receipt
+//!
+//! A [Coupon] is separated from what's above it by a dashed "cut here"
+//! line, frames the offer text and ends with a barcode that carries the
+//! expiry date next to the coupon code, so the till can reject expired
+//! coupons without a lookup. EAN-13 coupons take a 6 digit code followed by
+//! `YYMMDD` and a check digit, Code 128 ones the code followed by `YYMMDD`.
+//!
+//! ```
+//! use posify::barcode::BarcodeType;
+//! use posify::coupon::Coupon;
+//! use posify::locale::DateTime;
+//!
+//! let expiry = DateTime::new(2026, 12, 31, 0, 0, 0);
+//! let mut coupon = Coupon::new("123456", BarcodeType::EAN13, expiry, 32);
+//! coupon.offer("20% OFF").offer("your next coffee").cut(true);
+//! assert_eq!(coupon.barcode_data()?, "1234562612312");
+//! let doc = coupon.document()?;
+//! # Ok::<(), posify::barcode::BarcodeError>(())
+//! ```
+
+use crate::barcode::{self, BarcodeError, BarcodeType, Font};
+use crate::document::{Alignment, Document, TextStyle};
+use crate::locale::{DateTime, Locale};
+use crate::profile::Profile;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Coupon {
+    /// Characters per printed line
+    pub width: usize,
+    /// Lines printed centered in the frame
+    pub offer: Vec<String>,
+    pub code: String,
+    /// [BarcodeType::EAN13] or [BarcodeType::Code128]
+    pub kind: BarcodeType,
+    /// Last day the coupon is valid
+    pub expiry: DateTime,
+    /// Barcode height in dots
+    pub height: u8,
+    /// Label and date format of the expiry line
+    pub locale: Locale,
+    /// Partial cut after the coupon
+    pub cut: bool,
+}
+
+impl Coupon {
+    /// Coupon as wide as font A fits on the paper of profile, see
+    /// [Profile::chars_per_line]
+    pub fn for_profile(
+        code: &str,
+        kind: BarcodeType,
+        expiry: DateTime,
+        profile: &Profile,
+    ) -> Coupon {
+        Coupon::new(code, kind, expiry, profile.chars_per_line(Font::FontA, 1))
+    }
+
+    pub fn new(code: &str, kind: BarcodeType, expiry: DateTime, width: usize) -> Coupon {
+        Coupon {
+            width,
+            offer: Vec::new(),
+            code: code.to_string(),
+            kind,
+            expiry,
+            height: 80,
+            locale: Locale::default(),
+            cut: false,
+        }
+    }
+
+    pub fn offer(&mut self, line: &str) -> &mut Self {
+        self.offer.push(line.to_string());
+        self
+    }
+
+    pub fn cut(&mut self, cut: bool) -> &mut Self {
+        self.cut = cut;
+        self
+    }
+
+    /// The code with the expiry date, validated for the barcode type
+    pub fn barcode_data(&self) -> Result<String, BarcodeError> {
+        let expiry = format!(
+            "{:02}{:02}{:02}",
+            self.expiry.year.rem_euclid(100),
+            self.expiry.month,
+            self.expiry.day
+        );
+        let data = match self.kind {
+            BarcodeType::EAN13 => {
+                if let Some(c) = self.code.chars().find(|c| !c.is_ascii_digit()) {
+                    return Err(BarcodeError::InvalidCharacter(c));
+                }
+                if self.code.len() != 6 {
+                    return Err(BarcodeError::InvalidLength(self.code.len()));
+                }
+                let digits = format!("{}{}", self.code, expiry);
+                let check = barcode::gs1_check_digit(&digits).expect("only digits");
+                format!("{}{}", digits, check)
+            }
+            BarcodeType::Code128 => format!("{}{}", self.code, expiry),
+            _ => return Err(BarcodeError::Unsupported),
+        };
+        self.kind.validate(&data)?;
+        Ok(data)
+    }
+
+    pub fn document(&self) -> Result<Document, BarcodeError> {
+        let data = self.barcode_data()?;
+        let center = TextStyle {
+            align: Alignment::Center,
+            ..TextStyle::default()
+        };
+        let mut doc = Document::new();
+        doc.text(&cut_line(self.width));
+        doc.feed(1);
+
+        // The frame takes two characters on each side
+        let inner = self.width.saturating_sub(4);
+        let border = format!("+{}+", "-".repeat(self.width.saturating_sub(2)));
+        doc.text(&border);
+        for line in &self.offer {
+            let line: String = line.chars().take(inner).collect();
+            doc.styled(
+                &format!("| {:^inner$} |", line, inner = inner),
+                TextStyle {
+                    bold: true,
+                    ..TextStyle::default()
+                },
+            );
+        }
+        doc.text(&border);
+
+        doc.barcode(&data, self.kind, self.height);
+        let date = self.locale.date.format_date(&self.expiry);
+        doc.styled(&self.locale.text("valid_until", &[("date", &date)]), center);
+        if self.cut {
+            doc.feed(3);
+            doc.cut();
+        }
+        Ok(doc)
+    }
+}
+
+/// Dashes across width characters with "cut here" in the middle
+fn cut_line(width: usize) -> String {
+    let label = " cut here ";
+    let side = width.saturating_sub(label.len()) / 2;
+    let dashes = |n: usize| {
+        "- ".repeat(n.div_ceil(2))
+            .chars()
+            .take(n)
+            .collect::<String>()
+    };
+    let right = width.saturating_sub(side + label.len());
+    format!(
+        "{}{}{}",
+        dashes(side),
+        label,
+        dashes(right).chars().rev().collect::<String>()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Element;
+
+    #[test]
+    fn embeds_expiry() {
+        let expiry = DateTime::new(2027, 1, 5, 0, 0, 0);
+        let mut coupon = Coupon::new("SUMMER", BarcodeType::Code128, expiry, 24);
+        coupon.offer("Free dessert");
+        assert_eq!(coupon.barcode_data().unwrap(), "SUMMER270105");
+
+        let doc = coupon.document().unwrap();
+        let texts: Vec<&str> = doc
+            .elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Text { content, .. } => Some(content.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts[0], "- - - - cut here - - - -");
+        assert_eq!(texts[2], "|     Free dessert     |");
+        assert_eq!(texts[4], "Valid until 01/05/2027");
+        assert!(!matches!(doc.elements.last(), Some(Element::Cut)));
+
+        coupon.kind = BarcodeType::EAN13;
+        assert_eq!(
+            coupon.barcode_data(),
+            Err(BarcodeError::InvalidCharacter('S'))
+        );
+        coupon.kind = BarcodeType::QRCode;
+        assert_eq!(coupon.barcode_data(), Err(BarcodeError::Unsupported));
+    }
+}
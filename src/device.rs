@@ -2,6 +2,7 @@ use std::fs;
 use std::io;
 use std::net;
 use std::path;
+use std::time::Duration;
 
 pub struct Usb {}
 pub struct Serial {}
@@ -22,6 +23,18 @@ impl Network {
             stream,
         })
     }
+
+    /// How long reads wait for the printer to answer, forever with None
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+}
+
+/// Reads status responses from the printer
+impl io::Read for Network {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
 }
 
 impl io::Write for Network {
@@ -46,6 +59,7 @@ impl<W: io::Write> File<W> {
         let fobj = fs::OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(false)
             .open(&path)?;
         Ok(File { fobj })
     }
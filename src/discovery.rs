@@ -0,0 +1,453 @@
+//! Finding the printers attached to this machine
+//!
+//! [list] returns USB printers, by USB printer class or a known
+//! manufacturer, and the printer queues of the operating system, so a setup
+//! screen can offer a picker instead of asking for ids or device paths.
+//! System queues are read from CUPS with `lpstat` and printed to raw with
+//! `lp`, on Windows they are the local and connected printers of the spooler
+//! (WinSpool), printed to with the RAW datatype.
+//!
+//! ```no_run
+//! use posify::discovery::{self, Handle};
+//!
+//! for found in discovery::list() {
+//!     println!("{} ({:?})", found.name, found.location);
+//! }
+//! if let Some(found) = discovery::list().first() {
+//!     match found.open()? {
+//!         Handle::Usb(mut printer) => {
+//!             printer.println("Hello")?;
+//!         }
+//!         Handle::System(mut queue) => {
+//!             use std::io::Write;
+//!             queue.write_all(b"Hello\n")?;
+//!             queue.flush()?;
+//!         }
+//!     }
+//! }
+//! # Ok::<(), posify::printer::Error>(())
+//! ```
+
+use std::io::{self, Write};
+#[cfg(not(windows))]
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::printer::{self, Error, Printer, SupportedPrinters};
+
+/// USB class code of printers
+const USB_CLASS_PRINTER: u8 = 0x07;
+
+/// Where a found printer is attached
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    Usb {
+        vid: u16,
+        pid: u16,
+        bus: u8,
+        address: u8,
+    },
+    /// Printer queue of the operating system
+    System { queue: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FoundPrinter {
+    /// Product or queue name to show to users
+    pub name: String,
+    pub location: Location,
+    /// Dialect recognized from the manufacturer, [SupportedPrinters::Generic]
+    /// is a reasonable guess when None
+    pub dialect: Option<SupportedPrinters>,
+}
+
+/// An opened [FoundPrinter]
+pub enum Handle {
+    Usb(Box<Printer>),
+    System(SystemPrinter),
+}
+
+impl FoundPrinter {
+    /// Opens the printer, a USB printer at its bus and address so the one
+    /// picked is opened when several share the same ids
+    pub fn open(&self) -> Result<Handle, Error> {
+        match &self.location {
+            Location::Usb {
+                vid,
+                pid,
+                bus,
+                address,
+            } => {
+                let dialect = self.dialect.unwrap_or(SupportedPrinters::Generic);
+                Ok(Handle::Usb(Box::new(Printer::at_port(
+                    None, None, dialect, *vid, *pid, *bus, *address,
+                )?)))
+            }
+            Location::System { queue } => Ok(Handle::System(SystemPrinter::new(queue))),
+        }
+    }
+}
+
+/// USB printers followed by system printer queues
+pub fn list() -> Vec<FoundPrinter> {
+    let mut found = usb();
+    found.extend(system());
+    found
+}
+
+/// USB devices of the printer class or from a known printer manufacturer
+pub fn usb() -> Vec<FoundPrinter> {
+    let Ok(devices) = rusb::devices() else {
+        return Vec::new();
+    };
+    let timeout = Duration::from_millis(200);
+    let mut found = Vec::new();
+    for device in devices.iter() {
+        let Ok(desc) = device.device_descriptor() else {
+            continue;
+        };
+        let printer_class = device.config_descriptor(0).is_ok_and(|config| {
+            config.interfaces().any(|interface| {
+                interface
+                    .descriptors()
+                    .any(|d| d.class_code() == USB_CLASS_PRINTER)
+            })
+        });
+        // Strings need the device opened, which may need permissions
+        let (manufacturer, product) = match device.open() {
+            Ok(handle) => match handle
+                .read_languages(timeout)
+                .ok()
+                .and_then(|l| l.first().copied())
+            {
+                Some(language) => (
+                    handle
+                        .read_manufacturer_string(language, &desc, timeout)
+                        .ok(),
+                    handle.read_product_string(language, &desc, timeout).ok(),
+                ),
+                None => (None, None),
+            },
+            Err(_) => (None, None),
+        };
+        let (vid, pid) = (desc.vendor_id(), desc.product_id());
+        let dialect = printer::match_manufacturer(vid, pid, manufacturer.as_deref());
+        if !printer_class && dialect.is_none() {
+            continue;
+        }
+        let name = match (manufacturer, product) {
+            (Some(manufacturer), Some(product)) => format!("{} {}", manufacturer, product),
+            (None, Some(name)) | (Some(name), None) => name,
+            (None, None) => format!("USB printer {:04x}:{:04x}", vid, pid),
+        };
+        found.push(FoundPrinter {
+            name,
+            location: Location::Usb {
+                vid,
+                pid,
+                bus: device.bus_number(),
+                address: device.address(),
+            },
+            dialect,
+        });
+    }
+    found
+}
+
+/// CUPS printer queues, empty when CUPS isn't installed, or the Windows
+/// spooler's printers
+pub fn system() -> Vec<FoundPrinter> {
+    #[cfg(windows)]
+    let queues = winspool::printers().unwrap_or_default();
+    #[cfg(not(windows))]
+    let queues = match Command::new("lpstat").arg("-e").output() {
+        Ok(output) => parse_lpstat(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => Vec::new(),
+    };
+    queues
+        .into_iter()
+        .map(|queue| FoundPrinter {
+            name: queue.clone(),
+            location: Location::System { queue },
+            dialect: None,
+        })
+        .collect()
+}
+
+/// Queue names from `lpstat -e`, one per line
+#[cfg(any(not(windows), test))]
+fn parse_lpstat(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Raw print job on a system printer queue
+///
+/// Bytes are collected until [flush](Write::flush) or drop, then sent as
+/// one job with `lp -o raw`, or as a RAW WinSpool document on Windows.
+pub struct SystemPrinter {
+    queue: String,
+    buffer: Vec<u8>,
+}
+
+impl SystemPrinter {
+    pub fn new(queue: &str) -> SystemPrinter {
+        SystemPrinter {
+            queue: queue.to_string(),
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn queue(&self) -> &str {
+        &self.queue
+    }
+
+    /// Sends the buffer as one raw CUPS job
+    #[cfg(not(windows))]
+    fn lp(&self) -> io::Result<()> {
+        let mut lp = Command::new("lp")
+            .args(["-s", "-o", "raw", "-d", &self.queue])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()?;
+        // Waited on even when writing fails, lp's exit says more than a
+        // broken pipe
+        let written = match lp.stdin.take() {
+            Some(mut stdin) => stdin.write_all(&self.buffer),
+            None => Ok(()),
+        };
+        let status = lp.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "lp failed for {}: {}",
+                self.queue, status
+            )));
+        }
+        written
+    }
+}
+
+impl Write for SystemPrinter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        #[cfg(windows)]
+        winspool::print_raw(&self.queue, &self.buffer)?;
+        #[cfg(not(windows))]
+        self.lp()?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl Drop for SystemPrinter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// The Windows spooler, declared here instead of pulling in a bindings
+/// crate for the handful of calls
+#[cfg(windows)]
+mod winspool {
+    use std::ffi::c_void;
+    use std::io;
+    use std::ptr;
+
+    type Handle = *mut c_void;
+
+    const PRINTER_ENUM_LOCAL: u32 = 0x02;
+    const PRINTER_ENUM_CONNECTIONS: u32 = 0x04;
+    const ERROR_INSUFFICIENT_BUFFER: i32 = 122;
+
+    #[repr(C)]
+    struct PrinterInfo4 {
+        printer_name: *const u16,
+        server_name: *const u16,
+        attributes: u32,
+    }
+
+    #[repr(C)]
+    struct DocInfo1 {
+        doc_name: *const u16,
+        output_file: *const u16,
+        datatype: *const u16,
+    }
+
+    #[link(name = "winspool")]
+    extern "system" {
+        fn EnumPrintersW(
+            flags: u32,
+            name: *const u16,
+            level: u32,
+            printer_enum: *mut u8,
+            buf_size: u32,
+            needed: *mut u32,
+            returned: *mut u32,
+        ) -> i32;
+        fn OpenPrinterW(name: *const u16, printer: *mut Handle, default: *const c_void) -> i32;
+        fn ClosePrinter(printer: Handle) -> i32;
+        fn StartDocPrinterW(printer: Handle, level: u32, doc_info: *const DocInfo1) -> u32;
+        fn EndDocPrinter(printer: Handle) -> i32;
+        fn StartPagePrinter(printer: Handle) -> i32;
+        fn EndPagePrinter(printer: Handle) -> i32;
+        fn WritePrinter(printer: Handle, buf: *const c_void, size: u32, written: *mut u32) -> i32;
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    /// # Safety
+    /// s is null or points to a NUL terminated UTF-16 string
+    unsafe fn from_wide(s: *const u16) -> String {
+        if s.is_null() {
+            return String::new();
+        }
+        let mut len = 0;
+        while *s.add(len) != 0 {
+            len += 1;
+        }
+        String::from_utf16_lossy(std::slice::from_raw_parts(s, len))
+    }
+
+    /// Names of the local printers and the printer connections of the user
+    pub(super) fn printers() -> io::Result<Vec<String>> {
+        let flags = PRINTER_ENUM_LOCAL | PRINTER_ENUM_CONNECTIONS;
+        let (mut needed, mut returned) = (0, 0);
+        // The first call only says how much room the list needs
+        let ok = unsafe {
+            EnumPrintersW(
+                flags,
+                ptr::null(),
+                4,
+                ptr::null_mut(),
+                0,
+                &mut needed,
+                &mut returned,
+            )
+        };
+        if ok == 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(ERROR_INSUFFICIENT_BUFFER) {
+                return Err(err);
+            }
+        }
+        if needed == 0 {
+            return Ok(Vec::new());
+        }
+        // u64s to keep the structs in the buffer aligned
+        let mut buf = vec![0_u64; (needed as usize).div_ceil(8)];
+        let ok = unsafe {
+            EnumPrintersW(
+                flags,
+                ptr::null(),
+                4,
+                buf.as_mut_ptr().cast(),
+                needed,
+                &mut needed,
+                &mut returned,
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let infos = unsafe {
+            std::slice::from_raw_parts(buf.as_ptr().cast::<PrinterInfo4>(), returned as usize)
+        };
+        Ok(infos
+            .iter()
+            .map(|info| unsafe { from_wide(info.printer_name) })
+            .filter(|name| !name.is_empty())
+            .collect())
+    }
+
+    /// Sends data to queue as one document with the RAW datatype, which the
+    /// spooler passes to the printer untouched
+    pub(super) fn print_raw(queue: &str, data: &[u8]) -> io::Result<()> {
+        let name = wide(queue);
+        let mut printer: Handle = ptr::null_mut();
+        if unsafe { OpenPrinterW(name.as_ptr(), &mut printer, ptr::null()) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let res = write_document(printer, data);
+        unsafe { ClosePrinter(printer) };
+        res
+    }
+
+    fn write_document(printer: Handle, data: &[u8]) -> io::Result<()> {
+        let size = u32::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "print job too large"))?;
+        let (doc_name, datatype) = (wide("posify"), wide("RAW"));
+        let doc_info = DocInfo1 {
+            doc_name: doc_name.as_ptr(),
+            output_file: ptr::null(),
+            datatype: datatype.as_ptr(),
+        };
+        if unsafe { StartDocPrinterW(printer, 1, &doc_info) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut written = 0;
+        let ok = unsafe {
+            StartPagePrinter(printer) != 0
+                && WritePrinter(printer, data.as_ptr().cast(), size, &mut written) != 0
+                && EndPagePrinter(printer) != 0
+        };
+        // Read before EndDocPrinter can overwrite it
+        let err = io::Error::last_os_error();
+        let ended = unsafe { EndDocPrinter(printer) } != 0;
+        if !ok {
+            return Err(err);
+        }
+        if written != size {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                format!("spooler took {} of {} bytes", written, size),
+            ));
+        }
+        if !ended {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn converts_wide_strings() {
+            let name = wide("Küche TM-T20");
+            // One unit per character, then the NUL
+            assert_eq!(name.len(), 13);
+            assert_eq!(name.last(), Some(&0));
+            assert_eq!(unsafe { from_wide(name.as_ptr()) }, "Küche TM-T20");
+            assert_eq!(unsafe { from_wide(wide("").as_ptr()) }, "");
+            assert_eq!(unsafe { from_wide(ptr::null()) }, "");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lpstat() {
+        assert_eq!(
+            parse_lpstat("Kitchen_TM_T20\nBar\n\n"),
+            ["Kitchen_TM_T20", "Bar"]
+        );
+        assert!(parse_lpstat("").is_empty());
+    }
+}
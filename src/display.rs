@@ -0,0 +1,245 @@
+//! Customer facing line displays (VFD/LCD pole displays) that are daisy
+//! chained through the printer
+//!
+//! The printer passes data on to the display after `ESC = 2` selects the
+//! display as the peripheral device. Every [Display] operation is sent as one
+//! write that selects the display, sends the display command and selects the
+//! printer again, so printing is never left routed to the display.
+//!
+//! A [Job](crate::job::Job) can carry [DisplayCommand]s between its printed
+//! commands, so checkout code shows the total due and prints the receipt
+//! with one [Printer::submit](crate::printer::Printer::submit).
+//!
+//! ```no_run
+//! use posify::display::ScrollMode;
+//! use posify::printer::{Printer, SupportedPrinters};
+//!
+//! let mut printer = Printer::new(None, None, SupportedPrinters::SNBC, 0x154f, 0x0517)?;
+//! let mut display = printer.display()?;
+//! display.clear()?;
+//! display.scroll_mode(ScrollMode::Overwrite)?;
+//! display.text("TOTAL")?;
+//! display.set_cursor(15, 2)?;
+//! display.text("12.50")?;
+//! # Ok::<(), posify::printer::Error>(())
+//! ```
+
+use encoding::types::{EncoderTrap, EncodingRef};
+
+use crate::printer::{Error, Printer, SupportedPrinters};
+
+/// ESC = 2, select the customer display
+const SELECT_DISPLAY: &[u8] = &[0x1b, 0x3d, 0x02];
+/// ESC = 1, select the printer
+const SELECT_PRINTER: &[u8] = &[0x1b, 0x3d, 0x01];
+
+/// How the display behaves when text reaches the end of a line
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScrollMode {
+    /// US MD1 - Characters overwrite the display from the top left
+    Overwrite,
+    /// US MD2 - Lines scroll up when the bottom line is full
+    Vertical,
+    /// US MD3 - The line scrolls left as characters are added
+    Horizontal,
+}
+
+/// Display operation inside a [Job](crate::job::Job), see
+/// [Command::Display](crate::job::Command::Display)
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DisplayCommand {
+    /// See [Display::clear]
+    Clear,
+    /// See [Display::set_cursor]
+    Cursor { column: u8, row: u8 },
+    /// See [Display::text]
+    Text(String),
+}
+
+impl DisplayCommand {
+    /// Appends the command to out, framed like every [Display] operation
+    pub(crate) fn encode(
+        &self,
+        printer: SupportedPrinters,
+        codec: EncodingRef,
+        trap: EncoderTrap,
+        out: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        if !has_display_port(printer) {
+            return Err(Error::Unsupported);
+        }
+        out.extend_from_slice(SELECT_DISPLAY);
+        match self {
+            DisplayCommand::Clear => out.push(0x0c),
+            DisplayCommand::Cursor { column, row } => {
+                if !(1..=20).contains(column) || !(1..=2).contains(row) {
+                    return Err(Error::InvalidArgument);
+                }
+                out.extend_from_slice(&[0x1f, 0x24, *column, *row]);
+            }
+            DisplayCommand::Text(text) => codec
+                .encode_to(text, trap, out)
+                .map_err(|_| Error::InvalidArgument)?,
+        }
+        out.extend_from_slice(SELECT_PRINTER);
+        Ok(())
+    }
+}
+
+/// Whether the dialect passes data on to a display after ESC = 2, see
+/// [Display::new]
+fn has_display_port(printer: SupportedPrinters) -> bool {
+    !matches!(
+        printer,
+        SupportedPrinters::P3
+            | SupportedPrinters::Star
+            | SupportedPrinters::Generic
+            | SupportedPrinters::Unknown
+    )
+}
+
+/// A customer display connected to the printer's display port
+pub struct Display<'a> {
+    printer: &'a mut Printer,
+}
+
+impl<'a> Display<'a> {
+    /// Only printers that treat ESC = 2 as "select display" can pass data on
+    /// to a display, the P3 uses ESC = 2 to disable itself instead. Star Line
+    /// Mode has no ESC = at all and clones rarely implement it.
+    pub fn new(printer: &'a mut Printer) -> Result<Display<'a>, Error> {
+        match has_display_port(printer.printer) {
+            true => Ok(Display { printer }),
+            false => Err(Error::Unsupported),
+        }
+    }
+
+    /// Sends cmd to the display and selects the printer again
+    fn send(&mut self, cmd: &[u8]) -> Result<usize, Error> {
+        let mut buf = Vec::with_capacity(SELECT_DISPLAY.len() + cmd.len() + SELECT_PRINTER.len());
+        buf.extend_from_slice(SELECT_DISPLAY);
+        buf.extend_from_slice(cmd);
+        buf.extend_from_slice(SELECT_PRINTER);
+        self.printer.write(&buf)
+    }
+
+    /// ESC @ - Initialize the display
+    pub fn init(&mut self) -> Result<usize, Error> {
+        self.send(&[0x1b, 0x40])
+    }
+
+    /// CLR - Clear the display and move the cursor to the top left
+    ///
+    /// Hex      0c
+    pub fn clear(&mut self) -> Result<usize, Error> {
+        self.send(&[0x0c])
+    }
+
+    /// CAN - Clear the line the cursor is on
+    ///
+    /// Hex      18
+    pub fn clear_line(&mut self) -> Result<usize, Error> {
+        self.send(&[0x18])
+    }
+
+    /// HOM - Move the cursor to the top left
+    ///
+    /// Hex      0b
+    pub fn home(&mut self) -> Result<usize, Error> {
+        self.send(&[0x0b])
+    }
+
+    /// US $ n m - Move the cursor to column n, row m
+    ///
+    /// ASCII    US   $  n  m
+    /// Hex      1f  24  n  m
+    /// Decimal  31  36  n  m
+    /// Range: 1 <= n <= 20, 1 <= m <= 2
+    pub fn set_cursor(&mut self, column: u8, row: u8) -> Result<usize, Error> {
+        if !(1..=20).contains(&column) || !(1..=2).contains(&row) {
+            return Err(Error::InvalidArgument);
+        }
+        self.send(&[0x1f, 0x24, column, row])
+    }
+
+    /// US X n - Set display brightness
+    ///
+    /// ASCII    US   X  n
+    /// Hex      1f  58  n
+    /// Decimal  31  88  n
+    /// Range: 1 <= n <= 4 (4 is the brightest)
+    pub fn brightness(&mut self, level: u8) -> Result<usize, Error> {
+        if !(1..=4).contains(&level) {
+            return Err(Error::InvalidArgument);
+        }
+        self.send(&[0x1f, 0x58, level])
+    }
+
+    /// US MD1/MD2/MD3 - Select overwrite, vertical or horizontal scroll mode
+    ///
+    /// Hex      1f  01/02/03
+    pub fn scroll_mode(&mut self, mode: ScrollMode) -> Result<usize, Error> {
+        let n = match mode {
+            ScrollMode::Overwrite => 0x01,
+            ScrollMode::Vertical => 0x02,
+            ScrollMode::Horizontal => 0x03,
+        };
+        self.send(&[0x1f, n])
+    }
+
+    /// Shows text at the cursor, encoded with the printer's codec
+    pub fn text(&mut self, content: &str) -> Result<usize, Error> {
+        let encoded = self.printer.encode(content)?;
+        self.send(&encoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::{Command, Job};
+    use crate::testing::{self, assert_hex};
+
+    #[test]
+    fn frames_display_commands() {
+        let bytes = testing::capture(SupportedPrinters::SNBC, |p| {
+            let mut display = p.display()?;
+            assert!(matches!(
+                display.set_cursor(21, 1),
+                Err(Error::InvalidArgument)
+            ));
+            assert!(matches!(display.brightness(5), Err(Error::InvalidArgument)));
+            Ok(display.clear()?
+                + display.scroll_mode(ScrollMode::Horizontal)?
+                + display.set_cursor(15, 2)?
+                + display.text("9.99")?)
+        })
+        .unwrap();
+        assert_hex(
+            &bytes,
+            "1b 3d 02  0c  1b 3d 01
+             1b 3d 02  1f 03  1b 3d 01
+             1b 3d 02  1f 24 0f 02  1b 3d 01
+             1b 3d 02  39 2e 39 39  1b 3d 01",
+        );
+
+        // A job's display commands are framed the same way
+        let mut job = Job::new();
+        job.push(Command::Display(DisplayCommand::Text("9.99".to_string())));
+        let bytes = testing::capture(SupportedPrinters::SNBC, |p| p.submit(&job)).unwrap();
+        assert_hex(&bytes, "1b 3d 02  39 2e 39 39  1b 3d 01");
+
+        for printer in [SupportedPrinters::Star, SupportedPrinters::P3] {
+            assert!(matches!(
+                testing::capture(printer, |p| p.display().map(|_| 0)),
+                Err(Error::Unsupported)
+            ));
+            assert!(matches!(
+                testing::capture(printer, |p| p.submit(&job)),
+                Err(Error::Unsupported)
+            ));
+        }
+    }
+}
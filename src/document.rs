@@ -0,0 +1,195 @@
+//! Printer independent description of what to print
+//!
+//! A [Document] is built once and rendered by any backend, ESC/POS with
+//! [Printer::print_document](crate::printer::Printer::print_document) or ZPL
+//! with [ZplPrinter](crate::zpl::ZplPrinter).
+//!
+//! ```
+//! use posify::barcode::BarcodeType;
+//! use posify::document::{Alignment, Document, TextStyle};
+//!
+//! let mut doc = Document::new();
+//! doc.styled(
+//!     "ACME STORE",
+//!     TextStyle {
+//!         align: Alignment::Center,
+//!         bold: true,
+//!         ..TextStyle::default()
+//!     },
+//! )
+//! .text("1 x Coffee      2.50")
+//! .barcode("0123456789", BarcodeType::Code128, 64)
+//! .feed(2)
+//! .cut();
+//! ```
+
+use crate::barcode::BarcodeType;
+use crate::img::Image;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Alignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl Alignment {
+    /// Left and right swapped
+    pub fn mirror(self) -> Alignment {
+        match self {
+            Alignment::Left => Alignment::Right,
+            Alignment::Center => Alignment::Center,
+            Alignment::Right => Alignment::Left,
+        }
+    }
+}
+
+/// Reading direction of a [Document]
+///
+/// Right to left documents are mirrored when they are rendered: text
+/// aligned to the start of the line, [Alignment::Left], prints on the right.
+/// See [format::columns_rtl](crate::format::columns_rtl) for lines with
+/// two columns.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    #[default]
+    Ltr,
+    /// Hebrew, Arabic...
+    Rtl,
+}
+
+impl Direction {
+    /// Where text with align prints on paper
+    pub fn align(self, align: Alignment) -> Alignment {
+        match self {
+            Direction::Ltr => align,
+            Direction::Rtl => align.mirror(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct TextStyle {
+    pub align: Alignment,
+    pub bold: bool,
+    pub underline: bool,
+    /// Character width magnification, 1 or 2
+    pub width: u8,
+    /// Character height magnification, 1 or 2
+    pub height: u8,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            align: Alignment::Left,
+            bold: false,
+            underline: false,
+            width: 1,
+            height: 1,
+        }
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Element {
+    /// One line of text
+    Text {
+        content: String,
+        style: TextStyle,
+    },
+    /// 1D barcode, height in dots
+    Barcode {
+        code: String,
+        kind: BarcodeType,
+        height: u8,
+    },
+    /// QR code, size is the module size in dots
+    QrCode {
+        data: String,
+        size: u8,
+    },
+    Image(Image),
+    /// Blank lines
+    Feed(u8),
+    /// End of a receipt (ESC/POS) or label (ZPL)
+    Cut,
+}
+
+#[derive(Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Document {
+    pub elements: Vec<Element>,
+    pub direction: Direction,
+}
+
+impl Document {
+    pub fn new() -> Document {
+        Document::default()
+    }
+
+    /// Document in a right to left language, see [Direction]
+    pub fn rtl() -> Document {
+        Document {
+            direction: Direction::Rtl,
+            ..Document::default()
+        }
+    }
+
+    pub fn text(&mut self, content: &str) -> &mut Self {
+        self.styled(content, TextStyle::default())
+    }
+
+    pub fn styled(&mut self, content: &str, style: TextStyle) -> &mut Self {
+        self.elements.push(Element::Text {
+            content: content.to_string(),
+            style,
+        });
+        self
+    }
+
+    pub fn barcode(&mut self, code: &str, kind: BarcodeType, height: u8) -> &mut Self {
+        self.elements.push(Element::Barcode {
+            code: code.to_string(),
+            kind,
+            height,
+        });
+        self
+    }
+
+    pub fn qr(&mut self, data: &str, size: u8) -> &mut Self {
+        self.elements.push(Element::QrCode {
+            data: data.to_string(),
+            size,
+        });
+        self
+    }
+
+    pub fn image(&mut self, image: Image) -> &mut Self {
+        self.elements.push(Element::Image(image));
+        self
+    }
+
+    pub fn feed(&mut self, lines: u8) -> &mut Self {
+        self.elements.push(Element::Feed(lines));
+        self
+    }
+
+    pub fn cut(&mut self) -> &mut Self {
+        self.elements.push(Element::Cut);
+        self
+    }
+}
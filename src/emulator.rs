@@ -0,0 +1,338 @@
+//! Virtual ESC/POS printer for integration tests
+//!
+//! The emulator decodes everything it receives with the
+//! [parser](crate::parser), keeps simulated paper, cover and cutter state and
+//! answers DLE EOT and GS r status requests from it, so error handling can be
+//! tested in CI without hardware. Connect over TCP, for example with
+//! [Network](crate::device::Network), or in-process with [Emulator::pipe].
+//!
+//! Needs the `emulator` feature.
+//!
+//! ```
+//! use std::io::{Read, Write};
+//! use posify::emulator::Emulator;
+//! use posify::job::Command;
+//!
+//! let emulator = Emulator::bind("127.0.0.1:0")?;
+//! let mut printer = emulator.pipe();
+//! printer.write_all(b"\x1b\x40Hello\x0a")?;
+//!
+//! emulator.update(|state| state.paper_out = true);
+//! printer.write_all(b"\x10\x04\x04")?;
+//! let mut status = [0; 1];
+//! printer.read_exact(&mut status)?;
+//! assert_eq!(status[0], 0x72);
+//! assert!(emulator.job().commands.contains(&Command::Text("Hello".to_string())));
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use crate::job::{Command, Job};
+use crate::parser::{self, Token};
+
+/// Bits 1 and 4 are always set in DLE EOT responses
+const STATUS_FIXED_BITS: u8 = 0b0001_0010;
+
+/// Simulated hardware state
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct PrinterState {
+    pub cover_open: bool,
+    pub paper_near_end: bool,
+    pub paper_out: bool,
+    pub cutter_error: bool,
+    pub feed_button: bool,
+}
+
+impl PrinterState {
+    /// The printer stops printing while any of these are set
+    pub fn offline(&self) -> bool {
+        self.cover_open || self.paper_out || self.cutter_error
+    }
+
+    /// DLE EOT n response
+    fn real_time_status(&self, n: u8) -> Option<u8> {
+        let bit = |set: bool, bits: u8| if set { bits } else { 0 };
+        Some(
+            STATUS_FIXED_BITS
+                | match n {
+                    1 => bit(self.offline(), 1 << 3),
+                    2 => {
+                        bit(self.cover_open, 1 << 2)
+                            | bit(self.feed_button, 1 << 3)
+                            | bit(self.paper_out, 1 << 5)
+                            | bit(self.cutter_error, 1 << 6)
+                    }
+                    3 => bit(self.cutter_error, 1 << 3),
+                    4 => bit(self.paper_near_end, 0b0000_1100) | bit(self.paper_out, 0b0110_0000),
+                    _ => return None,
+                },
+        )
+    }
+
+    /// GS r 1 response
+    fn paper_sensor_status(&self) -> u8 {
+        let mut status = 0;
+        if self.paper_near_end {
+            status |= 0b0000_0011;
+        }
+        if self.paper_out {
+            status |= 0b0000_1100;
+        }
+        status
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    state: PrinterState,
+    /// Everything received, in order
+    tokens: Vec<Token>,
+    /// Commands received while offline, which a real printer wouldn't print
+    dropped: usize,
+}
+
+/// TCP server sharing one simulated printer between all connections
+pub struct Emulator {
+    shared: Arc<Mutex<Shared>>,
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+}
+
+impl Emulator {
+    /// Listens on addr, use port 0 to pick a free port and
+    /// [Emulator::local_addr] to find it
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Emulator> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let emulator = Emulator {
+            shared: shared.clone(),
+            addr,
+            stop: stop.clone(),
+        };
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else { continue };
+                let printer = VirtualPrinter::new(shared.clone());
+                thread::spawn(move || serve(stream, printer));
+            }
+        });
+        Ok(emulator)
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// In-process connection to the same simulated printer
+    pub fn pipe(&self) -> VirtualPrinter {
+        VirtualPrinter::new(self.shared.clone())
+    }
+
+    pub fn state(&self) -> PrinterState {
+        self.lock().state
+    }
+
+    /// Changes the simulated state, for example to open the cover
+    pub fn update<F: FnOnce(&mut PrinterState)>(&self, f: F) {
+        f(&mut self.lock().state)
+    }
+
+    /// Everything received so far
+    pub fn tokens(&self) -> Vec<Token> {
+        self.lock().tokens.clone()
+    }
+
+    /// The commands received so far, see [parser::to_job]
+    pub fn job(&self) -> Job {
+        let commands = self
+            .lock()
+            .tokens
+            .iter()
+            .map(|token| match token {
+                Token::Command(command) => command.clone(),
+                Token::Other { bytes, .. } | Token::Unknown { bytes, .. } => {
+                    Command::Raw(bytes.clone())
+                }
+            })
+            .collect();
+        Job {
+            commands,
+            ..Job::default()
+        }
+    }
+
+    /// Number of commands received while the printer was offline
+    pub fn dropped(&self) -> usize {
+        self.lock().dropped
+    }
+
+    /// Forgets everything received so far
+    pub fn clear(&self) {
+        let mut shared = self.lock();
+        shared.tokens.clear();
+        shared.dropped = 0;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wakes the accept loop so it sees the flag
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+fn serve(mut stream: TcpStream, mut printer: VirtualPrinter) {
+    let mut buffer = [0; 4096];
+    loop {
+        let n = match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        let _ = printer.write_all(&buffer[..n]);
+        let responses: Vec<u8> = printer.responses.drain(..).collect();
+        if !responses.is_empty() && stream.write_all(&responses).is_err() {
+            return;
+        }
+    }
+}
+
+/// One connection to the emulator, status responses are read back from it
+pub struct VirtualPrinter {
+    shared: Arc<Mutex<Shared>>,
+    /// Start of a command that hasn't been received completely
+    pending: Vec<u8>,
+    responses: VecDeque<u8>,
+}
+
+impl VirtualPrinter {
+    fn new(shared: Arc<Mutex<Shared>>) -> VirtualPrinter {
+        VirtualPrinter {
+            shared,
+            pending: Vec::new(),
+            responses: VecDeque::new(),
+        }
+    }
+
+    fn receive(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+        let mut tokens = parser::parse(&self.pending);
+        // A command cut off by the end of the write is kept for the next one
+        let consumed = match tokens.last() {
+            Some(Token::Unknown { offset, bytes })
+                if matches!(bytes.first(), Some(0x10 | 0x1b | 0x1c | 0x1d)) =>
+            {
+                let offset = *offset;
+                tokens.pop();
+                offset
+            }
+            _ => self.pending.len(),
+        };
+        self.pending.drain(..consumed);
+
+        let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        for token in tokens {
+            match &token {
+                Token::Other {
+                    name: "DLE EOT",
+                    bytes,
+                } => {
+                    if let Some(status) = shared.state.real_time_status(bytes[2]) {
+                        self.responses.push_back(status);
+                    }
+                }
+                Token::Other {
+                    name: "GS r",
+                    bytes,
+                } if matches!(bytes[2], 1 | 0x31) => {
+                    self.responses.push_back(shared.state.paper_sensor_status())
+                }
+                Token::Command(_) if shared.state.offline() => {
+                    shared.dropped += 1;
+                    continue;
+                }
+                _ => (),
+            }
+            shared.tokens.push(token);
+        }
+    }
+}
+
+impl Write for VirtualPrinter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.receive(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for VirtualPrinter {
+    /// Reads pending status responses, 0 bytes when there are none
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.responses.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn answers_status_over_tcp() {
+        let emulator = Emulator::bind("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(emulator.local_addr()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut status = [0; 1];
+
+        // The GS ! is split over two writes
+        stream.write_all(b"\x1b\x40Hi\x0a\x1d").unwrap();
+        stream.write_all(b"\x21\x11\x10\x04\x01").unwrap();
+        stream.read_exact(&mut status).unwrap();
+        assert_eq!(status[0], 0x12);
+
+        emulator.update(|state| state.cover_open = true);
+        stream.write_all(b"Lost\x10\x04\x02").unwrap();
+        stream.read_exact(&mut status).unwrap();
+        assert_eq!(status[0], 0x16);
+
+        assert_eq!(
+            emulator.job().commands[..4],
+            [
+                Command::Init,
+                Command::Text("Hi".to_string()),
+                Command::NewLine,
+                Command::Size {
+                    width: 2,
+                    height: 2
+                },
+            ]
+        );
+        assert_eq!(emulator.dropped(), 1);
+    }
+}
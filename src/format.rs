@@ -0,0 +1,390 @@
+//! Number and money formatting for receipt columns
+//!
+//! Amounts are integers in minor units (cents), so totals never pick up
+//! floating point errors. A [MoneyFormat] describes a locale's separators
+//! and currency symbol, [columns] lines amounts up on the right. [wrap],
+//! [justify] and the lists lay out paragraphs like the terms and
+//! instructions printed below a receipt.
+//!
+//! ```
+//! use posify::format::{self, MoneyFormat};
+//!
+//! assert_eq!(MoneyFormat::en_us().format(123456), "$1,234.56");
+//! assert_eq!(MoneyFormat::de_de().format(-123456), "-1.234,56 €");
+//! assert_eq!(
+//!     format::columns("Coffee", &MoneyFormat::en_us().format(250), 20),
+//!     "Coffee         $2.50"
+//! );
+//! ```
+
+/// Where the currency symbol goes
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SymbolPosition {
+    /// `$1.00`
+    Before,
+    /// `CHF 1.00`
+    BeforeSpaced,
+    /// `1.00€`
+    After,
+    /// `1,00 €`
+    AfterSpaced,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NumberFormat {
+    pub decimal_separator: char,
+    /// Between groups of three digits, None for no grouping
+    pub thousands_separator: Option<char>,
+    /// Digits after the decimal separator
+    pub decimals: u8,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            decimal_separator: '.',
+            thousands_separator: Some(','),
+            decimals: 2,
+        }
+    }
+}
+
+impl NumberFormat {
+    /// Formats value given in units of 10^-decimals, 1234 is `12.34` with two
+    /// decimals
+    pub fn format(&self, value: i64) -> String {
+        let sign = if value < 0 { "-" } else { "" };
+        format!("{}{}", sign, self.format_magnitude(value.unsigned_abs()))
+    }
+
+    fn format_magnitude(&self, magnitude: u64) -> String {
+        // Past 19 decimals the scale is more than any magnitude, it's all
+        // decimals
+        let (units, decimals) = match 10_u64.checked_pow(self.decimals as u32) {
+            Some(scale) => (magnitude / scale, magnitude % scale),
+            None => (0, magnitude),
+        };
+        let digits = units.to_string();
+
+        let mut out = String::new();
+        for (i, digit) in digits.chars().enumerate() {
+            let remaining = digits.len() - i;
+            if i > 0 && remaining.is_multiple_of(3) {
+                if let Some(separator) = self.thousands_separator {
+                    out.push(separator);
+                }
+            }
+            out.push(digit);
+        }
+        if self.decimals > 0 {
+            out.push(self.decimal_separator);
+            out.push_str(&format!(
+                "{:0width$}",
+                decimals,
+                width = self.decimals as usize
+            ));
+        }
+        out
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MoneyFormat {
+    pub number: NumberFormat,
+    pub symbol: String,
+    pub position: SymbolPosition,
+}
+
+impl Default for MoneyFormat {
+    fn default() -> Self {
+        MoneyFormat::en_us()
+    }
+}
+
+impl MoneyFormat {
+    pub fn new(number: NumberFormat, symbol: &str, position: SymbolPosition) -> MoneyFormat {
+        MoneyFormat {
+            number,
+            symbol: symbol.to_string(),
+            position,
+        }
+    }
+
+    /// `$1,234.56`
+    pub fn en_us() -> MoneyFormat {
+        MoneyFormat::new(NumberFormat::default(), "$", SymbolPosition::Before)
+    }
+
+    /// `£1,234.56`
+    pub fn en_gb() -> MoneyFormat {
+        MoneyFormat::new(NumberFormat::default(), "£", SymbolPosition::Before)
+    }
+
+    /// `1.234,56 €`
+    pub fn de_de() -> MoneyFormat {
+        let number = NumberFormat {
+            decimal_separator: ',',
+            thousands_separator: Some('.'),
+            decimals: 2,
+        };
+        MoneyFormat::new(number, "€", SymbolPosition::AfterSpaced)
+    }
+
+    /// `1 234,56 €`
+    pub fn fr_fr() -> MoneyFormat {
+        let number = NumberFormat {
+            decimal_separator: ',',
+            thousands_separator: Some(' '),
+            decimals: 2,
+        };
+        MoneyFormat::new(number, "€", SymbolPosition::AfterSpaced)
+    }
+
+    /// `CHF 1'234.56`
+    pub fn de_ch() -> MoneyFormat {
+        let number = NumberFormat {
+            decimal_separator: '.',
+            thousands_separator: Some('\''),
+            decimals: 2,
+        };
+        MoneyFormat::new(number, "CHF", SymbolPosition::BeforeSpaced)
+    }
+
+    /// `¥1,235`, yen have no minor unit
+    pub fn ja_jp() -> MoneyFormat {
+        let number = NumberFormat {
+            decimals: 0,
+            ..NumberFormat::default()
+        };
+        MoneyFormat::new(number, "¥", SymbolPosition::Before)
+    }
+
+    /// Formats an amount in minor units, the sign goes before the symbol
+    pub fn format(&self, amount: i64) -> String {
+        let number = self.number.format_magnitude(amount.unsigned_abs());
+        let sign = if amount < 0 { "-" } else { "" };
+        match self.position {
+            SymbolPosition::Before => format!("{}{}{}", sign, self.symbol, number),
+            SymbolPosition::BeforeSpaced => format!("{}{} {}", sign, self.symbol, number),
+            SymbolPosition::After => format!("{}{}{}", sign, number, self.symbol),
+            SymbolPosition::AfterSpaced => format!("{}{} {}", sign, number, self.symbol),
+        }
+    }
+}
+
+/// Right aligns text in width characters, longer text is returned as is
+pub fn pad_left(text: &str, width: usize) -> String {
+    format!("{:>width$}", text, width = width)
+}
+
+/// Left aligned label and right aligned value on one line of width
+/// characters
+///
+/// The label is shortened when both don't fit, the value is always printed
+/// in full.
+pub fn columns(label: &str, value: &str, width: usize) -> String {
+    let (label, padding) = fit_label(label, value, width);
+    format!("{}{}{}", label, " ".repeat(padding), value)
+}
+
+/// [columns] for right to left languages, the label on the right and the
+/// value on the left
+pub fn columns_rtl(label: &str, value: &str, width: usize) -> String {
+    let (label, padding) = fit_label(label, value, width);
+    format!("{}{}{}", value, " ".repeat(padding), label)
+}
+
+/// Breaks text into lines of at most width characters at spaces
+///
+/// Line breaks in text start a new line, runs of spaces between words
+/// become one and words longer than a line are split.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut len = 0;
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            if len > 0 && len + 1 + word.len() > width {
+                lines.push(std::mem::take(&mut line));
+                len = 0;
+            }
+            while word.len() > width {
+                let rest = word.split_off(width);
+                lines.push(word.into_iter().collect());
+                word = rest;
+            }
+            if len > 0 {
+                line.push(' ');
+                len += 1;
+            }
+            len += word.len();
+            line.extend(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// [wrap]s text and pads the spaces between words so lines reach both
+/// margins
+///
+/// The last line of each paragraph stays left aligned, as do lines of one
+/// word. The leftmost gaps get the extra spaces that don't divide evenly.
+pub fn justify(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        if paragraph.trim().is_empty() {
+            lines.push(String::new());
+            continue;
+        }
+        let wrapped = wrap(paragraph, width);
+        let last = wrapped.len() - 1;
+        for (i, line) in wrapped.into_iter().enumerate() {
+            let words: Vec<&str> = line.split(' ').collect();
+            let len = line.chars().count();
+            if i == last || words.len() < 2 || len >= width {
+                lines.push(line);
+                continue;
+            }
+            let gaps = words.len() - 1;
+            let spaces = width - len + gaps;
+            let mut justified = String::with_capacity(width);
+            for (n, word) in words.iter().enumerate() {
+                if n > 0 {
+                    let extra = (n <= spaces % gaps) as usize;
+                    justified.push_str(&" ".repeat(spaces / gaps + extra));
+                }
+                justified.push_str(word);
+            }
+            lines.push(justified);
+        }
+    }
+    lines
+}
+
+/// Items numbered from 1, wrapped to width with the continuation lines
+/// indented under the text
+///
+/// The numbers are right aligned, so the text of every item starts in the
+/// same column.
+pub fn numbered_list(items: &[&str], width: usize) -> Vec<String> {
+    let markers = (1..=items.len()).map(|n| format!("{}. ", n)).collect();
+    list(markers, items, width)
+}
+
+/// Items after a `-`, wrapped to width with the continuation lines
+/// indented under the text
+pub fn bullet_list(items: &[&str], width: usize) -> Vec<String> {
+    list(vec!["- ".to_string(); items.len()], items, width)
+}
+
+/// Lines of items after their markers, with a hanging indent as wide as the
+/// widest marker
+fn list(markers: Vec<String>, items: &[&str], width: usize) -> Vec<String> {
+    let indent = markers.iter().map(|m| m.chars().count()).max().unwrap_or(0);
+    let mut lines = Vec::new();
+    for (marker, item) in markers.iter().zip(items) {
+        let wrapped = wrap(item, width.saturating_sub(indent));
+        for (i, line) in wrapped.iter().enumerate() {
+            let prefix = if i == 0 { marker.as_str() } else { "" };
+            lines.push(format!("{:>indent$}{}", prefix, line, indent = indent));
+        }
+    }
+    lines
+}
+
+/// The label shortened to fit next to value and the spaces between them
+fn fit_label(label: &str, value: &str, width: usize) -> (String, usize) {
+    let value_len = value.chars().count();
+    // At least one space between them
+    let label_width = width.saturating_sub(value_len + 1);
+    let label: String = label.chars().take(label_width).collect();
+    let label = label.trim_end().to_string();
+    let padding = match label.chars().count() {
+        0 => width.saturating_sub(value_len),
+        len => width.saturating_sub(len + value_len).max(1),
+    };
+    (label, padding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_locales() {
+        assert_eq!(MoneyFormat::en_us().format(0), "$0.00");
+        assert_eq!(MoneyFormat::en_us().format(-5), "-$0.05");
+        assert_eq!(MoneyFormat::fr_fr().format(123456789), "1 234 567,89 €");
+        assert_eq!(MoneyFormat::de_ch().format(100000), "CHF 1'000.00");
+        assert_eq!(MoneyFormat::ja_jp().format(1235), "¥1,235");
+        assert_eq!(
+            MoneyFormat::en_gb().format(i64::MIN),
+            "-£92,233,720,368,547,758.08"
+        );
+        // More decimals than a u64 has digits
+        let tiny = NumberFormat {
+            decimals: 21,
+            ..NumberFormat::default()
+        };
+        assert_eq!(tiny.format(-12), "-0.000000000000000000012");
+        let all = NumberFormat {
+            decimals: u8::MAX,
+            ..NumberFormat::default()
+        };
+        assert_eq!(all.format(1).len(), 2 + 255);
+
+        assert_eq!(pad_left("1,00 €", 8), "  1,00 €");
+        assert_eq!(columns("Espresso doppio", "2,50 €", 16), "Espresso  2,50 €");
+        assert_eq!(columns("Tea", "1.00", 2), "1.00");
+        assert_eq!(columns_rtl("קפה", "₪12.00", 12), "₪12.00   קפה");
+    }
+
+    #[test]
+    fn justifies_paragraphs() {
+        let terms = "Returns accepted within 30 days with receipt.\nThank you!";
+        assert_eq!(
+            wrap(terms, 16),
+            [
+                "Returns accepted",
+                "within 30 days",
+                "with receipt.",
+                "Thank you!"
+            ]
+        );
+        assert_eq!(
+            justify(terms, 16),
+            [
+                "Returns accepted",
+                "within  30  days",
+                "with receipt.",
+                "Thank you!"
+            ]
+        );
+        assert_eq!(justify("a b c d", 5), ["a b c", "d"]);
+        assert_eq!(justify("x yy z", 5), ["x  yy", "z"]);
+        assert_eq!(justify("a b c d e", 8), ["a  b c d", "e"]);
+        assert_eq!(wrap("abcdefg", 3), ["abc", "def", "g"]);
+        assert_eq!(justify("a\n\nb", 5), ["a", "", "b"]);
+    }
+
+    #[test]
+    fn indents_lists() {
+        let mut steps = vec!["Keep this receipt for returns"; 10];
+        steps[0] = "Scan";
+        let lines = numbered_list(&steps, 21);
+        assert_eq!(lines[0], " 1. Scan");
+        assert_eq!(lines[1], " 2. Keep this receipt");
+        assert_eq!(lines[2], "    for returns");
+        assert_eq!(lines[lines.len() - 2], "10. Keep this receipt");
+        assert_eq!(
+            bullet_list(&["No refunds on sale items"], 14),
+            ["- No refunds", "  on sale", "  items"]
+        );
+    }
+}
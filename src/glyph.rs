@@ -0,0 +1,295 @@
+//! Emoji and symbols printed as pictures
+//!
+//! Printer fonts have no emoji and few symbols, so "Rate us ⭐⭐⭐⭐⭐" comes
+//! out as question marks. Characters with a [Glyph] in the printer's
+//! [GlyphSet] are sent as ESC * 24 dot bit images inside the text line
+//! instead, where they sit on the line like any other character. Stars,
+//! hearts, check marks, crosses and smileys are built in, others can be
+//! added from images.
+//!
+//! Printers with the [raster_only](crate::printer::Quirks::raster_only)
+//! quirk can't print ESC * bit images and a GS v 0 raster image can't sit
+//! inside a line of text, they get the text with the usual
+//! [trap](EncoderTrap) for these characters.
+//!
+//! ```
+//! use posify::glyph::{Glyph, GlyphSet};
+//!
+//! let mut glyphs = GlyphSet::default();
+//! assert!(glyphs.contains('⭐'));
+//! // A black square for the sun
+//! glyphs.insert('☀', Glyph::from_fn(24, |_, _| true));
+//! ```
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use encoding::types::{EncoderTrap, EncodingRef};
+
+use crate::img::Image;
+
+/// Height of every glyph in dots, font A's character height
+pub const GLYPH_HEIGHT: u32 = 24;
+
+/// Width of the built in glyphs, two font A characters
+const BUILTIN_WIDTH: u32 = 24;
+
+/// Picture of a character, GLYPH_HEIGHT dots high
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glyph {
+    width: u16,
+    /// ESC * columns left to right, three bytes each with the top dot in
+    /// the most significant bit
+    columns: Vec<u8>,
+}
+
+impl Glyph {
+    /// Glyph width dots wide with the dots f returns true for, called with
+    /// x and y from the top left
+    pub fn from_fn<F: Fn(u32, u32) -> bool>(width: u32, f: F) -> Glyph {
+        let width = width.clamp(1, u16::MAX as u32);
+        let mut columns = vec![0; width as usize * 3];
+        for x in 0..width {
+            for y in (0..GLYPH_HEIGHT).filter(|y| f(x, *y)) {
+                columns[(x * 3 + y / 8) as usize] |= 0x80 >> (y % 8);
+            }
+        }
+        Glyph {
+            width: width as u16,
+            columns,
+        }
+    }
+
+    /// The top GLYPH_HEIGHT rows of image
+    pub fn from_image(image: &Image) -> Glyph {
+        Glyph::from_fn(image.width, |x, y| {
+            y < image.height && !image.is_blank_pixel(x, y)
+        })
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Whether the dot at x and y from the top left is black
+    pub(crate) fn dot(&self, x: u32, y: u32) -> bool {
+        x < self.width as u32
+            && y < GLYPH_HEIGHT
+            && self.columns[(x * 3 + y / 8) as usize] & (0x80 >> (y % 8)) != 0
+    }
+
+    /// ESC * 33 - 24 dot double density bit image of the glyph
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[0x1b, 0x2a, 33]);
+        out.extend_from_slice(&self.width.to_le_bytes());
+        out.extend_from_slice(&self.columns);
+    }
+}
+
+/// Characters printed as a [Glyph], see the [module](self) documentation
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlyphSet {
+    builtin: bool,
+    custom: BTreeMap<char, Glyph>,
+}
+
+impl Default for GlyphSet {
+    /// The built in glyphs
+    fn default() -> Self {
+        GlyphSet {
+            builtin: true,
+            custom: BTreeMap::new(),
+        }
+    }
+}
+
+impl GlyphSet {
+    /// No glyphs, every character goes to the printer as text
+    pub fn empty() -> GlyphSet {
+        GlyphSet {
+            builtin: false,
+            custom: BTreeMap::new(),
+        }
+    }
+
+    /// Prints c as glyph, replacing a built in one
+    pub fn insert(&mut self, c: char, glyph: Glyph) -> &mut Self {
+        self.custom.insert(c, glyph);
+        self
+    }
+
+    pub fn get(&self, c: char) -> Option<Glyph> {
+        self.lookup(c).cloned()
+    }
+
+    fn lookup(&self, c: char) -> Option<&Glyph> {
+        match self.custom.get(&c) {
+            Some(glyph) => Some(glyph),
+            None if self.builtin => builtin(c),
+            None => None,
+        }
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.lookup(c).is_some()
+    }
+
+    /// Encodes text with codec, sending the characters that have a glyph
+    /// as bit images
+    ///
+    /// The emoji variation selector after a glyph is dropped.
+    pub(crate) fn encode(
+        &self,
+        text: &str,
+        codec: EncodingRef,
+        trap: EncoderTrap,
+        out: &mut Vec<u8>,
+    ) -> Result<(), Cow<'static, str>> {
+        // Plain text is the common case and has no glyphs
+        if text.is_ascii() || (self.custom.is_empty() && !self.builtin) {
+            return codec.encode_to(text, trap, out);
+        }
+        let mut start = 0;
+        let mut after_glyph = false;
+        for (i, c) in text.char_indices() {
+            if after_glyph && c == VARIATION_SELECTOR {
+                start = i + c.len_utf8();
+                continue;
+            }
+            after_glyph = false;
+            if let Some(glyph) = self.lookup(c) {
+                codec.encode_to(&text[start..i], trap, out)?;
+                glyph.write_to(out);
+                start = i + c.len_utf8();
+                after_glyph = true;
+            }
+        }
+        codec.encode_to(&text[start..], trap, out)
+    }
+}
+
+/// U+FE0F, asks for the emoji presentation of the character before it
+const VARIATION_SELECTOR: char = '\u{FE0F}';
+
+/// Characters with a built in glyph
+const BUILTIN_CHARS: &str = "⭐★🌟☆❤♥💖💙💚✓✔✅✗✘❌☺🙂😊☹🙁";
+
+/// The built in glyph of c, drawn once on first use
+pub(crate) fn builtin(c: char) -> Option<&'static Glyph> {
+    static GLYPHS: OnceLock<BTreeMap<char, Glyph>> = OnceLock::new();
+    GLYPHS
+        .get_or_init(|| {
+            BUILTIN_CHARS
+                .chars()
+                .filter_map(|c| Some((c, draw_builtin(c)?)))
+                .collect()
+        })
+        .get(&c)
+}
+
+fn draw_builtin(c: char) -> Option<Glyph> {
+    // Drawn on a 24 dot square around its center
+    let shape: fn(f32, f32) -> bool = match c {
+        // Stars reach less far down than up
+        '⭐' | '★' | '🌟' => |x, y| in_star(x, y - 1.5, 11.5),
+        '☆' => |x, y| in_star(x, y - 1.5, 11.5) && !in_star(x, y - 1.5, 6.5),
+        '❤' | '♥' | '💖' | '💙' | '💚' => |x, y| {
+            // The heart curve, taller than wide
+            let (x, y) = (x / 10.5, (1.5 - y) / 10.0);
+            (x * x + y * y - 1.0).powi(3) - x * x * y.powi(3) <= 0.0
+        },
+        '✓' | '✔' | '✅' => |x, y| {
+            segment_distance((x, y), (-8.0, 1.0), (-3.0, 7.5)) <= 2.2
+                || segment_distance((x, y), (-3.0, 7.5), (9.0, -8.0)) <= 2.2
+        },
+        '✗' | '✘' | '❌' => |x, y| {
+            x.abs() <= 8.5 && y.abs() <= 8.5 && ((x - y).abs() <= 3.0 || (x + y).abs() <= 3.0)
+        },
+        '☺' | '🙂' | '😊' => |x, y| face(x, y) || mouth(x, y - 1.0, true),
+        '☹' | '🙁' => |x, y| face(x, y) || mouth(x, y - 9.0, false),
+        _ => return None,
+    };
+    let center = (BUILTIN_WIDTH as f32 - 1.0) / 2.0;
+    Some(Glyph::from_fn(BUILTIN_WIDTH, |x, y| {
+        shape(x as f32 - center, y as f32 - center)
+    }))
+}
+
+/// Inside the five pointed star with an upward point radius from the center
+fn in_star(x: f32, y: f32, radius: f32) -> bool {
+    let points: Vec<(f32, f32)> = (0..10)
+        .map(|i| {
+            let r = if i % 2 == 0 { radius } else { radius * 0.4 };
+            let angle = std::f32::consts::PI * (i as f32 / 5.0 - 0.5);
+            (r * angle.cos(), r * angle.sin())
+        })
+        .collect();
+    // Even-odd ray casting
+    let mut inside = false;
+    for (i, &(x0, y0)) in points.iter().enumerate() {
+        let (x1, y1) = points[(i + 1) % points.len()];
+        if (y0 > y) != (y1 > y) && x < x0 + (y - y0) / (y1 - y0) * (x1 - x0) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Outline and eyes of a smiley
+fn face(x: f32, y: f32) -> bool {
+    let r = (x * x + y * y).sqrt();
+    let eye = |ex: f32| ((x - ex).powi(2) + (y + 4.0).powi(2)).sqrt() <= 1.8;
+    (9.8..=11.8).contains(&r) || eye(-4.0) || eye(4.0)
+}
+
+/// The lower half of a ring when smiling, the upper half otherwise
+fn mouth(x: f32, y: f32, smile: bool) -> bool {
+    let r = (x * x + y * y).sqrt();
+    (4.8..=6.6).contains(&r) && if smile { y > 1.0 } else { y < -1.0 }
+}
+
+fn segment_distance((x, y): (f32, f32), (ax, ay): (f32, f32), (bx, by): (f32, f32)) -> f32 {
+    let (dx, dy) = (bx - ax, by - ay);
+    let t = (((x - ax) * dx + (y - ay) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+    ((x - ax - t * dx).powi(2) + (y - ay - t * dy).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prints_glyphs_as_bit_images() {
+        let star = builtin('⭐').unwrap();
+        assert_eq!(star.width(), 24);
+        // The top point is in the middle columns and the middle row is full
+        assert!(star.dot(11, 4) && star.dot(12, 4) && !star.dot(10, 4));
+        assert!(star.dot(2, 10) && star.dot(21, 10) && !star.dot(1, 10));
+        assert!(builtin('a').is_none());
+        // Every character of the list draws
+        assert!(BUILTIN_CHARS.chars().all(|c| builtin(c).is_some()));
+        assert!(std::ptr::eq(star, builtin('⭐').unwrap()));
+
+        let mut out = Vec::new();
+        let glyphs = GlyphSet::default();
+        glyphs
+            .encode(
+                "Rate ⭐\u{FE0F}!",
+                encoding::all::IBM866,
+                EncoderTrap::Replace,
+                &mut out,
+            )
+            .unwrap();
+        assert_eq!(out[..8], *b"Rate \x1b*\x21");
+        assert_eq!(out[8..10], [24, 0]);
+        assert_eq!(out[10 + 72..], *b"!");
+
+        // Without glyphs the trap applies
+        out.clear();
+        GlyphSet::empty()
+            .encode("⭐", encoding::all::IBM866, EncoderTrap::Replace, &mut out)
+            .unwrap();
+        assert_eq!(out, b"?");
+    }
+}
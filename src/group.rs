@@ -0,0 +1,354 @@
+//! Printing one job on several printers
+//!
+//! A [PrinterGroup] sends each job to every printer whose routing tags
+//! match the job's tags, all printers at once, and reports how each one
+//! did. A printer added without tags gets every job.
+//!
+//! A [FailoverGroup] instead prints each job once, on the first printer
+//! that is healthy, and tells the operator with a [Failover] when that
+//! isn't the printer the last job went to.
+//!
+//! ```no_run
+//! use posify::document::Document;
+//! use posify::group::PrinterGroup;
+//! use posify::job::Job;
+//! use posify::printer::{Printer, SupportedPrinters};
+//!
+//! let mut group = PrinterGroup::new();
+//! group.add_routed(
+//!     "kitchen",
+//!     Printer::new(None, None, SupportedPrinters::SNBC, 0x0483, 0x5743)?,
+//!     &["food"],
+//! );
+//! group.add_routed(
+//!     "bar",
+//!     Printer::new(None, None, SupportedPrinters::SNBC, 0x0483, 0x5744)?,
+//!     &["drinks"],
+//! );
+//!
+//! let mut doc = Document::new();
+//! doc.text("Table 4: 2x Burger").cut();
+//! for (name, result) in group.print(&Job::from(&doc), &["food"]) {
+//!     println!("{}: {:?}", name, result);
+//! }
+//! # Ok::<(), posify::printer::Error>(())
+//! ```
+
+use std::thread;
+
+use crate::job::Job;
+use crate::printer::{Error, Printer};
+use crate::queue::Target;
+
+/// Jobs moved from one printer of a [FailoverGroup] to another
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Failover {
+    pub from: String,
+    pub to: String,
+    /// Why from wasn't used, None when jobs go back to a printer that
+    /// recovered
+    pub reason: Option<String>,
+}
+
+struct Member<T> {
+    name: String,
+    target: T,
+    /// Empty for every job
+    tags: Vec<String>,
+}
+
+impl<T> Member<T> {
+    fn accepts(&self, tags: &[&str]) -> bool {
+        self.tags.is_empty() || self.tags.iter().any(|tag| tags.contains(&tag.as_str()))
+    }
+}
+
+/// Named printers a job is fanned out to
+pub struct PrinterGroup<T: Target = Printer> {
+    members: Vec<Member<T>>,
+}
+
+impl<T: Target> Default for PrinterGroup<T> {
+    fn default() -> Self {
+        PrinterGroup {
+            members: Vec::new(),
+        }
+    }
+}
+
+impl<T: Target> PrinterGroup<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a printer that gets every job
+    pub fn add(&mut self, name: &str, target: T) -> &mut Self {
+        self.add_routed(name, target, &[])
+    }
+
+    /// Adds a printer that only gets jobs with at least one of tags
+    pub fn add_routed(&mut self, name: &str, target: T, tags: &[&str]) -> &mut Self {
+        self.members.push(Member {
+            name: name.to_string(),
+            target,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        });
+        self
+    }
+
+    /// Removes the printer added as name and returns it
+    pub fn remove(&mut self, name: &str) -> Option<T> {
+        let index = self.members.iter().position(|member| member.name == name)?;
+        Some(self.members.remove(index).target)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut T> {
+        self.members
+            .iter_mut()
+            .find(|member| member.name == name)
+            .map(|member| &mut member.target)
+    }
+
+    /// Names of the printers a job with tags is routed to
+    pub fn route(&self, tags: &[&str]) -> Vec<&str> {
+        self.members
+            .iter()
+            .filter(|member| member.accepts(tags))
+            .map(|member| member.name.as_str())
+            .collect()
+    }
+
+    /// Prints job on every printer it is routed to, in parallel
+    ///
+    /// Returns the result of each of those printers in the order they were
+    /// added. A failing printer doesn't stop the others.
+    pub fn print(&mut self, job: &Job, tags: &[&str]) -> Vec<(String, Result<usize, Error>)> {
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .members
+                .iter_mut()
+                .filter(|member| member.accepts(tags))
+                .map(|member| {
+                    let name = member.name.clone();
+                    let target = &mut member.target;
+                    (name, scope.spawn(move || target.submit(job)))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|(name, handle)| {
+                    let result = handle
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+                    (name, result)
+                })
+                .collect()
+        })
+    }
+}
+
+/// Callback of [FailoverGroup::on_failover]
+type Listener = dyn FnMut(&Failover) + Send;
+
+/// Printers in order of preference, each job goes to the first healthy one
+///
+/// ```no_run
+/// use posify::group::FailoverGroup;
+/// use posify::printer::{Printer, SupportedPrinters};
+///
+/// let mut desk = FailoverGroup::new();
+/// desk.add("front", Printer::new(None, None, SupportedPrinters::SNBC, 0x0483, 0x5743)?)
+///     .add("backup", Printer::new(None, None, SupportedPrinters::SNBC, 0x0483, 0x5744)?)
+///     .on_failover(|event| eprintln!("Printing on {} instead of {}", event.to, event.from));
+/// # Ok::<(), posify::printer::Error>(())
+/// ```
+pub struct FailoverGroup<T: Target = Printer> {
+    members: Vec<(String, T)>,
+    /// Index of the printer the last job went to
+    active: usize,
+    listener: Option<Box<Listener>>,
+}
+
+impl<T: Target> Default for FailoverGroup<T> {
+    fn default() -> Self {
+        FailoverGroup {
+            members: Vec::new(),
+            active: 0,
+            listener: None,
+        }
+    }
+}
+
+impl<T: Target> FailoverGroup<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a printer tried after the ones added before
+    pub fn add(&mut self, name: &str, target: T) -> &mut Self {
+        self.members.push((name.to_string(), target));
+        self
+    }
+
+    /// Calls f whenever a job goes to another printer than the last job,
+    /// replacing any callback set before
+    pub fn on_failover<F: FnMut(&Failover) + Send + 'static>(&mut self, f: F) -> &mut Self {
+        self.listener = Some(Box::new(f));
+        self
+    }
+
+    /// Name of the printer the last job went to, the first one before any
+    pub fn active(&self) -> Option<&str> {
+        self.members.get(self.active).map(|(name, _)| name.as_str())
+    }
+
+    /// Why the printer can't print now, None when it can or can't tell
+    fn problem(target: &mut T) -> Option<String> {
+        match target.health_check() {
+            Ok(report) => report.blocking_error().map(|error| error.to_string()),
+            Err(Error::Unsupported) => None,
+            Err(e) => Some(e.to_string()),
+        }
+    }
+
+    /// Prints job on the first printer that reports no error, trying the
+    /// next one when printing fails
+    ///
+    /// Returns the name of the printer and the bytes sent, or the error of
+    /// the last printer tried. [Error::NotFound] when no printer was healthy.
+    pub fn print(&mut self, job: &Job) -> Result<(String, usize), Error> {
+        let mut reasons = Vec::with_capacity(self.members.len());
+        let mut last_error = None;
+        for index in 0..self.members.len() {
+            let (name, target) = &mut self.members[index];
+            if let Some(problem) = Self::problem(target) {
+                log::warn!("Skipping {}: {}", name, problem);
+                reasons.push(Some(problem));
+                continue;
+            }
+            match target.submit(job) {
+                Ok(n) => {
+                    let name = name.clone();
+                    if index != self.active {
+                        let event = Failover {
+                            from: self.members[self.active].0.clone(),
+                            to: name.clone(),
+                            reason: reasons.get(self.active).cloned().flatten(),
+                        };
+                        self.active = index;
+                        if let Some(listener) = &mut self.listener {
+                            listener(&event);
+                        }
+                    }
+                    return Ok((name, n));
+                }
+                Err(e) => {
+                    log::warn!("Printing on {} failed: {}", name, e);
+                    reasons.push(Some(e.to_string()));
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or(Error::NotFound))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::Command;
+    use crate::printer::{HealthReport, StatusError};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Recorder {
+        jobs: usize,
+        offline: bool,
+        cover_open: bool,
+    }
+
+    impl Target for Recorder {
+        fn submit(&mut self, job: &Job) -> Result<usize, Error> {
+            if self.offline {
+                return Err(Error::Timeout);
+            }
+            self.jobs += 1;
+            Ok(job.commands.len())
+        }
+
+        fn health_check(&mut self) -> Result<HealthReport, Error> {
+            let mut report = HealthReport::default();
+            if self.cover_open {
+                report.errors.push(StatusError::DoorOpen);
+            }
+            Ok(report)
+        }
+    }
+
+    #[test]
+    fn routes_by_tag() {
+        let mut group = PrinterGroup::new();
+        group
+            .add("receipt", Recorder::default())
+            .add_routed("kitchen", Recorder::default(), &["food"])
+            .add_routed(
+                "bar",
+                Recorder {
+                    offline: true,
+                    ..Recorder::default()
+                },
+                &["drinks"],
+            );
+        let mut job = Job::new();
+        job.push(Command::Text("Burger".to_string()));
+
+        assert_eq!(group.route(&["food"]), ["receipt", "kitchen"]);
+        let results = group.print(&job, &["food", "drinks"]);
+        assert_eq!(results.len(), 3);
+        assert!(results[1].1.is_ok());
+        assert!(matches!(results[2], (ref name, Err(Error::Timeout)) if name == "bar"));
+
+        group.print(&job, &[]);
+        assert_eq!(group.get_mut("receipt").unwrap().jobs, 2);
+        assert_eq!(group.remove("kitchen").unwrap().jobs, 1);
+    }
+
+    #[test]
+    fn fails_over_to_healthy_printer() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = events.clone();
+        let mut group = FailoverGroup::new();
+        group
+            .add("front", Recorder::default())
+            .add("backup", Recorder::default())
+            .on_failover(move |event| seen.lock().unwrap().push(event.clone()));
+        let job = Job::new();
+
+        assert_eq!(group.print(&job).unwrap().0, "front");
+        group.members[0].1.cover_open = true;
+        assert_eq!(group.print(&job).unwrap().0, "backup");
+        assert_eq!(group.active(), Some("backup"));
+        group.members[0].1.cover_open = false;
+        assert_eq!(group.print(&job).unwrap().0, "front");
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                Failover {
+                    from: "front".to_string(),
+                    to: "backup".to_string(),
+                    reason: Some("Door Opened".to_string()),
+                },
+                Failover {
+                    from: "backup".to_string(),
+                    to: "front".to_string(),
+                    reason: None,
+                },
+            ]
+        );
+
+        group.members[0].1.offline = true;
+        group.members[1].1.cover_open = true;
+        assert!(matches!(group.print(&job), Err(Error::Timeout)));
+    }
+}
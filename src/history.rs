@@ -0,0 +1,123 @@
+//! Record of the commands recently sent to a printer, see
+//! [Printer::enable_history](crate::printer::Printer::enable_history)
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Most bytes shown when a raw buffer is used as a command parameter
+const HEX_PREVIEW_LEN: usize = 32;
+
+/// A command sent to the printer
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    /// Name of the Printer method that was called
+    pub command: &'static str,
+    /// Arguments the method was called with
+    pub parameters: String,
+    /// Number of bytes written to the printer for this command
+    pub bytes: usize,
+    pub timestamp: SystemTime,
+    /// Error message if the command failed
+    pub result: Result<(), String>,
+}
+
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let since_epoch = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        write!(
+            f,
+            "{}.{:03} {}({}) {} bytes",
+            since_epoch.as_secs(),
+            since_epoch.subsec_millis(),
+            self.command,
+            self.parameters,
+            self.bytes
+        )?;
+        match &self.result {
+            Ok(()) => write!(f, " ok"),
+            Err(e) => write!(f, " failed: {}", e),
+        }
+    }
+}
+
+/// Ring buffer holding the last `capacity` commands
+pub(crate) struct History {
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+}
+
+impl History {
+    pub(crate) fn new(capacity: usize) -> History {
+        History {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub(crate) fn push(&mut self, entry: HistoryEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Entries from oldest to newest
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+}
+
+/// Formats a raw buffer as hex, truncated so large writes don't bloat the
+/// history
+pub(crate) fn hex_preview(buf: &[u8]) -> String {
+    let shown = buf.len().min(HEX_PREVIEW_LEN);
+    let mut preview = buf[..shown]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if buf.len() > shown {
+        preview.push_str(" ..");
+    }
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &'static str) -> HistoryEntry {
+        HistoryEntry {
+            command,
+            parameters: String::new(),
+            bytes: 0,
+            timestamp: SystemTime::now(),
+            result: Ok(()),
+        }
+    }
+
+    #[test]
+    fn keeps_last_entries() {
+        let mut history = History::new(2);
+        history.push(entry("hwinit"));
+        history.push(entry("text"));
+        history.push(entry("partial_cut"));
+        let commands: Vec<_> = history.iter().map(|e| e.command).collect();
+        assert_eq!(commands, vec!["text", "partial_cut"]);
+    }
+
+    #[test]
+    fn hex_preview_truncates() {
+        assert_eq!(hex_preview(&[0x1b, 0x40]), "1b 40");
+        let preview = hex_preview(&[0_u8; 40]);
+        assert!(preview.ends_with(" .."));
+        assert_eq!(preview.matches("00").count(), HEX_PREVIEW_LEN);
+    }
+}
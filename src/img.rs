@@ -1,9 +1,24 @@
+use std::borrow::Cow;
 use std::iter::Iterator;
 use std::path;
 
 use image;
-use image::{error::ImageResult, DynamicImage, GenericImageView};
+use image::{error::ImageResult, DynamicImage, GenericImageView, GrayImage, RgbaImage};
 
+/// 8x8 Bayer matrix for ordered dithering
+#[rustfmt::skip]
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [ 0, 32,  8, 40,  2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44,  4, 36, 14, 46,  6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [ 3, 35, 11, 43,  1, 33,  9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47,  7, 39, 13, 45,  5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+#[derive(Clone)]
 pub struct Image {
     pub width: u32,
     pub height: u32,
@@ -63,13 +78,40 @@ impl Image {
         })
     }
 
+    /// Ordered dithered black and white copy, for photos and logos with
+    /// gradients that would otherwise print as solid black or nothing
+    ///
+    /// Transparent pixels are treated as white. Rows are dithered in
+    /// parallel with the `rayon` feature.
+    pub fn dither(&self) -> Image {
+        let rgba = self.rgba();
+        let width = self.width as usize;
+        let mut out = GrayImage::new(self.width, self.height);
+        for_each_row(&mut out, width, |y, row| {
+            let threshold = &BAYER_8X8[y % 8];
+            let pixels = &rgba.as_raw()[y * width * 4..(y + 1) * width * 4];
+            for (x, (value, pixel)) in row.iter_mut().zip(pixels.chunks_exact(4)).enumerate() {
+                let &[r, g, b, a] = pixel else { continue };
+                let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+                // Composited over white paper
+                let luma = (luma * a as u32 + 255 * (255 - a as u32)) / 255;
+                *value = if luma < threshold[x % 8] as u32 * 4 + 2 {
+                    0
+                } else {
+                    0xFF
+                };
+            }
+        });
+        Image::from(DynamicImage::ImageLuma8(out))
+    }
+
     pub fn is_blank_pixel(&self, x: u32, y: u32) -> bool {
         let pixel = self.img_buf.get_pixel(x, y);
         // full transprant OR is white
         pixel[3] == 0 || (pixel[0] & pixel[1] & pixel[2]) == 0xFF
     }
 
-    pub fn bitimage_lines(&self, density: u32) -> BitimageLines {
+    pub fn bitimage_lines(&self, density: u32) -> BitimageLines<'_> {
         BitimageLines {
             line: 0,
             density,
@@ -102,22 +144,87 @@ impl Image {
         Some(data.into_boxed_slice())
     }
 
-    #[allow(clippy::many_single_char_names)]
+    /// One bit per pixel rows padded to a byte, where 1 is black. Rows are
+    /// packed in parallel with the `rayon` feature.
     pub fn get_raster(&self) -> Box<[u8]> {
-        let n = (self.width + 7) / 8; // Number of bytes per line
-        let mut data: Vec<u8> = vec![0; (n * self.height) as usize];
-        for y in 0..self.height {
-            for x in 0..n {
-                for b in 0..8 {
-                    let i = x * 8 + b;
-                    if i < self.width && !self.is_blank_pixel(i, y) {
-                        data[(y * n + x) as usize] += 0x80 >> (b & 0x7);
+        let width = self.width as usize;
+        let n = self.width.div_ceil(8) as usize; // Number of bytes per line
+        let mut data: Vec<u8> = vec![0; n * self.height as usize];
+        // Dithered images are already gray, skip converting them
+        if let Some(gray) = self.img_buf.as_luma8() {
+            for_each_row(&mut data, n, |y, row| {
+                let pixels = &gray.as_raw()[y * width..(y + 1) * width];
+                for (x, &value) in pixels.iter().enumerate() {
+                    if value != 0xFF {
+                        row[x / 8] |= 0x80 >> (x % 8);
                     }
                 }
-            }
+            });
+            return data.into_boxed_slice();
         }
+
+        let rgba = self.rgba();
+        for_each_row(&mut data, n, |y, row| {
+            let pixels = &rgba.as_raw()[y * width * 4..(y + 1) * width * 4];
+            for (x, pixel) in pixels.chunks_exact(4).enumerate() {
+                let &[r, g, b, a] = pixel else { continue };
+                if a != 0 && (r & g & b) != 0xFF {
+                    row[x / 8] |= 0x80 >> (x % 8);
+                }
+            }
+        });
         data.into_boxed_slice()
     }
+
+    /// The image as RGBA, only converted when it's in another format
+    fn rgba(&self) -> Cow<'_, RgbaImage> {
+        match self.img_buf.as_rgba8() {
+            Some(rgba) => Cow::Borrowed(rgba),
+            None => Cow::Owned(self.img_buf.to_rgba8()),
+        }
+    }
+}
+
+/// Calls f with the index and contents of each row of data
+fn for_each_row<F>(data: &mut [u8], row_len: usize, f: F)
+where
+    F: Fn(usize, &mut [u8]) + Sync,
+{
+    if row_len == 0 {
+        return;
+    }
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        data.par_chunks_mut(row_len)
+            .enumerate()
+            .for_each(|(y, row)| f(y, row));
+    }
+    #[cfg(not(feature = "rayon"))]
+    data.chunks_mut(row_len)
+        .enumerate()
+        .for_each(|(y, row)| f(y, row));
+}
+
+/// Serialized as PNG bytes
+#[cfg(feature = "serde")]
+impl serde::Serialize for Image {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut png = std::io::Cursor::new(Vec::new());
+        self.img_buf
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .map_err(serde::ser::Error::custom)?;
+        serializer.serialize_bytes(png.get_ref())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Image {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let png: Vec<u8> = serde::Deserialize::deserialize(deserializer)?;
+        let img_buf = image::load_from_memory(&png).map_err(serde::de::Error::custom)?;
+        Ok(Image::from(img_buf))
+    }
 }
 
 pub struct BitimageLines<'a> {
@@ -134,3 +241,19 @@ impl<'a> Iterator for BitimageLines<'a> {
         self.image.get_line(self.line, self.density)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dithers_gray_to_pattern() {
+        let gray = GrayImage::from_fn(16, 8, |x, _| image::Luma([if x < 8 { 0x80 } else { 0xFF }]));
+        let image = Image::from(DynamicImage::ImageLuma8(gray)).dither();
+        let raster = image.get_raster();
+        // Mid gray is about half black, white stays white
+        let black: u32 = raster.chunks(2).map(|row| row[0].count_ones()).sum();
+        assert!((28..=36).contains(&black), "{} black dots", black);
+        assert!(raster.chunks(2).all(|row| row[1] == 0));
+    }
+}
@@ -0,0 +1,1751 @@
+//! Printer independent command list, encoded for a dialect when it is
+//! submitted
+//!
+//! A [Job] is a list of [Command]s, built directly or lowered from a
+//! [Document]. The same job can be encoded for any of the
+//! [SupportedPrinters] with an [Encoder], or sent with
+//! [Printer::submit](crate::printer::Printer::submit), which picks the
+//! printer's dialect. The per-dialect differences live here in one place
+//! instead of in every [Printer](crate::printer::Printer) method.
+//!
+//! ```
+//! use posify::document::{Alignment, Document};
+//! use posify::job::{Command, Encoder, Job};
+//! use posify::printer::SupportedPrinters;
+//!
+//! let mut doc = Document::new();
+//! doc.text("Hello").cut();
+//! let job = Job::from(&doc);
+//! assert_eq!(job.commands[0], Command::Align(Alignment::Left));
+//!
+//! let snbc = Encoder::new(SupportedPrinters::SNBC).encode(&job)?;
+//! let star = Encoder::new(SupportedPrinters::Star).encode(&job)?;
+//! assert_ne!(snbc, star);
+//! # Ok::<(), posify::printer::Error>(())
+//! ```
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use encoding::all::UTF_8;
+use encoding::types::{EncoderTrap, EncodingRef};
+
+use crate::barcode::{Barcode, BarcodeError, BarcodeType, Code39Ratio, Font, TextPosition};
+use crate::consts;
+use crate::display::DisplayCommand;
+use crate::document::{Alignment, Document, Element};
+use crate::glyph::GlyphSet;
+use crate::img::Image;
+use crate::preview::{char_width, qr_modules, CELL_HEIGHT, CELL_WIDTH, LINE_HEIGHT};
+use crate::printer::{cut_bytes, Error, Printer, Quirks, SupportedPrinters};
+use crate::profile::{CutFeed, Profile};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Command {
+    /// ESC @
+    Init,
+    /// Text without a line feed
+    Text(String),
+    NewLine,
+    Align(Alignment),
+    Bold(bool),
+    Underline(bool),
+    /// White on black text
+    Reverse(bool),
+    /// Character magnification, 1 to 8
+    Size {
+        width: u8,
+        height: u8,
+    },
+    /// 1D barcode with the HRI below it, height in dots
+    Barcode {
+        code: String,
+        kind: BarcodeType,
+        height: u8,
+    },
+    /// QR code, size is the module size in dots
+    QrCode {
+        data: String,
+        size: u8,
+    },
+    /// Raster image, one bit per dot with the MSB on the left and 1 printing
+    Raster {
+        width_bytes: u16,
+        height: u16,
+        data: Vec<u8>,
+    },
+    /// Feed n lines
+    Feed(u8),
+    Cut {
+        partial: bool,
+    },
+    /// Bytes passed through unchanged
+    Raw(Vec<u8>),
+    /// Sent to the customer display, the printer is selected again after it
+    Display(DisplayCommand),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Job {
+    pub commands: Vec<Command>,
+    /// Name of the [Profile](crate::profile::Profile) the job was made for
+    pub profile: Option<String>,
+    /// When the job was made, set by [Job::save] if missing
+    pub created: Option<SystemTime>,
+    /// Name of the encoding the text is printed in instead of the
+    /// printer's, see [Job::set_encoding]
+    pub encoding: Option<String>,
+    /// Replaces the printer's trap for this job
+    pub trap: Option<Trap>,
+    /// Replaces the profile's [Profile::cut_feed] for the cuts of this job
+    pub cut_feed: Option<CutFeed>,
+}
+
+/// What happens to characters the encoding can't represent, a
+/// [serde](https://serde.rs) friendly [EncoderTrap]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Trap {
+    /// Fails the job
+    Strict,
+    /// Prints `?`
+    #[default]
+    Replace,
+    /// Drops the character
+    Ignore,
+    /// Prints the character reference, e.g. `&#8364;`
+    NcrEscape,
+}
+
+impl From<Trap> for EncoderTrap {
+    fn from(trap: Trap) -> Self {
+        match trap {
+            Trap::Strict => EncoderTrap::Strict,
+            Trap::Replace => EncoderTrap::Replace,
+            Trap::Ignore => EncoderTrap::Ignore,
+            Trap::NcrEscape => EncoderTrap::NcrEscape,
+        }
+    }
+}
+
+impl Job {
+    pub fn new() -> Job {
+        Job::default()
+    }
+
+    pub fn push(&mut self, command: Command) -> &mut Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// Prints the text of this job in encoding, e.g. a Cyrillic coupon on a
+    /// printer set up for Latin receipts
+    ///
+    /// [Printer::submit](crate::printer::Printer::submit) selects the
+    /// profile's code page for it before the job and the printer's again
+    /// after.
+    pub fn set_encoding(&mut self, encoding: EncodingRef, trap: Trap) -> &mut Self {
+        self.encoding = Some(encoding.name().to_string());
+        self.trap = Some(trap);
+        self
+    }
+
+    /// Names of the `{{name}}` placeholders in the text, in order, see
+    /// [Template]
+    pub fn placeholders(&self) -> Vec<String> {
+        self.commands
+            .iter()
+            .filter_map(|command| match command {
+                Command::Text(text) => Some(placeholders(text)),
+                _ => None,
+            })
+            .flatten()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    /// The encoding named by [Job::encoding], which may also be a WHATWG
+    /// label like `cp1251`
+    ///
+    /// Returns [Error::InvalidArgument] for names no encoding has.
+    pub fn codec(&self) -> Result<Option<EncodingRef>, Error> {
+        let Some(name) = &self.encoding else {
+            return Ok(None);
+        };
+        encoding::all::encodings()
+            .iter()
+            .find(|e| e.name() == name)
+            .copied()
+            .or_else(|| encoding::label::encoding_from_whatwg_label(name))
+            .map(Some)
+            .ok_or(Error::InvalidArgument)
+    }
+
+    /// Paper the job feeds on a printer with profile, in millimetres
+    ///
+    /// The commands are laid out like [preview](crate::preview) draws them:
+    /// text wraps at [Profile::dots_per_line] and advances the ESC 2 line
+    /// spacing, taller at larger sizes, images and barcodes take their
+    /// height, built in [glyphs](crate::glyph) their width when the printer
+    /// prints them. [Command::Raw] bytes aren't interpreted. Dots are converted
+    /// with [Profile::dpi].
+    pub fn estimate_length_mm(&self, profile: &Profile) -> f32 {
+        self.estimate_length_dots(profile) as f32 * 25.4 / profile.dpi.unwrap_or(203) as f32
+    }
+
+    /// [Job::estimate_length_mm] in dots
+    pub(crate) fn estimate_length_dots(&self, profile: &Profile) -> u32 {
+        let width = profile.dots_per_line.unwrap_or(576) as u32;
+        let mut dots = 0;
+        let mut size = (1, 1);
+        // Dots used on the line waiting for a line feed and its tallest size
+        let mut line: Option<(u32, u32)> = None;
+        let line_height = |height: u32| LINE_HEIGHT + CELL_HEIGHT * (height - 1);
+        // Emoji are printed as glyphs wider than a character, see
+        // Encoder::glyphs
+        let glyphs = profile.base != SupportedPrinters::Star && !profile.quirks().raster_only;
+        for command in &self.commands {
+            if !matches!(
+                command,
+                Command::Text(_)
+                    | Command::Bold(_)
+                    | Command::Underline(_)
+                    | Command::Reverse(_)
+                    | Command::Size { .. }
+                    | Command::Align(_)
+                    | Command::Raw(_)
+                    | Command::Display(_)
+            ) {
+                if let Some((_, height)) = line.take() {
+                    dots += line_height(height);
+                } else if *command == Command::NewLine {
+                    dots += LINE_HEIGHT;
+                }
+            }
+            match command {
+                Command::Init => size = (1, 1),
+                Command::Text(text) => {
+                    for c in text.chars() {
+                        let cell = size.0 * if glyphs { char_width(c) } else { CELL_WIDTH };
+                        let (used, height) = line.get_or_insert((0, size.1));
+                        if *used + cell > width && *used > 0 {
+                            dots += line_height(*height);
+                            (*used, *height) = (0, size.1);
+                        }
+                        *used += cell;
+                        *height = (*height).max(size.1);
+                    }
+                }
+                Command::Size { width, height } => {
+                    size = ((*width).clamp(1, 8) as u32, (*height).clamp(1, 8) as u32)
+                }
+                // The human readable text below the bars
+                Command::Barcode { height, .. } => dots += (*height).max(1) as u32 + LINE_HEIGHT,
+                Command::QrCode { data, size } => {
+                    dots += (qr_modules(data) + 8) * (*size).max(1) as u32
+                }
+                Command::Raster { height, .. } => dots += *height as u32,
+                Command::Feed(lines) => dots += *lines as u32 * LINE_HEIGHT,
+                _ => (),
+            }
+        }
+        if let Some((_, height)) = line {
+            dots += line_height(height);
+        }
+        dots
+    }
+
+    /// Problems printing the job on a printer with profile would run into,
+    /// in command order
+    ///
+    /// Catches what otherwise fails halfway through the job or prints
+    /// nothing: commands the printer lacks, images wider than
+    /// [Profile::dots_per_line] (576 dots when unknown), barcode data the
+    /// symbology can't encode and parameters out of range. An empty list
+    /// doesn't promise the printer is ready, see
+    /// [Printer::health_check](crate::printer::Printer::health_check).
+    pub fn validate(&self, profile: &Profile) -> Vec<ValidationIssue> {
+        let encoder = Encoder {
+            quirks: profile.quirks(),
+            cut_feed: self.cut_feed.or(profile.cut_feed),
+            dpi: profile.dpi.unwrap_or(203),
+            ..Encoder::new(profile.base)
+        };
+        let max_width = profile.dots_per_line.unwrap_or(576) as u32;
+        // Star Line Mode magnifies at most 6 times
+        let max_size = match profile.base {
+            SupportedPrinters::Star => 6,
+            _ => 8,
+        };
+        let mut out = Vec::new();
+        let mut issues = Vec::new();
+        for (index, command) in self.commands.iter().enumerate() {
+            let kind = match command {
+                Command::Cut { .. } if !profile.capabilities.cutter => Some(IssueKind::Unsupported),
+                // Sent with the printer's cut commands, see JobBuilder::command
+                Command::Cut { .. } => None,
+                Command::QrCode { .. } if !profile.capabilities.qr_code => {
+                    Some(IssueKind::Unsupported)
+                }
+                Command::QrCode { size, .. } if !(1..=16).contains(size) => {
+                    Some(IssueKind::OutOfRange("QR code module size"))
+                }
+                Command::Size { width, height }
+                    if !(1..=max_size).contains(width) || !(1..=max_size).contains(height) =>
+                {
+                    Some(IssueKind::OutOfRange("character size"))
+                }
+                Command::Barcode { height: 0, .. } => Some(IssueKind::OutOfRange("barcode height")),
+                Command::Barcode { code, kind, .. } => match kind.validate(code) {
+                    Err(BarcodeError::Unsupported) => Some(IssueKind::Unsupported),
+                    Err(e) => Some(IssueKind::Barcode(e)),
+                    Ok(()) => None,
+                },
+                Command::Raster {
+                    width_bytes,
+                    height,
+                    data,
+                } if data.len() != *width_bytes as usize * *height as usize => {
+                    Some(IssueKind::OutOfRange("raster data length"))
+                }
+                Command::Raster { width_bytes, .. } if *width_bytes as u32 * 8 > max_width => {
+                    Some(IssueKind::TooWide {
+                        width: *width_bytes as u32 * 8,
+                        max: max_width,
+                    })
+                }
+                _ => None,
+            };
+            // What the checks above don't know about the dialect
+            let kind = kind.or_else(|| match command {
+                Command::Cut { .. } => None,
+                command => {
+                    out.clear();
+                    match encoder.encode_command(command, &mut out) {
+                        Ok(()) => None,
+                        Err(Error::Unsupported) => Some(IssueKind::Unsupported),
+                        Err(_) => Some(IssueKind::OutOfRange("parameters")),
+                    }
+                }
+            });
+            if let Some(kind) = kind {
+                issues.push(ValidationIssue { index, kind });
+            }
+        }
+        issues
+    }
+
+    /// Bytes the job encodes to for a generic ESC/POS printer with UTF-8
+    /// text, see [Encoder::byte_report] for other printers
+    pub fn byte_len(&self) -> Result<usize, Error> {
+        self.byte_report().map(|report| report.total())
+    }
+
+    /// [Job::byte_len] by kind of command
+    pub fn byte_report(&self) -> Result<ByteReport, Error> {
+        Encoder::new(SupportedPrinters::Generic).byte_report(self)
+    }
+
+    /// Saves the job so it can be printed again later, see [Job::write_to]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = io::BufWriter::new(File::create(path)?);
+        self.write_to(&mut file)?;
+        file.flush()
+    }
+
+    /// Loads a job saved by [Job::save], see [Job::read_from]
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Job> {
+        Job::read_from(&mut io::BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the job file format, all numbers are little endian:
+    ///
+    /// | Field    | Format                                        |
+    /// |----------|-----------------------------------------------|
+    /// | magic    | `POSJOB` followed by version 3                |
+    /// | profile  | u16 length and UTF-8 name, empty for none     |
+    /// | created  | u64 seconds since the epoch                   |
+    /// | encoding | u16 length and name, empty for none           |
+    /// | trap     | u8, 0 for none, else 1 plus the [Trap] variant |
+    /// | cut feed | u8, 0 for none, else 1 followed by u8 lines before and u8 mm after |
+    /// | commands | u32 count, then a tag byte and the fields of each [Command] |
+    ///
+    /// Strings and byte buffers are a u32 length followed by the bytes.
+    /// Names longer than 65535 bytes are an [io::ErrorKind::InvalidData]
+    /// error.
+    /// Version 1 files, without encoding and trap, and version 2 files,
+    /// without cut feed, are still read.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(JOB_MAGIC)?;
+        write_name(w, self.profile.as_deref().unwrap_or_default())?;
+        let created = self.created.unwrap_or_else(SystemTime::now);
+        let secs = created.duration_since(UNIX_EPOCH).unwrap_or_default();
+        w.write_u64::<LittleEndian>(secs.as_secs())?;
+        write_name(w, self.encoding.as_deref().unwrap_or_default())?;
+        w.write_u8(self.trap.map_or(0, |trap| trap as u8 + 1))?;
+        match self.cut_feed {
+            Some(feed) => w.write_all(&[1, feed.lines_before, feed.mm_after])?,
+            None => w.write_u8(0)?,
+        }
+        w.write_u32::<LittleEndian>(self.commands.len() as u32)?;
+        for command in &self.commands {
+            match command {
+                Command::Init => w.write_u8(0)?,
+                Command::Text(text) => {
+                    w.write_u8(1)?;
+                    write_bytes(w, text.as_bytes())?;
+                }
+                Command::NewLine => w.write_u8(2)?,
+                Command::Align(align) => w.write_all(&[3, *align as u8])?,
+                Command::Bold(on) => w.write_all(&[4, *on as u8])?,
+                Command::Underline(on) => w.write_all(&[5, *on as u8])?,
+                Command::Reverse(on) => w.write_all(&[13, *on as u8])?,
+                Command::Size { width, height } => w.write_all(&[6, *width, *height])?,
+                Command::Barcode { code, kind, height } => {
+                    w.write_u8(7)?;
+                    write_bytes(w, code.as_bytes())?;
+                    w.write_all(&[*kind as u8, *height])?;
+                }
+                Command::QrCode { data, size } => {
+                    w.write_u8(8)?;
+                    write_bytes(w, data.as_bytes())?;
+                    w.write_u8(*size)?;
+                }
+                Command::Raster {
+                    width_bytes,
+                    height,
+                    data,
+                } => {
+                    w.write_u8(9)?;
+                    w.write_u16::<LittleEndian>(*width_bytes)?;
+                    w.write_u16::<LittleEndian>(*height)?;
+                    write_bytes(w, data)?;
+                }
+                Command::Feed(lines) => w.write_all(&[10, *lines])?,
+                Command::Cut { partial } => w.write_all(&[11, *partial as u8])?,
+                Command::Raw(bytes) => {
+                    w.write_u8(12)?;
+                    write_bytes(w, bytes)?;
+                }
+                Command::Display(DisplayCommand::Clear) => w.write_all(&[14, 0])?,
+                Command::Display(DisplayCommand::Cursor { column, row }) => {
+                    w.write_all(&[14, 1, *column, *row])?
+                }
+                Command::Display(DisplayCommand::Text(text)) => {
+                    w.write_all(&[14, 2])?;
+                    write_bytes(w, text.as_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a job written by [Job::write_to]
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<Job> {
+        let mut magic = [0; 7];
+        r.read_exact(&mut magic)?;
+        let version = magic[6];
+        if magic[..6] != JOB_MAGIC[..6] || !(1..=JOB_MAGIC[6]).contains(&version) {
+            return Err(invalid("not a job file"));
+        }
+        let profile = read_name(r)?;
+        let created = UNIX_EPOCH + Duration::from_secs(r.read_u64::<LittleEndian>()?);
+        let (encoding, trap) = match version {
+            1 => (String::new(), None),
+            _ => (
+                read_name(r)?,
+                match r.read_u8()? {
+                    0 => None,
+                    1 => Some(Trap::Strict),
+                    2 => Some(Trap::Replace),
+                    3 => Some(Trap::Ignore),
+                    4 => Some(Trap::NcrEscape),
+                    _ => return Err(invalid("invalid trap")),
+                },
+            ),
+        };
+        let cut_feed = match version {
+            1 | 2 => None,
+            _ => match r.read_u8()? {
+                0 => None,
+                1 => Some(CutFeed {
+                    lines_before: r.read_u8()?,
+                    mm_after: r.read_u8()?,
+                }),
+                _ => return Err(invalid("invalid cut feed")),
+            },
+        };
+        let count = r.read_u32::<LittleEndian>()?;
+        let mut commands = Vec::new();
+        for _ in 0..count {
+            commands.push(match r.read_u8()? {
+                0 => Command::Init,
+                1 => Command::Text(read_string(r)?),
+                2 => Command::NewLine,
+                3 => Command::Align(match r.read_u8()? {
+                    0 => Alignment::Left,
+                    1 => Alignment::Center,
+                    2 => Alignment::Right,
+                    _ => return Err(invalid("invalid alignment")),
+                }),
+                4 => Command::Bold(r.read_u8()? != 0),
+                5 => Command::Underline(r.read_u8()? != 0),
+                6 => Command::Size {
+                    width: r.read_u8()?,
+                    height: r.read_u8()?,
+                },
+                7 => Command::Barcode {
+                    code: read_string(r)?,
+                    kind: barcode_type(r.read_u8()?)?,
+                    height: r.read_u8()?,
+                },
+                8 => Command::QrCode {
+                    data: read_string(r)?,
+                    size: r.read_u8()?,
+                },
+                9 => Command::Raster {
+                    width_bytes: r.read_u16::<LittleEndian>()?,
+                    height: r.read_u16::<LittleEndian>()?,
+                    data: read_bytes(r)?,
+                },
+                10 => Command::Feed(r.read_u8()?),
+                11 => Command::Cut {
+                    partial: r.read_u8()? != 0,
+                },
+                12 => Command::Raw(read_bytes(r)?),
+                13 => Command::Reverse(r.read_u8()? != 0),
+                14 => Command::Display(match r.read_u8()? {
+                    0 => DisplayCommand::Clear,
+                    1 => DisplayCommand::Cursor {
+                        column: r.read_u8()?,
+                        row: r.read_u8()?,
+                    },
+                    2 => DisplayCommand::Text(read_string(r)?),
+                    _ => return Err(invalid("invalid display command")),
+                }),
+                _ => return Err(invalid("unknown command")),
+            });
+        }
+        Ok(Job {
+            commands,
+            profile: (!profile.is_empty()).then_some(profile),
+            created: Some(created),
+            encoding: (!encoding.is_empty()).then_some(encoding),
+            trap,
+            cut_feed,
+        })
+    }
+}
+
+/// Start of a saved [Job], the last byte is the format version
+const JOB_MAGIC: &[u8; 7] = b"POSJOB\x03";
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn write_bytes<W: Write>(w: &mut W, bytes: &[u8]) -> io::Result<()> {
+    w.write_u32::<LittleEndian>(bytes.len() as u32)?;
+    w.write_all(bytes)
+}
+
+fn read_bytes<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let len = r.read_u32::<LittleEndian>()? as usize;
+    let mut bytes = Vec::new();
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+/// A u16 length and UTF-8 string
+fn write_name<W: Write>(w: &mut W, name: &str) -> io::Result<()> {
+    let len = u16::try_from(name.len()).map_err(|_| invalid("name too long"))?;
+    w.write_u16::<LittleEndian>(len)?;
+    w.write_all(name.as_bytes())
+}
+
+fn read_name<R: Read>(r: &mut R) -> io::Result<String> {
+    let mut name = vec![0; r.read_u16::<LittleEndian>()? as usize];
+    r.read_exact(&mut name)?;
+    String::from_utf8(name).map_err(|_| invalid("invalid name"))
+}
+
+fn read_string<R: Read>(r: &mut R) -> io::Result<String> {
+    String::from_utf8(read_bytes(r)?).map_err(|_| invalid("invalid text"))
+}
+
+fn barcode_type(value: u8) -> io::Result<BarcodeType> {
+    Ok(match value {
+        0 => BarcodeType::UPCA,
+        1 => BarcodeType::UPCE,
+        2 => BarcodeType::EAN13,
+        3 => BarcodeType::EAN8,
+        4 => BarcodeType::CODE39,
+        5 => BarcodeType::ITF,
+        72 => BarcodeType::Code93,
+        6 => BarcodeType::Codabar,
+        73 => BarcodeType::Code128,
+        10 => BarcodeType::PDF417,
+        11 => BarcodeType::QRCode,
+        12 => BarcodeType::Maxicode,
+        13 => BarcodeType::GS1,
+        _ => return Err(invalid("unknown barcode type")),
+    })
+}
+
+impl From<&Document> for Job {
+    fn from(doc: &Document) -> Self {
+        let mut commands = Vec::new();
+        for element in &doc.elements {
+            match element {
+                Element::Text { content, style } => {
+                    commands.push(Command::Align(doc.direction.align(style.align)));
+                    if style.bold {
+                        commands.push(Command::Bold(true));
+                    }
+                    if style.underline {
+                        commands.push(Command::Underline(true));
+                    }
+                    let scaled = style.width > 1 || style.height > 1;
+                    if scaled {
+                        commands.push(Command::Size {
+                            width: style.width,
+                            height: style.height,
+                        });
+                    }
+                    commands.push(Command::Text(content.clone()));
+                    commands.push(Command::NewLine);
+                    if style.bold {
+                        commands.push(Command::Bold(false));
+                    }
+                    if style.underline {
+                        commands.push(Command::Underline(false));
+                    }
+                    if scaled {
+                        commands.push(Command::Size {
+                            width: 1,
+                            height: 1,
+                        });
+                    }
+                }
+                Element::Barcode { code, kind, height } => commands.push(Command::Barcode {
+                    code: code.clone(),
+                    kind: *kind,
+                    height: *height,
+                }),
+                Element::QrCode { data, size } => commands.push(Command::QrCode {
+                    data: data.clone(),
+                    size: *size,
+                }),
+                Element::Image(image) => commands.push(Command::from(image)),
+                Element::Feed(lines) => commands.push(Command::Feed(*lines)),
+                Element::Cut => commands.push(Command::Cut { partial: true }),
+            }
+        }
+        Job {
+            commands,
+            ..Job::default()
+        }
+    }
+}
+
+impl From<&Image> for Command {
+    fn from(image: &Image) -> Self {
+        Command::Raster {
+            width_bytes: image.width.div_ceil(8).max(1) as u16,
+            height: image.height as u16,
+            data: image.get_raster().into_vec(),
+        }
+    }
+}
+
+/// Names of the `{{name}}` placeholders in text, in order
+///
+/// Names are letters, digits and `_`, anything else between braces is
+/// printed as it is.
+pub fn placeholders(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end)
+                if end > 0
+                    && after[..end]
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                names.push(&after[..end]);
+                rest = &after[end + 2..];
+            }
+            _ => rest = after,
+        }
+    }
+    names
+}
+
+/// A job encoded once with the `{{name}}` placeholders of its text filled
+/// in for each print, see
+/// [Printer::compile](crate::printer::Printer::compile)
+///
+/// Printing copies the encoded bytes and encodes only the values, for
+/// tickets printed by the thousand that differ in an order number.
+#[derive(Clone)]
+pub struct Template {
+    /// Bytes before, between and after the fields
+    parts: Vec<Vec<u8>>,
+    fields: Vec<String>,
+    codec: EncodingRef,
+    trap: EncoderTrap,
+    /// The compiled job, for archiving and counting paper
+    job: Job,
+}
+
+impl Template {
+    /// Splits bytes at the placeholders of fields, which they contain in
+    /// that order
+    pub(crate) fn split(
+        bytes: &[u8],
+        job: &Job,
+        codec: EncodingRef,
+        trap: EncoderTrap,
+    ) -> Result<Template, Error> {
+        let fields = job.placeholders();
+        let marker = |name: &str| format!("{{{{{}}}}}", name).into_bytes();
+        let find = |bytes: &[u8], marker: &[u8]| {
+            bytes
+                .windows(marker.len())
+                .position(|window| window == marker)
+        };
+        let mut parts = Vec::with_capacity(fields.len() + 1);
+        let mut rest = bytes;
+        for name in &fields {
+            let marker = marker(name);
+            let at = find(rest, &marker).ok_or(Error::InvalidArgument)?;
+            parts.push(rest[..at].to_vec());
+            rest = &rest[at + marker.len()..];
+        }
+        parts.push(rest.to_vec());
+        // A placeholder's bytes in an image or barcode would have been taken
+        // for the field, leaving the field's own in a part
+        if fields
+            .iter()
+            .any(|name| parts.iter().any(|part| find(part, &marker(name)).is_some()))
+        {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(Template {
+            parts,
+            fields,
+            codec,
+            trap,
+            job: job.clone(),
+        })
+    }
+
+    /// The compiled job with the fields set to values, as it prints
+    pub(crate) fn job(&self, values: &[(&str, &str)]) -> Job {
+        let mut job = self.job.clone();
+        for command in &mut job.commands {
+            if let Command::Text(text) = command {
+                for (name, value) in values {
+                    *text = text.replace(&format!("{{{{{}}}}}", name), value);
+                }
+            }
+        }
+        job
+    }
+
+    /// Names of the placeholders in the order they print
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    /// The job's bytes with the fields set to values, name and value pairs
+    ///
+    /// Returns [Error::InvalidArgument] when a field has no value or a
+    /// value can't be encoded.
+    pub fn render(&self, values: &[(&str, &str)]) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::with_capacity(self.parts.iter().map(Vec::len).sum::<usize>() + 64);
+        for (part, name) in self.parts.iter().zip(&self.fields) {
+            out.extend_from_slice(part);
+            let (_, value) = values
+                .iter()
+                .find(|(field, _)| field == name)
+                .ok_or(Error::InvalidArgument)?;
+            self.codec
+                .encode_to(value, self.trap, &mut out)
+                .map_err(|_| Error::InvalidArgument)?;
+        }
+        out.extend_from_slice(self.parts.last().expect("one more part than fields"));
+        Ok(out)
+    }
+}
+
+/// A [Job] built in one expression and submitted at once, see
+/// [Printer::builder]
+///
+/// The methods take the builder by value and return it, so it can be moved
+/// into closures and async blocks. Every command is checked against the
+/// printer's dialect as it's added and nothing is sent before
+/// [JobBuilder::finish], so a job that fails to build prints nothing.
+pub struct JobBuilder<'a> {
+    printer: &'a mut Printer,
+    job: Job,
+    /// Reused for checking commands
+    scratch: Vec<u8>,
+}
+
+impl<'a> JobBuilder<'a> {
+    pub(crate) fn new(printer: &'a mut Printer) -> JobBuilder<'a> {
+        JobBuilder {
+            printer,
+            job: Job::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Adds command when the printer can print it
+    pub fn command(mut self, command: Command) -> Result<Self, Error> {
+        match &command {
+            // Sent with the printer's cut commands, which a profile can
+            // override, see Printer::submit
+            Command::Cut { .. } if !self.printer.profile().capabilities.cutter => {
+                return Err(Error::Unsupported)
+            }
+            Command::Cut { .. } => (),
+            Command::QrCode { .. } if !self.printer.profile().capabilities.qr_code => {
+                return Err(Error::Unsupported)
+            }
+            command => {
+                self.scratch.clear();
+                self.printer
+                    .encoder()
+                    .encode_command(command, &mut self.scratch)?;
+            }
+        }
+        self.job.push(command);
+        Ok(self)
+    }
+
+    /// Text without a line feed
+    pub fn text(self, text: &str) -> Result<Self, Error> {
+        self.command(Command::Text(text.to_string()))
+    }
+
+    /// Text followed by a line feed
+    pub fn line(self, text: &str) -> Result<Self, Error> {
+        self.text(text)?.command(Command::NewLine)
+    }
+
+    pub fn align(self, align: Alignment) -> Result<Self, Error> {
+        self.command(Command::Align(align))
+    }
+
+    pub fn bold(self, on: bool) -> Result<Self, Error> {
+        self.command(Command::Bold(on))
+    }
+
+    pub fn underline(self, on: bool) -> Result<Self, Error> {
+        self.command(Command::Underline(on))
+    }
+
+    pub fn reverse(self, on: bool) -> Result<Self, Error> {
+        self.command(Command::Reverse(on))
+    }
+
+    /// Character magnification, 1 to 8
+    pub fn size(self, width: u8, height: u8) -> Result<Self, Error> {
+        self.command(Command::Size { width, height })
+    }
+
+    /// Checks code with [BarcodeType::validate] as well
+    pub fn barcode(self, code: &str, kind: BarcodeType, height: u8) -> Result<Self, Error> {
+        kind.validate(code).map_err(|e| match e {
+            BarcodeError::Unsupported => Error::Unsupported,
+            _ => Error::InvalidArgument,
+        })?;
+        self.command(Command::Barcode {
+            code: code.to_string(),
+            kind,
+            height,
+        })
+    }
+
+    pub fn qr(self, data: &str, size: u8) -> Result<Self, Error> {
+        self.command(Command::QrCode {
+            data: data.to_string(),
+            size,
+        })
+    }
+
+    pub fn image(self, image: &Image) -> Result<Self, Error> {
+        self.command(Command::from(image))
+    }
+
+    pub fn feed(self, lines: u8) -> Result<Self, Error> {
+        self.command(Command::Feed(lines))
+    }
+
+    pub fn cut(self) -> Result<Self, Error> {
+        self.command(Command::Cut { partial: false })
+    }
+
+    pub fn partial_cut(self) -> Result<Self, Error> {
+        self.command(Command::Cut { partial: true })
+    }
+
+    /// Shows something on the customer display between the printed
+    /// commands, see [display](crate::display)
+    pub fn display(self, command: DisplayCommand) -> Result<Self, Error> {
+        self.command(Command::Display(command))
+    }
+
+    /// Sends the job with [Printer::submit]
+    pub fn finish(self) -> Result<usize, Error> {
+        self.printer.submit(&self.job)
+    }
+
+    /// The job built so far, without sending it
+    pub fn into_job(self) -> Job {
+        self.job
+    }
+}
+
+/// Problem with a command of a job, see [Job::validate]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationIssue {
+    /// Index of the command in [Job::commands]
+    pub index: usize,
+    pub kind: IssueKind,
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "command {}: {}", self.index, self.kind)
+    }
+}
+
+#[derive(thiserror::Error, Clone, Debug, PartialEq)]
+pub enum IssueKind {
+    #[error("Not supported by the printer")]
+    Unsupported,
+    #[error("Image is {width} dots wide, the paper {max}")]
+    TooWide { width: u32, max: u32 },
+    #[error("Invalid barcode: {0}")]
+    Barcode(BarcodeError),
+    #[error("Invalid {0}")]
+    OutOfRange(&'static str),
+}
+
+/// Bytes a job encodes to by kind of command, see [Encoder::byte_report]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ByteReport {
+    /// Text, line feeds and text attributes
+    pub text: usize,
+    /// Rasters
+    pub images: usize,
+    /// Barcodes and QR codes
+    pub barcodes: usize,
+    /// Everything else, like feeds, cuts and raw bytes
+    pub other: usize,
+}
+
+impl ByteReport {
+    pub fn total(&self) -> usize {
+        self.text + self.images + self.barcodes + self.other
+    }
+}
+
+/// Encodes commands in the dialect of one of the [SupportedPrinters]
+pub struct Encoder {
+    pub printer: SupportedPrinters,
+    pub quirks: Quirks,
+    /// Encoding of [Command::Text]
+    pub codec: EncodingRef,
+    /// What happens to characters codec can't represent
+    pub trap: EncoderTrap,
+    /// Characters sent as pictures, not on Star Line Mode printers or with
+    /// the raster_only quirk
+    pub glyphs: GlyphSet,
+    /// Paper fed around [Command::Cut], none but the P3's three line feeds
+    /// when None
+    pub cut_feed: Option<CutFeed>,
+    /// Resolution the feed of cut_feed is converted with
+    pub dpi: u16,
+}
+
+impl Encoder {
+    /// Encoder with the printer's default quirks and UTF-8 text
+    pub fn new(printer: SupportedPrinters) -> Encoder {
+        Encoder {
+            printer,
+            quirks: printer.quirks(),
+            codec: UTF_8,
+            trap: EncoderTrap::Replace,
+            glyphs: GlyphSet::default(),
+            cut_feed: None,
+            dpi: 203,
+        }
+    }
+
+    pub fn encode(&self, job: &Job) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::new();
+        for command in &job.commands {
+            self.encode_command(command, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    /// Bytes job encodes to by kind of command, to find what makes a job
+    /// slow on a serial link
+    pub fn byte_report(&self, job: &Job) -> Result<ByteReport, Error> {
+        let mut report = ByteReport::default();
+        let mut out = Vec::new();
+        for command in &job.commands {
+            out.clear();
+            self.encode_command(command, &mut out)?;
+            *match command {
+                Command::Text(_)
+                | Command::NewLine
+                | Command::Align(_)
+                | Command::Bold(_)
+                | Command::Underline(_)
+                | Command::Reverse(_)
+                | Command::Size { .. } => &mut report.text,
+                Command::Raster { .. } => &mut report.images,
+                Command::Barcode { .. } | Command::QrCode { .. } => &mut report.barcodes,
+                Command::Init
+                | Command::Feed(_)
+                | Command::Cut { .. }
+                | Command::Raw(_)
+                | Command::Display(_) => &mut report.other,
+            } += out.len();
+        }
+        Ok(report)
+    }
+
+    pub fn encode_command(&self, command: &Command, out: &mut Vec<u8>) -> Result<(), Error> {
+        let star = self.printer == SupportedPrinters::Star;
+        match command {
+            Command::Init => out.extend_from_slice(&[0x1b, 0x40]),
+            Command::Text(text) if star || self.quirks.raster_only => {
+                self.codec
+                    .encode_to(text, self.trap, out)
+                    .map_err(|_| Error::InvalidArgument)?;
+            }
+            Command::Text(text) => {
+                self.glyphs
+                    .encode(text, self.codec, self.trap, out)
+                    .map_err(|_| Error::InvalidArgument)?;
+            }
+            Command::NewLine => out.extend_from_slice(consts::CTL_LF),
+            Command::Align(align) => out.extend_from_slice(match (align, star) {
+                (Alignment::Left, false) => consts::TXT_ALIGN_LT,
+                (Alignment::Center, false) => consts::TXT_ALIGN_CT,
+                (Alignment::Right, false) => consts::TXT_ALIGN_RT,
+                (Alignment::Left, true) => consts::STAR_ALIGN_LT,
+                (Alignment::Center, true) => consts::STAR_ALIGN_CT,
+                (Alignment::Right, true) => consts::STAR_ALIGN_RT,
+            }),
+            Command::Bold(on) => out.extend_from_slice(match (on, star) {
+                (true, false) => consts::TXT_BOLD_ON,
+                (false, false) => consts::TXT_BOLD_OFF,
+                (true, true) => consts::STAR_BOLD_ON,
+                (false, true) => consts::STAR_BOLD_OFF,
+            }),
+            Command::Underline(on) => out.extend_from_slice(match on {
+                true => consts::TXT_UNDERL_ON,
+                false => consts::TXT_UNDERL_OFF,
+            }),
+            Command::Reverse(on) => out.extend_from_slice(match (on, star) {
+                (true, false) => consts::TXT_REVERSE_ON,
+                (false, false) => consts::TXT_REVERSE_OFF,
+                (true, true) => consts::STAR_REVERSE_ON,
+                (false, true) => consts::STAR_REVERSE_OFF,
+            }),
+            Command::Size { width, height } => {
+                if !(1..=8).contains(width) || !(1..=8).contains(height) {
+                    return Err(Error::InvalidArgument);
+                }
+                if star {
+                    // ESC i n1 n2 takes 0 to 5
+                    if *width > 6 || *height > 6 {
+                        return Err(Error::InvalidArgument);
+                    }
+                    out.extend_from_slice(&[0x1b, 0x69, height - 1, width - 1]);
+                } else {
+                    out.extend_from_slice(&[0x1d, 0x21, (width - 1) << 4 | (height - 1)]);
+                }
+            }
+            Command::Barcode { code, kind, height } => out.extend_from_slice(&barcode(
+                self.printer,
+                &self.quirks,
+                code,
+                *kind,
+                TextPosition::Below,
+                Font::FontA,
+                2,
+                *height,
+            )?),
+            Command::QrCode { data, size } => match self.printer {
+                SupportedPrinters::Star => return Err(Error::Unsupported),
+                SupportedPrinters::Citizen | SupportedPrinters::Generic => {
+                    out.extend_from_slice(&qrcode_gs_k(data, b'M', *size)?)
+                }
+                _ => {
+                    let len = u16::try_from(data.len()).map_err(|_| Error::InvalidArgument)?;
+                    out.extend_from_slice(consts::TYPE_QR);
+                    out.extend_from_slice(consts::CODE2D);
+                    out.push(0x03);
+                    out.extend_from_slice(consts::QR_LEVEL_M);
+                    out.push(*size);
+                    out.extend_from_slice(&len.to_le_bytes());
+                    out.extend_from_slice(data.as_bytes());
+                }
+            },
+            Command::Raster {
+                width_bytes,
+                height,
+                data,
+            } => {
+                let width = *width_bytes as usize;
+                if width == 0 || data.len() != width * *height as usize {
+                    return Err(Error::InvalidArgument);
+                }
+                if star {
+                    out.extend_from_slice(consts::STAR_RASTER_START);
+                    for row in data.chunks(width) {
+                        out.push(0x62);
+                        out.extend_from_slice(&width_bytes.to_le_bytes());
+                        out.extend_from_slice(row);
+                    }
+                    out.extend_from_slice(consts::STAR_RASTER_END);
+                } else {
+                    out.extend_from_slice(consts::GSV0_NORMAL);
+                    out.extend_from_slice(&width_bytes.to_le_bytes());
+                    out.extend_from_slice(&height.to_le_bytes());
+                    out.extend_from_slice(data);
+                }
+            }
+            Command::Feed(lines) => match star {
+                true => out.extend_from_slice(&[0x1b, 0x61, *lines]),
+                false => out.extend_from_slice(&[0x1b, 0x64, *lines]),
+            },
+            Command::Cut { partial } => out.extend_from_slice(&self.cut(*partial)?),
+            Command::Raw(bytes) => out.extend_from_slice(bytes),
+            Command::Display(display) => {
+                display.encode(self.printer, self.codec, self.trap, out)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Same bytes as [Printer::cut](crate::printer::Printer::cut), fed as
+    /// cut_feed says
+    fn cut(&self, partial: bool) -> Result<Vec<u8>, Error> {
+        let feed = match (self.cut_feed, self.printer) {
+            (Some(feed), _) => feed,
+            (None, SupportedPrinters::P3) => CutFeed::LEGACY,
+            (None, _) => CutFeed::default(),
+        };
+        cut_bytes(self.printer, partial, feed, self.dpi)
+    }
+}
+
+/// Barcode in the printer's dialect, see
+/// [Printer::barcode](crate::printer::Printer::barcode)
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn barcode(
+    printer: SupportedPrinters,
+    quirks: &Quirks,
+    code: &str,
+    kind: BarcodeType,
+    position: TextPosition,
+    font: Font,
+    width: u8,
+    height: u8,
+) -> Result<Vec<u8>, Error> {
+    kind.check_len(code)?;
+    let mut out = Vec::new();
+    let mut bc = Barcode {
+        printer,
+        width,
+        height,
+        position,
+        font,
+        kind,
+    };
+
+    // Code128 requires the Code Set to be sent before the barcode text
+    //
+    // Currently we just default to Code B, but we might want to think about
+    // allowing the selection of the code set
+    //
+    // 128A (Code Set A) – ASCII characters 00 to 95 (0–9, A–Z and control codes), special characters, and FNC 1–4
+    // 128B (Code Set B) – ASCII characters 32 to 127 (0–9, A–Z, a–z), special characters, and FNC 1–4
+    // 128C (Code Set C) – 00–99 (encodes two digits with a single code point) and FNC1
+    // SNBC Also requires sending the number of bytes in the Code128 receipt
+    if kind == BarcodeType::Code128 && printer == SupportedPrinters::SNBC {
+        out.extend_from_slice(&bc.set_width()?);
+        out.extend_from_slice(&bc.set_height());
+        out.extend_from_slice(&bc.set_text_position());
+        out.extend_from_slice(&bc.set_font());
+        out.extend_from_slice(&bc.set_barcode_type());
+        let mut code128_bytes: Vec<u8> = vec![0x7b]; // Next byte will set the code set
+        if code.len().is_multiple_of(2) && code.chars().all(|x| x.is_ascii_digit()) {
+            // even number of chars and they are all numbers, we can use Code Set C
+            code128_bytes.push(0x43); // Codeset C
+            let mut converted: Vec<u8> = Barcode::to_codeset_c(code.to_string()).unwrap();
+            code128_bytes.append(&mut converted);
+        } else {
+            // otherwise we just push the characters which match up with Code Set B
+            code128_bytes.push(0x42); // Codeset B
+            code128_bytes.extend_from_slice(code.as_bytes());
+        }
+
+        let count = code128_bytes.len();
+        code128_bytes.insert(0, count as u8);
+        out.extend_from_slice(&code128_bytes);
+        return Ok(out);
+    } else if printer == SupportedPrinters::Star {
+        return star_barcode(code, kind, position, width.clamp(1, 3), height);
+    } else if printer == SupportedPrinters::Generic {
+        return generic_barcode(code, kind, position, width, height, quirks);
+    } else if printer == SupportedPrinters::Epic {
+        out.extend_from_slice(&[
+            0x1D,
+            0x48,
+            0x02,
+            0x1D,
+            0x77,
+            0x02,
+            0x1D,
+            0x6B,
+            0x49,
+            code.len() as u8,
+        ]);
+    } else {
+        return Err(Error::Unsupported);
+    }
+
+    out.extend_from_slice(code.as_bytes());
+    out.push(0x00); // Need to send NULL to finish
+
+    Ok(out)
+}
+
+/// GS k m n d1...dn - Print barcode (m = 65-73)
+///
+/// Sent with the HRI position (GS H), width (GS w) and height (GS h)
+/// clones agree on. Code128 data is prefixed with `{B` to select code set
+/// B.
+fn generic_barcode(
+    code: &str,
+    kind: BarcodeType,
+    position: TextPosition,
+    width: u8,
+    height: u8,
+    quirks: &Quirks,
+) -> Result<Vec<u8>, Error> {
+    let m = match kind {
+        BarcodeType::UPCA => 65,
+        BarcodeType::UPCE => 66,
+        BarcodeType::EAN13 => 67,
+        BarcodeType::EAN8 => 68,
+        BarcodeType::CODE39 => 69,
+        BarcodeType::ITF => 70,
+        BarcodeType::Codabar => 71,
+        BarcodeType::Code93 if !quirks.no_code93 => 72,
+        BarcodeType::Code128 => 73,
+        _ => return Err(Error::Unsupported),
+    };
+    let mut data = Vec::with_capacity(code.len() + 2);
+    if kind == BarcodeType::Code128 {
+        data.extend_from_slice(b"{B");
+    }
+    data.extend_from_slice(code.as_bytes());
+    if data.len() > 255 {
+        return Err(Error::InvalidArgument);
+    }
+    let mut out = vec![
+        0x1d,
+        0x48,
+        position as u8,
+        0x1d,
+        0x77,
+        width.clamp(2, 6),
+        0x1d,
+        0x68,
+        height,
+        0x1d,
+        0x6b,
+        m,
+        data.len() as u8,
+    ];
+    out.extend_from_slice(&data);
+    if quirks.barcode_nul_terminator {
+        out.push(0x00);
+    }
+    Ok(out)
+}
+
+/// Code 39 barcode of data from [code39_data](crate::barcode::code39_data)
+/// with the narrow to wide ratio, which only Star Line Mode can select
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn code39(
+    printer: SupportedPrinters,
+    quirks: &Quirks,
+    data: &str,
+    position: TextPosition,
+    font: Font,
+    width: u8,
+    height: u8,
+    ratio: Option<Code39Ratio>,
+) -> Result<Vec<u8>, Error> {
+    let offset = match (ratio, printer) {
+        (None, _) => {
+            let kind = BarcodeType::CODE39;
+            return barcode(printer, quirks, data, kind, position, font, width, height);
+        }
+        (Some(_), printer) if printer != SupportedPrinters::Star => return Err(Error::Unsupported),
+        (Some(Code39Ratio::Three), _) => 0,
+        (Some(Code39Ratio::TwoAndAHalf), _) => 3,
+        (Some(Code39Ratio::Two), _) => 6,
+    };
+    BarcodeType::CODE39.check_len(data)?;
+    let mode = width.clamp(1, 3) + offset;
+    star_barcode(data, BarcodeType::CODE39, position, mode, height)
+}
+
+/// ESC b n1 n2 n3 n4 d1...dk RS - Star Line Mode barcode
+///
+/// | n1 | Barcode | n2 | HRI       | n3    | Module width     |
+/// |----|---------|----|-----------|-------|------------------|
+/// | 0  | UPC-E   | 1  | none      | 1 - 3 | mode 1 - 3       |
+/// | 1  | UPC-A   | 2  | below     |       |                  |
+/// | 2  | EAN8    |    |           |       |                  |
+/// | 3  | EAN13   |    |           |       |                  |
+/// | 4  | Code39  |    |           |       |                  |
+/// | 5  | ITF     |    |           |       |                  |
+/// | 6  | Code128 |    |           |       |                  |
+/// | 7  | Code93  |    |           |       |                  |
+/// | 8  | NW-7    |    |           |       |                  |
+///
+/// Code39, ITF and NW-7 have modes 1 - 9, narrow bars of 2, 3 and 4 dots
+/// with wide ones 3, 2.5 and 2 times as wide. n4 is the height in dots.
+/// Star prints no HRI above the barcode, so anything but
+/// [TextPosition::Off] prints it below.
+fn star_barcode(
+    code: &str,
+    kind: BarcodeType,
+    position: TextPosition,
+    mode: u8,
+    height: u8,
+) -> Result<Vec<u8>, Error> {
+    let n1 = match kind {
+        BarcodeType::UPCE => 0x00,
+        BarcodeType::UPCA => 0x01,
+        BarcodeType::EAN8 => 0x02,
+        BarcodeType::EAN13 => 0x03,
+        BarcodeType::CODE39 => 0x04,
+        BarcodeType::ITF => 0x05,
+        BarcodeType::Code128 => 0x06,
+        BarcodeType::Code93 => 0x07,
+        BarcodeType::Codabar => 0x08,
+        _ => return Err(Error::Unsupported),
+    };
+    let n2 = match position {
+        TextPosition::Off => 0x01,
+        _ => 0x02,
+    };
+    let mut out = vec![0x1b, 0x62, n1, n2, mode, height];
+    out.extend_from_slice(code.as_bytes());
+    out.push(0x1e);
+    Ok(out)
+}
+
+/// GS ( k - QR Code (cn = 49), for printers without GS Z/ESC Z
+///
+/// | fn | Function                   |
+/// |----|----------------------------|
+/// | 65 | Select model (model 2)     |
+/// | 67 | Module size                |
+/// | 69 | Error correction level     |
+/// | 80 | Store data                 |
+/// | 81 | Print stored data          |
+pub(crate) fn qrcode_gs_k(code: &str, level: u8, size: u8) -> Result<Vec<u8>, Error> {
+    let ecc = match level {
+        b'M' => 49,
+        b'Q' => 50,
+        b'H' => 51,
+        _ => 48,
+    };
+    let len = u16::try_from(code.len() + 3).map_err(|_| Error::InvalidArgument)?;
+    let mut out = vec![0x1d, 0x28, 0x6b, 0x04, 0x00, 0x31, 0x41, 0x32, 0x00];
+    out.extend_from_slice(&[0x1d, 0x28, 0x6b, 0x03, 0x00, 0x31, 0x43, size]);
+    out.extend_from_slice(&[0x1d, 0x28, 0x6b, 0x03, 0x00, 0x31, 0x45, ecc]);
+    out.extend_from_slice(&[0x1d, 0x28, 0x6b]);
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(&[0x31, 0x50, 0x30]);
+    out.extend_from_slice(code.as_bytes());
+    out.extend_from_slice(&[0x1d, 0x28, 0x6b, 0x03, 0x00, 0x31, 0x51, 0x30]);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::TextStyle;
+
+    #[test]
+    fn lowers_document() {
+        let mut doc = Document::new();
+        doc.styled(
+            "Hi",
+            TextStyle {
+                bold: true,
+                ..TextStyle::default()
+            },
+        )
+        .cut();
+        let job = Job::from(&doc);
+        assert_eq!(
+            job.commands,
+            vec![
+                Command::Align(Alignment::Left),
+                Command::Bold(true),
+                Command::Text("Hi".to_string()),
+                Command::NewLine,
+                Command::Bold(false),
+                Command::Cut { partial: true },
+            ]
+        );
+
+        let mut rtl = Document::rtl();
+        rtl.text("שלום");
+        assert_eq!(
+            Job::from(&rtl).commands[0],
+            Command::Align(Alignment::Right)
+        );
+    }
+
+    #[cfg(feature = "json_profiles")]
+    #[test]
+    fn documents_as_json() {
+        let mut doc = Document::new();
+        let gray = image::GrayImage::from_fn(8, 2, |x, _| image::Luma([(x * 32) as u8]));
+        doc.text("Hi")
+            .barcode("123", BarcodeType::CODE39, 40)
+            .image(Image::from(image::DynamicImage::ImageLuma8(gray)))
+            .cut();
+        let json = serde_json::to_string(&doc).unwrap();
+        let decoded: Document = serde_json::from_str(&json).unwrap();
+        assert_eq!(Job::from(&decoded), Job::from(&doc));
+
+        let style: TextStyle = serde_json::from_str(r#"{"bold": true}"#).unwrap();
+        assert_eq!(style.width, 1);
+    }
+
+    #[test]
+    fn estimates_paper_length() {
+        let mut job = Job::new();
+        job.push(Command::Init)
+            .push(Command::Size {
+                width: 2,
+                height: 2,
+            })
+            .push(Command::Text("TOTAL".to_string()))
+            .push(Command::NewLine)
+            .push(Command::Size {
+                width: 1,
+                height: 1,
+            })
+            // Wraps onto a second line
+            .push(Command::Text("x".repeat(60)))
+            .push(Command::NewLine)
+            .push(Command::NewLine)
+            .push(Command::Barcode {
+                code: "12345678".to_string(),
+                kind: BarcodeType::EAN8,
+                height: 80,
+            })
+            .push(Command::Raster {
+                width_bytes: 1,
+                height: 100,
+                data: vec![0; 100],
+            })
+            .push(Command::Feed(3));
+        let mut profile = Profile::from(SupportedPrinters::Generic);
+        profile.dots_per_line = Some(576);
+        let dots: u32 = crate::preview::layout(&job, 576)
+            .iter()
+            .map(|receipt| receipt.height())
+            .sum();
+        assert_eq!(dots, 54 + 2 * 30 + 30 + 80 + 30 + 100 + 90);
+        let mm = job.estimate_length_mm(&profile);
+        assert!((mm - dots as f32 * 25.4 / 203.0).abs() < 0.01);
+
+        profile.dpi = Some(180);
+        assert!(job.estimate_length_mm(&profile) > mm);
+        assert_eq!(qr_modules("https://example.com"), 25);
+    }
+
+    #[test]
+    fn reports_bytes() {
+        let mut job = Job::new();
+        job.push(Command::Bold(true))
+            .push(Command::Text("Hi".to_string()))
+            .push(Command::Raster {
+                width_bytes: 2,
+                height: 3,
+                data: vec![0; 6],
+            })
+            .push(Command::Feed(2));
+        let report = job.byte_report().unwrap();
+        assert_eq!(report.text, 3 + 2);
+        // GS v 0, mode, and the size before the data
+        assert_eq!(report.images, 8 + 6);
+        assert_eq!(report.barcodes, 0);
+        assert_eq!(
+            job.byte_len().unwrap(),
+            Encoder::new(SupportedPrinters::Generic)
+                .encode(&job)
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn validates_against_profile() {
+        let mut job = Job::new();
+        job.push(Command::Text("ok".to_string()))
+            .push(Command::Raster {
+                width_bytes: 80,
+                height: 1,
+                data: vec![0; 80],
+            })
+            .push(Command::Barcode {
+                code: "12A45678".to_string(),
+                kind: BarcodeType::EAN8,
+                height: 50,
+            })
+            .push(Command::Size {
+                width: 9,
+                height: 1,
+            })
+            .push(Command::Cut { partial: false });
+        let mut profile = Profile::from(SupportedPrinters::Generic);
+        profile.capabilities.cutter = false;
+        let issues = job.validate(&profile);
+        let kinds: Vec<_> = issues.iter().map(|i| (i.index, i.kind.clone())).collect();
+        assert_eq!(
+            kinds,
+            [
+                (
+                    1,
+                    IssueKind::TooWide {
+                        width: 640,
+                        max: 576
+                    }
+                ),
+                (2, IssueKind::Barcode(BarcodeError::InvalidCharacter('A'))),
+                (3, IssueKind::OutOfRange("character size")),
+                (4, IssueKind::Unsupported),
+            ]
+        );
+        assert_eq!(
+            issues[3].to_string(),
+            "command 4: Not supported by the printer"
+        );
+
+        // Star Line Mode has no QR codes
+        let qr = Job::from(&{
+            let mut doc = Document::new();
+            doc.qr("hi", 4);
+            doc
+        });
+        let star = Profile::from(SupportedPrinters::Star);
+        assert!(qr
+            .validate(&star)
+            .iter()
+            .any(|i| i.kind == IssueKind::Unsupported));
+        assert!(qr.validate(&profile).is_empty());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut job = Job::from(&{
+            let mut doc = Document::new();
+            doc.text("Total 9.99")
+                .barcode("12345678", BarcodeType::EAN8, 50)
+                .cut();
+            doc
+        });
+        job.push(Command::Raw(vec![0x1b, 0x70, 0x00]));
+        job.profile = Some("Acme TP-80".to_string());
+        job.created = Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        job.save(file.path()).unwrap();
+        assert_eq!(Job::load(file.path()).unwrap(), job);
+
+        job.set_encoding(encoding::all::WINDOWS_1251, Trap::Strict);
+        job.cut_feed = Some(CutFeed {
+            lines_before: 1,
+            mm_after: 4,
+        });
+        job.save(file.path()).unwrap();
+        let loaded = Job::load(file.path()).unwrap();
+        assert_eq!(loaded, job);
+        assert_eq!(loaded.codec().unwrap().unwrap().name(), "windows-1251");
+
+        // Version 1 has no encoding
+        let mut v1 = b"POSJOB\x01".to_vec();
+        v1.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(Job::read_from(&mut &v1[..]).unwrap().encoding, None);
+
+        let err = Job::read_from(&mut &b"POSJOB\x04"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // The name length is a u16
+        job.profile = Some("x".repeat(65536));
+        let err = job.write_to(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn builds_by_value() {
+        let mut printer = Printer::lazy(None, None, SupportedPrinters::Star, 0, 0);
+        let job = printer
+            .builder()
+            .align(Alignment::Center)
+            .and_then(|b| b.line("Hi"))
+            .and_then(|b| b.barcode("12345678", BarcodeType::EAN8, 40))
+            .and_then(|b| b.cut())
+            .unwrap()
+            .into_job();
+        assert_eq!(job.commands.len(), 5);
+
+        // Star Line Mode has no QR codes, bad barcodes never get that far
+        assert!(matches!(
+            printer.builder().qr("x", 4),
+            Err(Error::Unsupported)
+        ));
+        assert!(matches!(
+            printer.builder().barcode("x", BarcodeType::EAN8, 40),
+            Err(Error::InvalidArgument)
+        ));
+    }
+
+    #[test]
+    fn prints_glyphs_where_bit_images_print() {
+        let mut job = Job::new();
+        job.push(Command::Text("⭐".repeat(30)));
+        let bytes = Encoder::new(SupportedPrinters::SNBC).encode(&job).unwrap();
+        assert_eq!(bytes[..5], [0x1b, 0x2a, 33, 24, 0]);
+        // Raster only printers get the text
+        let bytes = Encoder::new(SupportedPrinters::Generic)
+            .encode(&job)
+            .unwrap();
+        assert_eq!(bytes, "⭐".repeat(30).as_bytes());
+
+        // 30 glyphs of 24 dots take two lines of 576, as text one
+        let snbc = Profile::from(SupportedPrinters::SNBC);
+        let generic = Profile::from(SupportedPrinters::Generic);
+        let lines = |profile: &Profile| job.estimate_length_mm(profile) * 203.0 / 25.4 / 30.0;
+        assert!((lines(&snbc) - 2.0).abs() < 0.01);
+        assert!((lines(&generic) - 1.0).abs() < 0.01);
+        assert_eq!(crate::preview::layout(&job, 576)[0].height(), 60);
+    }
+
+    #[test]
+    fn encodes_per_dialect() {
+        let mut job = Job::new();
+        job.push(Command::Align(Alignment::Center))
+            .push(Command::Cut { partial: false });
+        assert_eq!(
+            Encoder::new(SupportedPrinters::SNBC).encode(&job).unwrap(),
+            vec![0x1b, 0x61, 0x01, 0x1d, 0x56, 0x41, 0x00]
+        );
+        assert_eq!(
+            Encoder::new(SupportedPrinters::Star).encode(&job).unwrap(),
+            vec![0x1b, 0x1d, 0x61, 0x01, 0x1b, 0x64, 0x02]
+        );
+        assert!(matches!(
+            Encoder::new(SupportedPrinters::P3).encode(&job),
+            Err(Error::Unsupported)
+        ));
+
+        // Star magnifies at most 6 times
+        let mut job = Job::new();
+        job.push(Command::Size {
+            width: 6,
+            height: 1,
+        });
+        assert_eq!(
+            Encoder::new(SupportedPrinters::Star).encode(&job).unwrap(),
+            vec![0x1b, 0x69, 0x00, 0x05]
+        );
+        job.push(Command::Size {
+            width: 7,
+            height: 1,
+        });
+        assert!(matches!(
+            Encoder::new(SupportedPrinters::Star).encode(&job),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(Encoder::new(SupportedPrinters::SNBC).encode(&job).is_ok());
+        let star = Profile::from(SupportedPrinters::Star);
+        assert_eq!(job.validate(&star)[0].index, 1);
+    }
+
+    #[test]
+    fn mixes_display_and_printer() {
+        let mut printer = Printer::lazy(None, None, SupportedPrinters::SNBC, 0, 0);
+        let job = printer
+            .builder()
+            .display(DisplayCommand::Clear)
+            .and_then(|b| b.display(DisplayCommand::Text("DUE 9.99".to_string())))
+            .and_then(|b| b.line("Total 9.99"))
+            .unwrap()
+            .into_job();
+        let bytes = Encoder::new(SupportedPrinters::SNBC).encode(&job).unwrap();
+        assert!(bytes.starts_with(b"\x1b=\x02\x0c\x1b=\x01\x1b=\x02DUE 9.99\x1b=\x01Total"));
+        // Paper isn't fed for what the display shows
+        let mut printed = Job::new();
+        printed
+            .push(Command::Text("Total 9.99".to_string()))
+            .push(Command::NewLine);
+        let profile = Profile::default();
+        assert_eq!(
+            job.estimate_length_mm(&profile),
+            printed.estimate_length_mm(&profile)
+        );
+
+        let mut saved = Vec::new();
+        job.write_to(&mut saved).unwrap();
+        assert_eq!(
+            Job::read_from(&mut &saved[..]).unwrap().commands,
+            job.commands
+        );
+
+        let mut star = Printer::lazy(None, None, SupportedPrinters::Star, 0, 0);
+        assert!(matches!(
+            star.builder().display(DisplayCommand::Clear),
+            Err(Error::Unsupported)
+        ));
+    }
+}
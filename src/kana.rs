@@ -0,0 +1,114 @@
+//! Japanese kana printed as half-width katakana
+//!
+//! Printers without a Kanji ROM can't print hiragana or full-width
+//! katakana, but almost all of them have the JIS X 0201 katakana page with
+//! the half-width forms in its upper half. [to_halfwidth] turns kana into
+//! those forms, a voiced sound mark becoming a character of its own after
+//! the kana, so at least how the text reads gets printed. Kanji stay as
+//! they are and are encoded like any other character the page lacks.
+//!
+//! The conversion is off unless turned on with
+//! [Printer::set_halfwidth_kana](crate::printer::Printer::set_halfwidth_kana).
+//!
+//! ```
+//! use posify::kana;
+//!
+//! // "Coffee", "thank you"
+//! assert_eq!(kana::to_halfwidth("コーヒー"), "ｺｰﾋｰ");
+//! assert_eq!(kana::to_halfwidth("ありがとう"), "ｱﾘｶﾞﾄｳ");
+//! ```
+
+use encoding::types::{EncoderTrap, EncodingRef};
+use std::borrow::Cow;
+
+/// Name [Profile::code_page](crate::profile::Profile::code_page) knows the
+/// katakana page by
+pub const ENCODING: &str = "jis_x0201";
+
+/// Half-width forms of the katakana U+30A1 to U+30FA
+const KATAKANA: [&str; 90] = [
+    "ｧ", "ｱ", "ｨ", "ｲ", "ｩ", "ｳ", "ｪ", "ｴ", "ｫ", "ｵ", //
+    "ｶ", "ｶﾞ", "ｷ", "ｷﾞ", "ｸ", "ｸﾞ", "ｹ", "ｹﾞ", "ｺ", "ｺﾞ", //
+    "ｻ", "ｻﾞ", "ｼ", "ｼﾞ", "ｽ", "ｽﾞ", "ｾ", "ｾﾞ", "ｿ", "ｿﾞ", //
+    "ﾀ", "ﾀﾞ", "ﾁ", "ﾁﾞ", "ｯ", "ﾂ", "ﾂﾞ", "ﾃ", "ﾃﾞ", "ﾄ", //
+    "ﾄﾞ", "ﾅ", "ﾆ", "ﾇ", "ﾈ", "ﾉ", "ﾊ", "ﾊﾞ", "ﾊﾟ", "ﾋ", //
+    "ﾋﾞ", "ﾋﾟ", "ﾌ", "ﾌﾞ", "ﾌﾟ", "ﾍ", "ﾍﾞ", "ﾍﾟ", "ﾎ", "ﾎﾞ", //
+    "ﾎﾟ", "ﾏ", "ﾐ", "ﾑ", "ﾒ", "ﾓ", "ｬ", "ﾔ", "ｭ", "ﾕ", //
+    "ｮ", "ﾖ", "ﾗ", "ﾘ", "ﾙ", "ﾚ", "ﾛ", "ﾜ", "ﾜ", "ｲ", //
+    "ｴ", "ｦ", "ﾝ", "ｳﾞ", "ｶ", "ｹ", "ﾜﾞ", "ｲﾞ", "ｴﾞ", "ｦﾞ",
+];
+
+/// Whether c is one of the half-width forms, U+FF61 to U+FF9F
+pub fn is_halfwidth(c: char) -> bool {
+    matches!(c, '\u{ff61}'..='\u{ff9f}')
+}
+
+/// Replaces hiragana, katakana and Japanese punctuation with their
+/// half-width katakana forms
+///
+/// Small ヮ, ヵ and ヶ lose their size and the archaic ヰ and ヱ become イ
+/// and エ, the half-width set has no forms for them.
+pub fn to_halfwidth(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            // Hiragana are 0x60 below the katakana of the same sound
+            '\u{3041}'..='\u{3096}' => {
+                let katakana = char::from_u32(c as u32 + 0x60).expect("katakana");
+                out.push_str(KATAKANA[katakana as usize - 0x30a1]);
+            }
+            '\u{30a1}'..='\u{30fa}' => out.push_str(KATAKANA[c as usize - 0x30a1]),
+            '。' => out.push('｡'),
+            '「' => out.push('｢'),
+            '」' => out.push('｣'),
+            '、' => out.push('､'),
+            '・' => out.push('･'),
+            'ー' => out.push('ｰ'),
+            '\u{3099}' | '゛' => out.push('ﾞ'),
+            '\u{309a}' | '゜' => out.push('ﾟ'),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Encodes text for the katakana page, half-width forms as the bytes 0xA1
+/// to 0xDF and everything else with codec
+pub fn encode(
+    text: &str,
+    codec: EncodingRef,
+    trap: EncoderTrap,
+    out: &mut Vec<u8>,
+) -> Result<(), Cow<'static, str>> {
+    let mut rest = text;
+    while let Some(start) = rest.find(is_halfwidth) {
+        codec.encode_to(&rest[..start], trap, out)?;
+        let kana = &rest[start..];
+        let end = kana.find(|c| !is_halfwidth(c)).unwrap_or(kana.len());
+        out.extend(
+            kana[..end]
+                .chars()
+                .map(|c| (c as u32 - 0xff61 + 0xa1) as u8),
+        );
+        rest = &kana[end..];
+    }
+    codec.encode_to(rest, trap, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding::all::ASCII;
+
+    #[test]
+    fn converts_kana() {
+        // "Receipt", "total", with a kanji left as it is
+        assert_eq!(to_halfwidth("レシート"), "ﾚｼｰﾄ");
+        assert_eq!(to_halfwidth("ごうけい 合計。"), "ｺﾞｳｹｲ 合計｡");
+        assert_eq!(to_halfwidth("ぱヴァ"), "ﾊﾟｳﾞｧ");
+
+        let mut out = Vec::new();
+        encode("ｺｰﾋｰ 350", ASCII, EncoderTrap::Replace, &mut out).unwrap();
+        assert_eq!(out, [0xba, 0xb0, 0xcb, 0xb0, b' ', b'3', b'5', b'0']);
+    }
+}
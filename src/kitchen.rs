@@ -0,0 +1,302 @@
+//! Kitchen order tickets
+//!
+//! A [KitchenTicket] is what the line cooks read from across the kitchen:
+//! the order number as large as the printer prints it, the table and
+//! server, a [Banner] printed white on black for new, voided and rush
+//! orders, and the items with their modifiers indented below them. There
+//! are no prices. [KitchenTicket::print] can sound the printer's buzzer
+//! after the ticket is out.
+//!
+//! ```
+//! use posify::kitchen::{Banner, KitchenItem, KitchenTicket};
+//!
+//! # fn main() -> Result<(), posify::printer::Error> {
+//! let mut ticket = KitchenTicket::new("42", 48);
+//! ticket
+//!     .banner(Banner::Rush)
+//!     .table("12")
+//!     .server("Ana")
+//!     .item(KitchenItem::new(2, "Burger").modifier("no onions").modifier("well done"))
+//!     .item(KitchenItem::new(1, "Fries"))
+//!     .buzz(2)?;
+//! let job = ticket.job();
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::barcode::Font;
+use crate::document::Alignment;
+use crate::job::{Command, Job};
+use crate::printer::{Error, Printer};
+use crate::profile::Profile;
+
+/// Largest character magnification of GS !
+const MAX_SIZE: u8 = 8;
+
+/// Most times DLE DC4 3 sounds the buzzer
+const MAX_BUZZ: u8 = 63;
+
+/// Shown across the top of the ticket in reverse print
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Banner {
+    New,
+    /// The items are cancelled, the kitchen should stop making them
+    Void,
+    Rush,
+}
+
+impl Banner {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Banner::New => "NEW",
+            Banner::Void => "VOID",
+            Banner::Rush => "RUSH",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KitchenItem {
+    pub quantity: u32,
+    pub name: String,
+    /// Printed indented below the item, e.g. "no onions"
+    pub modifiers: Vec<String>,
+}
+
+impl KitchenItem {
+    pub fn new(quantity: u32, name: &str) -> KitchenItem {
+        KitchenItem {
+            quantity,
+            name: name.to_string(),
+            modifiers: Vec::new(),
+        }
+    }
+
+    pub fn modifier(mut self, modifier: &str) -> KitchenItem {
+        self.modifiers.push(modifier.to_string());
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KitchenTicket {
+    /// Characters per line at normal size
+    pub width: usize,
+    pub order: String,
+    pub banner: Option<Banner>,
+    pub table: Option<String>,
+    pub server: Option<String>,
+    /// Free text lines below the table and server, e.g. "Takeaway"
+    pub notes: Vec<String>,
+    pub items: Vec<KitchenItem>,
+    /// Times the buzzer sounds after printing, 0 for none
+    pub buzzer: u8,
+}
+
+impl KitchenTicket {
+    /// Ticket as wide as font A fits on the paper of profile, see
+    /// [Profile::chars_per_line]
+    pub fn for_profile(order: &str, profile: &Profile) -> KitchenTicket {
+        KitchenTicket::new(order, profile.chars_per_line(Font::FontA, 1))
+    }
+
+    pub fn new(order: &str, width: usize) -> KitchenTicket {
+        KitchenTicket {
+            width,
+            order: order.to_string(),
+            banner: None,
+            table: None,
+            server: None,
+            notes: Vec::new(),
+            items: Vec::new(),
+            buzzer: 0,
+        }
+    }
+
+    pub fn banner(&mut self, banner: Banner) -> &mut Self {
+        self.banner = Some(banner);
+        self
+    }
+
+    pub fn table(&mut self, table: &str) -> &mut Self {
+        self.table = Some(table.to_string());
+        self
+    }
+
+    pub fn server(&mut self, server: &str) -> &mut Self {
+        self.server = Some(server.to_string());
+        self
+    }
+
+    pub fn note(&mut self, note: &str) -> &mut Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    pub fn item(&mut self, item: KitchenItem) -> &mut Self {
+        self.items.push(item);
+        self
+    }
+
+    /// Sounds the buzzer times times after printing, at most 63
+    ///
+    /// Returns [Error::InvalidArgument] for more than 63.
+    pub fn buzz(&mut self, times: u8) -> Result<&mut Self, Error> {
+        if times > MAX_BUZZ {
+            return Err(Error::InvalidArgument);
+        }
+        self.buzzer = times;
+        Ok(self)
+    }
+
+    /// Largest magnification the order number fits the line at
+    pub fn order_size(&self) -> u8 {
+        let len = self.order.chars().count().max(1);
+        (self.width / len).clamp(1, MAX_SIZE as usize) as u8
+    }
+
+    /// The ticket without the buzzer, ending with a partial cut
+    pub fn job(&self) -> Job {
+        let mut job = Job::new();
+        job.push(Command::Init);
+
+        if let Some(banner) = self.banner {
+            // Padded so the black bar runs across the paper
+            let label = format!("{:^width$}", banner.label(), width = self.width / 2);
+            job.push(Command::Align(Alignment::Center))
+                .push(Command::Bold(true))
+                .push(Command::Reverse(true))
+                .push(Command::Size {
+                    width: 2,
+                    height: 2,
+                })
+                .push(Command::Text(label))
+                .push(Command::NewLine)
+                .push(Command::Reverse(false))
+                .push(Command::Bold(false));
+        }
+
+        let size = self.order_size();
+        job.push(Command::Align(Alignment::Center))
+            .push(Command::Size {
+                width: size,
+                height: size,
+            })
+            .push(Command::Text(self.order.clone()))
+            .push(Command::NewLine)
+            .push(Command::Size {
+                width: 1,
+                height: 1,
+            });
+
+        let mut info = Vec::new();
+        if let Some(table) = &self.table {
+            info.push(format!("Table {}", table));
+        }
+        if let Some(server) = &self.server {
+            info.push(format!("Server {}", server));
+        }
+        job.push(Command::Align(Alignment::Left));
+        if !info.is_empty() {
+            job.push(Command::Bold(true))
+                .push(Command::Text(info.join("  ")))
+                .push(Command::NewLine)
+                .push(Command::Bold(false));
+        }
+        for note in &self.notes {
+            job.push(Command::Text(note.clone())).push(Command::NewLine);
+        }
+        job.push(Command::Text("-".repeat(self.width)))
+            .push(Command::NewLine);
+
+        // Double height reads better from a distance and keeps the full
+        // line width for long names
+        for item in &self.items {
+            job.push(Command::Size {
+                width: 1,
+                height: 2,
+            })
+            .push(Command::Bold(true))
+            .push(Command::Text(format!("{} x {}", item.quantity, item.name)))
+            .push(Command::NewLine)
+            .push(Command::Bold(false));
+            for modifier in &item.modifiers {
+                job.push(Command::Text(format!("    {}", modifier)))
+                    .push(Command::NewLine);
+            }
+            job.push(Command::Size {
+                width: 1,
+                height: 1,
+            });
+        }
+
+        job.push(Command::Feed(3))
+            .push(Command::Cut { partial: true });
+        job
+    }
+
+    /// Prints the ticket and sounds the buzzer, see
+    /// [Printer::real_time_buzzer]
+    pub fn print(&self, printer: &mut Printer) -> Result<usize, Error> {
+        // Checked before the ticket prints, buzzer can be set directly
+        if self.buzzer > MAX_BUZZ {
+            return Err(Error::InvalidArgument);
+        }
+        let mut n = printer.submit(&self.job())?;
+        if self.buzzer > 0 {
+            // Pattern 1, 300 ms on and 200 ms off
+            n += printer.real_time_buzzer(1, self.buzzer, 3, 2)?;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::SupportedPrinters;
+    use crate::testing;
+
+    #[test]
+    fn builds_ticket() {
+        let mut ticket = KitchenTicket::new("A-7", 48);
+        ticket
+            .banner(Banner::Void)
+            .table("5")
+            .item(KitchenItem::new(1, "Soup").modifier("no croutons"));
+        assert_eq!(ticket.order_size(), 8);
+        assert_eq!(KitchenTicket::new("1234567", 48).order_size(), 6);
+
+        let job = ticket.job();
+        let reverse = job
+            .commands
+            .iter()
+            .position(|c| *c == Command::Reverse(true))
+            .unwrap();
+        assert_eq!(
+            job.commands[reverse + 2],
+            Command::Text(format!("{:^24}", "VOID"))
+        );
+        assert!(job
+            .commands
+            .contains(&Command::Text("    no croutons".to_string())));
+        assert!(job.commands.contains(&Command::Text("Table 5".to_string())));
+        assert_eq!(job.commands.last(), Some(&Command::Cut { partial: true }));
+    }
+
+    #[test]
+    fn rejects_long_buzzes() {
+        let mut ticket = KitchenTicket::new("42", 48);
+        assert_eq!(ticket.buzz(63).unwrap().buzzer, 63);
+        assert!(matches!(ticket.buzz(64), Err(Error::InvalidArgument)));
+        assert_eq!(ticket.buzzer, 63);
+
+        // Nothing prints
+        ticket.buzzer = 64;
+        let bytes = testing::capture(SupportedPrinters::SNBC, |p| ticket.print(p));
+        assert!(matches!(bytes, Err(Error::InvalidArgument)));
+    }
+}
@@ -1,7 +1,41 @@
 //! posify - A ESC/POS driver for Rust
 
+pub mod archive;
 pub mod barcode;
+pub mod card_slip;
 pub mod consts;
+pub mod coupon;
 pub mod device;
+pub mod discovery;
+pub mod display;
+pub mod document;
+#[cfg(feature = "emulator")]
+pub mod emulator;
+pub mod format;
+pub mod glyph;
+pub mod group;
+pub mod history;
 pub mod img;
+pub mod job;
+pub mod kana;
+pub mod kitchen;
+pub mod locale;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod micr;
+pub mod parser;
+pub mod pdf;
+pub mod preview;
 pub mod printer;
+pub mod profile;
+pub mod queue;
+pub mod receipt;
+pub mod report;
+pub mod ruled_lines;
+pub mod signature;
+pub mod spool;
+pub mod testing;
+pub mod thai;
+pub mod ticket;
+pub mod tspl;
+pub mod zpl;
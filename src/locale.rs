@@ -0,0 +1,528 @@
+//! Receipt text in the language and formats of a country
+//!
+//! A [Locale] is a table of strings by key plus the money and date formats
+//! of one country. The [Receipt](crate::receipt::Receipt) looks its labels
+//! up by key, so one receipt builder prints German receipts in Germany and
+//! French ones in France. Keys a locale doesn't have fall back to the
+//! English text, and [Locales] falls back from `de-AT` to `de`.
+//!
+//! Strings may contain `{name}` placeholders, filled by [Locale::text].
+//! [format_datetime] prints dates with the locale's month and weekday
+//! names, abbreviated until they fit the line.
+//!
+//! ```
+//! use posify::locale::{DateTime, Locale, Locales};
+//!
+//! let mut austria = Locale::de_de();
+//! austria.name = "de-AT".to_string();
+//! austria.set("total", "SUMME");
+//! let mut locales = Locales::default();
+//! locales.insert(Locale::de_de()).insert(austria);
+//!
+//! let locale = locales.get("de-AT-x-vienna").unwrap();
+//! assert_eq!(locale.get("total"), "SUMME");
+//! assert_eq!(locale.get("subtotal"), "Zwischensumme");
+//! let date = DateTime::new(2026, 10, 15, 9, 5, 0);
+//! assert_eq!(locale.date.format(&date), "15.10.2026 09:05");
+//! ```
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::format::MoneyFormat;
+
+/// English text of the keys the library uses
+///
+/// | Key             | Placeholders                      |
+/// |-----------------|-----------------------------------|
+/// | `subtotal`      |                                   |
+/// | `total`         |                                   |
+/// | `discount`      |                                   |
+/// | `rounding`      |                                   |
+/// | `tax`           | `group`, `rate`                   |
+/// | `tax_included`  | `group`, `rate`                   |
+/// | `date_long`     | `weekday`, `day`, `month`, `year` |
+/// | `date_medium`   | `day`, `month`, `year`            |
+/// | `months`        | comma separated                   |
+/// | `months_short`  | comma separated                   |
+/// | `weekdays`      | comma separated, Monday first     |
+/// | `valid_until`   | `date`                            |
+/// | `amount`        |                                   |
+/// | `tip`           |                                   |
+/// | `signature`     |                                   |
+/// | `merchant_copy` |                                   |
+/// | `customer_copy` |                                   |
+/// | `auth`          | `code`                            |
+pub const ENGLISH: &[(&str, &str)] = &[
+    ("subtotal", "Subtotal"),
+    ("total", "TOTAL"),
+    ("discount", "Discount"),
+    ("rounding", "Rounding"),
+    ("tax", "Tax {group} {rate}"),
+    ("tax_included", "incl. tax {group} {rate}"),
+    ("date_long", "{weekday}, {month} {day}, {year}"),
+    ("date_medium", "{month} {day}, {year}"),
+    (
+        "months",
+        "January,February,March,April,May,June,July,August,September,October,November,December",
+    ),
+    (
+        "months_short",
+        "Jan,Feb,Mar,Apr,May,Jun,Jul,Aug,Sep,Oct,Nov,Dec",
+    ),
+    (
+        "weekdays",
+        "Monday,Tuesday,Wednesday,Thursday,Friday,Saturday,Sunday",
+    ),
+    ("valid_until", "Valid until {date}"),
+    ("amount", "Amount"),
+    ("tip", "Tip"),
+    ("signature", "Signature"),
+    ("merchant_copy", "MERCHANT COPY"),
+    ("customer_copy", "CUSTOMER COPY"),
+    ("auth", "Auth {code}"),
+];
+
+/// Order of the day, month and year in dates
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DateOrder {
+    /// `15.10.2026`
+    DayMonthYear,
+    /// `10/15/2026`
+    MonthDayYear,
+    /// `2026-10-15`
+    YearMonthDay,
+}
+
+/// Numeric date and time of a locale
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DateFormat {
+    pub order: DateOrder,
+    pub separator: char,
+    /// 14:05 instead of 2:05 PM
+    pub hour24: bool,
+}
+
+impl Default for DateFormat {
+    fn default() -> Self {
+        DateFormat {
+            order: DateOrder::MonthDayYear,
+            separator: '/',
+            hour24: false,
+        }
+    }
+}
+
+impl DateFormat {
+    pub fn format_date(&self, dt: &DateTime) -> String {
+        let (y, m, d) = (dt.year, dt.month, dt.day);
+        let s = self.separator;
+        match self.order {
+            DateOrder::DayMonthYear => format!("{:02}{s}{:02}{s}{}", d, m, y),
+            DateOrder::MonthDayYear => format!("{:02}{s}{:02}{s}{}", m, d, y),
+            DateOrder::YearMonthDay => format!("{}{s}{:02}{s}{:02}", y, m, d),
+        }
+    }
+
+    pub fn format_time(&self, dt: &DateTime) -> String {
+        if self.hour24 {
+            return format!("{:02}:{:02}", dt.hour, dt.minute);
+        }
+        let hour = match dt.hour % 12 {
+            0 => 12,
+            hour => hour,
+        };
+        let suffix = if dt.hour < 12 { "AM" } else { "PM" };
+        format!("{}:{:02} {}", hour, dt.minute, suffix)
+    }
+
+    /// Date and time separated by a space
+    pub fn format(&self, dt: &DateTime) -> String {
+        format!("{} {}", self.format_date(dt), self.format_time(dt))
+    }
+
+    /// [DateFormat::format_date] with the year in two digits, `15.10.26`
+    fn format_date_short(&self, dt: &DateTime) -> String {
+        let (y, m, d) = (dt.year.rem_euclid(100), dt.month, dt.day);
+        let s = self.separator;
+        match self.order {
+            DateOrder::DayMonthYear => format!("{:02}{s}{:02}{s}{:02}", d, m, y),
+            DateOrder::MonthDayYear => format!("{:02}{s}{:02}{s}{:02}", m, d, y),
+            DateOrder::YearMonthDay => format!("{:02}{s}{:02}{s}{:02}", y, m, d),
+        }
+    }
+}
+
+/// How much of a date [format_datetime] spells out
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DateStyle {
+    /// `Thursday, October 15, 2026 9:05 AM`
+    Long,
+    /// `Oct 15, 2026 9:05 AM`
+    Medium,
+    /// `10/15/2026 9:05 AM`
+    #[default]
+    Short,
+}
+
+/// dt in style, shortened until it fits width characters
+///
+/// Each step drops a little: the weekday, then the full month name, then
+/// the month name altogether, the century and finally the time. Text still
+/// too long for width is cut off, so the result always fits one line.
+///
+/// ```
+/// use posify::locale::{format_datetime, DateStyle, DateTime, Locale};
+///
+/// let dt = DateTime::new(2026, 10, 15, 9, 5, 0);
+/// let locale = Locale::de_de();
+/// assert_eq!(
+///     format_datetime(&dt, DateStyle::Long, &locale, 48),
+///     "Donnerstag, 15. Oktober 2026 09:05"
+/// );
+/// assert_eq!(
+///     format_datetime(&dt, DateStyle::Long, &locale, 20),
+///     "15. Okt. 2026 09:05"
+/// );
+/// assert_eq!(format_datetime(&dt, DateStyle::Short, &locale, 14), "15.10.26 09:05");
+/// ```
+pub fn format_datetime(dt: &DateTime, style: DateStyle, locale: &Locale, width: usize) -> String {
+    let nth = |key, n: usize| {
+        locale
+            .get(key)
+            .split(',')
+            .nth(n)
+            .unwrap_or("")
+            .trim()
+            .to_string()
+    };
+    let month = nth("months", dt.month.saturating_sub(1) as usize);
+    let short_month = nth("months_short", dt.month.saturating_sub(1) as usize);
+    let weekday = nth("weekdays", dt.weekday() as usize);
+    let (day, year) = (dt.day.to_string(), dt.year.to_string());
+    let spelled = |key, month: &str| {
+        locale.text(
+            key,
+            &[
+                ("weekday", &weekday),
+                ("day", &day),
+                ("month", month),
+                ("year", &year),
+            ],
+        )
+    };
+    let time = locale.date.format_time(dt);
+    let mut candidates = Vec::new();
+    if style == DateStyle::Long {
+        candidates.push(spelled("date_long", &month));
+        candidates.push(spelled("date_medium", &month));
+    }
+    if style != DateStyle::Short {
+        candidates.push(spelled("date_medium", &short_month));
+    }
+    candidates.push(locale.date.format_date(dt));
+    candidates.push(locale.date.format_date_short(dt));
+    let mut candidates: Vec<String> = candidates
+        .into_iter()
+        .map(|date| format!("{} {}", date, time))
+        .collect();
+    candidates.push(locale.date.format_date_short(dt));
+    for candidate in &candidates {
+        if candidate.chars().count() <= width {
+            return candidate.clone();
+        }
+    }
+    candidates
+        .last()
+        .map(|date| date.chars().take(width).collect())
+        .unwrap_or_default()
+}
+
+/// Calendar date and wall clock time, without a time zone
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DateTime {
+    pub year: i32,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    pub fn new(year: i32, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    /// Day of the week, 0 for Monday to 6 for Sunday
+    pub fn weekday(&self) -> u8 {
+        // Howard Hinnant's days_from_civil, 1970-01-01 was a Thursday
+        let year = self.year as i64 - (self.month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let month = self.month as i64;
+        let doy =
+            (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+        (days + 3).rem_euclid(7) as u8
+    }
+
+    /// The local time of time offset_minutes east of UTC
+    pub fn from_system_time(time: SystemTime, offset_minutes: i32) -> DateTime {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        } + offset_minutes as i64 * 60;
+        let (days, secs) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+        // Howard Hinnant's civil_from_days, with eras of 400 years
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = (yoe + era * 400 + (month <= 2) as i64) as i32;
+        DateTime::new(
+            year,
+            month,
+            day,
+            (secs / 3600) as u8,
+            (secs / 60 % 60) as u8,
+            (secs % 60) as u8,
+        )
+    }
+}
+
+/// Strings and formats of one locale, see the [module](self) documentation
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Locale {
+    /// BCP 47 tag, `de-DE`
+    pub name: String,
+    pub money: MoneyFormat,
+    pub date: DateFormat,
+    strings: BTreeMap<String, String>,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::en_us()
+    }
+}
+
+impl Locale {
+    /// Locale with no strings of its own, every key is printed in English
+    pub fn new(name: &str, money: MoneyFormat, date: DateFormat) -> Locale {
+        Locale {
+            name: name.to_string(),
+            money,
+            date,
+            strings: BTreeMap::new(),
+        }
+    }
+
+    pub fn en_us() -> Locale {
+        Locale::new("en-US", MoneyFormat::en_us(), DateFormat::default())
+    }
+
+    pub fn en_gb() -> Locale {
+        let date = DateFormat {
+            order: DateOrder::DayMonthYear,
+            separator: '/',
+            hour24: true,
+        };
+        let mut locale = Locale::new("en-GB", MoneyFormat::en_gb(), date);
+        locale
+            .set("date_long", "{weekday} {day} {month} {year}")
+            .set("date_medium", "{day} {month} {year}");
+        locale
+    }
+
+    pub fn de_de() -> Locale {
+        let date = DateFormat {
+            order: DateOrder::DayMonthYear,
+            separator: '.',
+            hour24: true,
+        };
+        let mut locale = Locale::new("de-DE", MoneyFormat::de_de(), date);
+        locale
+            .set("subtotal", "Zwischensumme")
+            .set("total", "SUMME")
+            .set("discount", "Rabatt")
+            .set("rounding", "Rundung")
+            .set("tax", "MwSt. {group} {rate}")
+            .set("tax_included", "enth. MwSt. {group} {rate}")
+            .set("date_long", "{weekday}, {day}. {month} {year}")
+            .set("date_medium", "{day}. {month} {year}")
+            .set(
+                "months",
+                "Januar,Februar,März,April,Mai,Juni,Juli,August,September,Oktober,November,Dezember",
+            )
+            .set(
+                "months_short",
+                "Jan.,Feb.,März,Apr.,Mai,Juni,Juli,Aug.,Sep.,Okt.,Nov.,Dez.",
+            )
+            .set(
+                "weekdays",
+                "Montag,Dienstag,Mittwoch,Donnerstag,Freitag,Samstag,Sonntag",
+            )
+            .set("valid_until", "Gültig bis {date}")
+            .set("amount", "Betrag")
+            .set("tip", "Trinkgeld")
+            .set("signature", "Unterschrift")
+            .set("merchant_copy", "HÄNDLERBELEG")
+            .set("customer_copy", "KUNDENBELEG")
+            .set("auth", "Genehmigung {code}");
+        locale
+    }
+
+    pub fn fr_fr() -> Locale {
+        let date = DateFormat {
+            order: DateOrder::DayMonthYear,
+            separator: '/',
+            hour24: true,
+        };
+        let mut locale = Locale::new("fr-FR", MoneyFormat::fr_fr(), date);
+        locale
+            .set("subtotal", "Sous-total")
+            .set("total", "TOTAL")
+            .set("discount", "Remise")
+            .set("rounding", "Arrondi")
+            .set("tax", "TVA {group} {rate}")
+            .set("tax_included", "dont TVA {group} {rate}")
+            .set("date_long", "{weekday} {day} {month} {year}")
+            .set("date_medium", "{day} {month} {year}")
+            .set(
+                "months",
+                "janvier,février,mars,avril,mai,juin,juillet,août,septembre,octobre,novembre,décembre",
+            )
+            .set(
+                "months_short",
+                "janv.,févr.,mars,avr.,mai,juin,juil.,août,sept.,oct.,nov.,déc.",
+            )
+            .set(
+                "weekdays",
+                "lundi,mardi,mercredi,jeudi,vendredi,samedi,dimanche",
+            )
+            .set("valid_until", "Valable jusqu'au {date}")
+            .set("amount", "Montant")
+            .set("tip", "Pourboire")
+            .set("signature", "Signature")
+            .set("merchant_copy", "TICKET COMMERÇANT")
+            .set("customer_copy", "TICKET CLIENT")
+            .set("auth", "Autorisation {code}");
+        locale
+    }
+
+    /// Sets the text of key
+    pub fn set(&mut self, key: &str, text: &str) -> &mut Self {
+        self.strings.insert(key.to_string(), text.to_string());
+        self
+    }
+
+    /// The text of key, the [ENGLISH] text when the locale doesn't have it
+    /// and the key itself for unknown keys
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        match self.strings.get(key) {
+            Some(text) => text,
+            None => ENGLISH
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map_or(key, |(_, text)| text),
+        }
+    }
+
+    /// The text of key with its `{name}` placeholders replaced by args,
+    /// unknown placeholders are left as they are
+    pub fn text(&self, key: &str, args: &[(&str, &str)]) -> String {
+        let mut text = self.get(key).to_string();
+        for (name, value) in args {
+            text = text.replace(&format!("{{{}}}", name), value);
+        }
+        text
+    }
+}
+
+/// Locales by name, see [Locales::get]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Locales {
+    locales: BTreeMap<String, Locale>,
+}
+
+impl Locales {
+    /// Adds locale under its name, replacing one with the same name
+    pub fn insert(&mut self, locale: Locale) -> &mut Self {
+        self.locales
+            .insert(locale.name.to_ascii_lowercase(), locale);
+        self
+    }
+
+    /// The locale named tag, or the one of its closest parent: `de-AT`
+    /// then `de`, ignoring case
+    pub fn get(&self, tag: &str) -> Option<&Locale> {
+        let mut tag = tag.to_ascii_lowercase();
+        loop {
+            if let Some(locale) = self.locales.get(&tag) {
+                return Some(locale);
+            }
+            tag.truncate(tag.rfind('-')?);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_strings_and_dates() {
+        let locale = Locale::fr_fr();
+        assert_eq!(
+            locale.text("tax", &[("group", "A"), ("rate", "20%")]),
+            "TVA A 20%"
+        );
+        let mut spanish = Locale::new("es", MoneyFormat::de_de(), DateFormat::default());
+        spanish.set("total", "TOTAL {currency}");
+        assert_eq!(spanish.get("subtotal"), "Subtotal");
+        assert_eq!(spanish.get("missing"), "missing");
+        assert_eq!(spanish.text("total", &[]), "TOTAL {currency}");
+
+        let mut locales = Locales::default();
+        locales.insert(spanish).insert(Locale::de_de());
+        assert_eq!(locales.get("ES-mx").unwrap().name, "es");
+        assert!(locales.get("de").is_none());
+        assert!(locales.get("it-IT").is_none());
+
+        // 2000-02-29 23:59:59 UTC
+        let leap = UNIX_EPOCH + std::time::Duration::from_secs(951_868_799);
+        assert_eq!(
+            DateTime::from_system_time(leap, 0),
+            DateTime::new(2000, 2, 29, 23, 59, 59)
+        );
+        let dt = DateTime::from_system_time(leap, 60);
+        assert_eq!(dt, DateTime::new(2000, 3, 1, 0, 59, 59));
+        assert_eq!(Locale::en_us().date.format(&dt), "03/01/2000 12:59 AM");
+        assert_eq!(Locale::en_gb().date.format(&dt), "01/03/2000 00:59");
+    }
+}
@@ -0,0 +1,166 @@
+//! Throughput and latency measurements, see
+//! [Printer::set_metrics](crate::printer::Printer::set_metrics)
+//!
+//! The printer reports an [Event] for every USB transfer, status query,
+//! command and job to a [Metrics] callback, which can forward them to any
+//! monitoring system. [Counters] keeps running totals.
+//!
+//! Needs the `metrics` feature.
+//!
+//! ```no_run
+//! use posify::metrics::{Counters, Event, Metrics};
+//! use posify::printer::{Printer, SupportedPrinters};
+//! use std::sync::{Arc, Mutex};
+//!
+//! let mut printer = Printer::new(None, None, SupportedPrinters::SNBC, 0x0483, 0x5743)?;
+//! let counters = Arc::new(Mutex::new(Counters::default()));
+//! let shared = counters.clone();
+//! printer.set_metrics(move |event: &Event| shared.lock().unwrap().record(event));
+//!
+//! printer.println("Hello")?;
+//! println!("{} bytes sent", counters.lock().unwrap().bytes_sent);
+//! # Ok::<(), posify::printer::Error>(())
+//! ```
+
+use std::time::Duration;
+
+/// Something the printer did
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event<'a> {
+    /// Bytes were written to the device in one USB transfer
+    Transfer { bytes: usize, duration: Duration },
+    /// The printer answered a status query after latency
+    StatusQuery { latency: Duration },
+    /// A command finished, commands run by other commands are part of them
+    Command {
+        name: &'a str,
+        bytes: usize,
+        duration: Duration,
+        ok: bool,
+    },
+    /// A [Job](crate::job::Job) was printed
+    Job {
+        commands: usize,
+        bytes: usize,
+        duration: Duration,
+        ok: bool,
+    },
+}
+
+/// Receives the printer's events, closures taking an [Event] implement it
+pub trait Metrics: Send {
+    fn record(&mut self, event: &Event);
+}
+
+impl<F: FnMut(&Event) + Send> Metrics for F {
+    fn record(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Running totals of the events recorded
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Counters {
+    pub bytes_sent: usize,
+    pub transfers: usize,
+    /// Time spent in USB transfers
+    pub transfer_time: Duration,
+    pub status_queries: usize,
+    pub status_latency: Duration,
+    pub commands: usize,
+    pub failed_commands: usize,
+    pub jobs: usize,
+    /// Commands in all the jobs
+    pub job_commands: usize,
+}
+
+impl Counters {
+    /// Average bytes per second while transferring
+    pub fn throughput(&self) -> f64 {
+        match self.transfer_time.as_secs_f64() {
+            secs if secs > 0.0 => self.bytes_sent as f64 / secs,
+            _ => 0.0,
+        }
+    }
+
+    /// Average time the printer took to answer a status query
+    pub fn average_status_latency(&self) -> Option<Duration> {
+        (self.status_queries > 0).then(|| self.status_latency / self.status_queries as u32)
+    }
+
+    pub fn commands_per_job(&self) -> f64 {
+        match self.jobs {
+            0 => 0.0,
+            jobs => self.job_commands as f64 / jobs as f64,
+        }
+    }
+}
+
+impl Metrics for Counters {
+    fn record(&mut self, event: &Event) {
+        match *event {
+            Event::Transfer { bytes, duration } => {
+                self.bytes_sent += bytes;
+                self.transfers += 1;
+                self.transfer_time += duration;
+            }
+            Event::StatusQuery { latency } => {
+                self.status_queries += 1;
+                self.status_latency += latency;
+            }
+            Event::Command { ok, .. } => {
+                self.commands += 1;
+                if !ok {
+                    self.failed_commands += 1;
+                }
+            }
+            Event::Job { commands, .. } => {
+                self.jobs += 1;
+                self.job_commands += commands;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_events() {
+        let mut counters = Counters::default();
+        let ms = Duration::from_millis;
+        counters.record(&Event::Transfer {
+            bytes: 100,
+            duration: ms(50),
+        });
+        counters.record(&Event::Transfer {
+            bytes: 100,
+            duration: ms(50),
+        });
+        counters.record(&Event::StatusQuery { latency: ms(4) });
+        counters.record(&Event::StatusQuery { latency: ms(8) });
+        counters.record(&Event::Command {
+            name: "print",
+            bytes: 200,
+            duration: ms(100),
+            ok: false,
+        });
+        counters.record(&Event::Job {
+            commands: 6,
+            bytes: 200,
+            duration: ms(100),
+            ok: true,
+        });
+
+        assert_eq!(counters.throughput(), 2000.0);
+        assert_eq!(counters.average_status_latency(), Some(ms(6)));
+        assert_eq!(counters.failed_commands, 1);
+        assert_eq!(counters.commands_per_job(), 6.0);
+    }
+}
@@ -0,0 +1,174 @@
+//! Parsing of the MICR line read from cheques, see
+//! [Printer::read_micr](crate::printer::Printer::read_micr)
+//!
+//! Printers transmit the MICR special symbols as letters:
+//!
+//! | E-13B   | Symbol  | CMC-7 | Symbol |
+//! |---------|---------|-------|--------|
+//! | A       | Transit | A     | S1     |
+//! | B       | Amount  | B     | S2     |
+//! | C       | On-us   | C     | S3     |
+//! | D       | Dash    | D     | S4     |
+//! |         |         | E     | S5     |
+
+use std::fmt;
+
+const E13B_TRANSIT: char = 'A';
+const E13B_ON_US: char = 'C';
+const E13B_DASH: char = 'D';
+
+/// MICR font printed on the cheque
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MicrFont {
+    /// Used in North America, the UK and Australia
+    E13B,
+    /// Used in France, Spain and Latin America
+    CMC7,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum MicrError {
+    #[error("No routing number in MICR line")]
+    NoRouting,
+    #[error("Unreadable character in MICR line")]
+    Unreadable,
+}
+
+/// Fields of a cheque's MICR line
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MicrData {
+    pub routing: String,
+    pub account: String,
+    /// Empty when the cheque doesn't carry a serial number in the MICR line
+    pub check_number: String,
+    /// The line as transmitted by the printer
+    pub raw: String,
+}
+
+impl fmt::Display for MicrData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "routing {} account {} check {}",
+            self.routing, self.account, self.check_number
+        )
+    }
+}
+
+impl MicrData {
+    pub fn parse(line: &str, font: MicrFont) -> Result<MicrData, MicrError> {
+        // Printers send '?' for characters they couldn't recognise
+        if line.contains('?') {
+            return Err(MicrError::Unreadable);
+        }
+        match font {
+            MicrFont::E13B => MicrData::parse_e13b(line),
+            MicrFont::CMC7 => MicrData::parse_cmc7(line),
+        }
+    }
+
+    /// Parses the US layout: `[C check C] A routing A account C [check]`
+    ///
+    /// Business cheques carry the cheque number in the auxiliary on-us field
+    /// before the routing number, personal cheques after the account number.
+    fn parse_e13b(line: &str) -> Result<MicrData, MicrError> {
+        let start = line.find(E13B_TRANSIT).ok_or(MicrError::NoRouting)?;
+        let rest = &line[start + 1..];
+        let end = rest.find(E13B_TRANSIT).ok_or(MicrError::NoRouting)?;
+        let routing = digits(&rest[..end]);
+        if routing.is_empty() {
+            return Err(MicrError::NoRouting);
+        }
+
+        let on_us = &rest[end + 1..];
+        let (account, after) = match on_us.find(E13B_ON_US) {
+            Some(i) => (&on_us[..i], &on_us[i + 1..]),
+            None => (on_us, ""),
+        };
+        let mut check_number = digits(after);
+        if check_number.is_empty() {
+            // Auxiliary on-us field
+            check_number = digits(&line[..start]);
+        }
+
+        Ok(MicrData {
+            routing,
+            account: account
+                .chars()
+                .filter(|c| c.is_ascii_digit() || *c == E13B_DASH)
+                .map(|c| if c == E13B_DASH { '-' } else { c })
+                .collect(),
+            check_number,
+            raw: line.to_string(),
+        })
+    }
+
+    /// Parses the French layout, digit groups in order: cheque number,
+    /// routing (bank and branch) and account
+    fn parse_cmc7(line: &str) -> Result<MicrData, MicrError> {
+        let fields: Vec<String> = line
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|f| !f.is_empty())
+            .map(|f| f.to_string())
+            .collect();
+        if fields.len() < 2 {
+            return Err(MicrError::NoRouting);
+        }
+        Ok(MicrData {
+            check_number: fields[0].clone(),
+            routing: fields[1].clone(),
+            account: fields.get(2).cloned().unwrap_or_default(),
+            raw: line.to_string(),
+        })
+    }
+}
+
+fn digits(field: &str) -> String {
+    field.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn e13b_personal_cheque() {
+        let micr = MicrData::parse("A021000021A 123456789C 0101", MicrFont::E13B).unwrap();
+        assert_eq!(micr.routing, "021000021");
+        assert_eq!(micr.account, "123456789");
+        assert_eq!(micr.check_number, "0101");
+    }
+
+    #[test]
+    fn e13b_business_cheque() {
+        let line = "C000512C A021000021A 12D3456C";
+        let micr = MicrData::parse(line, MicrFont::E13B).unwrap();
+        assert_eq!(micr.routing, "021000021");
+        assert_eq!(micr.account, "12-3456");
+        assert_eq!(micr.check_number, "000512");
+        assert_eq!(micr.raw, line);
+    }
+
+    #[test]
+    fn e13b_errors() {
+        assert_eq!(
+            MicrData::parse("123456789C", MicrFont::E13B),
+            Err(MicrError::NoRouting)
+        );
+        assert_eq!(
+            MicrData::parse("A0210?0021A 123C", MicrFont::E13B),
+            Err(MicrError::Unreadable)
+        );
+    }
+
+    #[test]
+    fn cmc7_cheque() {
+        let micr =
+            MicrData::parse("1234567E 0123456789012D 987654321098A", MicrFont::CMC7).unwrap();
+        assert_eq!(micr.check_number, "1234567");
+        assert_eq!(micr.routing, "0123456789012");
+        assert_eq!(micr.account, "987654321098");
+    }
+}
@@ -0,0 +1,540 @@
+//! Decodes an ESC/POS byte stream back into [Command]s
+//!
+//! Useful for golden-file tests, for finding out what a third party job
+//! does, and to [preview](crate::preview) captured jobs. Sequences that are
+//! understood but have no [Command] (code page, line spacing, status
+//! requests...) are returned as [Token::Other], anything the parser doesn't
+//! know as [Token::Unknown].
+//!
+//! Text is decoded as UTF-8. Star Line Mode streams aren't supported, ESC d
+//! alone means something else there.
+//!
+//! ```
+//! use posify::job::Command;
+//! use posify::parser::{self, Token};
+//!
+//! let tokens = parser::parse(b"\x1b\x40Hi\x0a\x1b\x99");
+//! assert_eq!(tokens[0], Token::Command(Command::Init));
+//! assert_eq!(tokens[1], Token::Command(Command::Text("Hi".to_string())));
+//! assert_eq!(tokens[2], Token::Command(Command::NewLine));
+//! assert!(matches!(tokens[3], Token::Unknown { offset: 5, .. }));
+//! ```
+
+use std::ops::Range;
+
+use crate::barcode::BarcodeType;
+use crate::document::Alignment;
+use crate::job::{Command, Job};
+
+/// GS h default
+const DEFAULT_BARCODE_HEIGHT: u8 = 162;
+/// GS ( k default module size
+const DEFAULT_QR_SIZE: u8 = 3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Command(Command),
+    /// A known sequence without a [Command], name is the mnemonic (`ESC t`)
+    Other {
+        name: &'static str,
+        bytes: Vec<u8>,
+    },
+    /// Bytes the parser doesn't know, starting at offset. For an unknown
+    /// ESC or GS command only the first two bytes are skipped since the
+    /// length of the parameters isn't known.
+    Unknown {
+        offset: usize,
+        bytes: Vec<u8>,
+    },
+}
+
+/// Splits data into tokens
+pub fn parse(data: &[u8]) -> Vec<Token> {
+    parse_spans(data)
+        .into_iter()
+        .map(|(_, token)| token)
+        .collect()
+}
+
+/// Splits data into tokens with the bytes each was decoded from
+///
+/// Sequences that only set something up for a later command, like storing
+/// QR code data, belong to the token of that command. Trailing ones that
+/// no token follows aren't in any span.
+pub fn parse_spans(data: &[u8]) -> Vec<(Range<usize>, Token)> {
+    let mut parser = Parser {
+        data,
+        pos: 0,
+        barcode_height: DEFAULT_BARCODE_HEIGHT,
+        qr_size: DEFAULT_QR_SIZE,
+        qr_data: None,
+        tokens: Vec::new(),
+        spans: Vec::new(),
+    };
+    while parser.pos < data.len() {
+        parser.next();
+    }
+    parser.spans.into_iter().zip(parser.tokens).collect()
+}
+
+/// Decodes data as a [Job], for the [preview](crate::preview) and
+/// [pdf](crate::pdf) backends
+///
+/// Everything that isn't a [Command] is kept as [Command::Raw].
+pub fn to_job(data: &[u8]) -> Job {
+    let commands = parse(data)
+        .into_iter()
+        .map(|token| match token {
+            Token::Command(command) => command,
+            Token::Other { bytes, .. } | Token::Unknown { bytes, .. } => Command::Raw(bytes),
+        })
+        .collect();
+    Job {
+        commands,
+        ..Job::default()
+    }
+}
+
+/// ESC commands with a fixed number of parameter bytes and no [Command]
+const ESC_OTHER: &[(u8, usize, &str)] = &[
+    (0x20, 1, "ESC SP"),
+    (0x21, 1, "ESC !"),
+    (0x24, 2, "ESC $"),
+    (0x25, 1, "ESC %"),
+    (0x32, 0, "ESC 2"),
+    (0x33, 1, "ESC 3"),
+    (0x3c, 0, "ESC <"),
+    (0x3d, 1, "ESC ="),
+    (0x47, 1, "ESC G"),
+    (0x4a, 1, "ESC J"),
+    (0x4c, 0, "ESC L"),
+    (0x4d, 1, "ESC M"),
+    (0x52, 1, "ESC R"),
+    (0x53, 0, "ESC S"),
+    (0x54, 1, "ESC T"),
+    (0x56, 1, "ESC V"),
+    (0x5c, 2, "ESC \\"),
+    (0x63, 2, "ESC c"),
+    (0x65, 1, "ESC e"),
+    (0x69, 0, "ESC i"),
+    (0x70, 3, "ESC p"),
+    (0x74, 1, "ESC t"),
+    (0x7b, 1, "ESC {"),
+];
+
+/// GS commands with a fixed number of parameter bytes and no [Command]
+const GS_OTHER: &[(u8, usize, &str)] = &[
+    (0x2f, 1, "GS /"),
+    (0x3a, 0, "GS :"),
+    (0x48, 1, "GS H"),
+    (0x49, 1, "GS I"),
+    (0x4c, 2, "GS L"),
+    (0x50, 2, "GS P"),
+    (0x57, 2, "GS W"),
+    (0x5a, 1, "GS Z"),
+    (0x5e, 3, "GS ^"),
+    (0x61, 1, "GS a"),
+    (0x62, 1, "GS b"),
+    (0x65, 2, "GS e"),
+    (0x66, 1, "GS f"),
+    (0x72, 1, "GS r"),
+    (0x77, 1, "GS w"),
+];
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// Last GS h
+    barcode_height: u8,
+    /// Last GS ( k module size
+    qr_size: u8,
+    /// Data stored with GS ( k, printed by fn 81
+    qr_data: Option<String>,
+    tokens: Vec<Token>,
+    /// Bytes of each token
+    spans: Vec<Range<usize>>,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) {
+        let count = self.tokens.len();
+        self.token();
+        let start = self.spans.last().map_or(0, |span| span.end);
+        for _ in count..self.tokens.len() {
+            self.spans.push(start..self.pos);
+        }
+    }
+
+    fn token(&mut self) {
+        let start = self.pos;
+        match self.data[start] {
+            0x0a => {
+                self.pos += 1;
+                self.command(Command::NewLine);
+            }
+            0x1b => self.esc(start),
+            0x1d => self.gs(start),
+            0x10 => self.dle(start),
+            0x1c => match self.byte(1) {
+                Some(0x70) => self.other(start, 4, "FS p"),
+                Some(0x71) => self.fs_q(start),
+                _ => self.unknown(start, 2),
+            },
+            0x0c => self.other(start, 1, "FF"),
+            0x0d => self.other(start, 1, "CR"),
+            0x00 => self.other(start, 1, "NUL"),
+            byte if byte < 0x20 || byte == 0x7f => self.unknown(start, 1),
+            _ => {
+                let end = self.data[start..]
+                    .iter()
+                    .position(|b| *b < 0x20 || *b == 0x7f)
+                    .map_or(self.data.len(), |n| start + n);
+                self.pos = end;
+                let text = String::from_utf8_lossy(&self.data[start..end]).into_owned();
+                self.command(Command::Text(text));
+            }
+        }
+    }
+
+    fn esc(&mut self, start: usize) {
+        let Some(cmd) = self.byte(1) else {
+            return self.unknown(start, 1);
+        };
+        let arg = self.byte(2);
+        match (cmd, arg) {
+            (0x40, _) => {
+                self.pos += 2;
+                self.command(Command::Init);
+            }
+            (0x61, Some(n)) => {
+                self.pos += 3;
+                self.command(Command::Align(match n {
+                    1 | 0x31 => Alignment::Center,
+                    2 | 0x32 => Alignment::Right,
+                    _ => Alignment::Left,
+                }));
+            }
+            (0x45, Some(n)) => {
+                self.pos += 3;
+                self.command(Command::Bold(n & 1 == 1));
+            }
+            (0x2d, Some(n)) => {
+                self.pos += 3;
+                self.command(Command::Underline(n & 3 != 0 && n != 0x30));
+            }
+            (0x64, Some(n)) => {
+                self.pos += 3;
+                self.command(Command::Feed(n));
+            }
+            // P3 partial cut
+            (0x6d, _) => {
+                self.pos += 2;
+                self.command(Command::Cut { partial: true });
+            }
+            // ESC Z v l k dL dH d1...dn, the Bixolon style QR code
+            (0x5a, _) => match (self.byte(4), self.u16(5)) {
+                (Some(size), Some(len)) if start + 7 + len <= self.data.len() => {
+                    let data = &self.data[start + 7..start + 7 + len];
+                    self.pos = start + 7 + len;
+                    self.command(Command::QrCode {
+                        data: String::from_utf8_lossy(data).into_owned(),
+                        size,
+                    });
+                }
+                _ => self.truncated(start),
+            },
+            // ESC * m nL nH d1...dk
+            (0x2a, Some(m)) => match self.u16(3) {
+                Some(columns) => {
+                    let per_column = if m == 32 || m == 33 { 3 } else { 1 };
+                    self.other(start, 5 + columns * per_column, "ESC *");
+                }
+                None => self.truncated(start),
+            },
+            _ => match ESC_OTHER.iter().find(|(b, _, _)| *b == cmd) {
+                Some((_, n, name)) => self.other(start, 2 + n, name),
+                None => self.unknown(start, 2),
+            },
+        }
+    }
+
+    fn gs(&mut self, start: usize) {
+        let Some(cmd) = self.byte(1) else {
+            return self.unknown(start, 1);
+        };
+        match cmd {
+            0x21 => match self.byte(2) {
+                Some(n) => {
+                    self.pos += 3;
+                    self.command(Command::Size {
+                        width: (n >> 4) + 1,
+                        height: (n & 0x0f) + 1,
+                    });
+                }
+                None => self.truncated(start),
+            },
+            0x42 => match self.byte(2) {
+                Some(n) => {
+                    self.pos += 3;
+                    self.command(Command::Reverse(n & 1 == 1));
+                }
+                None => self.truncated(start),
+            },
+            0x68 => match self.byte(2) {
+                Some(n) => {
+                    self.barcode_height = n;
+                    self.other(start, 3, "GS h");
+                }
+                None => self.truncated(start),
+            },
+            0x56 => match self.byte(2) {
+                Some(m @ (0 | 1 | 0x30 | 0x31)) => {
+                    self.pos += 3;
+                    self.command(Command::Cut {
+                        partial: m & 1 == 1,
+                    });
+                }
+                Some(m @ (65 | 66)) if self.byte(3).is_some() => {
+                    self.pos += 4;
+                    self.command(Command::Cut { partial: m == 66 });
+                }
+                _ => self.truncated(start),
+            },
+            0x76 => self.raster(start),
+            0x6b => self.barcode(start),
+            0x28 => self.gs_paren(start),
+            // GS * x y d1...d(x * y * 8)
+            0x2a => match (self.byte(2), self.byte(3)) {
+                (Some(x), Some(y)) => self.other(start, 4 + x as usize * y as usize * 8, "GS *"),
+                _ => self.truncated(start),
+            },
+            _ => match GS_OTHER.iter().find(|(b, _, _)| *b == cmd) {
+                Some((_, n, name)) => self.other(start, 2 + n, name),
+                None => self.unknown(start, 2),
+            },
+        }
+    }
+
+    fn dle(&mut self, start: usize) {
+        match (self.byte(1), self.byte(2)) {
+            (Some(0x04), Some(_)) => self.other(start, 3, "DLE EOT"),
+            (Some(0x05), Some(_)) => self.other(start, 3, "DLE ENQ"),
+            // DLE DC4 fn ..., see the real time commands of the Printer
+            (Some(0x14), Some(f)) => {
+                let params = match f {
+                    1 => 2,
+                    2 => 2,
+                    3 => 5,
+                    8 => 7,
+                    _ => return self.unknown(start, 3),
+                };
+                self.other(start, 3 + params, "DLE DC4");
+            }
+            _ => self.unknown(start, 1),
+        }
+    }
+
+    /// GS v 0 m xL xH yL yH d1...dk
+    fn raster(&mut self, start: usize) {
+        match (self.byte(2), self.u16(4), self.u16(6)) {
+            (Some(0x30), Some(width), Some(height))
+                if start + 8 + width * height <= self.data.len() =>
+            {
+                let data = self.data[start + 8..start + 8 + width * height].to_vec();
+                self.pos = start + 8 + width * height;
+                self.command(Command::Raster {
+                    width_bytes: width as u16,
+                    height: height as u16,
+                    data,
+                });
+            }
+            _ => self.truncated(start),
+        }
+    }
+
+    /// GS k m d1...dk NUL (m = 0-6) or GS k m n d1...dn (m = 65-73)
+    fn barcode(&mut self, start: usize) {
+        let Some(m) = self.byte(2) else {
+            return self.truncated(start);
+        };
+        let kind = match m {
+            0 | 65 => BarcodeType::UPCA,
+            1 | 66 => BarcodeType::UPCE,
+            2 | 67 => BarcodeType::EAN13,
+            3 | 68 => BarcodeType::EAN8,
+            4 | 69 => BarcodeType::CODE39,
+            5 | 70 => BarcodeType::ITF,
+            6 | 71 => BarcodeType::Codabar,
+            72 => BarcodeType::Code93,
+            73 => BarcodeType::Code128,
+            _ => return self.unknown(start, 3),
+        };
+        let (data, end) = if m < 65 {
+            match self.data[start + 3..].iter().position(|b| *b == 0) {
+                Some(n) => (&self.data[start + 3..start + 3 + n], start + 4 + n),
+                None => return self.truncated(start),
+            }
+        } else {
+            match self.byte(3) {
+                Some(n) if start + 4 + n as usize <= self.data.len() => (
+                    &self.data[start + 4..start + 4 + n as usize],
+                    start + 4 + n as usize,
+                ),
+                _ => return self.truncated(start),
+            }
+        };
+        let code = match (kind, data) {
+            // Code set C packs two digits in a byte
+            (BarcodeType::Code128, [b'{', b'C', rest @ ..]) => {
+                rest.iter().map(|b| format!("{:02}", b)).collect()
+            }
+            (BarcodeType::Code128, [b'{', _, rest @ ..]) => {
+                String::from_utf8_lossy(rest).into_owned()
+            }
+            _ => String::from_utf8_lossy(data).into_owned(),
+        };
+        self.pos = end;
+        self.command(Command::Barcode {
+            code,
+            kind,
+            height: self.barcode_height,
+        });
+    }
+
+    /// GS ( k pL pH cn fn ..., only QR codes (cn = 49) become a [Command]
+    fn gs_paren(&mut self, start: usize) {
+        let (Some(function), Some(len)) = (self.byte(2), self.u16(3)) else {
+            return self.truncated(start);
+        };
+        let end = start + 5 + len;
+        if end > self.data.len() {
+            return self.truncated(start);
+        }
+        let params = &self.data[start + 5..end];
+        match (function, params) {
+            (0x6b, [49, 67, size]) => self.qr_size = *size,
+            (0x6b, [49, 80, 48, data @ ..]) => {
+                self.qr_data = Some(String::from_utf8_lossy(data).into_owned())
+            }
+            (0x6b, [49, 81, 48]) => {
+                if let Some(data) = self.qr_data.take() {
+                    self.pos = end;
+                    let size = self.qr_size;
+                    return self.command(Command::QrCode { data, size });
+                }
+            }
+            _ => (),
+        }
+        let name = match function {
+            0x6b => "GS ( k",
+            0x41 => "GS ( A",
+            0x45 => "GS ( E",
+            0x4c => "GS ( L",
+            _ => "GS (",
+        };
+        self.other(start, 5 + len, name);
+    }
+
+    /// FS q n [xL xH yL yH d1...dk]1...[xL xH yL yH d1...dk]n
+    fn fs_q(&mut self, start: usize) {
+        let Some(images) = self.byte(2) else {
+            return self.truncated(start);
+        };
+        let mut end = start + 3;
+        for _ in 0..images {
+            let (Some(x), Some(y)) = (self.u16(end - start), self.u16(end - start + 2)) else {
+                return self.truncated(start);
+            };
+            end += 4 + x * y * 8;
+        }
+        self.other(start, end - start, "FS q");
+    }
+
+    /// Byte n after the start of the current sequence
+    fn byte(&self, n: usize) -> Option<u8> {
+        self.data.get(self.pos + n).copied()
+    }
+
+    /// Little endian u16 at n after the start of the current sequence
+    fn u16(&self, n: usize) -> Option<usize> {
+        Some(self.byte(n)? as usize | (self.byte(n + 1)? as usize) << 8)
+    }
+
+    fn command(&mut self, command: Command) {
+        self.tokens.push(Token::Command(command));
+    }
+
+    fn other(&mut self, start: usize, len: usize, name: &'static str) {
+        if start + len > self.data.len() {
+            return self.truncated(start);
+        }
+        self.pos = start + len;
+        self.tokens.push(Token::Other {
+            name,
+            bytes: self.data[start..self.pos].to_vec(),
+        });
+    }
+
+    fn unknown(&mut self, start: usize, len: usize) {
+        self.pos = (start + len).min(self.data.len());
+        self.tokens.push(Token::Unknown {
+            offset: start,
+            bytes: self.data[start..self.pos].to_vec(),
+        });
+    }
+
+    /// The stream ends in the middle of a command
+    fn truncated(&mut self, start: usize) {
+        self.unknown(start, self.data.len() - start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::Encoder;
+    use crate::printer::SupportedPrinters;
+
+    #[test]
+    fn decodes_encoded_job() {
+        let mut job = Job::new();
+        job.push(Command::Init)
+            .push(Command::Align(Alignment::Right))
+            .push(Command::Size {
+                width: 2,
+                height: 3,
+            })
+            .push(Command::Text("Total".to_string()))
+            .push(Command::NewLine)
+            .push(Command::Barcode {
+                code: "ABC-1".to_string(),
+                kind: BarcodeType::Code128,
+                height: 60,
+            })
+            .push(Command::QrCode {
+                data: "https://example.com".to_string(),
+                size: 4,
+            })
+            .push(Command::Raster {
+                width_bytes: 1,
+                height: 2,
+                data: vec![0xf0, 0x0f],
+            })
+            .push(Command::Cut { partial: true });
+        let bytes = Encoder::new(SupportedPrinters::Generic)
+            .encode(&job)
+            .unwrap();
+        let commands: Vec<Command> = parse(&bytes)
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Command(Command::NewLine) => None,
+                Token::Command(command) => Some(command),
+                _ => None,
+            })
+            .collect();
+        let mut expected = job.commands.clone();
+        expected.retain(|c| *c != Command::NewLine);
+        assert_eq!(commands, expected);
+        assert_eq!(to_job(&bytes).commands.len(), parse(&bytes).len());
+    }
+}
@@ -0,0 +1,200 @@
+//! PDF copy of a [Job], for emailing or archiving receipts
+//!
+//! Uses the same layout as the [preview](crate::preview) PNG. Every receipt
+//! starts on a new page the width of the paper, long receipts continue on
+//! the next page.
+//!
+//! ```
+//! use posify::document::Document;
+//! use posify::job::Job;
+//! use posify::pdf::{self, PageSetup};
+//!
+//! let mut doc = Document::new();
+//! doc.text("Thank you").cut();
+//! let pdf = pdf::render(&Job::from(&doc), &PageSetup::default());
+//! assert!(pdf.starts_with(b"%PDF-1.4"));
+//! ```
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use image::GrayImage;
+
+use crate::job::Job;
+use crate::preview;
+
+/// Paper the receipt is laid out on
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct PageSetup {
+    /// Printable width in dots
+    pub width: u32,
+    /// Printer resolution, sets the page size in the PDF
+    pub dpi: u32,
+    /// Longest page in dots before the receipt continues on the next one
+    pub max_height: u32,
+}
+
+impl Default for PageSetup {
+    /// 80mm paper at 203 dpi, pages at most 11" long
+    fn default() -> Self {
+        PageSetup {
+            width: 576,
+            dpi: 203,
+            max_height: 2233,
+        }
+    }
+}
+
+/// Renders job as a PDF with one image per page
+pub fn render(job: &Job, setup: &PageSetup) -> Vec<u8> {
+    let mut pages = Vec::new();
+    for receipt in preview::layout(job, setup.width) {
+        let max_height = setup.max_height.max(1);
+        let mut y = 0;
+        while y < receipt.height() {
+            let height = max_height.min(receipt.height() - y);
+            pages.push(image::imageops::crop_imm(&receipt, 0, y, setup.width, height).to_image());
+            y += height;
+        }
+    }
+
+    let mut pdf = Pdf::default();
+    // Objects 1 and 2 are the catalog and page tree, each page takes 3
+    let kids: Vec<String> = (0..pages.len())
+        .map(|n| format!("{} 0 R", 3 + 3 * n))
+        .collect();
+    pdf.object(b"<< /Type /Catalog /Pages 2 0 R >>");
+    pdf.object(
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        )
+        .as_bytes(),
+    );
+    for (n, page) in pages.iter().enumerate() {
+        let id = 3 + 3 * n;
+        let width = points(page.width(), setup.dpi);
+        let height = points(page.height(), setup.dpi);
+        pdf.object(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+                 /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                width,
+                height,
+                id + 1,
+                id + 2
+            )
+            .as_bytes(),
+        );
+        let data = pack(page);
+        let mut image = format!(
+            "<< /Type /XObject /Subtype /Image /Width {} /Height {} \
+             /ColorSpace /DeviceGray /BitsPerComponent 1 /Length {} >>\nstream\n",
+            page.width(),
+            page.height(),
+            data.len()
+        )
+        .into_bytes();
+        image.extend_from_slice(&data);
+        image.extend_from_slice(b"\nendstream");
+        pdf.object(&image);
+        let contents = format!("q {:.2} 0 0 {:.2} 0 0 cm /Im0 Do Q", width, height);
+        pdf.object(
+            format!(
+                "<< /Length {} >>\nstream\n{}\nendstream",
+                contents.len(),
+                contents
+            )
+            .as_bytes(),
+        );
+    }
+    pdf.finish()
+}
+
+/// Renders job and saves it as a PDF
+pub fn save<P: AsRef<Path>>(job: &Job, setup: &PageSetup, path: P) -> io::Result<()> {
+    std::fs::write(path, render(job, setup))
+}
+
+fn points(dots: u32, dpi: u32) -> f32 {
+    dots as f32 * 72.0 / dpi.max(1) as f32
+}
+
+/// One bit per pixel rows, padded to a byte, where 1 is white
+fn pack(page: &GrayImage) -> Vec<u8> {
+    let width_bytes = page.width().div_ceil(8) as usize;
+    let mut data = vec![0xff; width_bytes * page.height() as usize];
+    for (x, y, pixel) in page.enumerate_pixels() {
+        if pixel[0] < 128 {
+            data[y as usize * width_bytes + x as usize / 8] &= !(0x80 >> (x % 8));
+        }
+    }
+    data
+}
+
+#[derive(Default)]
+struct Pdf {
+    out: Vec<u8>,
+    /// Byte offset of each object for the xref table
+    offsets: Vec<usize>,
+}
+
+impl Pdf {
+    fn object(&mut self, body: &[u8]) {
+        if self.out.is_empty() {
+            self.out.extend_from_slice(b"%PDF-1.4\n");
+        }
+        self.offsets.push(self.out.len());
+        let _ = writeln!(self.out, "{} 0 obj", self.offsets.len());
+        self.out.extend_from_slice(body);
+        self.out.extend_from_slice(b"\nendobj\n");
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let xref = self.out.len();
+        let _ = write!(
+            self.out,
+            "xref\n0 {}\n0000000000 65535 f \n",
+            self.offsets.len() + 1
+        );
+        for offset in &self.offsets {
+            let _ = writeln!(self.out, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            self.out,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            xref
+        );
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    #[test]
+    fn paginates() {
+        let mut doc = Document::new();
+        doc.text("One").cut().feed(4).text("Two");
+        let setup = PageSetup {
+            width: 96,
+            dpi: 203,
+            max_height: 100,
+        };
+        let pdf = String::from_utf8_lossy(&render(&Job::from(&doc), &setup)).into_owned();
+        // The second receipt is 150 dots long and needs two pages
+        assert!(pdf.contains("/Count 3"));
+        assert!(pdf.contains("/Width 96 /Height 100"));
+        assert!(pdf.contains("/Width 96 /Height 50"));
+        assert!(pdf.ends_with("%%EOF\n"));
+    }
+}
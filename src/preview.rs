@@ -0,0 +1,595 @@
+//! Picture of what a [Job] would print, without a printer
+//!
+//! The command list is laid out the way a thermal printer would: text in a
+//! 12 x 24 dot cell with the built in 5 x 7 font scaled up, rasters dot for
+//! dot and cuts drawn as dashed lines. Characters with a built in
+//! [glyph](crate::glyph) are drawn as the glyph, at its width. The output is deterministic, so the
+//! PNG can be compared against a golden image in tests.
+//!
+//! Barcodes are drawn as a stand-in pattern of their data with the human
+//! readable text below, they look like a barcode but won't scan. QR codes
+//! are real with the `qrcode` feature and an outlined square without it.
+//!
+//! ```
+//! use posify::document::Document;
+//! use posify::job::Job;
+//! use posify::preview;
+//!
+//! let mut doc = Document::new();
+//! doc.text("Hello").cut();
+//! let png = preview::to_png(&Job::from(&doc), 576)?;
+//! assert_eq!(&png[1..4], b"PNG");
+//! # Ok::<(), image::ImageError>(())
+//! ```
+
+use std::io::Cursor;
+use std::path::Path;
+
+use image::{GrayImage, ImageError, ImageOutputFormat, Luma};
+
+use crate::document::Alignment;
+use crate::glyph;
+use crate::job::{Command, Job};
+
+/// Width of a character cell in dots, font A
+pub(crate) const CELL_WIDTH: u32 = 12;
+/// Height of a character cell in dots, font A
+pub(crate) const CELL_HEIGHT: u32 = 24;
+/// Default ESC/POS line spacing (ESC 2)
+pub(crate) const LINE_HEIGHT: u32 = 30;
+/// Space above and below the dashed cut line
+const CUT_MARGIN: u32 = 8;
+/// Stand-in barcode module width in dots
+const BAR_WIDTH: u32 = 2;
+
+const WHITE: Luma<u8> = Luma([255]);
+const BLACK: Luma<u8> = Luma([0]);
+
+/// Lays out job on paper width dots wide, one image per receipt
+///
+/// A new receipt starts after every [Command::Cut]. A trailing cut doesn't
+/// leave an empty receipt behind it.
+pub(crate) fn layout(job: &Job, width: u32) -> Vec<GrayImage> {
+    let mut layout = Layout::new(width);
+    for command in &job.commands {
+        layout.run(command);
+    }
+    layout.flush_line();
+    if !layout.rows.is_empty() || layout.receipts.is_empty() {
+        let receipt = layout.finish();
+        layout.receipts.push(receipt);
+    }
+    layout.receipts
+}
+
+/// Renders job as a single strip, with a dashed line at every cut
+pub fn render(job: &Job, width: u32) -> GrayImage {
+    let receipts = layout(job, width);
+    let cuts = job
+        .commands
+        .iter()
+        .filter(|c| matches!(c, Command::Cut { .. }))
+        .count() as u32;
+    let height = receipts.iter().map(|r| r.height()).sum::<u32>() + cuts * (2 * CUT_MARGIN + 1);
+    let mut strip = GrayImage::from_pixel(width, height.max(1), WHITE);
+    let mut y = 0;
+    for (n, receipt) in receipts.iter().enumerate() {
+        image::imageops::replace(&mut strip, receipt, 0, y as i64);
+        y += receipt.height();
+        if (n as u32) < cuts {
+            y += CUT_MARGIN;
+            for x in (0..width).filter(|x| (x / 8) % 2 == 0) {
+                strip.put_pixel(x, y, BLACK);
+            }
+            y += CUT_MARGIN + 1;
+        }
+    }
+    strip
+}
+
+/// Renders job as a PNG, see [render]
+pub fn to_png(job: &Job, width: u32) -> Result<Vec<u8>, ImageError> {
+    let mut png = Cursor::new(Vec::new());
+    render(job, width).write_to(&mut png, ImageOutputFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+/// Renders job and saves it as a PNG
+pub fn save_png<P: AsRef<Path>>(job: &Job, width: u32, path: P) -> Result<(), ImageError> {
+    render(job, width).save_with_format(path, image::ImageFormat::Png)
+}
+
+/// Lays out job as plain text columns characters wide, for searching and
+/// archiving
+///
+/// Text wraps and aligns like on paper, magnified characters count for as
+/// many columns as they are wide. Barcodes, QR codes and images are
+/// written as a description in brackets, cuts as a dashed line.
+pub fn to_text(job: &Job, columns: usize) -> String {
+    let columns = columns.max(1);
+    let mut out = String::new();
+    let mut align = Alignment::Left;
+    let mut scale = 1;
+    // Characters waiting for a line feed and the columns each takes
+    let mut line: Vec<(char, usize)> = Vec::new();
+    for command in &job.commands {
+        let described = match command {
+            Command::Init => {
+                text_lines(&mut out, &std::mem::take(&mut line), align, columns);
+                align = Alignment::Left;
+                scale = 1;
+                continue;
+            }
+            Command::Text(text) => {
+                line.extend(text.chars().map(|c| (c, scale)));
+                continue;
+            }
+            Command::NewLine if line.is_empty() => {
+                out.push('\n');
+                continue;
+            }
+            Command::NewLine => None,
+            Command::Align(a) => {
+                align = *a;
+                continue;
+            }
+            Command::Size { width, .. } => {
+                scale = (*width).clamp(1, 8) as usize;
+                continue;
+            }
+            Command::Barcode { code, .. } => Some(format!("[barcode {}]", code)),
+            Command::QrCode { data, .. } => Some(format!("[QR code {}]", data)),
+            Command::Raster {
+                width_bytes,
+                height,
+                ..
+            } => Some(format!("[image {}x{}]", *width_bytes as u32 * 8, height)),
+            Command::Feed(lines) => {
+                text_lines(&mut out, &std::mem::take(&mut line), align, columns);
+                out.push_str(&"\n".repeat(*lines as usize));
+                continue;
+            }
+            Command::Cut { .. } => Some("-".repeat(columns)),
+            Command::Bold(_)
+            | Command::Underline(_)
+            | Command::Reverse(_)
+            | Command::Raw(_)
+            | Command::Display(_) => continue,
+        };
+        text_lines(&mut out, &std::mem::take(&mut line), align, columns);
+        if let Some(described) = described {
+            let chars: Vec<_> = described.chars().map(|c| (c, 1)).collect();
+            text_lines(&mut out, &chars, align, columns);
+        }
+    }
+    text_lines(&mut out, &line, align, columns);
+    out
+}
+
+/// Appends chars to out wrapped at columns, each line aligned with spaces
+fn text_lines(out: &mut String, chars: &[(char, usize)], align: Alignment, columns: usize) {
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = start;
+        let mut used = 0;
+        while end < chars.len() && (used + chars[end].1 <= columns || end == start) {
+            used += chars[end].1;
+            end += 1;
+        }
+        let free = columns.saturating_sub(used);
+        let pad = match align {
+            Alignment::Left => 0,
+            Alignment::Center => free / 2,
+            Alignment::Right => free,
+        };
+        out.push_str(&" ".repeat(pad));
+        out.extend(chars[start..end].iter().map(|(c, _)| c));
+        out.push('\n');
+        start = end;
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Style {
+    bold: bool,
+    underline: bool,
+    reverse: bool,
+    width: u8,
+    height: u8,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            bold: false,
+            underline: false,
+            reverse: false,
+            width: 1,
+            height: 1,
+        }
+    }
+}
+
+struct Layout {
+    width: u32,
+    align: Alignment,
+    style: Style,
+    /// Text waiting for a line feed
+    line: Vec<(char, Style)>,
+    /// Rows of the current receipt, true prints
+    rows: Vec<Vec<bool>>,
+    receipts: Vec<GrayImage>,
+}
+
+impl Layout {
+    fn new(width: u32) -> Layout {
+        Layout {
+            width,
+            align: Alignment::Left,
+            style: Style::default(),
+            line: Vec::new(),
+            rows: Vec::new(),
+            receipts: Vec::new(),
+        }
+    }
+
+    fn run(&mut self, command: &Command) {
+        match command {
+            Command::Init => {
+                self.flush_line();
+                self.align = Alignment::Left;
+                self.style = Style::default();
+            }
+            Command::Text(text) => {
+                let style = self.style;
+                self.line.extend(text.chars().map(|c| (c, style)));
+            }
+            Command::NewLine => {
+                if self.line.is_empty() {
+                    self.blank(LINE_HEIGHT);
+                } else {
+                    self.flush_line();
+                }
+            }
+            Command::Align(align) => self.align = *align,
+            Command::Bold(on) => self.style.bold = *on,
+            Command::Underline(on) => self.style.underline = *on,
+            Command::Reverse(on) => self.style.reverse = *on,
+            Command::Size { width, height } => {
+                self.style.width = (*width).clamp(1, 8);
+                self.style.height = (*height).clamp(1, 8);
+            }
+            Command::Barcode { code, height, .. } => {
+                self.flush_line();
+                self.barcode(code, *height as u32);
+            }
+            Command::QrCode { data, size } => {
+                self.flush_line();
+                self.qrcode(data, (*size).max(1) as u32);
+            }
+            Command::Raster {
+                width_bytes,
+                height,
+                data,
+            } => {
+                self.flush_line();
+                let width = *width_bytes as u32 * 8;
+                let x = self.offset(width);
+                for row in data
+                    .chunks((*width_bytes).max(1) as usize)
+                    .take(*height as usize)
+                {
+                    let mut dots = vec![false; self.width as usize];
+                    for (i, dot) in dots.iter_mut().enumerate().skip(x as usize) {
+                        let bit = i - x as usize;
+                        if let Some(byte) = row.get(bit / 8) {
+                            *dot = byte & (0x80 >> (bit % 8)) != 0;
+                        }
+                    }
+                    self.rows.push(dots);
+                }
+            }
+            Command::Feed(lines) => {
+                self.flush_line();
+                self.blank(*lines as u32 * LINE_HEIGHT);
+            }
+            Command::Cut { .. } => {
+                self.flush_line();
+                let receipt = self.finish();
+                self.receipts.push(receipt);
+            }
+            // Unknown to the layout, printers would act on it but there's
+            // nothing to draw
+            Command::Raw(_) => (),
+            // Shown on the customer display, not the paper
+            Command::Display(_) => (),
+        }
+    }
+
+    /// Prints the pending text, wrapping at the paper width like a printer
+    fn flush_line(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        let mut start = 0;
+        while start < line.len() {
+            let mut end = start;
+            let mut used = 0;
+            while end < line.len() {
+                let cell = char_width(line[end].0) * line[end].1.width as u32;
+                if used + cell > self.width && end > start {
+                    break;
+                }
+                used += cell;
+                end += 1;
+            }
+            self.text_line(&line[start..end], used);
+            start = end;
+        }
+    }
+
+    fn text_line(&mut self, chars: &[(char, Style)], used: u32) {
+        let scale_y = chars.iter().map(|(_, s)| s.height).max().unwrap_or(1) as u32;
+        let height = LINE_HEIGHT + CELL_HEIGHT * (scale_y - 1);
+        let top = self.rows.len();
+        self.blank(height);
+        let mut x = self.offset(used);
+        for (c, style) in chars {
+            let sx = style.width as u32;
+            let sy = style.height as u32;
+            // Characters of different heights share a baseline
+            let cell_top = top as u32 + CELL_HEIGHT * (scale_y - sy);
+            let cell = char_width(*c);
+            if style.reverse {
+                self.fill(x, cell_top, cell * sx, CELL_HEIGHT * sy);
+            }
+            if let Some(picture) = glyph::builtin(*c) {
+                for gx in 0..cell {
+                    for gy in (0..CELL_HEIGHT).filter(|gy| picture.dot(gx, *gy)) {
+                        self.paint(x + gx * sx, cell_top + gy * sy, sx, sy, !style.reverse);
+                    }
+                }
+            } else {
+                for (col, bits) in glyph(*c).iter().enumerate() {
+                    for row in 0..7 {
+                        if bits & (1 << row) == 0 {
+                            continue;
+                        }
+                        // The 5 x 7 font is scaled 2x into the 12 x 24 cell
+                        let gx = x + (1 + 2 * col as u32) * sx;
+                        let gy = cell_top + (5 + 2 * row) * sy;
+                        let bold = if style.bold { sx } else { 0 };
+                        self.paint(gx, gy, 2 * sx + bold, 2 * sy, !style.reverse);
+                    }
+                }
+            }
+            if style.underline {
+                self.fill(x, cell_top + CELL_HEIGHT * sy - 2, cell * sx, 1);
+            }
+            x += cell * sx;
+        }
+    }
+
+    /// Bars from the bits of the data between start and stop guards, then
+    /// the data as text
+    fn barcode(&mut self, code: &str, height: u32) {
+        let mut bits = vec![true, false, true];
+        for byte in code.bytes() {
+            bits.extend((0..8).map(|bit| byte & (0x80 >> bit) != 0));
+        }
+        bits.extend([true, false, true]);
+        let width = bits.len() as u32 * BAR_WIDTH;
+        let x = self.offset(width);
+        let top = self.rows.len() as u32;
+        self.blank(height.max(1));
+        for (i, bar) in bits.iter().enumerate() {
+            if *bar {
+                self.fill(x + i as u32 * BAR_WIDTH, top, BAR_WIDTH, height.max(1));
+            }
+        }
+        let style = Style::default();
+        self.line = code.chars().map(|c| (c, style)).collect();
+        self.flush_line();
+    }
+
+    #[cfg(feature = "qrcode")]
+    fn qrcode(&mut self, data: &str, size: u32) {
+        let code = match qrcode::QrCode::new(data) {
+            Ok(code) => code,
+            Err(_) => return self.qr_placeholder(size),
+        };
+        let modules = code.width() as u32;
+        let width = (modules + 8) * size;
+        let x = self.offset(width) + 4 * size;
+        let top = self.rows.len() as u32 + 4 * size;
+        self.blank(width);
+        for (i, color) in code.to_colors().iter().enumerate() {
+            if *color == qrcode::Color::Dark {
+                let (col, row) = (i as u32 % modules, i as u32 / modules);
+                self.fill(x + col * size, top + row * size, size, size);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "qrcode"))]
+    fn qrcode(&mut self, _data: &str, size: u32) {
+        self.qr_placeholder(size)
+    }
+
+    /// Outline the size of a version 1 QR code with its quiet zone
+    fn qr_placeholder(&mut self, size: u32) {
+        let width = 29 * size;
+        let x = self.offset(width) + 4 * size;
+        let top = self.rows.len() as u32 + 4 * size;
+        let side = 21 * size;
+        self.blank(width);
+        self.fill(x, top, side, size);
+        self.fill(x, top + side - size, side, size);
+        self.fill(x, top, size, side);
+        self.fill(x + side - size, top, size, side);
+    }
+
+    /// Left edge of something width dots wide with the current alignment
+    fn offset(&self, width: u32) -> u32 {
+        let free = self.width.saturating_sub(width);
+        match self.align {
+            Alignment::Left => 0,
+            Alignment::Center => free / 2,
+            Alignment::Right => free,
+        }
+    }
+
+    fn blank(&mut self, rows: u32) {
+        let width = self.width as usize;
+        self.rows.extend((0..rows).map(|_| vec![false; width]));
+    }
+
+    fn fill(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        self.paint(x, y, width, height, true);
+    }
+
+    fn paint(&mut self, x: u32, y: u32, width: u32, height: u32, dot: bool) {
+        for row in self.rows.iter_mut().skip(y as usize).take(height as usize) {
+            for d in row.iter_mut().skip(x as usize).take(width as usize) {
+                *d = dot;
+            }
+        }
+    }
+
+    fn finish(&mut self) -> GrayImage {
+        let rows = std::mem::take(&mut self.rows);
+        let mut image = GrayImage::from_pixel(self.width, (rows.len() as u32).max(1), WHITE);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, dot) in row.iter().enumerate() {
+                if *dot {
+                    image.put_pixel(x as u32, y as u32, BLACK);
+                }
+            }
+        }
+        image
+    }
+}
+
+/// Modules across the QR code of data, without the quiet zone
+#[cfg(feature = "qrcode")]
+pub(crate) fn qr_modules(data: &str) -> u32 {
+    match qrcode::QrCode::new(data) {
+        Ok(code) => code.width() as u32,
+        Err(_) => 21,
+    }
+}
+
+/// Modules across the QR code of data, without the quiet zone, from the
+/// byte mode capacity at error correction level M
+#[cfg(not(feature = "qrcode"))]
+pub(crate) fn qr_modules(data: &str) -> u32 {
+    const CAPACITY: [usize; 40] = [
+        14, 26, 42, 62, 84, 106, 122, 152, 180, 213, 251, 287, 331, 362, 412, 450, 504, 560, 624,
+        666, 711, 779, 857, 911, 997, 1059, 1125, 1190, 1264, 1370, 1452, 1538, 1628, 1722, 1809,
+        1911, 1989, 2099, 2213, 2331,
+    ];
+    let version = CAPACITY
+        .iter()
+        .position(|capacity| data.len() <= *capacity)
+        .unwrap_or(39) as u32
+        + 1;
+    17 + 4 * version
+}
+
+/// Width of c in dots at normal size, wider for a built in
+/// [glyph](crate::glyph)
+pub(crate) fn char_width(c: char) -> u32 {
+    glyph::builtin(c).map_or(CELL_WIDTH, |glyph| glyph.width() as u32)
+}
+
+/// Columns of the 5 x 7 glyph, least significant bit at the top. Characters
+/// outside printable ASCII are drawn as `?`.
+pub(crate) fn glyph(c: char) -> [u8; 5] {
+    match c {
+        ' '..='~' => FONT_5X7[c as usize - 0x20],
+        _ => FONT_5X7[b'?' as usize - 0x20],
+    }
+}
+
+#[rustfmt::skip]
+const FONT_5X7: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5f, 0x00, 0x00], // ' ' !
+    [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7f, 0x14, 0x7f, 0x14], // " #
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], // $ %
+    [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00], // & '
+    [0x00, 0x1c, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1c, 0x00], // ( )
+    [0x08, 0x2a, 0x1c, 0x2a, 0x08], [0x08, 0x08, 0x3e, 0x08, 0x08], // * +
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], // , -
+    [0x00, 0x60, 0x60, 0x00, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02], // . /
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], [0x00, 0x42, 0x7f, 0x40, 0x00], // 0 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4b, 0x31], // 2 3
+    [0x18, 0x14, 0x12, 0x7f, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39], // 4 5
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03], // 6 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1e], // 8 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], [0x00, 0x56, 0x36, 0x00, 0x00], // : ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14], // < =
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06], // > ?
+    [0x32, 0x49, 0x79, 0x41, 0x3e], [0x7e, 0x11, 0x11, 0x11, 0x7e], // @ A
+    [0x7f, 0x49, 0x49, 0x49, 0x36], [0x3e, 0x41, 0x41, 0x41, 0x22], // B C
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], [0x7f, 0x49, 0x49, 0x49, 0x41], // D E
+    [0x7f, 0x09, 0x09, 0x01, 0x01], [0x3e, 0x41, 0x41, 0x51, 0x32], // F G
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], [0x00, 0x41, 0x7f, 0x41, 0x00], // H I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], [0x7f, 0x08, 0x14, 0x22, 0x41], // J K
+    [0x7f, 0x40, 0x40, 0x40, 0x40], [0x7f, 0x02, 0x04, 0x02, 0x7f], // L M
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], [0x3e, 0x41, 0x41, 0x41, 0x3e], // N O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], [0x3e, 0x41, 0x51, 0x21, 0x5e], // P Q
+    [0x7f, 0x09, 0x19, 0x29, 0x46], [0x46, 0x49, 0x49, 0x49, 0x31], // R S
+    [0x01, 0x01, 0x7f, 0x01, 0x01], [0x3f, 0x40, 0x40, 0x40, 0x3f], // T U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], [0x7f, 0x20, 0x18, 0x20, 0x7f], // V W
+    [0x63, 0x14, 0x08, 0x14, 0x63], [0x03, 0x04, 0x78, 0x04, 0x03], // X Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7f, 0x41, 0x41, 0x00], // Z [
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7f, 0x00], // \ ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40], // ^ _
+    [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78], // ` a
+    [0x7f, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], // b c
+    [0x38, 0x44, 0x44, 0x48, 0x7f], [0x38, 0x54, 0x54, 0x54, 0x18], // d e
+    [0x08, 0x7e, 0x09, 0x01, 0x02], [0x08, 0x14, 0x54, 0x54, 0x3c], // f g
+    [0x7f, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7d, 0x40, 0x00], // h i
+    [0x20, 0x40, 0x44, 0x3d, 0x00], [0x00, 0x7f, 0x10, 0x28, 0x44], // j k
+    [0x00, 0x41, 0x7f, 0x40, 0x00], [0x7c, 0x04, 0x18, 0x04, 0x78], // l m
+    [0x7c, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], // n o
+    [0x7c, 0x14, 0x14, 0x14, 0x08], [0x08, 0x14, 0x14, 0x18, 0x7c], // p q
+    [0x7c, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20], // r s
+    [0x04, 0x3f, 0x44, 0x40, 0x20], [0x3c, 0x40, 0x40, 0x20, 0x7c], // t u
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], [0x3c, 0x40, 0x30, 0x40, 0x3c], // v w
+    [0x44, 0x28, 0x10, 0x28, 0x44], [0x0c, 0x50, 0x50, 0x50, 0x3c], // x y
+    [0x44, 0x64, 0x54, 0x4c, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], // z {
+    [0x00, 0x00, 0x7f, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00], // | }
+    [0x08, 0x04, 0x08, 0x10, 0x08],                                 // ~
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    #[test]
+    fn renders_receipts() {
+        let mut doc = Document::new();
+        doc.text("AB").cut().text("C");
+        let job = Job::from(&doc);
+        let receipts = layout(&job, 96);
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[0].height(), LINE_HEIGHT);
+        // Left column of the A, scaled 2x
+        assert_eq!(receipts[0].get_pixel(1, 7), &BLACK);
+        assert_eq!(receipts[0].get_pixel(0, 7), &WHITE);
+
+        let strip = render(&job, 96);
+        assert_eq!(strip.height(), 2 * LINE_HEIGHT + 2 * CUT_MARGIN + 1);
+        assert_eq!(strip.get_pixel(0, LINE_HEIGHT + CUT_MARGIN), &BLACK);
+        assert_eq!(strip.get_pixel(8, LINE_HEIGHT + CUT_MARGIN), &WHITE);
+
+        let mut job = Job::new();
+        job.push(Command::Align(Alignment::Center))
+            .push(Command::Text("Total".to_string()))
+            .push(Command::NewLine)
+            .push(Command::Align(Alignment::Left))
+            .push(Command::Text("1234567890".to_string()))
+            .push(Command::Cut { partial: true });
+        assert_eq!(to_text(&job, 8), " Total\n12345678\n90\n--------\n");
+    }
+}
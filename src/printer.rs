@@ -1,19 +1,64 @@
 use std::io;
 
 use std::collections::VecDeque;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use byteorder::{LittleEndian, WriteBytesExt};
-use encoding::all::UTF_8;
-use encoding::types::{EncoderTrap, EncodingRef};
+use encoding::all::{UTF_8, WINDOWS_874};
+use encoding::types::{EncoderTrap, Encoding, EncodingRef};
 
+use crate::archive::{Archive, ArchiveOptions, ArchivedJob};
 use crate::barcode::*;
 use crate::consts;
+use crate::display::Display;
+use crate::document::{Alignment, Document};
+use crate::format;
+use crate::glyph::GlyphSet;
+use crate::history::{self, History, HistoryEntry};
 use crate::img::Image;
+use crate::job::{self, Command, Encoder, Job, JobBuilder, Template};
+use crate::kana;
+#[cfg(feature = "metrics")]
+use crate::metrics::{Event, Metrics};
+use crate::micr::{MicrData, MicrError, MicrFont};
+use crate::parser::{self, Token};
+use crate::profile::{self, CutFeed, Profile, SaleStep, Throttle, DEFAULT_PAGE_HEIGHT};
+use crate::ruled_lines::RuledLines;
+use crate::thai;
 
 /// Timeout for sending/receiving USB messages
 pub const TIMEOUT: u64 = 400;
 
+/// Time allowed for the printer to read a cheque
+const MICR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the presenter is polled while waiting for a ticket to be taken
+const TICKET_TAKEN_POLL: Duration = Duration::from_millis(100);
+
+/// Modules of an ITF-14 symbol: start, 14 digits of two wide (3) and three
+/// narrow modules, stop
+const ITF14_MODULES: u32 = 4 + 14 * 9 + 5;
+
+/// Largest macro the printer stores, in bytes
+const MACRO_MAX_LEN: usize = 2048;
+
+/// DLE DC4 8, clears the receive and print buffers, see
+/// [Printer::clear_buffers]
+const CLEAR_BUFFERS: &[u8] = &[0x10, 0x14, 0x08, 0x01, 0x03, 0x14, 0x01, 0x06, 0x02, 0x08];
+
+/// GS I 67, asks for the model name, see [Printer::model_name]
+const MODEL_NAME: &[u8] = &[0x1d, 0x49, 0x43];
+
+/// [CLEAR_BUFFERS] in the dialects that have it, Star Line Mode and the P3
+/// don't
+fn clear_buffers_cmd(printer: SupportedPrinters) -> Option<&'static [u8]> {
+    match printer {
+        SupportedPrinters::Star | SupportedPrinters::P3 => None,
+        _ => Some(CLEAR_BUFFERS),
+    }
+}
+
 // SNBC
 // First Byte
 const OFFLINE_BIT: u8 = 3;
@@ -36,19 +81,253 @@ const EPIC_STATUS_COVER_OPEN_BIT: i32 = 2;
 const EPIC_STATUS_PAPER_END_BIT: i32 = 5;
 const EPIC_STATUS_AUTO_CUTTER_BIT: i32 = 3;
 
+// Custom (P3) DLE EOT 0x14 full status
+const FULL_STATUS_PAPER_BYTE: usize = 2;
+const FULL_STATUS_NO_PAPER_BIT: u8 = 0;
+const FULL_STATUS_NEAR_END_BIT: u8 = 2;
+const FULL_STATUS_TICKET_PRESENT_BIT: u8 = 5;
+
+// DLE EOT n (real-time status)
+// n = 1, printer status
+const DRAWER_STATUS_BIT: u8 = 2;
+const RT_OFFLINE_BIT: u8 = 3;
+// n = 2, off-line status
+const RT_COVER_OPEN_BIT: u8 = 2;
+const RT_PAPER_FEED_BIT: u8 = 3;
+const RT_PAPER_END_STOP_BIT: u8 = 5;
+const RT_ERROR_BIT: u8 = 6;
+// n = 3, error status
+const RT_AUTO_CUTTER_BIT: u8 = 3;
+const RT_RECOVERABLE_BIT: u8 = 5;
+const RT_AUTOMATIC_RECOVERABLE_BIT: u8 = 6;
+// n = 4, paper roll sensor status
+const RT_PAPER_NEAR_END_BITS: u8 = 0b0000_1100;
+const RT_PAPER_END_BITS: u8 = 0b0110_0000;
+// n = 5, slip status
+const RT_SLIP_WAITING_BIT: u8 = 2;
+const RT_SLIP_TOF_NO_PAPER_BIT: u8 = 5;
+const RT_SLIP_BOF_NO_PAPER_BIT: u8 = 6;
+
 /// SupportedPrinters enumerates the list of printers that this library knows
 /// about. Should be easy to add your own to this library or you could try
 /// using an existing one if the command set is similar.
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SupportedPrinters {
     /// Tested on the SNBC BTP-R880NPV
     SNBC,
     /// Tested on the Custom P3 printer
     P3,
     Epic,
+    /// Star Micronics TSP100/TSP650 and similar in Star Line Mode, which
+    /// replaces many ESC/POS commands (alignment is ESC GS a, cuts are ESC d,
+    /// raster images use ESC * r)
+    Star,
+    /// Star Micronics printers switched to ESC/POS emulation
+    StarEscPos,
+    /// Bixolon SRP-350 family
+    Bixolon,
+    /// Citizen CT-S310 family
+    Citizen,
+    /// No-name "ESC/POS compatible" printers (Xprinter, Rongta, Goojprt...),
+    /// limited to the commands they all understand, see [Quirks]
+    Generic,
     Unknown, // Adding to allow _ no not raise warnings to make adding printers easier
 }
 
+impl SupportedPrinters {
+    /// Level of the drawer kick-out connector (pin 3) that means the drawer is
+    /// open. This is really a property of the drawer rather than the printer
+    /// so it can be overridden with [Printer::set_drawer_polarity].
+    pub fn drawer_polarity(&self) -> DrawerPolarity {
+        DrawerPolarity::OpenHigh
+    }
+
+    /// Deviations from the ESC/POS spec the printer is known for, can be
+    /// overridden with [Printer::set_quirks]
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            SupportedPrinters::Generic => Quirks {
+                barcode_nul_terminator: true,
+                no_code93: true,
+                raster_only: true,
+            },
+            _ => Quirks::default(),
+        }
+    }
+}
+
+/// Deviations from the ESC/POS spec, mostly found on cheap clones
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Quirks {
+    /// Barcode data must end with NUL even when its length is sent
+    pub barcode_nul_terminator: bool,
+    /// Code93 isn't supported, barcodes return [Error::Unsupported]
+    pub no_code93: bool,
+    /// Only GS v 0 raster images print, ESC * bit images are sent as raster
+    /// images and the GS * RAM logo is unsupported
+    pub raster_only: bool,
+}
+
+/// Command set for the NV images of [Printer::define_nv_images]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NvImageCommands {
+    /// GS ( L NV graphics, keyed by two characters
+    Graphics,
+    /// FS q and FS p, numbered images on firmware predating GS ( L
+    Legacy,
+}
+
+/// First SNBC firmware version with GS ( L
+const SNBC_NV_GRAPHICS_FIRMWARE: (u32, u32) = (2, 0);
+
+impl NvImageCommands {
+    /// Command set for printer with firmware, the GS I 3 version
+    ///
+    /// SNBC firmware before 2.0 or with a version that doesn't parse gets
+    /// the legacy commands, which newer firmware keeps understanding.
+    pub fn for_firmware(printer: SupportedPrinters, firmware: &str) -> NvImageCommands {
+        if printer != SupportedPrinters::SNBC {
+            return NvImageCommands::Graphics;
+        }
+        let version = firmware.trim_start_matches(|c: char| !c.is_ascii_digit());
+        let mut parts = version
+            .split(|c: char| !c.is_ascii_digit())
+            .map(|part| part.parse::<u32>().ok());
+        match (parts.next().flatten(), parts.next().flatten()) {
+            (Some(major), minor) if (major, minor.unwrap_or(0)) >= SNBC_NV_GRAPHICS_FIRMWARE => {
+                NvImageCommands::Graphics
+            }
+            _ => NvImageCommands::Legacy,
+        }
+    }
+}
+
+/// Kind of cut of [Printer::cut]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CutMode {
+    Full,
+    /// Leaves a small uncut bridge so the receipt doesn't drop
+    Partial,
+}
+
+/// Printable area of a page, see [Printer::begin_page], in dots from the
+/// top left corner of the paper
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageArea {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// Setting kept in the printer's NV memory, see [Printer::write_config]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConfigSetting {
+    /// Paper width in mm, 58 or 80
+    PaperWidth(u8),
+    /// Print density the printer starts with, -6 to 6, see
+    /// [Printer::print_density]
+    Density(i8),
+    /// Customized value number of the model's manual, the default code page
+    /// of the models that keep one for example
+    Custom { number: u8, value: u16 },
+}
+
+impl ConfigSetting {
+    /// Customized value number and value sent with GS ( E fn = 5
+    fn value(&self) -> Result<(u8, u16), Error> {
+        match *self {
+            ConfigSetting::PaperWidth(mm @ (58 | 80)) => Ok((3, if mm == 58 { 6 } else { 8 })),
+            ConfigSetting::Density(n) if (-6..=6).contains(&n) => Ok((5, n as i16 as u16)),
+            ConfigSetting::Custom { number, value } => Ok((number, value)),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// What [Printer::finish_sale] does after the receipt is printed
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct SaleFinish {
+    /// Fed past the last line before the cut, the profile's [CutFeed] when
+    /// None, see [Printer::cut]
+    pub feed_mm: Option<u8>,
+    /// None leaves the receipt uncut
+    pub cut: Option<CutMode>,
+    /// Drawer opened, None e.g. for card payments
+    pub drawer: Option<DrawerPin>,
+    /// Times the buzzer sounds, 0 for none
+    pub buzzer: u8,
+}
+
+impl Default for SaleFinish {
+    fn default() -> Self {
+        SaleFinish {
+            feed_mm: None,
+            cut: Some(CutMode::Partial),
+            drawer: Some(DrawerPin::Pin2),
+            buzzer: 0,
+        }
+    }
+}
+
+/// How [Printer::reprint] marks a copy
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CopyMark {
+    /// Double size white on black `COPY` across the paper
+    #[default]
+    Inverse,
+    /// `COPY` repeated along diagonals, drawn as an image
+    Diagonal,
+}
+
+/// What a command does when the printer can't do what it asks for, see
+/// [Printer::set_degradation]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Degradation {
+    /// The closest thing the printer can do, e.g. a partial cut instead of
+    /// a full one, or only feeding on printers without a cutter
+    #[default]
+    Substitute,
+    /// Return [Error::Unsupported]
+    Fail,
+}
+
+/// Drawer kick-out connector pin a pulse is sent to
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DrawerPin {
+    Pin2,
+    Pin5,
+}
+
+/// How the drawer open/close switch is wired to pin 3 of the drawer kick-out
+/// connector
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DrawerPolarity {
+    /// Pin 3 is HIGH while the drawer is open
+    OpenHigh,
+    /// Pin 3 is LOW while the drawer is open
+    OpenLow,
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("USB error: {:?}", 0)]
@@ -77,9 +356,25 @@ pub enum Error {
 
     #[error("Unsupported printer")]
     Unsupported,
+
+    #[error("Unexpected response from printer")]
+    InvalidResponse,
+
+    #[error("MICR error: {0}")]
+    Micr(MicrError),
+
+    #[error("Barcode error: {0}")]
+    Barcode(BarcodeError),
+
+    #[error("Malformed command at byte {0}")]
+    Malformed(usize),
+
+    #[error("Canceled")]
+    Canceled,
 }
 
 #[derive(std::cmp::Eq, thiserror::Error, Clone, Copy, Hash, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StatusError {
     #[error("Communication Error")]
     Communication,
@@ -110,6 +405,41 @@ pub enum StatusError {
 
     #[error("Paper end")]
     PaperEnd,
+
+    #[error("Ticket not taken")]
+    TicketPresent,
+}
+
+impl Error {
+    /// Whether trying again later could succeed, a busy or unplugged printer
+    /// rather than a command it doesn't support
+    pub fn is_transient(&self) -> bool {
+        if let Error::Io(e) = self {
+            return matches!(
+                e.kind(),
+                std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::WouldBlock
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::NotConnected
+            );
+        }
+        matches!(
+            self,
+            Error::Timeout
+                | Error::Usb(
+                    rusb::Error::Timeout
+                        | rusb::Error::Busy
+                        | rusb::Error::Io
+                        | rusb::Error::Interrupted
+                        | rusb::Error::Pipe
+                        | rusb::Error::NoDevice
+                )
+        )
+    }
 }
 
 impl From<std::io::Error> for Error {
@@ -118,13 +448,397 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<MicrError> for Error {
+    fn from(e: MicrError) -> Self {
+        Error::Micr(e)
+    }
+}
+
+impl From<BarcodeError> for Error {
+    fn from(e: BarcodeError) -> Self {
+        Error::Barcode(e)
+    }
+}
+
 impl From<rusb::Error> for Error {
     fn from(e: rusb::Error) -> Self {
         Error::Usb(e)
     }
 }
 
+/// How large transmissions (images) are paced so they don't overrun the
+/// printer's receive buffer
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FlowControl {
+    /// Send data as fast as the transport accepts it
+    #[default]
+    None,
+    /// Send at most `chunk_size` bytes, then wait (up to `timeout`) for the
+    /// printer to report that it has processed them before sending more.
+    ///
+    /// Images are split into separate commands so the wait always happens
+    /// between commands, never inside image data. The printer is asked with
+    /// GS r 1, see [Printer::set_flow_control] for the printers without it.
+    /// None of the supported printers report their free receive-buffer space
+    /// (the SNBC extended status GS 0xE1 to 0xE5 only has the paper
+    /// counters), so the wait is for the buffer to drain rather than for
+    /// enough room.
+    StatusPaced {
+        chunk_size: usize,
+        timeout: Duration,
+    },
+}
+
+/// Off-line status (DLE EOT 2) parsed from the printer's response byte
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OfflineStatus {
+    pub cover_open: bool,
+    /// Paper is being fed by the feed button
+    pub feed_button_pressed: bool,
+    /// Printing stopped because the paper ran out
+    pub paper_end: bool,
+    pub error: bool,
+}
+
+impl From<u8> for OfflineStatus {
+    fn from(status: u8) -> Self {
+        OfflineStatus {
+            cover_open: ((status >> RT_COVER_OPEN_BIT) & 1) == 1,
+            feed_button_pressed: ((status >> RT_PAPER_FEED_BIT) & 1) == 1,
+            paper_end: ((status >> RT_PAPER_END_STOP_BIT) & 1) == 1,
+            error: ((status >> RT_ERROR_BIT) & 1) == 1,
+        }
+    }
+}
+
+impl std::fmt::Display for OfflineStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut conditions = Vec::new();
+        if self.cover_open {
+            conditions.push("cover open");
+        }
+        if self.feed_button_pressed {
+            conditions.push("feed button pressed");
+        }
+        if self.paper_end {
+            conditions.push("paper end");
+        }
+        if self.error {
+            conditions.push("error");
+        }
+        if conditions.is_empty() {
+            write!(f, "ok")
+        } else {
+            write!(f, "{}", conditions.join(", "))
+        }
+    }
+}
+
+/// Presenter state of kiosk printers, see [Printer::presenter_status]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PresenterStatus {
+    /// A ticket is waiting in the presenter to be taken
+    pub ticket_present: bool,
+    pub paper_present: bool,
+    pub paper_near_end: bool,
+}
+
+/// From the paper status byte of the full status
+impl From<u8> for PresenterStatus {
+    fn from(paper: u8) -> Self {
+        PresenterStatus {
+            ticket_present: ((paper >> FULL_STATUS_TICKET_PRESENT_BIT) & 1) == 1,
+            paper_present: ((paper >> FULL_STATUS_NO_PAPER_BIT) & 1) == 0,
+            paper_near_end: ((paper >> FULL_STATUS_NEAR_END_BIT) & 1) == 1,
+        }
+    }
+}
+
+/// State of the paper roll as reported by the paper sensors
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PaperState {
+    Present,
+    NearEnd,
+    End,
+}
+
+impl PaperState {
+    /// From the DLE EOT 4 roll paper sensor status
+    fn from_roll_status(status: u8) -> PaperState {
+        if status & RT_PAPER_END_BITS == RT_PAPER_END_BITS {
+            PaperState::End
+        } else if status & RT_PAPER_NEAR_END_BITS == RT_PAPER_NEAR_END_BITS {
+            PaperState::NearEnd
+        } else {
+            PaperState::Present
+        }
+    }
+}
+
+/// Unit of a [PaperLength]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LengthUnit {
+    Centimeters,
+    Meters,
+}
+
+/// Length of paper in the unit the printer counts it in, see
+/// [Printer::get_printed_length] and [Printer::get_remaining_paper]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PaperLength {
+    pub value: u32,
+    pub unit: LengthUnit,
+}
+
+impl PaperLength {
+    pub fn millimeters(&self) -> u64 {
+        match self.unit {
+            LengthUnit::Centimeters => self.value as u64 * 10,
+            LengthUnit::Meters => self.value as u64 * 1000,
+        }
+    }
+
+    pub fn centimeters(&self) -> u64 {
+        self.millimeters() / 10
+    }
+
+    pub fn meters(&self) -> f64 {
+        self.millimeters() as f64 / 1000.0
+    }
+}
+
+/// Part of a submitted job, see [Printer::set_header]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Section {
+    Header,
+    /// The job's own commands
+    Body,
+    Footer,
+}
+
+/// How far [Printer::submit_with_progress] got
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Progress {
+    /// Bytes of the job transferred to the printer
+    pub sent: usize,
+    /// Bytes the job encodes to, estimated before sending, cuts follow the
+    /// profile and may differ by a few bytes
+    pub total: usize,
+    /// Section the last bytes sent belong to
+    pub section: Section,
+}
+
+/// Stops a [Printer::submit_cancellable] from another thread, e.g. when
+/// the cashier gives up on a stuck printer
+///
+/// Clones cancel the same submission.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Bytes transferred between checks of a [CancellationToken]
+const CANCEL_STEP: usize = 1024;
+
+/// Bytes sent between progress reports, besides one per raster band
+const PROGRESS_STEP: usize = 4096;
+
+/// Real-time status read while a job is being sent, see
+/// [Printer::live_status]
+///
+/// Fields are None when the printer didn't answer the query.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LiveStatus {
+    pub online: Option<bool>,
+    pub paper: Option<PaperState>,
+    /// Total bytes sent to the printer so far
+    pub bytes_sent: usize,
+}
+
+/// Receives the status polled while printing, closures taking a
+/// [LiveStatus] implement it, see [Printer::set_status_monitor]
+pub trait StatusMonitor: Send {
+    fn status(&mut self, status: &LiveStatus);
+}
+
+impl<F: FnMut(&LiveStatus) + Send> StatusMonitor for F {
+    fn status(&mut self, status: &LiveStatus) {
+        self(status)
+    }
+}
+
+/// Sees every command of a submitted job with the bytes it encodes to and
+/// may change them, closures taking both implement it, see
+/// [Printer::add_job_middleware]
+pub trait JobMiddleware: Send {
+    fn process(&mut self, command: &Command, bytes: &mut Vec<u8>);
+}
+
+impl<F: FnMut(&Command, &mut Vec<u8>) + Send> JobMiddleware for F {
+    fn process(&mut self, command: &Command, bytes: &mut Vec<u8>) {
+        self(command, bytes)
+    }
+}
+
+/// Snapshot of the printer state returned by [Printer::health_check]
+///
+/// Fields are None when the printer doesn't support the query or didn't
+/// answer it.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HealthReport {
+    pub online: Option<bool>,
+    pub paper: Option<PaperState>,
+    pub cover_open: Option<bool>,
+    /// Every error condition that was reported
+    pub errors: Vec<StatusError>,
+    pub firmware: Option<String>,
+    pub serial: Option<String>,
+    /// Only queried once the slip station has been selected
+    pub slip: Option<SlipStatus>,
+}
+
+impl HealthReport {
+    /// The first of the errors that keeps the printer from printing, paper
+    /// near its end isn't one
+    pub fn blocking_error(&self) -> Option<StatusError> {
+        self.errors
+            .iter()
+            .copied()
+            .find(|error| *error != StatusError::PaperNearEnd)
+    }
+}
+
+/// Identity and wear counters of a printer returned by [Printer::inventory],
+/// for asset management
+///
+/// Fields are None when the printer doesn't support the query or didn't
+/// answer it.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PrinterInventory {
+    pub serial: Option<String>,
+    pub rom_version: Option<String>,
+    pub cut_count: Option<u32>,
+    pub power_count: Option<u32>,
+    pub printed_length: Option<PaperLength>,
+    pub remaining_paper: Option<PaperLength>,
+}
+
+/// Dots per stripe and horizontal density of ESC * bit images
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BitImageDensity {
+    /// 8 dots, half the horizontal resolution
+    S8,
+    /// 8 dots, full horizontal resolution
+    D8,
+    /// 24 dots, half the horizontal resolution
+    S24,
+    /// 24 dots, full horizontal resolution
+    D24,
+}
+
+impl BitImageDensity {
+    /// Density for an image height dots tall on a printer with dpi
+    ///
+    /// Impact printers (below 150 dpi) only print 8 dot stripes, and images
+    /// shorter than a 24 dot stripe would print nothing with one. Draft
+    /// quality halves the horizontal resolution, and the data sent.
+    pub fn auto(height: u32, dpi: u16, quality: ImageQuality) -> BitImageDensity {
+        let stripes_24 = dpi >= 150 && height >= 24;
+        match (stripes_24, quality) {
+            (false, ImageQuality::Draft) => BitImageDensity::S8,
+            (false, ImageQuality::Fine) => BitImageDensity::D8,
+            (true, ImageQuality::Draft) => BitImageDensity::S24,
+            (true, ImageQuality::Fine) => BitImageDensity::D24,
+        }
+    }
+
+    fn header(self) -> &'static [u8] {
+        match self {
+            BitImageDensity::S8 => consts::BITMAP_S8,
+            BitImageDensity::D8 => consts::BITMAP_D8,
+            BitImageDensity::S24 => consts::BITMAP_S24,
+            BitImageDensity::D24 => consts::BITMAP_D24,
+        }
+    }
+
+    /// Dots in one stripe
+    fn dots(self) -> u32 {
+        match self {
+            BitImageDensity::S8 | BitImageDensity::D8 => 8,
+            BitImageDensity::S24 | BitImageDensity::D24 => 24,
+        }
+    }
+}
+
+/// Trade-off of [Printer::bit_image] without a density
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImageQuality {
+    /// Less data and faster printing
+    Draft,
+    #[default]
+    Fine,
+}
+
+/// Print station of printers with more than one paper path
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Station {
+    #[default]
+    Receipt,
+    /// Cut sheets (cheques, hotel folios) inserted from the front
+    Slip,
+    Journal,
+}
+
+/// Slip station status (DLE EOT 5), see [Printer::slip_status]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SlipStatus {
+    /// The printer is waiting for a slip to be inserted
+    pub waiting_for_insertion: bool,
+    /// Top of form sensor sees paper
+    pub top_sensor_paper: bool,
+    /// Bottom of form sensor sees paper
+    pub bottom_sensor_paper: bool,
+}
+
+impl From<u8> for SlipStatus {
+    fn from(status: u8) -> Self {
+        SlipStatus {
+            waiting_for_insertion: ((status >> RT_SLIP_WAITING_BIT) & 1) == 1,
+            top_sensor_paper: ((status >> RT_SLIP_TOF_NO_PAPER_BIT) & 1) == 0,
+            bottom_sensor_paper: ((status >> RT_SLIP_BOF_NO_PAPER_BIT) & 1) == 0,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UsbInfo {
     /// vendor_id is the USB vendor id used when initializing the printer
     pub vendor_id: u16,
@@ -140,79 +854,19 @@ pub struct UsbInfo {
     // pub serial: String,
 }
 
-/// Allows for printing to a [::device]
-pub struct Printer {
-    codec: EncodingRef,
-    trap: EncoderTrap,
-    pub printer: SupportedPrinters,
+/// Open USB device of a [Printer]
+struct Connection {
     device: rusb::Device<rusb::GlobalContext>,
     handle: rusb::DeviceHandle<rusb::GlobalContext>,
     descriptor: rusb::DeviceDescriptor,
-    timeout: Duration,
-
-    /// USB Vendor ID
-    vid: u16,
-    /// USB Product ID
-    pid: u16,
     /// USB Command Endpoint (output)
     cmd_ep: u8,
     /// USB Status Endpoint (input)
     stat_ep: u8,
 }
 
-impl Printer {
-    pub fn get_mfg_info() -> Result<(SupportedPrinters, u16, u16), Box<dyn std::error::Error>> {
-        for device in rusb::devices().unwrap().iter() {
-            let timeout = Duration::from_millis(200);
-            let device_desc = match device.device_descriptor() {
-                Ok(d) => d,
-                Err(_) => continue,
-            };
-            let handle = match device.open() {
-                Ok(h) => h,
-                Err(_) => continue,
-            };
-            let languages = match handle.read_languages(timeout) {
-                Ok(l) => l,
-                Err(_) => continue,
-            };
-            let language = languages[0];
-            let vid: u16 = device_desc.vendor_id();
-            let pid: u16 = device_desc.product_id();
-            let ids = (vid, pid);
-            // SNBC in API mode doesn't have a MFG or Product string to match
-            // so we'll add a section to match on vid/pid
-            // Should we move all of the matches here?
-            if let (0x154f, 0x154f) = ids {
-                return Ok((SupportedPrinters::SNBC, vid, pid));
-            }
-            match handle.read_manufacturer_string(language, &device_desc, timeout) {
-                Ok(m) => {
-                    if m.starts_with("SNBC") {
-                        return Ok((SupportedPrinters::SNBC, vid, pid));
-                    } else if m.starts_with("Custom SpA") {
-                        return Ok((SupportedPrinters::P3, vid, pid));
-                    } else if m.starts_with("TransAct") {
-                        return Ok((SupportedPrinters::Epic, vid, pid));
-                    } else {
-                        continue;
-                    }
-                }
-                Err(_) => continue,
-            }
-        }
-        Err(Box::new(io::Error::new(
-            io::ErrorKind::Unsupported,
-            "Error no supported printers found",
-        )))
-    }
-    pub fn new(
-        codec: Option<EncodingRef>,
-        trap: Option<EncoderTrap>,
-        printer: SupportedPrinters,
-        vid: u16,
-        pid: u16,
-    ) -> Result<Self, Error> {
+impl Connection {
+    fn open(vid: u16, pid: u16, port: Option<(u8, u8)>) -> Result<Connection, Error> {
         // Iterate over the devices to find the printer
         let mut matches: VecDeque<_> = rusb::devices()?
             .iter()
@@ -224,7 +878,8 @@ impl Printer {
                         return None;
                     }
                 };
-                if desc.vendor_id() == vid && desc.product_id() == pid {
+                let at_port = port.is_none_or(|port| port == (d.bus_number(), d.address()));
+                if desc.vendor_id() == vid && desc.product_id() == pid && at_port {
                     Some((d, desc))
                 } else {
                     None
@@ -236,10 +891,10 @@ impl Printer {
             None => return Err(Error::NotFound),
         };
 
-        let mut handle = device.open()?;
+        let handle = device.open()?;
 
         let _ = handle.set_auto_detach_kernel_driver(true);
-        handle.claim_interface(0).expect("Cannot claim_interface");
+        handle.claim_interface(0)?;
 
         let config_desc = match device.config_descriptor(0) {
             Ok(v) => v,
@@ -288,862 +943,6762 @@ impl Printer {
         }
         let _ = handle.claim_interface(interface.number());
 
-        Ok(Printer {
-            // file,
-            codec: codec.unwrap_or(UTF_8 as EncodingRef),
-            trap: trap.unwrap_or(EncoderTrap::Replace),
-            printer,
+        Ok(Connection {
             device,
             handle,
             descriptor,
-            timeout: Duration::from_millis(TIMEOUT),
-            vid,
-            pid,
             cmd_ep,
             stat_ep,
         })
     }
+}
 
-    pub fn release(&mut self) -> Result<(), Error> {
-        let config_desc = match self.device.config_descriptor(0) {
-            Ok(v) => v,
-            Err(e) => {
-                return Err(e.into());
-            }
-        };
+/// Allows for printing to a [::device]
+pub struct Printer {
+    codec: EncodingRef,
+    trap: EncoderTrap,
+    pub printer: SupportedPrinters,
+    /// None until first use or after the device went away
+    connection: Option<Connection>,
+    timeout: Duration,
 
-        let interface = match config_desc.interfaces().next() {
-            Some(x) => x,
-            None => {
-                return Err(Error::InvalidEndpoints);
+    /// USB Vendor ID
+    vid: u16,
+    /// USB Product ID
+    pid: u16,
+    /// USB bus and address, to tell apart printers with the same ids
+    port: Option<(u8, u8)>,
+
+    drawer_polarity: DrawerPolarity,
+    quirks: Quirks,
+    profile: Profile,
+    /// Detected on first use, see [Printer::nv_image_commands]
+    nv_commands: Option<NvImageCommands>,
+
+    flow_control: FlowControl,
+    /// Bytes sent since the printer last reported its buffer drained
+    unpaced_bytes: usize,
+    /// Bytes sent since the last [Throttle::DelayAfter] pause
+    throttled_bytes: usize,
+
+    /// Total bytes sent to the printer
+    bytes_sent: usize,
+    /// Bytes waiting to be transferred together while batching, see
+    /// [Printer::batch]. Kept between batches so its capacity is reused.
+    batch: Vec<u8>,
+    batching: bool,
+    /// Whether the batch is the outermost command's own, not a
+    /// [Printer::batch]
+    batch_owned: bool,
+    /// Inside a [Printer::batch]
+    in_batch: bool,
+    /// Inside [Printer::capture], nothing goes to the printer
+    capturing: bool,
+    /// Reused for encoding text
+    text_buffer: Vec<u8>,
+    /// Characters printed as pictures, see [Printer::set_glyphs]
+    glyphs: GlyphSet,
+    /// Kana printed as half-width katakana, see [Printer::set_halfwidth_kana]
+    halfwidth_kana: bool,
+    /// Reject arguments instead of using defaults, see [Printer::set_strict]
+    strict: bool,
+    history: Option<History>,
+    /// Check [Printer::raw] bytes with the parser
+    validate_raw: bool,
+    degradation: Degradation,
+    image_quality: ImageQuality,
+    /// Number of commands currently running, see [Printer::command]
+    command_depth: usize,
+
+    session: Session,
+    /// Text attributes the printer has, see [Printer::forget_state]
+    text_state: TextState,
+    /// Area of the open page and the attributes from before it, see
+    /// [Printer::begin_page]
+    page: Option<(PageArea, TextState)>,
+    /// Bytes sent to the open page, to check they fit it
+    page_content: Vec<u8>,
+    station: Station,
+    /// Bytes sent while a macro is being defined, see [Printer::define_macro]
+    recording: Option<Vec<u8>>,
+    /// Printed around every submitted job, see [Printer::set_header]
+    header: Option<Job>,
+    footer: Option<Job>,
+    /// Whether the macro holds the header
+    header_in_macro: bool,
+    /// Gets a copy of every job printed, see [Printer::set_archive]
+    archive: Option<(ArchiveOptions, Box<dyn Archive>)>,
+    /// Polled while sending, see [Printer::set_status_monitor]
+    monitor: Option<(Duration, Box<dyn StatusMonitor>)>,
+    /// Run on every command of a job, see [Printer::add_job_middleware]
+    middleware: Vec<Box<dyn JobMiddleware>>,
+    last_poll: Option<Instant>,
+    /// A GS r was sent for flow control and its answer not read yet
+    drain_pending: bool,
+    /// Its answer, when it arrived while reading a DLE EOT answer
+    drain_reply: Option<u8>,
+    /// Checked between transfers, see [Printer::submit_cancellable]
+    cancel: Option<CancellationToken>,
+    /// Negotiate the code pages on connecting, see
+    /// [Printer::set_auto_code_pages]
+    auto_code_pages: bool,
+    /// Paper used since the roll was changed, see
+    /// [Printer::estimated_remaining_mm]
+    paper_used_mm: f32,
+    /// The printer's printed length counter when the roll was changed, see
+    /// [Printer::sync_paper_used]
+    roll_start_mm: Option<u64>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Box<dyn Metrics>>,
+}
+
+/// Settings and downloaded data the printer forgets when it is reset or power
+/// cycled, re-sent by [Printer::resync]
+#[derive(Clone, Debug, Default)]
+struct Session {
+    code_page: Option<u8>,
+    density: Option<i8>,
+    /// Complete GS * command defining the RAM logo
+    logo: Option<Vec<u8>>,
+    /// Macro contents, without the enclosing GS :
+    macro_data: Option<Vec<u8>>,
+    needs_resync: bool,
+    /// Online state from the last status query
+    online: Option<bool>,
+    /// Power-on counter from the last [Printer::check_power_cycle]
+    power_count: Option<u32>,
+}
+
+impl Session {
+    fn is_empty(&self) -> bool {
+        self.code_page.is_none()
+            && self.density.is_none()
+            && self.logo.is_none()
+            && self.macro_data.is_none()
+    }
+
+    /// Whether any of the state ESC @ clears was set, the macro survives it
+    fn reset_clears(&self) -> bool {
+        self.code_page.is_some() || self.density.is_some() || self.logo.is_some()
+    }
+
+    /// Records the online state, a printer that comes back online may have
+    /// been power cycled while it was offline
+    ///
+    /// Returns true when it came back online.
+    fn track_online(&mut self, online: bool) -> bool {
+        let back = self.online == Some(false) && online;
+        if back && !self.is_empty() {
+            self.needs_resync = true;
+        }
+        self.online = Some(online);
+        back
+    }
+}
+
+/// Text attributes last sent to the printer, None when unknown
+///
+/// Attribute commands are skipped when they wouldn't change anything, see
+/// [Printer::attribute].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct TextState {
+    align: Option<Alignment>,
+    font: Option<u8>,
+    bold: Option<bool>,
+    /// Underline thickness in dots, 0 for none
+    underline: Option<u8>,
+    reverse: Option<bool>,
+    /// Width and height magnification
+    size: Option<(u8, u8)>,
+    code_page: Option<u8>,
+}
+
+/// Dialect of a USB device from its ids and manufacturer string
+pub(crate) fn match_manufacturer(
+    vid: u16,
+    pid: u16,
+    manufacturer: Option<&str>,
+) -> Option<SupportedPrinters> {
+    // SNBC in API mode doesn't have a MFG or Product string to match
+    // so we'll add a section to match on vid/pid
+    // Should we move all of the matches here?
+    if let (0x154f, 0x154f) = (vid, pid) {
+        return Some(SupportedPrinters::SNBC);
+    }
+    let m = manufacturer?;
+    if m.starts_with("SNBC") {
+        Some(SupportedPrinters::SNBC)
+    } else if m.starts_with("Custom SpA") {
+        Some(SupportedPrinters::P3)
+    } else if m.starts_with("TransAct") {
+        Some(SupportedPrinters::Epic)
+    } else if m.starts_with("BIXOLON") {
+        Some(SupportedPrinters::Bixolon)
+    } else if m.starts_with("CITIZEN") {
+        Some(SupportedPrinters::Citizen)
+    } else if m.starts_with("STAR") || m.starts_with("Star Micronics") {
+        // Star Line Mode is the factory default
+        Some(SupportedPrinters::Star)
+    } else {
+        None
+    }
+}
+
+/// The [Printer::align] argument for alignment
+fn alignment_code(alignment: Alignment) -> &'static str {
+    match alignment {
+        Alignment::Left => "LT",
+        Alignment::Center => "CT",
+        Alignment::Right => "RT",
+    }
+}
+
+/// image as columns of rows bytes of 8 vertical dots each, left to right and
+/// padded with white to columns dots wide
+fn column_bytes(image: &Image, columns: u32, rows: u32) -> Vec<u8> {
+    let mut data = Vec::with_capacity((columns * rows) as usize);
+    for column in 0..columns {
+        for row in 0..rows {
+            let mut byte = 0_u8;
+            for bit in 0..8 {
+                let py = row * 8 + bit;
+                if column < image.width && py < image.height && !image.is_blank_pixel(column, py) {
+                    byte |= 0x80 >> bit;
+                }
+            }
+            data.push(byte);
+        }
+    }
+    data
+}
+
+/// Key code of NV graphics image n, two printable characters
+fn nv_key(n: u8) -> [u8; 2] {
+    [0x20 + n / 95, 0x20 + n % 95]
+}
+
+/// Model name from a GS I 67 answer, printers without the `_` header send
+/// just the name
+fn parse_model_name(answer: &[u8]) -> Option<String> {
+    let answer = answer.strip_prefix(b"_").unwrap_or(answer);
+    let end = answer.iter().position(|b| *b == 0).unwrap_or(answer.len());
+    let name = std::str::from_utf8(&answer[..end]).ok()?.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// GS ( E fn = 5 with the customized values of settings
+fn config_cmd(settings: &[ConfigSetting]) -> Result<Vec<u8>, Error> {
+    let len = u16::try_from(1 + settings.len() * 3).map_err(|_| Error::InvalidArgument)?;
+    let mut cmd = vec![0x1d, 0x28, 0x45];
+    cmd.extend_from_slice(&len.to_le_bytes());
+    cmd.push(0x05);
+    for setting in settings {
+        let (number, value) = setting.value()?;
+        cmd.push(number);
+        cmd.extend_from_slice(&value.to_le_bytes());
+    }
+    Ok(cmd)
+}
+
+/// Value in a GS ( E fn = 6 answer: `7`, an identifier, the setting number,
+/// 1F, the value in ASCII decimal and NUL
+/// Paper width in mm of the customized value 3, see
+/// [ConfigSetting::PaperWidth]
+fn paper_width_mm(value: u16) -> Option<u8> {
+    match value {
+        6 => Some(58),
+        8 => Some(80),
+        _ => None,
+    }
+}
+
+fn parse_config_value(answer: &[u8]) -> Option<u16> {
+    let answer = answer.strip_prefix(b"7")?;
+    let start = answer.iter().position(|b| *b == 0x1f)? + 1;
+    let end = answer.iter().position(|b| *b == 0).unwrap_or(answer.len());
+    std::str::from_utf8(answer.get(start..end)?)
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Commands of job with the header before them and the footer before the
+/// final feeds and cut, with the section each is from
+fn framed<'a>(
+    header: Option<&'a Job>,
+    job: &'a Job,
+    footer: Option<&'a Job>,
+) -> impl Iterator<Item = (Section, &'a Command)> {
+    let tail = footer_position(&job.commands);
+    let section = |section| move |command| (section, command);
+    header
+        .into_iter()
+        .flat_map(|header| &header.commands)
+        .map(section(Section::Header))
+        .chain(job.commands[..tail].iter().map(section(Section::Body)))
+        .chain(
+            footer
+                .into_iter()
+                .flat_map(|footer| &footer.commands)
+                .map(section(Section::Footer)),
+        )
+        .chain(job.commands[tail..].iter().map(section(Section::Body)))
+}
+
+/// text cut into lines of at most width characters, after the last `/`,
+/// `?`, `&`, `-` or space of a line when there is one past its middle
+#[cfg(feature = "qrcode")]
+fn wrap_code(text: &str, width: usize) -> Vec<&str> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let Some((end, _)) = rest.char_indices().nth(width) else {
+            lines.push(rest);
+            break;
+        };
+        let end = rest[..end]
+            .rfind(['/', '?', '&', '-', ' '])
+            .filter(|i| *i >= end / 2)
+            .map_or(end, |i| i + 1);
+        lines.push(rest[..end].trim_end());
+        rest = rest[end..].trim_start();
+    }
+    lines
+}
+
+/// Text of the [Printer::reprint] marks
+const COPY_LABEL: &str = "COPY";
+
+/// Commands of a [CopyMark] on paper columns characters and dots wide
+fn copy_mark(mark: CopyMark, columns: usize, dots: u32) -> Vec<Command> {
+    match mark {
+        CopyMark::Inverse => vec![
+            Command::Align(Alignment::Center),
+            Command::Reverse(true),
+            Command::Bold(true),
+            Command::Size {
+                width: 2,
+                height: 2,
+            },
+            // Padded so the black bar runs across the paper
+            Command::Text(format!("{:^width$}", COPY_LABEL, width = columns / 2)),
+            Command::NewLine,
+            Command::Size {
+                width: 1,
+                height: 1,
+            },
+            Command::Bold(false),
+            Command::Reverse(false),
+            Command::Align(Alignment::Left),
+        ],
+        CopyMark::Diagonal => vec![copy_watermark(dots)],
+    }
+}
+
+/// `COPY ` repeated across dots, every letter lower than the one before
+fn copy_watermark(dots: u32) -> Command {
+    // The 5 x 7 preview font at 4 times, a letter every 24 dots dropping 12
+    const SCALE: u32 = 4;
+    const STEP: u32 = 24;
+    const DROP: u32 = 12;
+    let pattern: Vec<char> = format!("{} ", COPY_LABEL).chars().collect();
+    let width_bytes = dots.div_ceil(8).max(1);
+    let height = 7 * SCALE + DROP * (pattern.len() as u32 - 1);
+    let mut data = vec![0u8; (width_bytes * height) as usize];
+    for (i, x0) in (0..dots.saturating_sub(5 * SCALE - 1))
+        .step_by(STEP as usize)
+        .enumerate()
+    {
+        let y0 = (i % pattern.len()) as u32 * DROP;
+        for (col, bits) in crate::preview::glyph(pattern[i % pattern.len()])
+            .iter()
+            .enumerate()
+        {
+            for row in (0..7).filter(|row| bits & (1 << row) != 0) {
+                for y in y0 + row * SCALE..y0 + (row + 1) * SCALE {
+                    for x in x0 + col as u32 * SCALE..x0 + (col as u32 + 1) * SCALE {
+                        data[(y * width_bytes + x / 8) as usize] |= 0x80 >> (x % 8);
+                    }
+                }
+            }
+        }
+    }
+    Command::Raster {
+        width_bytes: width_bytes as u16,
+        height: height as u16,
+        data,
+    }
+}
+
+/// Line feeds, then GS V m, or GS V m n to feed past the cutting position
+fn feed_and_cut(feed: CutFeed, partial: bool, dpi: u16) -> Result<Vec<u8>, Error> {
+    let mut bytes = vec![0x0a; feed.lines_before as usize];
+    match feed.mm_after {
+        0 => bytes.extend_from_slice(&[0x1d, 0x56, partial as u8]),
+        mm => bytes.extend_from_slice(&[
+            0x1d,
+            0x56,
+            0x41 + partial as u8,
+            feed_units_for(SupportedPrinters::Generic, mm, dpi)?,
+        ]),
+    }
+    Ok(bytes)
+}
+
+/// Line feeds, then a cut on printer feeding mm_after past the cutting
+/// position, the bytes of [Printer::cut]
+pub(crate) fn cut_bytes(
+    printer: SupportedPrinters,
+    partial: bool,
+    feed: CutFeed,
+    dpi: u16,
+) -> Result<Vec<u8>, Error> {
+    let units = feed_units_for(printer, feed.mm_after, dpi)?;
+    let mut bytes = vec![0x0a; feed.lines_before as usize];
+    // ESC J
+    let esc_j = match units {
+        0 => Vec::new(),
+        _ => vec![0x1b, 0x4a, units],
+    };
+    match (printer, partial) {
+        (SupportedPrinters::Star, _) => {
+            bytes.extend_from_slice(&esc_j);
+            bytes.extend_from_slice(match partial {
+                true => consts::STAR_PARTIAL_CUT,
+                false => consts::STAR_FULL_CUT,
+            });
+        }
+        (SupportedPrinters::P3, true) => {
+            bytes.extend_from_slice(&esc_j);
+            bytes.extend_from_slice(&[0x1b, 0x6d]);
+        }
+        (SupportedPrinters::P3 | SupportedPrinters::Bixolon, false)
+        | (SupportedPrinters::Unknown, _) => return Err(Error::Unsupported),
+        (_, partial) => bytes.extend_from_slice(&[0x1d, 0x56, 0x41 + partial as u8, units]),
+    }
+    Ok(bytes)
+}
+
+/// mm in the units ESC J feeds on printer with dpi, the default vertical
+/// motion unit of one dot, or 1/4 mm in Star Line Mode
+///
+/// Returns [Error::InvalidArgument] when that's more than ESC J can feed.
+pub(crate) fn feed_units_for(printer: SupportedPrinters, mm: u8, dpi: u16) -> Result<u8, Error> {
+    let units = match printer {
+        SupportedPrinters::Star => mm as u32 * 4,
+        _ => (mm as f32 * dpi as f32 / 25.4).round() as u32,
+    };
+    u8::try_from(units).map_err(|_| Error::InvalidArgument)
+}
+
+/// Index of the feeds and cut ending commands, where the footer goes
+fn footer_position(commands: &[Command]) -> usize {
+    commands
+        .iter()
+        .rposition(|c| !matches!(c, Command::Feed(_) | Command::Cut { .. }))
+        .map_or(0, |i| i + 1)
+}
+
+/// ESC t n, or ESC GS t n in Star Line Mode
+fn code_page_cmd(printer: SupportedPrinters, n: u8) -> Vec<u8> {
+    match printer {
+        SupportedPrinters::Star => vec![0x1b, 0x1d, 0x74, n],
+        _ => vec![0x1b, 0x74, n],
+    }
+}
+
+/// How much of len bytes throttle lets through before its next pause, when
+/// sent bytes went out since the last one
+fn throttle_part(throttle: Throttle, sent: usize, len: usize) -> usize {
+    match throttle {
+        // A tenth of a second each, so the rate holds within a transfer too
+        Throttle::BytesPerSecond(rate) => len.min((rate as usize / 10).max(1)),
+        Throttle::DelayAfter { bytes, .. } => {
+            let bytes = bytes.max(1);
+            len.min(bytes - sent.min(bytes - 1))
+        }
+    }
+}
+
+impl Printer {
+    pub fn get_mfg_info() -> Result<(SupportedPrinters, u16, u16), Box<dyn std::error::Error>> {
+        for device in rusb::devices().unwrap().iter() {
+            let timeout = Duration::from_millis(200);
+            let device_desc = match device.device_descriptor() {
+                Ok(d) => d,
+                Err(_) => continue,
+            };
+            let handle = match device.open() {
+                Ok(h) => h,
+                Err(_) => continue,
+            };
+            let languages = match handle.read_languages(timeout) {
+                Ok(l) => l,
+                Err(_) => continue,
+            };
+            let language = languages[0];
+            let vid: u16 = device_desc.vendor_id();
+            let pid: u16 = device_desc.product_id();
+            let manufacturer = handle
+                .read_manufacturer_string(language, &device_desc, timeout)
+                .ok();
+            if let Some(printer) = match_manufacturer(vid, pid, manufacturer.as_deref()) {
+                return Ok((printer, vid, pid));
+            }
+        }
+        Err(Box::new(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Error no supported printers found",
+        )))
+    }
+    pub fn new(
+        codec: Option<EncodingRef>,
+        trap: Option<EncoderTrap>,
+        printer: SupportedPrinters,
+        vid: u16,
+        pid: u16,
+    ) -> Result<Self, Error> {
+        let mut p = Printer::lazy(codec, trap, printer, vid, pid);
+        p.reconnect()?;
+        Ok(p)
+    }
+
+    /// Creates the printer without opening the device, it's opened on first
+    /// use, so the printer can be created before it's plugged in
+    ///
+    /// Until then commands fail with the error opening it, [Error::NotFound]
+    /// when it isn't plugged in. See [Printer::is_connected].
+    pub fn lazy(
+        codec: Option<EncodingRef>,
+        trap: Option<EncoderTrap>,
+        printer: SupportedPrinters,
+        vid: u16,
+        pid: u16,
+    ) -> Self {
+        Printer {
+            // file,
+            codec: codec.unwrap_or(UTF_8 as EncodingRef),
+            trap: trap.unwrap_or(EncoderTrap::Replace),
+            printer,
+            connection: None,
+            timeout: Duration::from_millis(TIMEOUT),
+            vid,
+            pid,
+            port: None,
+            drawer_polarity: printer.drawer_polarity(),
+            quirks: printer.quirks(),
+            nv_commands: None,
+            profile: Profile::from(printer),
+            flow_control: FlowControl::None,
+            unpaced_bytes: 0,
+            throttled_bytes: 0,
+            bytes_sent: 0,
+            batch: Vec::new(),
+            batching: false,
+            batch_owned: false,
+            in_batch: false,
+            capturing: false,
+            text_buffer: Vec::new(),
+            glyphs: GlyphSet::default(),
+            halfwidth_kana: false,
+            strict: false,
+            history: None,
+            validate_raw: false,
+            degradation: Degradation::default(),
+            image_quality: ImageQuality::default(),
+            command_depth: 0,
+            session: Session::default(),
+            text_state: TextState::default(),
+            page: None,
+            page_content: Vec::new(),
+            station: Station::Receipt,
+            recording: None,
+            header: None,
+            footer: None,
+            header_in_macro: false,
+            archive: None,
+            monitor: None,
+            middleware: Vec::new(),
+            last_poll: None,
+            drain_pending: false,
+            drain_reply: None,
+            cancel: None,
+            auto_code_pages: true,
+            paper_used_mm: 0.0,
+            roll_start_mm: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Opens the printer at a USB bus and address, as [discovery](crate::discovery)
+    /// lists them, for when several printers share the same ids
+    ///
+    /// The address changes when the printer is plugged in again, then
+    /// reconnecting fails with [Error::NotFound].
+    pub fn at_port(
+        codec: Option<EncodingRef>,
+        trap: Option<EncoderTrap>,
+        printer: SupportedPrinters,
+        vid: u16,
+        pid: u16,
+        bus: u8,
+        address: u8,
+    ) -> Result<Self, Error> {
+        let mut p = Printer::lazy(codec, trap, printer, vid, pid);
+        p.port = Some((bus, address));
+        p.reconnect()?;
+        Ok(p)
+    }
+
+    /// Whether the device is open. It's closed until first use with
+    /// [Printer::lazy] and after it was unplugged.
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Opens the device again, releasing it first when it's open
+    pub fn reconnect(&mut self) -> Result<(), Error> {
+        if self.connection.is_some() {
+            self.release()?;
+            self.connection = None;
+        }
+        self.connection = Some(Connection::open(self.vid, self.pid, self.port)?);
+        // A new connection is a printer in an unknown state, it may have
+        // been power cycled while it was away
+        self.unpaced_bytes = 0;
+        self.throttled_bytes = 0;
+        self.session.needs_resync = !self.session.is_empty();
+        self.text_state = TextState::default();
+        if self.auto_code_pages {
+            self.negotiate_on_connect();
+        }
+        Ok(())
+    }
+
+    /// [Printer::negotiate_code_pages] for a new connection, which may be
+    /// opened in the middle of sending a batch, so the query is transferred
+    /// on its own. Printers that don't answer keep the profile's pages.
+    fn negotiate_on_connect(&mut self) {
+        if matches!(
+            self.printer,
+            SupportedPrinters::Star | SupportedPrinters::P3 | SupportedPrinters::Unknown
+        ) {
+            return;
+        }
+        let timeout = self.timeout;
+        let mut buffer = [0_u8; 32];
+        let answer = self.write_bulk(MODEL_NAME).and_then(|_| {
+            let connection = self.connection()?;
+            let res = connection
+                .handle
+                .read_bulk(connection.stat_ep, &mut buffer, timeout);
+            self.check_connection(res)
+        });
+        match answer.map(|n| parse_model_name(&buffer[..n])) {
+            Ok(Some(model)) => {
+                if !self.profile.restrict_code_pages(&model) {
+                    log::debug!("No code page list for {}, keeping the profile's", model);
+                }
+            }
+            Ok(None) => log::debug!("No model name to negotiate the code pages with"),
+            Err(e) => log::debug!("Asking for the model name failed: {}", e),
+        }
+    }
+
+    /// Whether the code pages are negotiated with
+    /// [Printer::negotiate_code_pages] whenever the printer is connected, on
+    /// by default
+    ///
+    /// Turn it off for printers whose model name is misleading, or to keep
+    /// every page of a hand-written profile.
+    pub fn set_auto_code_pages(&mut self, on: bool) {
+        self.auto_code_pages = on;
+    }
+
+    /// The open device, opening it when it isn't
+    fn connection(&mut self) -> Result<&Connection, Error> {
+        if self.connection.is_none() {
+            self.reconnect()?;
+        }
+        self.connection.as_ref().ok_or(Error::NotFound)
+    }
+
+    /// Forgets the connection when the device went away, so the next command
+    /// opens it again
+    fn check_connection<T>(&mut self, res: rusb::Result<T>) -> Result<T, Error> {
+        if let Err(rusb::Error::NoDevice) = res {
+            self.connection = None;
+        }
+        Ok(res?)
+    }
+
+    pub fn release(&mut self) -> Result<(), Error> {
+        let Some(connection) = &self.connection else {
+            return Ok(());
+        };
+        let config_desc = match connection.device.config_descriptor(0) {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(e.into());
+            }
+        };
+
+        let interface = match config_desc.interfaces().next() {
+            Some(x) => x,
+            None => {
+                return Err(Error::InvalidEndpoints);
             }
         };
-        let _ = self.handle.release_interface(interface.number());
-        let _ = self.handle.release_interface(0);
+        let _ = connection.handle.release_interface(interface.number());
+        let _ = connection.handle.release_interface(0);
         Ok(())
     }
 
-    pub fn info(&mut self) -> Result<UsbInfo, Error> {
-        let languages = self.handle.read_languages(self.timeout)?;
-        let language = languages[0];
-
-        let manufacturer = self
-            .handle
-            .read_manufacturer_string(language, &self.descriptor, self.timeout)
-            .unwrap_or("".to_string());
-        let product = self
-            .handle
-            .read_product_string(language, &self.descriptor, self.timeout)
-            .unwrap_or("".to_string());
-        Ok(UsbInfo {
-            vendor_id: self.vid,
-            product_id: self.pid,
-            manufacturer,
-            product,
-        })
+    pub fn info(&mut self) -> Result<UsbInfo, Error> {
+        let timeout = self.timeout;
+        let connection = self.connection()?;
+        let languages = connection.handle.read_languages(timeout)?;
+        let language = languages[0];
+
+        let manufacturer = connection
+            .handle
+            .read_manufacturer_string(language, &connection.descriptor, timeout)
+            .unwrap_or("".to_string());
+        let product = connection
+            .handle
+            .read_product_string(language, &connection.descriptor, timeout)
+            .unwrap_or("".to_string());
+        Ok(UsbInfo {
+            vendor_id: self.vid,
+            product_id: self.pid,
+            manufacturer,
+            product,
+        })
+    }
+
+    // --------------------------------------------------
+
+    pub(crate) fn encode(&mut self, content: &str) -> io::Result<Vec<u8>> {
+        self.codec
+            .encode(content, self.trap)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+    }
+
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if self.command_depth == 0 {
+            // Raw bytes may change any attribute
+            self.text_state = TextState::default();
+        }
+        self.command("write", || history::hex_preview(buf), |p| p.send(buf))
+    }
+
+    /// Forgets the text attributes the printer is known to have, so the
+    /// next attribute commands are sent even when they repeat the last ones
+    ///
+    /// Needed after something else printed on the printer, commands sent
+    /// through [Printer::write] already do this.
+    pub fn forget_state(&mut self) {
+        self.text_state = TextState::default();
+    }
+
+    /// Runs an attribute command, or nothing when the printer already has
+    /// state. A state of None is always sent.
+    ///
+    /// Nothing is skipped while a macro is recorded, the macro runs later
+    /// with whatever the attributes are then.
+    fn attribute<P, F>(
+        &mut self,
+        name: &'static str,
+        parameters: P,
+        state: Option<TextState>,
+        f: F,
+    ) -> Result<usize, Error>
+    where
+        P: FnOnce() -> String,
+        F: FnOnce(&mut Self) -> Result<usize, Error>,
+    {
+        let recording = self.recording.is_some();
+        if !recording && state == Some(self.text_state) {
+            return Ok(0);
+        }
+        let n = self.command(name, parameters, f)?;
+        if let (Some(state), false) = (state, recording) {
+            self.text_state = state;
+        }
+        Ok(n)
+    }
+
+    /// Sends buf to the printer, or adds it to the batch when one is open
+    pub(crate) fn send(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.bytes_sent += buf.len();
+        if let Some(recording) = self.recording.as_mut() {
+            recording.extend_from_slice(buf);
+        }
+        if self.page.is_some() {
+            self.page_content.extend_from_slice(buf);
+        }
+        if self.batching {
+            self.batch.extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+        self.transfer(buf)
+    }
+
+    /// Transfers buf to the printer, in parts while a [CancellationToken]
+    /// can stop it
+    fn transfer(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let Some(token) = self.cancel.clone() else {
+            return self.transfer_throttled(buf);
+        };
+        for part in buf.chunks(CANCEL_STEP) {
+            if token.is_canceled() {
+                return Err(self.abort_transfer());
+            }
+            self.transfer_throttled(part)?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Clears what the printer received but hasn't printed yet, after a
+    /// [CancellationToken] stopped a transfer
+    ///
+    /// Dialects without DLE DC4 8 print what they already received. The code
+    /// page the printer is known to be in is selected again, a job may have
+    /// switched to its own before it was canceled.
+    fn abort_transfer(&mut self) -> Error {
+        if let Some(clear) = clear_buffers_cmd(self.printer) {
+            if let Err(e) = self.write_bulk(clear) {
+                log::warn!("Clearing the buffers of a canceled job failed: {}", e);
+            }
+        }
+        if let Some(page) = self.session.code_page {
+            if let Err(e) = self.write_bulk(&code_page_cmd(self.printer, page)) {
+                log::warn!("Selecting the code page after a cancel failed: {}", e);
+            }
+        }
+        self.unpaced_bytes = 0;
+        Error::Canceled
+    }
+
+    fn transfer_throttled(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        match self.profile.throttle {
+            None => self.write_bulk(buf),
+            // Throttled printers get several smaller transfers instead
+            Some(throttle @ Throttle::BytesPerSecond(rate)) => {
+                let rate = rate.max(1) as usize;
+                for part in buf.chunks(throttle_part(throttle, 0, buf.len()).max(1)) {
+                    let start = Instant::now();
+                    self.write_bulk(part)?;
+                    let budget = Duration::from_secs_f64(part.len() as f64 / rate as f64);
+                    if let Some(rest) = budget.checked_sub(start.elapsed()) {
+                        std::thread::sleep(rest);
+                    }
+                }
+                Ok(buf.len())
+            }
+            Some(throttle @ Throttle::DelayAfter { bytes, delay_ms }) => {
+                let mut rest = buf;
+                while !rest.is_empty() {
+                    let n = throttle_part(throttle, self.throttled_bytes, rest.len());
+                    self.write_bulk(&rest[..n])?;
+                    rest = &rest[n..];
+                    self.throttled_bytes += n;
+                    if self.throttled_bytes >= bytes.max(1) {
+                        std::thread::sleep(Duration::from_millis(delay_ms));
+                        self.throttled_bytes = 0;
+                    }
+                }
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn write_bulk(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+        let timeout = self.timeout;
+        let connection = self.connection()?;
+        let res = connection
+            .handle
+            .write_bulk(connection.cmd_ep, buf, timeout);
+        let n_bytes = self.check_connection(res)?;
+        if n_bytes != buf.len() {
+            return Err(Error::Timeout);
+        }
+        #[cfg(feature = "metrics")]
+        self.emit(Event::Transfer {
+            bytes: n_bytes,
+            duration: start.elapsed(),
+        });
+
+        Ok(n_bytes)
+    }
+
+    /// Transfers the batched bytes, the batch stays open
+    fn flush_batch(&mut self) -> Result<(), Error> {
+        // Captured bytes are returned by capture, never sent
+        if self.batch.is_empty() || self.capturing {
+            return Ok(());
+        }
+        let mut batch = std::mem::take(&mut self.batch);
+        let res = self.transfer(&batch);
+        batch.clear();
+        self.batch = batch;
+        res.map(|_| ())
+    }
+
+    /// Runs f with everything it sends going to the printer in as few USB
+    /// transfers as possible, for example a whole receipt
+    ///
+    /// Every command is already batched on its own, this extends the batch
+    /// over several commands. Anything that waits for an answer from the
+    /// printer (status queries, [FlowControl::StatusPaced]) sends the batch
+    /// so far first. Real-time commands such as [Printer::clear_buffers]
+    /// aren't batched, they're transferred right away.
+    pub fn batch<F>(&mut self, f: F) -> Result<usize, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<usize, Error>,
+    {
+        if self.batching {
+            return f(self);
+        }
+        self.batching = true;
+        self.in_batch = true;
+        let res = f(self);
+        let flushed = self.flush_batch();
+        self.batching = false;
+        self.in_batch = false;
+        res.and_then(|n| flushed.map(|_| n))
+    }
+
+    /// Runs f and returns the bytes it sent instead of sending them, for
+    /// tests, see [testing](crate::testing)
+    ///
+    /// Anything that waits for an answer from the printer still needs it
+    /// connected. Commands that send the batch early, like a cut on the
+    /// Epic or [Printer::flush], keep it instead.
+    pub fn capture<F>(&mut self, f: F) -> Result<Vec<u8>, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<usize, Error>,
+    {
+        let batching = std::mem::replace(&mut self.batching, true);
+        let capturing = std::mem::replace(&mut self.capturing, true);
+        let start = self.batch.len();
+        let res = f(self);
+        let bytes = self.batch.split_off(start.min(self.batch.len()));
+        self.batching = batching;
+        self.capturing = capturing;
+        res.map(|_| bytes)
+    }
+
+    /// Reads from the status endpoint once everything batched was sent
+    fn read_status(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error> {
+        self.flush_batch()?;
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+        let connection = self.connection()?;
+        let res = connection
+            .handle
+            .read_bulk(connection.stat_ep, buf, timeout);
+        let n_bytes = self.check_connection(res)?;
+        #[cfg(feature = "metrics")]
+        if n_bytes > 0 {
+            self.emit(Event::StatusQuery {
+                latency: start.elapsed(),
+            });
+        }
+        Ok(n_bytes)
+    }
+
+    /// Runs one logical command, recording it in the history when enabled
+    ///
+    /// Commands run by other commands (println calling print, everything
+    /// calling write) are recorded as part of the outermost command.
+    ///
+    /// When the printer lost its session state (see [Printer::resync]) the state
+    /// is restored before the command runs. Commands the profile overrides send
+    /// the profile's bytes instead.
+    ///
+    /// The bytes of the outermost command are sent in one transfer when it
+    /// finishes, unless a [Printer::batch] is open.
+    pub(crate) fn command<P, F>(
+        &mut self,
+        name: &'static str,
+        parameters: P,
+        f: F,
+    ) -> Result<usize, Error>
+    where
+        P: FnOnce() -> String,
+        F: FnOnce(&mut Self) -> Result<usize, Error>,
+    {
+        if self.command_depth == 0 && self.session.needs_resync {
+            self.session.needs_resync = false;
+            if let Err(e) = self.resync() {
+                self.session.needs_resync = true;
+                return Err(e);
+            }
+        }
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+        let owns_batch = self.command_depth == 0 && !self.batching;
+        if owns_batch {
+            self.batching = true;
+            self.batch_owned = true;
+        }
+        self.command_depth += 1;
+        let bytes_before = self.bytes_sent;
+        let mut res = match self.profile.command(name) {
+            Some(cmd) => self.send(&cmd),
+            None => f(self),
+        };
+        if owns_batch {
+            // Whatever was sent before an error still goes out, as it would
+            // have without batching
+            let flushed = self.flush_batch();
+            self.batching = false;
+            self.batch_owned = false;
+            if let (Ok(_), Err(e)) = (&res, flushed) {
+                res = Err(e);
+            }
+        }
+        let bytes = self.bytes_sent - bytes_before;
+        self.command_depth -= 1;
+        if res.is_err() {
+            // Part of the command may have been sent, so the attributes
+            // are resent rather than assumed
+            self.text_state = TextState::default();
+        }
+        if self.command_depth == 0 {
+            if let Some(history) = self.history.as_mut() {
+                history.push(HistoryEntry {
+                    command: name,
+                    parameters: parameters(),
+                    bytes,
+                    timestamp: SystemTime::now(),
+                    result: res.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+                });
+            }
+            #[cfg(feature = "metrics")]
+            self.emit(Event::Command {
+                name,
+                bytes,
+                duration: start.elapsed(),
+                ok: res.is_ok(),
+            });
+        }
+        res
+    }
+
+    /// Reports transfers, status queries, commands and jobs to metrics,
+    /// replacing any callback set before
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics<M: Metrics + 'static>(&mut self, metrics: M) {
+        self.metrics = Some(Box::new(metrics));
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn clear_metrics(&mut self) {
+        self.metrics = None;
+    }
+
+    #[cfg(feature = "metrics")]
+    fn emit(&mut self, event: Event) {
+        if let Some(metrics) = self.metrics.as_mut() {
+            metrics.record(&event);
+        }
+    }
+
+    /// Starts recording the last `capacity` commands sent to the printer,
+    /// replacing any history recorded so far
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History::new(capacity));
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Commands recorded since [Printer::enable_history], oldest first
+    pub fn history(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.history.iter().flat_map(|history| history.iter())
+    }
+    // Old file based write
+    // fn write2(&mut self, buf: &[u8]) -> io::Result<usize> {
+    //     self.file.write(buf)
+    // }
+
+    pub fn chain_write_u8(&mut self, n: u8) -> Result<&mut Self, Error> {
+        self.write_u8(n).map(|_| self)
+    }
+    pub fn write_u8(&mut self, n: u8) -> Result<usize, Error> {
+        self.command("write_u8", || format!("{:#04x}", n), |p| p.write(&[n]))
+    }
+
+    fn write_u16le(&mut self, n: u16) -> Result<usize, Error> {
+        self.write(&n.to_le_bytes())
+    }
+
+    /// Sends what an open [Printer::batch] collected so far
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.flush_batch()
+    }
+
+    /// ESC @ - Initialize printer, clear data in print buffer and set print mode
+    /// to the default mode when powered on.
+    ///
+    /// Seems to be the same for SNBC and P3 printers
+    ///
+    /// ASCII    ESC   @
+    /// Hex      1b   40
+    /// Decimal  27   64
+    /// Notes:
+    ///   - The data in the receive buffer is not cleared
+    ///   - The macro definition is not cleared
+    ///   - The NV bitmap data is not cleared (SNBC, not sure about P3), see
+    ///     [Printer::define_nv_images]
+    pub fn hwinit(&mut self) -> Result<usize, Error> {
+        self.command("hwinit", String::new, |p| {
+            let n = p.write(&[0x1b, 0x40])?;
+            // ESC @ also resets the code page and clears the RAM logo
+            p.session.needs_resync = p.session.reset_clears();
+            p.text_state = TextState::default();
+            Ok(n)
+        })
+    }
+    pub fn chain_hwinit(&mut self) -> Result<&mut Self, Error> {
+        self.hwinit().map(|_| self)
+    }
+
+    /// ESC = n - Enable/Disable Printer
+    /// Docs describe this as "Select printer to which host computer sends data"
+    ///
+    /// SNBC:
+    ///
+    /// ASCII    ESC   =  n
+    /// Hex      1b   3d  n
+    /// Decimal  27   61  n
+    /// Range: 0 <= n <= 1
+    ///
+    /// Meaning of n is as follows:
+    ///
+    /// | Bit | 1/0 | Hex | Decimal | Function         |
+    /// |-----|-----|-----|---------|------------------|
+    /// |  0  |  0  |  00 |    0    | Printer disabled |
+    /// |  0  |  1  |  01 |    1    | Printer enabled  |
+    /// | 1-7 |     |     |         | Undefined        |
+    ///
+    /// Notes:
+    /// When the printer is disabled, it ignores all commands except for
+    /// real-time commands (DLE EOT, DLE ENQ, DLE DC4) until it is enabled by
+    /// this command.
+    ///
+    /// Default: n = 1
+    ///
+    /// P3:
+    /// Select the device to which the host computer sends data, using n as follows:
+    ///
+    /// |      n       | Function        |
+    /// |--------------|-----------------|
+    /// |  0x01, 0x03  | Device enabled  |
+    /// |     0x02     | Device disabled |
+    ///
+    /// Default: n = 0x01
+    pub fn enable(&mut self) -> Result<usize, Error> {
+        self.command("enable", String::new, |p| match p.printer {
+            SupportedPrinters::SNBC => p.write(&[0x1b, 0x3d, 0x01]),
+            SupportedPrinters::P3 => p.write(&[0x1b, 0x3d, 0x01]),
+            _ => Err(Error::Unsupported),
+        })
+    }
+    pub fn chain_enable(&mut self) -> Result<&mut Self, Error> {
+        self.enable().map(|_| self)
+    }
+
+    pub fn disable(&mut self) -> Result<usize, Error> {
+        self.command("disable", String::new, |p| match p.printer {
+            SupportedPrinters::SNBC => p.write(&[0x1b, 0x3d, 0x00]),
+            SupportedPrinters::P3 => p.write(&[0x1b, 0x3d, 0x02]),
+            _ => Err(Error::Unsupported),
+        })
+    }
+    pub fn chain_disable(&mut self) -> Result<&mut Self, Error> {
+        self.disable().map(|_| self)
+    }
+
+    pub fn chain_panel_buttons(&mut self, enabled: bool) -> Result<&mut Self, Error> {
+        self.panel_buttons(enabled).map(|_| self)
+    }
+
+    /// ESC c 5 n - Enable/Disable panel buttons
+    ///
+    /// ASCII    ESC   c  5  n
+    /// Hex      1b   63 35  n
+    /// Decimal  27   99 53  n
+    ///
+    /// | Bit | 1/0 | Function               |
+    /// |-----|-----|------------------------|
+    /// |  0  |  0  | Panel buttons enabled  |
+    /// |  0  |  1  | Panel buttons disabled |
+    ///
+    /// Notes:
+    ///   - Disabling the buttons stops customers feeding paper out of
+    ///     unattended kiosks
+    ///
+    /// Default: n = 0 (enabled)
+    pub fn panel_buttons(&mut self, enabled: bool) -> Result<usize, Error> {
+        self.command(
+            "panel_buttons",
+            || enabled.to_string(),
+            |p| p.write(&[0x1b, 0x63, 0x35, if enabled { 0x00 } else { 0x01 }]),
+        )
+    }
+
+    pub fn chain_paper_end_signal_sensors(
+        &mut self,
+        near_end: bool,
+        end: bool,
+    ) -> Result<&mut Self, Error> {
+        self.paper_end_signal_sensors(near_end, end).map(|_| self)
+    }
+
+    /// ESC c 3 n - Select paper sensor(s) to output paper-end signals
+    ///
+    /// ASCII    ESC   c  3  n
+    /// Hex      1b   63 33  n
+    /// Decimal  27   99 51  n
+    ///
+    /// | Bit | Function                            |
+    /// |-----|-------------------------------------|
+    /// | 0,1 | Roll paper near-end sensor enabled  |
+    /// | 2,3 | Roll paper end sensor enabled       |
+    pub fn paper_end_signal_sensors(&mut self, near_end: bool, end: bool) -> Result<usize, Error> {
+        let mut n = 0x00;
+        if near_end {
+            n |= 0b0000_0011;
+        }
+        if end {
+            n |= 0b0000_1100;
+        }
+        self.command(
+            "paper_end_signal_sensors",
+            || format!("{}, {}", near_end, end),
+            |p| p.write(&[0x1b, 0x63, 0x33, n]),
+        )
+    }
+
+    pub fn chain_stop_printing_sensors(&mut self, near_end: bool) -> Result<&mut Self, Error> {
+        self.stop_printing_sensors(near_end).map(|_| self)
+    }
+
+    /// ESC c 4 n - Select paper sensor(s) to stop printing
+    ///
+    /// ASCII    ESC   c  4  n
+    /// Hex      1b   63 34  n
+    /// Decimal  27   99 52  n
+    ///
+    /// | Bit | Function                            |
+    /// |-----|-------------------------------------|
+    /// | 0,1 | Roll paper near-end sensor enabled  |
+    ///
+    /// Notes:
+    ///   - When enabled the printer stops after the current line once the
+    ///     near-end sensor triggers, otherwise it prints until the paper runs
+    ///     out
+    pub fn stop_printing_sensors(&mut self, near_end: bool) -> Result<usize, Error> {
+        let n = if near_end { 0b0000_0011 } else { 0x00 };
+        self.command(
+            "stop_printing_sensors",
+            || near_end.to_string(),
+            |p| p.write(&[0x1b, 0x63, 0x34, n]),
+        )
+    }
+
+    // TODO: There doesn't seem to be a hwreset command for snbc
+    // pub fn hwreset(&mut self) -> io::Result<usize> {
+    //     self.write(consts::HW_RESET)
+    // }
+    // pub fn chain_hwreset(&mut self) -> io::Result<&mut Self> {
+    //     self.hwreset().map(|_| self)
+    // }
+
+    pub fn print(&mut self, content: &str) -> Result<usize, Error> {
+        if self.halfwidth_kana {
+            let converted = kana::to_halfwidth(content);
+            if converted.contains(kana::is_halfwidth) {
+                return self.print_kana(content, &converted);
+            }
+        }
+        self.command(
+            "print",
+            || format!("{:?}", content),
+            |p| {
+                let mut encoded = std::mem::take(&mut p.text_buffer);
+                encoded.clear();
+                if p.printer == SupportedPrinters::Star || p.quirks.raster_only {
+                    p.codec.encode_to(content, p.trap, &mut encoded)
+                } else {
+                    p.glyphs.encode(content, p.codec, p.trap, &mut encoded)
+                }
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+                let res = p.write(&encoded);
+                p.text_buffer = encoded;
+                res
+            },
+        )
+    }
+    pub fn chain_print(&mut self, content: &str) -> Result<&mut Self, Error> {
+        self.print(content).map(|_| self)
+    }
+
+    /// Prints text converted by [kana::to_halfwidth] in the katakana page,
+    /// then selects the page from before again
+    fn print_kana(&mut self, content: &str, converted: &str) -> Result<usize, Error> {
+        let page = self
+            .profile
+            .code_page(kana::ENCODING)
+            .ok_or(Error::Unsupported)?;
+        self.command(
+            "print",
+            || format!("{:?}", content),
+            |p| {
+                let restore = p.session.code_page.unwrap_or(0);
+                let n = p.code_page(page)?;
+                let mut encoded = std::mem::take(&mut p.text_buffer);
+                encoded.clear();
+                let res = kana::encode(converted, p.codec, p.trap, &mut encoded)
+                    .map_err(|err| {
+                        io::Error::new(io::ErrorKind::InvalidData, err.to_string()).into()
+                    })
+                    .and_then(|_| p.write(&encoded));
+                p.text_buffer = encoded;
+                // The page goes back also when the text couldn't be encoded
+                let restored = p.code_page(restore);
+                Ok(n + res? + restored?)
+            },
+        )
+    }
+
+    pub fn println(&mut self, content: &str) -> Result<usize, Error> {
+        self.command(
+            "println",
+            || format!("{:?}", content),
+            |p| Ok(p.print(content)? + p.write(consts::CTL_LF)?),
+        )
+    }
+    pub fn chain_println(&mut self, content: &str) -> Result<&mut Self, Error> {
+        self.println(content).map(|_| self)
+    }
+
+    /// What happens to characters the code page lacks in text printed from
+    /// now on, [EncoderTrap::Replace] unless given to [Printer::new]
+    pub fn set_trap(&mut self, trap: EncoderTrap) {
+        self.trap = trap;
+    }
+
+    /// Prints what f prints with trap instead of the printer's trap, then
+    /// goes back to it, also when f fails
+    ///
+    /// A fiscal field that mustn't be altered can fail on characters the
+    /// code page lacks while the rest of the receipt replaces them.
+    /// [EncoderTrap::Call] transliterates with a function of its own.
+    ///
+    /// ```no_run
+    /// # use encoding::EncoderTrap;
+    /// # use posify::printer::{Error, Printer};
+    /// # fn receipt(p: &mut Printer) -> Result<(), Error> {
+    /// p.println("Café au lait ☕")?;
+    /// p.with_trap(EncoderTrap::Strict, |p| p.println("VAT ID DE123456789"))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_trap<T, F>(&mut self, trap: EncoderTrap, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        let previous = std::mem::replace(&mut self.trap, trap);
+        let result = f(self);
+        self.trap = previous;
+        result
+    }
+
+    pub fn chain_thai_println(&mut self, content: &str) -> Result<&mut Self, Error> {
+        self.thai_println(content).map(|_| self)
+    }
+
+    /// Prints a line of Thai in three passes, see [thai::three_pass]
+    ///
+    /// The marks above and below print on lines of their own, with the line
+    /// spacing set to the font's height so they touch the base line. The
+    /// profile needs the page of `windows-874` in its
+    /// [code_pages](crate::profile::Profile::code_pages), see
+    /// [Profile::code_page](crate::profile::Profile::code_page). The code
+    /// page and line spacing are set back afterwards.
+    pub fn thai_println(&mut self, content: &str) -> Result<usize, Error> {
+        let page = self
+            .profile
+            .code_page(WINDOWS_874.name())
+            .ok_or(Error::Unsupported)?;
+        let lines = thai::three_pass(content);
+        self.command(
+            "thai_println",
+            || format!("{:?}", content),
+            |p| {
+                let restore = p.session.code_page.unwrap_or(0);
+                let mut n = p.code_page(page)?;
+                let res = p.line_space(24).and_then(|spacing| {
+                    let mut n = spacing;
+                    for line in lines.lines() {
+                        let encoded = WINDOWS_874.encode(line, p.trap).map_err(|err| {
+                            io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+                        })?;
+                        n += p.write(&encoded)? + p.write(consts::CTL_LF)?;
+                    }
+                    Ok(n)
+                });
+                // Spacing and page go back also when a line couldn't be
+                // encoded
+                let spacing = p.line_space(-1);
+                let restored = p.code_page(restore);
+                n += res?;
+                Ok(n + spacing? + restored?)
+            },
+        )
+    }
+
+    // TODO: This seems useless? just use print/println?
+    pub fn text(&mut self, content: &str) -> Result<usize, Error> {
+        self.command("text", || format!("{:?}", content), |p| p.println(content))
+    }
+    pub fn chain_text(&mut self, content: &str) -> Result<&mut Self, Error> {
+        self.text(content).map(|_| self)
+    }
+
+    pub fn underline_mode(&mut self, mode: Option<&str>) -> Result<usize, Error> {
+        let mut buf = [0; 8];
+        let mode_upper = upper(mode.unwrap_or("OFF"), &mut buf);
+        self.check_strict(matches!(mode_upper, "OFF" | "ON" | "THICK"))?;
+        let underline = match mode_upper {
+            "ON" => 1,
+            "THICK" => 2,
+            _ => 0,
+        };
+        let state = TextState {
+            underline: Some(underline),
+            ..self.text_state
+        };
+        self.attribute(
+            "underline_mode",
+            || format!("{:?}", mode),
+            Some(state),
+            |p| {
+                let mode = mode.unwrap_or("OFF");
+                let mut buf = [0; 8];
+                let mode_upper = upper(mode, &mut buf);
+                match mode_upper {
+                    "OFF" => Ok(p.write(&[0x1b, 0x2d, 0x00])?),
+                    "ON" => Ok(p.write(&[0x1b, 0x2d, 0x01])?),
+                    "THICK" => Ok(p.write(&[0x1b, 0x2d, 0x02])?),
+                    _ => Ok(p.write(&[0x1b, 0x2d, 0x00])?),
+                }
+            },
+        )
+    }
+    pub fn chain_underline_mode(&mut self, mode: Option<&str>) -> Result<&mut Self, Error> {
+        self.underline_mode(mode).map(|_| self)
+    }
+
+    pub fn chain_reverse(&mut self, on: bool) -> Result<&mut Self, Error> {
+        self.reverse(on).map(|_| self)
+    }
+
+    /// GS B n - Turn white/black reverse print mode on/off
+    ///
+    /// ASCII    GS  B  n
+    /// Hex      1D  42 n
+    /// Decimal  29  66 n
+    ///
+    /// Star Line Mode uses ESC 4 and ESC 5 (highlight printing) instead.
+    pub fn reverse(&mut self, on: bool) -> Result<usize, Error> {
+        let state = TextState {
+            reverse: Some(on),
+            ..self.text_state
+        };
+        self.attribute(
+            "reverse",
+            || format!("{}", on),
+            Some(state),
+            |p| {
+                let bytes = match (on, p.printer) {
+                    (true, SupportedPrinters::Star) => consts::STAR_REVERSE_ON,
+                    (false, SupportedPrinters::Star) => consts::STAR_REVERSE_OFF,
+                    (true, _) => consts::TXT_REVERSE_ON,
+                    (false, _) => consts::TXT_REVERSE_OFF,
+                };
+                p.write(bytes)
+            },
+        )
+    }
+
+    pub fn chain_raw(&mut self, bytes: &[u8]) -> Result<&mut Self, Error> {
+        self.raw(bytes).map(|_| self)
+    }
+
+    /// Sends bytes unchanged, for vendor commands posify doesn't have
+    ///
+    /// With [Printer::set_raw_validation] the bytes are decoded with the
+    /// [parser](crate::parser) first and nothing is sent when it finds an
+    /// unknown or truncated command, returning [Error::Malformed] with its
+    /// offset. Text attributes are sent again afterwards since the bytes
+    /// may have changed them.
+    pub fn raw(&mut self, bytes: &[u8]) -> Result<usize, Error> {
+        if self.validate_raw {
+            let unknown = parser::parse(bytes)
+                .into_iter()
+                .find_map(|token| match token {
+                    Token::Unknown { offset, .. } => Some(offset),
+                    _ => None,
+                });
+            if let Some(offset) = unknown {
+                return Err(Error::Malformed(offset));
+            }
+        }
+        self.command(
+            "raw",
+            || history::hex_preview(bytes),
+            |p| {
+                p.text_state = TextState::default();
+                p.send(bytes)
+            },
+        )
+    }
+
+    pub fn chain_raw_hex(&mut self, hex: &str) -> Result<&mut Self, Error> {
+        self.raw_hex(hex).map(|_| self)
+    }
+
+    /// [Printer::raw] with the bytes as whitespace separated hex,
+    /// `"1b 40"` or `"0x1b 0x40"`
+    pub fn raw_hex(&mut self, hex: &str) -> Result<usize, Error> {
+        let bytes = profile::parse_hex(hex).ok_or(Error::InvalidArgument)?;
+        self.raw(&bytes)
+    }
+
+    /// Checks [Printer::raw] bytes for malformed ESC/POS, off by default.
+    /// Star Line Mode isn't understood by the parser.
+    pub fn set_raw_validation(&mut self, enabled: bool) {
+        self.validate_raw = enabled;
+    }
+
+    /// Quality of bit images printed without a density, see
+    /// [BitImageDensity::auto]
+    pub fn set_image_quality(&mut self, quality: ImageQuality) {
+        self.image_quality = quality;
+    }
+
+    /// Characters printed as bit images inside the text, see
+    /// [glyph](crate::glyph), the built in emoji by default
+    ///
+    /// Star Line Mode printers and printers with the raster_only quirk always
+    /// get text.
+    pub fn set_glyphs(&mut self, glyphs: GlyphSet) {
+        self.glyphs = glyphs;
+    }
+
+    /// Prints kana as half-width katakana, off by default
+    ///
+    /// For printers without a Kanji ROM. Text given to [Printer::print] and
+    /// the methods built on it that has kana is converted with
+    /// [kana::to_halfwidth] and printed in the katakana page, see
+    /// [katakana_code_page](crate::profile::katakana_code_page), without
+    /// [Printer::set_glyphs] pictures. Text without kana prints as before.
+    /// Fails with [Error::Unsupported] when the profile has no katakana
+    /// page.
+    pub fn set_halfwidth_kana(&mut self, on: bool) {
+        self.halfwidth_kana = on;
+    }
+
+    /// Rejects arguments that would otherwise be replaced by a default with
+    /// [Error::InvalidArgument], off by default
+    ///
+    /// For development, to catch template bugs. Without it unknown keywords
+    /// of [Printer::style], [Printer::underline_mode], `qrcode` levels and
+    /// image modes print normal text and images, sizes other than 1 and 2
+    /// print at 1, barcode widths are clamped to 2 to 6 and barcodes aren't
+    /// checked with [BarcodeType::validate].
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// [Error::InvalidArgument] in strict mode when the argument isn't
+    /// known, see [Printer::set_strict]
+    fn check_strict(&self, known: bool) -> Result<(), Error> {
+        match self.strict && !known {
+            true => Err(Error::InvalidArgument),
+            false => Ok(()),
+        }
+    }
+
+    /// Whether commands the printer can't do are substituted or fail
+    pub fn set_degradation(&mut self, degradation: Degradation) {
+        self.degradation = degradation;
+    }
+
+    /// Characters of the current font and width that fit one line, see
+    /// [Profile::chars_per_line]
+    ///
+    /// Font A and normal width are assumed when the printer's aren't known.
+    pub fn chars_per_line(&self) -> usize {
+        let font = match self.text_state.font {
+            Some(1) => Font::FontB,
+            _ => Font::FontA,
+        };
+        let width = self.text_state.size.map_or(1, |(width, _)| width);
+        self.profile.chars_per_line(font, width)
+    }
+
+    pub fn chain_justify(&mut self, text: &str) -> Result<&mut Self, Error> {
+        self.justify(text).map(|_| self)
+    }
+
+    /// Prints text as paragraphs flush with both margins, see
+    /// [format::justify], [Printer::chars_per_line] wide
+    pub fn justify(&mut self, text: &str) -> Result<usize, Error> {
+        let lines = format::justify(text, self.chars_per_line());
+        self.command(
+            "justify",
+            || format!("{:?}", text),
+            |p| p.print_lines(&lines),
+        )
+    }
+
+    pub fn chain_numbered_list(&mut self, items: &[&str]) -> Result<&mut Self, Error> {
+        self.numbered_list(items).map(|_| self)
+    }
+
+    /// Prints items numbered from 1 with wrapped lines indented under the
+    /// text, see [format::numbered_list], [Printer::chars_per_line] wide
+    pub fn numbered_list(&mut self, items: &[&str]) -> Result<usize, Error> {
+        let lines = format::numbered_list(items, self.chars_per_line());
+        self.command(
+            "numbered_list",
+            || format!("{:?}", items),
+            |p| p.print_lines(&lines),
+        )
+    }
+
+    pub fn chain_bullet_list(&mut self, items: &[&str]) -> Result<&mut Self, Error> {
+        self.bullet_list(items).map(|_| self)
+    }
+
+    /// Prints items after a `-` with wrapped lines indented under the text,
+    /// see [format::bullet_list], [Printer::chars_per_line] wide
+    pub fn bullet_list(&mut self, items: &[&str]) -> Result<usize, Error> {
+        let lines = format::bullet_list(items, self.chars_per_line());
+        self.command(
+            "bullet_list",
+            || format!("{:?}", items),
+            |p| p.print_lines(&lines),
+        )
+    }
+
+    fn print_lines(&mut self, lines: &[String]) -> Result<usize, Error> {
+        let mut n = 0;
+        for line in lines {
+            n += self.println(line)?;
+        }
+        Ok(n)
+    }
+
+    pub fn chain_columns(&mut self, label: &str, value: &str) -> Result<&mut Self, Error> {
+        self.columns(label, value).map(|_| self)
+    }
+
+    /// Prints label on the left and value on the right of one line, see
+    /// [format::columns], [Printer::chars_per_line] wide
+    pub fn columns(&mut self, label: &str, value: &str) -> Result<usize, Error> {
+        let line = format::columns(label, value, self.chars_per_line());
+        self.command(
+            "columns",
+            || format!("{:?}, {:?}", label, value),
+            |p| p.println(&line),
+        )
+    }
+
+    pub fn chain_hr(&mut self, width: usize) -> Result<&mut Self, Error> {
+        self.hr(width).map(|_| self)
+    }
+
+    /// hr generates a line of width <width>, 0 for a line across the paper,
+    /// [Printer::chars_per_line] wide
+    pub fn hr(&mut self, width: usize) -> Result<usize, Error> {
+        let width = match width {
+            0 => self.chars_per_line(),
+            width => width,
+        };
+        self.command(
+            "hr",
+            || width.to_string(),
+            |p| {
+                let mut n_bytes = 0;
+                let line = vec![0xc4; width];
+                n_bytes += p.write(&line)?;
+                n_bytes += p.write("\n".as_ref())?;
+                Ok(n_bytes)
+            },
+        )
+    }
+
+    pub fn chain_char_size(&mut self, height: u8) -> Result<&mut Self, Error> {
+        self.char_size(height).map(|_| self)
+    }
+
+    pub fn char_size(&mut self, height: u8) -> Result<usize, Error> {
+        // Star magnifies at most 6 times, ESC i n1 n2 takes 0 to 5
+        if self.printer == SupportedPrinters::Star && (height & 0x0f > 5 || height >> 4 > 5) {
+            return Err(Error::InvalidArgument);
+        }
+        let state = TextState {
+            size: Some(((height >> 4) + 1, (height & 0x0f) + 1)),
+            ..self.text_state
+        };
+        self.attribute(
+            "char_size",
+            || height.to_string(),
+            Some(state),
+            |p| match p.printer {
+                // ESC i n1 n2 takes the height and width magnification
+                // separately instead of as the nibbles of one byte
+                SupportedPrinters::Star => p.write(&[0x1b, 0x69, height & 0x0f, height >> 4]),
+                _ => p.write(&[0x1d, 0x21, height]),
+            },
+        )
+    }
+
+    /// ESC 2/ESC 3 n - Set line spacing
+    ///
+    /// ESC 2 (0x1b, 0x32) Sets line spacing to default
+    /// ESC 3 (0x1b, 0x33, n) Specifies a specific line spacing
+    ///
+    /// ASCII    ESC   2
+    /// Hex      1b   32
+    /// Decimal  27   50
+    ///
+    /// ASCII    ESC   3  n
+    /// Hex      1b   33  n
+    /// Decimal  27   51  n
+    /// Range: 0 <= n <= 255
+    ///
+    /// Notes:
+    ///   - The line spacing can be set independently in standard mode and in
+    ///     page mode.
+    ///   - The horizontal and vertical motion units are specified by GS P.
+    ///     Changing the horizontal or vertical motion unit does not affect the
+    ///     current line spacing.
+    ///   - In standard mode, the vertical motion unit (y) is used.
+    ///   - In page mode, this command functions as follows, depending on the
+    ///     direction and starting position of the printable area:
+    ///     1) When the starting position is set to the upper left or lower right
+    ///        of the printable area by ESC T, the vertical motion unit (y) is
+    ///        used.
+    ///     2) When the starting position is set to the upper right or lower left
+    ///        of the printable area by ESC T, the horizontal motion unit (x) is
+    ///        used.
+    ///   - The maximum paper feed amount is 1016 mm (40 inches). Even if a paper
+    ///     feed amount of more than 1016 mm (40 inches) is set, the printer
+    ///     feeds the paper only 1016 mm (40 inches).
+    ///
+    /// Default: The default line spacing is approximately 4.23mm (1/6 inches).
+    pub fn line_space(&mut self, n: i32) -> Result<usize, Error> {
+        self.command(
+            "line_space",
+            || n.to_string(),
+            |p| {
+                if (0..=255).contains(&n) {
+                    Ok(p.write(&[0x1b, 0x33, n as u8])?)
+                } else {
+                    p.write(&[0x1b, 0x32])
+                }
+            },
+        )
+    }
+    pub fn chain_line_space(&mut self, n: i32) -> Result<&mut Self, Error> {
+        self.line_space(n).map(|_| self)
+    }
+
+    pub fn feed(&mut self, n: usize) -> Result<usize, Error> {
+        self.command(
+            "feed",
+            || n.to_string(),
+            |p| {
+                let n = if n < 1 { 1 } else { n };
+                p.write("\n".repeat(n).as_ref())
+            },
+        )
+    }
+    pub fn chain_feed(&mut self, n: usize) -> Result<&mut Self, Error> {
+        self.feed(n).map(|_| self)
+    }
+
+    pub fn chain_feed_units(&mut self, n: u8) -> Result<&mut Self, Error> {
+        self.feed_units(n).map(|_| self)
+    }
+
+    /// ESC J n - Print and feed paper
+    ///
+    /// ASCII    ESC   J  n
+    /// Hex      1b   4a  n
+    /// Decimal  27   74  n
+    /// Range: 0 <= n <= 255
+    ///
+    /// Prints the buffer and feeds n x the vertical motion unit (see
+    /// [Printer::set_motion_units]), usually 1/203 inch (~0.125 mm).
+    pub fn feed_units(&mut self, n: u8) -> Result<usize, Error> {
+        self.command(
+            "feed_units",
+            || n.to_string(),
+            |p| p.write(&[0x1b, 0x4a, n]),
+        )
+    }
+
+    pub fn chain_reverse_feed_units(&mut self, n: u8) -> Result<&mut Self, Error> {
+        self.reverse_feed_units(n).map(|_| self)
+    }
+
+    /// ESC K n - Print and reverse feed paper
+    ///
+    /// ASCII    ESC   K  n
+    /// Hex      1b   4b  n
+    /// Decimal  27   75  n
+    /// Range: 0 <= n <= 255
+    ///
+    /// Notes:
+    ///   - Printers limit how far they can reverse feed (often 48 units or
+    ///     less) and ignore the rest, some don't support reverse feed at all
+    pub fn reverse_feed_units(&mut self, n: u8) -> Result<usize, Error> {
+        self.command(
+            "reverse_feed_units",
+            || n.to_string(),
+            |p| p.write(&[0x1b, 0x4b, n]),
+        )
+    }
+
+    pub fn chain_feed_lines(&mut self, n: u8) -> Result<&mut Self, Error> {
+        self.feed_lines(n).map(|_| self)
+    }
+
+    /// ESC d n - Print and feed n lines
+    ///
+    /// ASCII    ESC   d  n
+    /// Hex      1b   64  n
+    /// Decimal  27  100  n
+    /// Range: 0 <= n <= 255
+    ///
+    /// Unlike [Printer::feed] the lines use the current line spacing without
+    /// sending a newline per line.
+    ///
+    /// Star Line Mode uses ESC d to cut, so ESC a n is sent instead.
+    pub fn feed_lines(&mut self, n: u8) -> Result<usize, Error> {
+        self.command(
+            "feed_lines",
+            || n.to_string(),
+            |p| match p.printer {
+                SupportedPrinters::Star => p.write(&[0x1b, 0x61, n]),
+                _ => p.write(&[0x1b, 0x64, n]),
+            },
+        )
+    }
+
+    pub fn chain_set_motion_units(&mut self, x: u8, y: u8) -> Result<&mut Self, Error> {
+        self.set_motion_units(x, y).map(|_| self)
+    }
+
+    /// GS P x y - Set horizontal and vertical motion units
+    ///
+    /// ASCII    GS   P  x  y
+    /// Hex      1d  50  x  y
+    /// Decimal  29  80  x  y
+    /// Range: 0 <= x <= 255, 0 <= y <= 255
+    ///
+    /// Sets the horizontal unit to 1/x inch and the vertical unit to 1/y inch.
+    /// 0 restores the printer's default for that direction.
+    pub fn set_motion_units(&mut self, x: u8, y: u8) -> Result<usize, Error> {
+        self.command(
+            "set_motion_units",
+            || format!("{}, {}", x, y),
+            |p| p.write(&[0x1d, 0x50, x, y]),
+        )
+    }
+
+    pub fn chain_control(&mut self, ctrl: &str) -> Result<&mut Self, Error> {
+        self.control(ctrl).map(|_| self)
+    }
+    pub fn control(&mut self, ctrl: &str) -> Result<usize, Error> {
+        self.command(
+            "control",
+            || format!("{:?}", ctrl),
+            |p| {
+                let mut buf = [0; 8];
+                let ctrl_upper = upper(ctrl, &mut buf);
+                let ctrl_value = match ctrl_upper {
+                    "LF" => consts::CTL_LF,
+                    "FF" => consts::CTL_FF,
+                    "CR" => consts::CTL_CR,
+                    "HT" => consts::CTL_HT,
+                    "VT" => consts::CTL_VT,
+                    _ => return Err(Error::Unsupported),
+                };
+                p.write(ctrl_value)
+            },
+        )
+    }
+
+    pub fn chain_align(&mut self, alignment: &str) -> Result<&mut Self, Error> {
+        self.align(alignment).map(|_| self)
+    }
+    pub fn align(&mut self, alignment: &str) -> Result<usize, Error> {
+        let mut buf = [0; 8];
+        let align = match upper(alignment, &mut buf) {
+            "LT" => Some(Alignment::Left),
+            "CT" => Some(Alignment::Center),
+            "RT" => Some(Alignment::Right),
+            _ => None,
+        };
+        let state = align.map(|align| TextState {
+            align: Some(align),
+            ..self.text_state
+        });
+        self.attribute(
+            "align",
+            || format!("{:?}", alignment),
+            state,
+            |p| {
+                let mut buf = [0; 8];
+                let align_upper = upper(alignment, &mut buf);
+                let star = p.printer == SupportedPrinters::Star;
+                let align_value = match (align_upper, star) {
+                    ("LT", false) => consts::TXT_ALIGN_LT,
+                    ("CT", false) => consts::TXT_ALIGN_CT,
+                    ("RT", false) => consts::TXT_ALIGN_RT,
+                    ("LT", true) => consts::STAR_ALIGN_LT,
+                    ("CT", true) => consts::STAR_ALIGN_CT,
+                    ("RT", true) => consts::STAR_ALIGN_RT,
+                    _ => return Err(Error::InvalidArgument),
+                };
+                p.write(align_value)
+            },
+        )
+    }
+
+    /// Aligns what f prints, then restores the alignment from before
+    ///
+    /// The alignment is restored when f fails too. One the printer state
+    /// doesn't know, e.g. after [Printer::forget_state], is restored as left.
+    ///
+    /// ```no_run
+    /// # use posify::document::Alignment;
+    /// # use posify::printer::{Error, Printer};
+    /// # fn receipt(p: &mut Printer) -> Result<(), Error> {
+    /// p.with_alignment(Alignment::Center, |p| p.text("Thank you"))?;
+    /// p.text("Left aligned again")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_alignment<T, F>(&mut self, alignment: Alignment, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        let previous = self.text_state.align.unwrap_or(Alignment::Left);
+        self.align(alignment_code(alignment))?;
+        let result = f(self);
+        let restored = self.align(alignment_code(previous));
+        let value = result?;
+        restored?;
+        Ok(value)
+    }
+
+    /// Runs f, then sets the text attributes back to what they were before,
+    /// for blocks like a header or a promo box that mustn't change how the
+    /// rest prints
+    ///
+    /// Only attributes f changed are sent. One that wasn't known before,
+    /// e.g. after [Printer::forget_state], is set back to the printer's
+    /// default, except the code page. The attributes are restored when f
+    /// fails too, and sections can be nested.
+    ///
+    /// ```no_run
+    /// # use posify::printer::{Error, Printer};
+    /// # fn receipt(p: &mut Printer) -> Result<(), Error> {
+    /// p.section(|p| {
+    ///     p.align("CT")?;
+    ///     p.size(2, 2)?;
+    ///     p.text("SALE")
+    /// })?;
+    /// p.text("Left aligned and normal size again")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn section<T, F>(&mut self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        let before = self.text_state;
+        let result = f(self);
+        let after = self.text_state;
+        fn back<T>(before: Option<T>, after: Option<T>, default: T) -> Option<T> {
+            before.or(after.map(|_| default))
+        }
+        let state = TextState {
+            align: back(before.align, after.align, Alignment::Left),
+            font: back(before.font, after.font, 0),
+            bold: back(before.bold, after.bold, false),
+            underline: back(before.underline, after.underline, 0),
+            reverse: back(before.reverse, after.reverse, false),
+            size: back(before.size, after.size, (1, 1)),
+            code_page: before.code_page,
+        };
+        let restored = self.restore_text_state(state);
+        let value = result?;
+        restored?;
+        Ok(value)
+    }
+
+    pub fn chain_begin_page(&mut self, area: PageArea) -> Result<&mut Self, Error> {
+        self.begin_page(area).map(|_| self)
+    }
+
+    /// ESC L, ESC W - Select page mode and set the printable area
+    ///
+    /// ASCII    ESC   L  ESC   W  xL xH yL yH dxL dxH dyL dyH
+    /// Hex      1b   4c   1b  57  xL xH yL yH dxL dxH dyL dyH
+    /// Decimal  27   76   27  87  xL xH yL yH dxL dxH dyL dyH
+    ///
+    /// What is printed next is composed in area until [Printer::commit_page]
+    /// prints it. The text attributes from before are kept to be restored
+    /// then. Star Line Mode and the P3 have no page mode.
+    pub fn begin_page(&mut self, area: PageArea) -> Result<usize, Error> {
+        if matches!(
+            self.printer,
+            SupportedPrinters::Star | SupportedPrinters::P3 | SupportedPrinters::Unknown
+        ) {
+            return Err(Error::Unsupported);
+        }
+        if self.page.is_some() || !self.fits_page(area) {
+            return Err(Error::InvalidArgument);
+        }
+        let state = self.text_state;
+        let n = self.command(
+            "begin_page",
+            || format!("{:?}", area),
+            |p| {
+                let mut bytes = vec![0x1b, 0x4c, 0x1b, 0x57];
+                for value in [area.x, area.y, area.width, area.height] {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                p.write(&bytes)
+            },
+        )?;
+        self.page = Some((area, state));
+        self.page_content.clear();
+        Ok(n)
+    }
+
+    pub fn chain_commit_page(&mut self) -> Result<&mut Self, Error> {
+        self.commit_page().map(|_| self)
+    }
+
+    /// FF - Print the page and return to standard mode
+    ///
+    /// ASCII    FF
+    /// Hex      0c
+    /// Decimal  12
+    ///
+    /// ESC FF would print the page but stay in page mode.
+    ///
+    /// Checks that the page's area still fits the profile's paper and that
+    /// what was printed in it, estimated like [Job::estimate_length_mm] at
+    /// the area's width, ends above the profile's page height. Then prints it
+    /// and restores the text attributes from before [Printer::begin_page],
+    /// all in one batch. A page that doesn't fit is cancelled with CAN and
+    /// ESC S instead and [Error::InvalidArgument] returned, so nothing of it
+    /// prints and standard mode is back either way.
+    pub fn commit_page(&mut self) -> Result<usize, Error> {
+        let (area, state) = self.page.take().ok_or(Error::InvalidArgument)?;
+        let content = parser::to_job(&std::mem::take(&mut self.page_content));
+        let profile = Profile {
+            dots_per_line: Some(area.width),
+            ..self.profile.clone()
+        };
+        let height = self.profile.page_height.unwrap_or(DEFAULT_PAGE_HEIGHT) as u32;
+        let fits = self.fits_page(area)
+            && area.y as u32 + content.estimate_length_dots(&profile) <= height;
+        let n = self.command("commit_page", String::new, |p| {
+            let bytes: &[u8] = match fits {
+                true => &[0x0c],
+                false => &[0x18, 0x1b, 0x53],
+            };
+            let n = p.write(bytes)?;
+            Ok(n + p.restore_text_state(state)?)
+        })?;
+        if !fits {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(n)
+    }
+
+    /// Whether area is on the paper of the profile and no taller than its
+    /// page mode allows
+    fn fits_page(&self, area: PageArea) -> bool {
+        let dots = self.profile.dots_per_line.unwrap_or(576) as u32;
+        let height = self.profile.page_height.unwrap_or(DEFAULT_PAGE_HEIGHT) as u32;
+        area.width > 0
+            && area.height > 0
+            && area.x as u32 + area.width as u32 <= dots
+            && area.y as u32 + area.height as u32 <= height
+    }
+
+    /// Sends the attributes of state that differ from what the printer has,
+    /// unknown ones are left as they are
+    fn restore_text_state(&mut self, state: TextState) -> Result<usize, Error> {
+        let mut n = 0;
+        // ESC ! resets the font, bold and underline, so it goes first
+        if let Some((width, height)) = state.size {
+            n += self.size(width as usize, height as usize)?;
+        }
+        if let Some(font) = state.font {
+            n += self.font(["A", "B", "C"][font.min(2) as usize])?;
+        }
+        if let (Some(bold), Some(underline)) = (state.bold, state.underline) {
+            let kind = match (bold, underline) {
+                (false, 0) => "NORMAL",
+                (true, 0) => "B",
+                (false, 1) => "U",
+                (true, 1) => "BU",
+                (false, _) => "U2",
+                (true, _) => "BU2",
+            };
+            n += self.style(kind)?;
+        }
+        if let Some(on) = state.reverse {
+            n += self.reverse(on)?;
+        }
+        if let Some(align) = state.align {
+            n += self.align(alignment_code(align))?;
+        }
+        if let Some(page) = state.code_page {
+            n += self.code_page(page)?;
+        }
+        Ok(n)
+    }
+
+    pub fn chain_font(&mut self, family: &str) -> Result<&mut Self, Error> {
+        self.font(family).map(|_| self)
+    }
+    pub fn font(&mut self, family: &str) -> Result<usize, Error> {
+        let mut buf = [0; 8];
+        let font = match upper(family, &mut buf) {
+            "A" => Some(0),
+            "B" => Some(1),
+            "C" => Some(2),
+            _ => None,
+        };
+        let state = font.map(|font| TextState {
+            font: Some(font),
+            ..self.text_state
+        });
+        self.attribute(
+            "font",
+            || format!("{:?}", family),
+            state,
+            |p| {
+                let mut buf = [0; 8];
+                let family_upper = upper(family, &mut buf);
+                let star = p.printer == SupportedPrinters::Star;
+                let family_value = match (family_upper, star) {
+                    ("A", false) => consts::TXT_FONT_A,
+                    ("B", false) => consts::TXT_FONT_B,
+                    ("C", false) => consts::TXT_FONT_C,
+                    ("A", true) => consts::STAR_FONT_A,
+                    ("B", true) => consts::STAR_FONT_B,
+                    _ => return Err(Error::InvalidArgument),
+                };
+                p.write(family_value)
+            },
+        )
+    }
+
+    pub fn chain_style(&mut self, kind: &str) -> Result<&mut Self, Error> {
+        self.style(kind).map(|_| self)
+    }
+    pub fn style(&mut self, kind: &str) -> Result<usize, Error> {
+        let mut buf = [0; 8];
+        let kind_upper = upper(kind, &mut buf);
+        self.check_strict(matches!(
+            kind_upper,
+            "NORMAL" | "B" | "U" | "U2" | "BU" | "BU2"
+        ))?;
+        let (bold, underline) = match kind_upper {
+            "B" => (true, 0),
+            "U" => (false, 1),
+            "U2" => (false, 2),
+            "BU" => (true, 1),
+            "BU2" => (true, 2),
+            _ => (false, 0),
+        };
+        let state = TextState {
+            bold: Some(bold),
+            underline: Some(underline),
+            ..self.text_state
+        };
+        self.attribute(
+            "style",
+            || format!("{:?}", kind),
+            Some(state),
+            |p| {
+                let mut buf = [0; 8];
+                let kind_upper = upper(kind, &mut buf);
+                let (bold_on, bold_off) = match p.printer {
+                    SupportedPrinters::Star => (consts::STAR_BOLD_ON, consts::STAR_BOLD_OFF),
+                    _ => (consts::TXT_BOLD_ON, consts::TXT_BOLD_OFF),
+                };
+                match kind_upper {
+                    "B" => Ok(p.write(consts::TXT_UNDERL_OFF)? + p.write(bold_on)?),
+                    "U" => Ok(p.write(bold_off)? + p.write(consts::TXT_UNDERL_ON)?),
+                    "U2" => Ok(p.write(bold_off)? + p.write(consts::TXT_UNDERL2_ON)?),
+                    "BU" => Ok(p.write(bold_on)? + p.write(consts::TXT_UNDERL_ON)?),
+                    "BU2" => Ok(p.write(bold_on)? + p.write(consts::TXT_UNDERL2_ON)?),
+                    // "NORMAL" | _ =>
+                    _ => Ok(p.write(bold_off)? + p.write(consts::TXT_UNDERL_OFF)?),
+                }
+            },
+        )
+    }
+
+    pub fn chain_size(&mut self, width: usize, height: usize) -> Result<&mut Self, Error> {
+        self.size(width, height).map(|_| self)
+    }
+    pub fn size(&mut self, width: usize, height: usize) -> Result<usize, Error> {
+        self.check_strict((1..=2).contains(&width) && (1..=2).contains(&height))?;
+        let size = (1 + (width == 2) as u8, 1 + (height == 2) as u8);
+        let state = match self.printer {
+            SupportedPrinters::Star => TextState {
+                size: Some(size),
+                ..self.text_state
+            },
+            // ESC ! also resets the font, bold and underline
+            _ => TextState {
+                size: Some(size),
+                font: None,
+                bold: None,
+                underline: None,
+                ..self.text_state
+            },
+        };
+        self.attribute(
+            "size",
+            || format!("{}, {}", width, height),
+            Some(state),
+            |p| {
+                if p.printer == SupportedPrinters::Star {
+                    let height = (height == 2) as u8;
+                    let width = (width == 2) as u8;
+                    return p.write(&[0x1b, 0x69, height, width]);
+                }
+                let mut n = p.write(consts::TXT_NORMAL)?;
+                if width == 2 {
+                    n += p.write(consts::TXT_2WIDTH)?;
+                }
+                if height == 2 {
+                    n += p.write(consts::TXT_2HEIGHT)?;
+                }
+                Ok(n)
+            },
+        )
+    }
+
+    pub fn chain_barcode_simple(
+        &mut self,
+        code: &str,
+        kind: BarcodeType,
+    ) -> Result<&mut Self, Error> {
+        self.barcode_simple(code, kind).map(|_| self)
+    }
+
+    /// Prints a barcode with the width, height, text position and font of
+    /// [Profile::barcode_defaults]
+    pub fn barcode_simple(&mut self, code: &str, kind: BarcodeType) -> Result<usize, Error> {
+        let defaults = self.profile.barcode_defaults();
+        self.barcode(
+            code,
+            kind,
+            defaults.position,
+            defaults.font,
+            defaults.width,
+            defaults.height,
+        )
+    }
+
+    pub fn chain_itf14(&mut self, code: &str) -> Result<&mut Self, Error> {
+        self.itf14(code).map(|_| self)
+    }
+
+    /// Prints the ITF-14 carton code of 13 digits, adding the check digit,
+    /// or of 14 digits with a correct one, see [itf14_code]
+    ///
+    /// The symbol is centered for its quiet zones, 255 dots (32 mm at 203
+    /// dpi) high, the spec's minimum for cartons, with modules of 4 dots
+    /// (0.5 mm, the minimum X dimension) when the paper is wide enough.
+    /// Bearer bars are left to the label.
+    pub fn itf14(&mut self, code: &str) -> Result<usize, Error> {
+        let code = itf14_code(code).map_err(|_| Error::InvalidArgument)?;
+        let dots = self.profile.dots_per_line.unwrap_or(576) as u32;
+        let width = (dots / ITF14_MODULES).clamp(2, 4) as u8;
+        if width < 4 {
+            log::warn!("ITF-14 modules narrowed to {} dots to fit the paper", width);
+        }
+        self.with_alignment(Alignment::Center, |p| {
+            p.barcode(
+                &code,
+                BarcodeType::ITF,
+                TextPosition::Below,
+                Font::FontA,
+                width,
+                255,
+            )
+        })
+    }
+
+    pub fn chain_pharmacode(&mut self, n: u32, height: u8) -> Result<&mut Self, Error> {
+        self.pharmacode(n, height).map(|_| self)
+    }
+
+    /// Prints one track Pharmacode n, 3 to 131070, height dots high
+    ///
+    /// Printers don't know the symbology, it's drawn by [pharmacode_image]
+    /// and sent as a raster image.
+    pub fn pharmacode(&mut self, n: u32, height: u8) -> Result<usize, Error> {
+        let image = pharmacode_image(n, height as u32).map_err(|_| Error::InvalidArgument)?;
+        self.with_alignment(Alignment::Center, |p| p.raster(&image, None))
+    }
+
+    pub fn chain_code32(&mut self, code: &str) -> Result<&mut Self, Error> {
+        self.code32(code).map(|_| self)
+    }
+
+    /// Prints the Code 32 (Italian pharmacode) of an 8 digit AIC code, or of
+    /// 9 digits with the check digit
+    ///
+    /// Code 32 is Code 39 of the code in base 32, see [code32_data]. The
+    /// printer's human readable text would show the base 32 digits, so the
+    /// `A` and decimal digits are printed below instead. Width and height
+    /// come from [Profile::barcode_defaults].
+    pub fn code32(&mut self, code: &str) -> Result<usize, Error> {
+        let digits = code32_digits(code).map_err(|_| Error::InvalidArgument)?;
+        let data = code32_data(&digits).map_err(|_| Error::InvalidArgument)?;
+        let defaults = self.profile.barcode_defaults();
+        self.with_alignment(Alignment::Center, |p| {
+            let n = p.barcode(
+                &data,
+                BarcodeType::CODE39,
+                TextPosition::Off,
+                defaults.font,
+                defaults.width,
+                defaults.height,
+            )?;
+            Ok(n + p.text(&format!("A{}", digits))?)
+        })
+    }
+
+    pub fn chain_code39(
+        &mut self,
+        code: &str,
+        options: &Code39Options,
+    ) -> Result<&mut Self, Error> {
+        self.code39(code, options).map(|_| self)
+    }
+
+    /// Prints Code 39 with the check character, start and stop characters
+    /// and ratio of options, see [code39_data]
+    ///
+    /// Width, height and font come from [Profile::barcode_defaults]. A
+    /// ratio is [Error::Unsupported] but on Star Line Mode printers, others
+    /// have theirs fixed in firmware.
+    pub fn code39(&mut self, code: &str, options: &Code39Options) -> Result<usize, Error> {
+        let data = code39_data(code, options)?;
+        let defaults = self.profile.barcode_defaults();
+        self.command(
+            "code39",
+            || format!("{:?}, {:?}", code, options),
+            |p| {
+                let bytes = job::code39(
+                    p.printer,
+                    &p.quirks,
+                    &data,
+                    TextPosition::Below,
+                    defaults.font,
+                    defaults.width,
+                    defaults.height,
+                    options.ratio,
+                )?;
+                p.write(&bytes)
+            },
+        )
+    }
+
+    pub fn chain_barcode(
+        &mut self,
+        code: &str,
+        kind: BarcodeType,
+        position: TextPosition,
+        font: Font,
+        width: u8,
+        height: u8,
+    ) -> Result<&mut Self, Error> {
+        self.barcode(code, kind, position, font, width, height)
+            .map(|_| self)
+    }
+    pub fn barcode(
+        &mut self,
+        code: &str,
+        kind: BarcodeType,
+        position: TextPosition,
+        font: Font,
+        width: u8,
+        height: u8,
+    ) -> Result<usize, Error> {
+        self.command(
+            "barcode",
+            || {
+                format!(
+                    "{:?}, {:?}, {:?}, {:?}, {}, {}",
+                    code, kind, position, font, width, height
+                )
+            },
+            |p| {
+                if p.strict {
+                    p.check_strict((2..=6).contains(&width))?;
+                    kind.validate(code).map_err(|_| Error::InvalidArgument)?;
+                }
+                let bytes = job::barcode(
+                    p.printer, &p.quirks, code, kind, position, font, width, height,
+                )?;
+                p.write(&bytes)
+            },
+        )
+    }
+
+    #[cfg(feature = "qrcode")]
+    pub fn chain_qrimage(&mut self) -> Result<&mut Self, Error> {
+        self.qrimage().map(|_| self)
+    }
+    #[cfg(feature = "qrcode")]
+    pub fn qrimage(&mut self) -> Result<usize, Error> {
+        Ok(0)
+    }
+
+    #[cfg(feature = "qrcode")]
+    pub fn chain_qrcode(
+        &mut self,
+        code: &str,
+        version: Option<i32>,
+        level: &str,
+        size: Option<i32>,
+    ) -> Result<&mut Self, Error> {
+        self.qrcode(code, version, level, size).map(|_| self)
+    }
+    /// GS Z n, ESC Z m n k dL dH d1...dn - Select and print 2D barcode
+    ///
+    /// The Bixolon style 2D commands. The Citizen profile doesn't know them
+    /// and uses GS ( k instead.
+    #[cfg(feature = "qrcode")]
+    pub fn qrcode(
+        &mut self,
+        code: &str,
+        version: Option<i32>,
+        level: &str,
+        size: Option<i32>,
+    ) -> Result<usize, Error> {
+        if !self.profile.capabilities.qr_code {
+            return Err(Error::Unsupported);
+        }
+        let mut buf = [0; 8];
+        self.check_strict(matches!(upper(level, &mut buf), "L" | "M" | "Q" | "H"))?;
+        self.command(
+            "qrcode",
+            || format!("{:?}, {:?}, {:?}, {:?}", code, version, level, size),
+            |p| {
+                let mut buf = [0; 8];
+                let level = upper(level, &mut buf);
+                let level_value = match level {
+                    "M" => consts::QR_LEVEL_M,
+                    "Q" => consts::QR_LEVEL_Q,
+                    "H" => consts::QR_LEVEL_H,
+                    // "L" | _ =>
+                    _ => consts::QR_LEVEL_L,
+                };
+                if p.printer == SupportedPrinters::Citizen {
+                    return p.write(&job::qrcode_gs_k(
+                        code,
+                        level_value[0],
+                        size.unwrap_or(3) as u8,
+                    )?);
+                }
+                let mut n = 0;
+                n += p.write(consts::TYPE_QR)?;
+                n += p.write(consts::CODE2D)?;
+                n += p.write_u8(version.unwrap_or(3) as u8)?;
+                n += p.write(level_value)?;
+                n += p.write_u8(size.unwrap_or(3) as u8)?;
+                n += p.write_u16le(code.len() as u16)?;
+                n += p.write(code.as_bytes())?;
+                Ok(n)
+            },
+        )
+    }
+
+    #[cfg(feature = "qrcode")]
+    pub fn chain_qrcode_with_text(
+        &mut self,
+        code: &str,
+        text: &str,
+        size: Option<i32>,
+    ) -> Result<&mut Self, Error> {
+        self.qrcode_with_text(code, text, size).map(|_| self)
+    }
+
+    /// Prints a centered QR code with text, e.g. its short URL, below it in
+    /// font B for when it can't be scanned
+    ///
+    /// The text wraps at [Profile::chars_per_line] for font B, preferably
+    /// after a `/`, `?`, `&`, `-` or space. Alignment and font are restored
+    /// afterwards, to font A when the font wasn't known.
+    #[cfg(feature = "qrcode")]
+    pub fn qrcode_with_text(
+        &mut self,
+        code: &str,
+        text: &str,
+        size: Option<i32>,
+    ) -> Result<usize, Error> {
+        let width = self.profile.chars_per_line(Font::FontB, 1);
+        let font = match self.text_state.font {
+            Some(1) => "B",
+            Some(2) => "C",
+            _ => "A",
+        };
+        self.with_alignment(Alignment::Center, |p| {
+            let mut n = p.qrcode(code, None, "M", size)?;
+            n += p.font("B")?;
+            for line in wrap_code(text, width) {
+                n += p.text(line)?;
+            }
+            Ok(n + p.font(font)?)
+        })
+    }
+
+    pub fn chain_cashdraw(
+        &mut self,
+        pin: DrawerPin,
+        on_time_ms: u16,
+        off_time_ms: u16,
+    ) -> Result<&mut Self, Error> {
+        self.cashdraw(pin, on_time_ms, off_time_ms).map(|_| self)
+    }
+
+    /// ESC p m t1 t2 - Generate pulse
+    ///
+    /// ASCII    ESC   p  m  t1  t2
+    /// Hex      1b   70  m  t1  t2
+    /// Decimal  27  112  m  t1  t2
+    /// Range: m = 0 (pin 2), 1 (pin 5), 0 <= t1 <= 255, 0 <= t2 <= 255
+    ///
+    /// The pulse is on for t1 x 2 ms and off for t2 x 2 ms, so both times are
+    /// rounded up to a multiple of 2 ms.
+    ///
+    /// Range: 2 <= on_time_ms <= 510, 0 <= off_time_ms <= 510
+    ///
+    /// Notes:
+    ///   - Printers use the on time for the off time when the off time is
+    ///     shorter than the on time
+    ///   - Some drawer solenoids need a longer pulse than others, 100 ms on and
+    ///     200 ms off is a common starting point
+    ///   - Star Line Mode sets the pulse with ESC BEL n1 n2 in 10 ms units and
+    ///     fires it with BEL (pin 2) or SUB (pin 5)
+    pub fn cashdraw(
+        &mut self,
+        pin: DrawerPin,
+        on_time_ms: u16,
+        off_time_ms: u16,
+    ) -> Result<usize, Error> {
+        if !self.profile.capabilities.drawer {
+            return Err(Error::Unsupported);
+        }
+        if !(2..=510).contains(&on_time_ms) || off_time_ms > 510 {
+            return Err(Error::InvalidArgument);
+        }
+        let m = match pin {
+            DrawerPin::Pin2 => 0x00,
+            DrawerPin::Pin5 => 0x01,
+        };
+        let t1 = on_time_ms.div_ceil(2) as u8;
+        let t2 = off_time_ms.div_ceil(2) as u8;
+        self.command(
+            "cashdraw",
+            || format!("{:?}, {}, {}", pin, on_time_ms, off_time_ms),
+            |p| match p.printer {
+                // ESC BEL n1 n2 sets the pulse in 10 ms units, BEL/SUB fires it
+                SupportedPrinters::Star => {
+                    let n1 = on_time_ms.div_ceil(10) as u8;
+                    let n2 = off_time_ms.div_ceil(10) as u8;
+                    let kick = match pin {
+                        DrawerPin::Pin2 => consts::STAR_DRAWER_1,
+                        DrawerPin::Pin5 => consts::STAR_DRAWER_2,
+                    };
+                    Ok(p.write(&[0x1b, 0x07, n1, n2])? + p.write(kick)?)
+                }
+                _ => p.write(&[0x1b, 0x70, m, t1, t2]),
+            },
+        )
+    }
+
+    pub fn chain_cut(&mut self, mode: CutMode, feed_mm: Option<u8>) -> Result<&mut Self, Error> {
+        self.cut(mode, feed_mm).map(|_| self)
+    }
+
+    /// GS V m n - Feed paper and cut (m = 65 full, 66 partial)
+    ///
+    /// ASCII    GS   V  m  n
+    /// Hex      1d  56  m  n
+    /// Decimal  29  86  m  n
+    ///
+    /// Feeds the last printed line past the cutter, then feed_mm more, and
+    /// cuts. Without feed_mm the profile's [CutFeed] is used, its line feeds
+    /// and feed, or no feed when it has none. The feed is converted with
+    /// [Profile::dpi] (203 when unset) and assumes the default motion unit
+    /// of one dot, so at most 31 mm at 203 dpi, more returns
+    /// [Error::InvalidArgument]. Star Line Mode feeds with ESC J in 1/4 mm
+    /// and cuts with ESC d, the P3 feeds the lines of the profile's
+    /// [CutFeed] before ESC m, three when it has none.
+    ///
+    /// A full cut on a printer that only cuts partially is a partial cut, and
+    /// a printer without a cutter only feeds, unless the
+    /// [Degradation] is [Degradation::Fail].
+    pub fn cut(&mut self, mode: CutMode, feed_mm: Option<u8>) -> Result<usize, Error> {
+        if let (None, Some(feed)) = (feed_mm, self.profile.cut_feed) {
+            return self.cut_fed(mode, feed);
+        }
+        // GS V and Star Line Mode feed to the cutter by themselves
+        let lines_before = match self.printer {
+            _ if !self.profile.capabilities.cutter => self.cut_lines(),
+            SupportedPrinters::P3 => self.cut_lines(),
+            _ => 0,
+        };
+        let feed = CutFeed {
+            lines_before,
+            mm_after: feed_mm.unwrap_or(0),
+        };
+        self.cut_fed(mode, feed)
+    }
+
+    /// Line feeds the profile's [CutFeed] gives the printers that need
+    /// them before a cut, three when it has none
+    fn cut_lines(&self) -> u8 {
+        self.profile
+            .cut_feed
+            .unwrap_or(CutFeed::LEGACY)
+            .lines_before
+    }
+
+    /// [Printer::cut] with the line feeds and feed of feed
+    fn cut_fed(&mut self, mode: CutMode, feed: CutFeed) -> Result<usize, Error> {
+        let dpi = self.profile.dpi.unwrap_or(203);
+        let units = feed_units_for(self.printer, feed.mm_after, dpi)?;
+        let substitute = self.degradation == Degradation::Substitute;
+        if !self.profile.capabilities.cutter {
+            if !substitute {
+                return Err(Error::Unsupported);
+            }
+            log::debug!("No cutter, feeding instead of cutting");
+            return self.command(
+                "cut",
+                || format!("{:?}, {}", mode, feed.mm_after),
+                |p| {
+                    let mut n = p.write(&vec![0x0a; feed.lines_before as usize])?;
+                    if units > 0 {
+                        n += p.feed_units(units)?;
+                    }
+                    Ok(n)
+                },
+            );
+        }
+        let partial_only = matches!(
+            self.printer,
+            SupportedPrinters::P3 | SupportedPrinters::Bixolon
+        );
+        let mode = match mode {
+            CutMode::Full if partial_only && substitute => CutMode::Partial,
+            mode => mode,
+        };
+        // The profile's bytes for the cut, when it overrides them
+        let name = match mode {
+            CutMode::Full => "full_cut",
+            CutMode::Partial => "partial_cut",
+        };
+        self.command(
+            name,
+            || format!("{:?}, {}", mode, feed.mm_after),
+            |p| {
+                let bytes = cut_bytes(p.printer, mode == CutMode::Partial, feed, dpi)?;
+                let n = p.write(&bytes)?;
+                p.wait_after_cut()?;
+                Ok(n)
+            },
+        )
+    }
+
+    pub fn chain_finish_sale(&mut self, finish: SaleFinish) -> Result<&mut Self, Error> {
+        self.finish_sale(finish).map(|_| self)
+    }
+
+    /// Ends a sale: opens the drawer, feeds and cuts the receipt, then sounds
+    /// the buzzer
+    ///
+    /// The drawer pulse goes first, the printer stops for the cut and some
+    /// (the Epic) take seconds before they accept the pulse after it. The
+    /// buzzer is a real-time command and goes last so it can't hold up the
+    /// rest. A profile with its own [Profile::sale_order] changes the order,
+    /// e.g. for a drawer that must not open before the receipt is cut.
+    /// Without a drawer or buzzer in the profile the pulse or buzzer is left
+    /// out, unless the [Degradation] is [Degradation::Fail]. More than 63
+    /// buzzes is [Error::InvalidArgument].
+    pub fn finish_sale(&mut self, finish: SaleFinish) -> Result<usize, Error> {
+        // Checked before the drawer opens
+        if finish.buzzer > 63 {
+            return Err(Error::InvalidArgument);
+        }
+        let feed_mm = finish
+            .feed_mm
+            .or(self.profile.cut_feed.map(|feed| feed.mm_after))
+            .unwrap_or(0);
+        let units = feed_units_for(self.printer, feed_mm, self.profile.dpi.unwrap_or(203))?;
+        let drawer = finish.drawer.filter(|_| self.profile.capabilities.drawer);
+        let buzzer = if self.profile.capabilities.buzzer {
+            finish.buzzer
+        } else {
+            0
+        };
+        let missing = (finish.drawer.is_some() && drawer.is_none()) || finish.buzzer != buzzer;
+        if missing && self.degradation == Degradation::Fail {
+            return Err(Error::Unsupported);
+        }
+        self.command(
+            "finish_sale",
+            || format!("{:?}", finish),
+            |p| {
+                let mut n = 0;
+                for step in p.profile.sale_order() {
+                    match (step, drawer, finish.cut) {
+                        // The common starting point, see cashdraw
+                        (SaleStep::Drawer, Some(pin), _) => n += p.cashdraw(pin, 100, 200)?,
+                        (SaleStep::Cut, _, Some(mode)) => n += p.cut(mode, finish.feed_mm)?,
+                        (SaleStep::Cut, _, None) if units > 0 => n += p.feed_units(units)?,
+                        // Pattern 1, 300 ms on and 200 ms off
+                        (SaleStep::Buzzer, _, _) if buzzer > 0 => {
+                            n += p.real_time_buzzer(1, buzzer, 3, 2)?
+                        }
+                        _ => (),
+                    }
+                }
+                Ok(n)
+            },
+        )
+    }
+
+    #[deprecated(note = "feeds lines before cutting, use Printer::chain_cut")]
+    pub fn chain_full_cut(&mut self) -> Result<&mut Self, Error> {
+        #[allow(deprecated)]
+        self.full_cut().map(|_| self)
+    }
+
+    /// Feeds and cuts as the profile's [CutFeed] says, three lines before
+    /// the cut when it has none
+    #[deprecated(note = "feeds lines before cutting, use Printer::cut")]
+    pub fn full_cut(&mut self) -> Result<usize, Error> {
+        if !self.profile.capabilities.cutter {
+            return Err(Error::Unsupported);
+        }
+        let feed = self.profile.cut_feed.unwrap_or(CutFeed::LEGACY);
+        let dpi = self.profile.dpi.unwrap_or(203);
+        self.command("full_cut", String::new, |p| {
+            match p.printer {
+                SupportedPrinters::SNBC
+                | SupportedPrinters::Epic
+                | SupportedPrinters::StarEscPos
+                | SupportedPrinters::Citizen
+                | SupportedPrinters::Generic => p.write(&feed_and_cut(feed, false, dpi)?),
+                // Feeds to the cutting position by itself
+                SupportedPrinters::Star => p.write(consts::STAR_FULL_CUT),
+                // p3 and the SRP-350 only support partial cut
+                _ => Err(Error::Unsupported),
+            }
+        })
+    }
+
+    #[deprecated(note = "feeds lines before cutting, use Printer::chain_cut")]
+    pub fn chain_partial_cut(&mut self) -> Result<&mut Self, Error> {
+        #[allow(deprecated)]
+        self.partial_cut().map(|_| self)
+    }
+
+    /// Feeds and cuts as the profile's [CutFeed] says, three lines before
+    /// the cut when it has none
+    #[deprecated(note = "feeds lines before cutting, use Printer::cut")]
+    pub fn partial_cut(&mut self) -> Result<usize, Error> {
+        if !self.profile.capabilities.cutter {
+            return Err(Error::Unsupported);
+        }
+        let feed = self.profile.cut_feed.unwrap_or(CutFeed::LEGACY);
+        let dpi = self.profile.dpi.unwrap_or(203);
+        self.command("partial_cut", String::new, |p| {
+            let n = match p.printer {
+                SupportedPrinters::SNBC
+                | SupportedPrinters::Epic
+                | SupportedPrinters::StarEscPos
+                | SupportedPrinters::Bixolon
+                | SupportedPrinters::Citizen
+                | SupportedPrinters::Generic => p.write(&feed_and_cut(feed, true, dpi)?),
+                SupportedPrinters::P3 => {
+                    let mut bytes = vec![0x0a; feed.lines_before as usize];
+                    if feed.mm_after > 0 {
+                        // ESC J
+                        bytes.extend_from_slice(&[
+                            0x1b,
+                            0x4a,
+                            feed_units_for(p.printer, feed.mm_after, dpi)?,
+                        ]);
+                    }
+                    bytes.extend_from_slice(&[0x1b, 0x6d]);
+                    p.write(&bytes)
+                }
+                SupportedPrinters::Star => p.write(consts::STAR_PARTIAL_CUT),
+                _ => Err(Error::Unsupported),
+            }?;
+            // Nothing to wait for when the cut wasn't sent
+            p.wait_after_cut()?;
+            Ok(n)
+        })
+    }
+
+    pub fn chain_cut_after_feed(&mut self, n: u8) -> Result<&mut Self, Error> {
+        self.cut_after_feed(n).map(|_| self)
+    }
+
+    /// GS V m n - Feed paper and partial cut (m = 66)
+    ///
+    /// ASCII    GS   V  m  n
+    /// Hex      1d  56 42  n
+    /// Decimal  29  86 66  n
+    /// Range: 0 <= n <= 255
+    ///
+    /// Feeds the paper to the cutting position plus n x the vertical motion
+    /// unit and cuts, so the paper stops right where it needs to instead of
+    /// feeding a fixed three lines first like [Printer::partial_cut].
+    pub fn cut_after_feed(&mut self, n: u8) -> Result<usize, Error> {
+        if !self.profile.capabilities.cutter {
+            return Err(Error::Unsupported);
+        }
+        self.command(
+            "cut_after_feed",
+            || n.to_string(),
+            |p| {
+                let n_bytes = match p.printer {
+                    SupportedPrinters::SNBC
+                    | SupportedPrinters::Epic
+                    | SupportedPrinters::StarEscPos
+                    | SupportedPrinters::Bixolon
+                    | SupportedPrinters::Citizen
+                    | SupportedPrinters::Generic => p.write(&[0x1d, 0x56, 0x42, n]),
+                    // p3 only documents ESC m, Star Line Mode has no extra feed
+                    _ => Err(Error::Unsupported),
+                }?;
+                // Nothing to wait for when the cut wasn't sent
+                p.wait_after_cut()?;
+                Ok(n_bytes)
+            },
+        )
+    }
+
+    /// The Epic doesn't accept data while it's cutting
+    fn wait_after_cut(&mut self) -> Result<(), Error> {
+        if self.printer == SupportedPrinters::Epic {
+            match self.flow_control {
+                // Wait for the cut to be processed instead of guessing
+                FlowControl::StatusPaced { timeout, .. } => self.wait_for_drain(timeout)?,
+                // Nothing was sent while capturing
+                FlowControl::None if self.capturing => (),
+                FlowControl::None => {
+                    self.flush_batch()?;
+                    std::thread::sleep(std::time::Duration::new(3, 0))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn chain_present_ticket(&mut self, length_mm: u8) -> Result<&mut Self, Error> {
+        self.present_ticket(length_mm).map(|_| self)
+    }
+
+    /// GS e n m - Presenter control (Custom kiosk printers)
+    ///
+    /// ASCII    GS   e  n  m
+    /// Hex      1d  65  n  m
+    /// Decimal  29 101  n  m
+    ///
+    /// | n    | Function                                      |
+    /// |------|-----------------------------------------------|
+    /// | 0x03 | Present the ticket leaving m mm outside       |
+    /// | 0x05 | Retract the ticket into the printer           |
+    /// | 0x0c | Eject the ticket completely                   |
+    /// | 0x12 | Retract a ticket not taken within m seconds   |
+    ///
+    /// Presents the printed ticket with length_mm of it outside the mouth
+    pub fn present_ticket(&mut self, length_mm: u8) -> Result<usize, Error> {
+        self.command(
+            "present_ticket",
+            || length_mm.to_string(),
+            |p| p.presenter(0x03, length_mm),
+        )
+    }
+
+    pub fn chain_retract_ticket(&mut self) -> Result<&mut Self, Error> {
+        self.retract_ticket().map(|_| self)
+    }
+
+    /// Pulls a presented ticket back into the printer, see
+    /// [Printer::present_ticket]
+    pub fn retract_ticket(&mut self) -> Result<usize, Error> {
+        self.command("retract_ticket", String::new, |p| p.presenter(0x05, 0x00))
+    }
+
+    pub fn chain_eject_ticket(&mut self) -> Result<&mut Self, Error> {
+        self.eject_ticket().map(|_| self)
+    }
+
+    /// Pushes the ticket completely out of the printer, see
+    /// [Printer::present_ticket]
+    pub fn eject_ticket(&mut self) -> Result<usize, Error> {
+        self.command("eject_ticket", String::new, |p| p.presenter(0x0c, 0x00))
+    }
+
+    pub fn chain_set_retract_timeout(&mut self, seconds: u8) -> Result<&mut Self, Error> {
+        self.set_retract_timeout(seconds).map(|_| self)
+    }
+
+    /// Retracts presented tickets that aren't taken within seconds, 0 leaves
+    /// tickets in the presenter until they are taken. See
+    /// [Printer::present_ticket]
+    pub fn set_retract_timeout(&mut self, seconds: u8) -> Result<usize, Error> {
+        self.command(
+            "set_retract_timeout",
+            || seconds.to_string(),
+            |p| p.presenter(0x12, seconds),
+        )
+    }
+
+    fn presenter(&mut self, n: u8, m: u8) -> Result<usize, Error> {
+        match self.printer {
+            SupportedPrinters::P3 => self.write(&[0x1d, 0x65, n, m]),
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    /// DLE EOT 0x14 - Transmit full status (Custom kiosk printers)
+    ///
+    /// ASCII    DLE  EOT  n
+    /// Hex      10   04   14
+    /// Decimal  16   4    20
+    ///
+    /// The answer is 6 bytes: 0x10 0x0f, paper status, user status,
+    /// recoverable errors and unrecoverable errors. The paper status byte has
+    /// the presenter state:
+    ///
+    /// | Bit | Meaning when set           |
+    /// |-----|----------------------------|
+    /// |  0  | Paper not present          |
+    /// |  2  | Paper near end             |
+    /// |  5  | Ticket present in output   |
+    pub fn presenter_status(&mut self) -> Result<PresenterStatus, Error> {
+        if self.printer != SupportedPrinters::P3 {
+            return Err(Error::Unsupported);
+        }
+        let mut buffer = [0_u8; 6];
+        let transferred = self.query(&[0x10, 0x04, 0x14], &mut buffer)?;
+        if transferred < 3 || buffer[0] != 0x10 || buffer[1] != 0x0f {
+            return Err(Error::InvalidResponse);
+        }
+        Ok(PresenterStatus::from(buffer[FULL_STATUS_PAPER_BYTE]))
+    }
+
+    /// Blocks until the presented ticket has been taken, so the next ticket
+    /// isn't printed behind one the customer hasn't picked up yet
+    ///
+    /// Returns [Error::Timeout] when the ticket is still there after timeout.
+    /// Uses the ticket present bit of [Printer::presenter_status].
+    pub fn wait_for_ticket_taken(&mut self, timeout: Duration) -> Result<(), Error> {
+        let start = std::time::Instant::now();
+        loop {
+            if !self.presenter_status()?.ticket_present {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                return Err(Error::Timeout);
+            }
+            std::thread::sleep(TICKET_TAKEN_POLL);
+        }
+    }
+
+    pub fn chain_select_station(&mut self, station: Station) -> Result<&mut Self, Error> {
+        self.select_station(station).map(|_| self)
+    }
+
+    /// ESC c 0 n - Select paper type(s) for printing
+    ///
+    /// ASCII    ESC   c  0  n
+    /// Hex      1b   63 30  n
+    /// Decimal  27   99 48  n
+    ///
+    /// | n    | Station  |
+    /// |------|----------|
+    /// | 0x01 | Journal  |
+    /// | 0x02 | Receipt  |
+    /// | 0x04 | Slip     |
+    ///
+    /// Only printers with more than one station support this command.
+    pub fn select_station(&mut self, station: Station) -> Result<usize, Error> {
+        let n = match station {
+            Station::Journal => 0x01,
+            Station::Receipt => 0x02,
+            Station::Slip => 0x04,
+        };
+        self.command(
+            "select_station",
+            || format!("{:?}", station),
+            |p| {
+                let n_bytes = p.write(&[0x1b, 0x63, 0x30, n])?;
+                p.station = station;
+                Ok(n_bytes)
+            },
+        )
+    }
+
+    pub fn chain_wait_for_slip(
+        &mut self,
+        insert_timeout_min: u8,
+        print_delay_ds: u8,
+    ) -> Result<&mut Self, Error> {
+        self.wait_for_slip(insert_timeout_min, print_delay_ds)
+            .map(|_| self)
+    }
+
+    /// ESC f t1 t2 - Set cut sheet wait time
+    ///
+    /// ASCII    ESC   f  t1  t2
+    /// Hex      1b   66  t1  t2
+    /// Decimal  27  102  t1  t2
+    /// Range: 0 <= t1 <= 64, 0 <= t2 <= 255
+    ///
+    /// Makes the slip station wait up to t1 minutes for a slip to be inserted
+    /// and starts printing t2 x 0.1 s after it is detected.
+    pub fn wait_for_slip(
+        &mut self,
+        insert_timeout_min: u8,
+        print_delay_ds: u8,
+    ) -> Result<usize, Error> {
+        if insert_timeout_min > 64 {
+            return Err(Error::InvalidArgument);
+        }
+        self.command(
+            "wait_for_slip",
+            || format!("{}, {}", insert_timeout_min, print_delay_ds),
+            |p| p.write(&[0x1b, 0x66, insert_timeout_min, print_delay_ds]),
+        )
+    }
+
+    pub fn chain_eject_slip(&mut self) -> Result<&mut Self, Error> {
+        self.eject_slip().map(|_| self)
+    }
+
+    /// FF - Print and eject cut sheet
+    ///
+    /// Hex      0c
+    ///
+    /// Prints what's buffered for the slip and ejects it. Receipt printing
+    /// continues after selecting [Station::Receipt] again.
+    pub fn eject_slip(&mut self) -> Result<usize, Error> {
+        if self.station != Station::Slip {
+            return Err(Error::InvalidArgument);
+        }
+        self.command("eject_slip", String::new, |p| p.write(consts::CTL_FF))
+    }
+
+    /// DLE EOT 5 - Transmit slip status
+    ///
+    /// ASCII    DLE  EOT  n
+    /// Hex      10   04   05
+    /// Decimal  16   4    5
+    ///
+    /// | Bit | Meaning when set                     |
+    /// |-----|--------------------------------------|
+    /// |  2  | Waiting for slip insertion           |
+    /// |  5  | Top of form sensor: no paper         |
+    /// |  6  | Bottom of form sensor: no paper      |
+    pub fn slip_status(&mut self) -> Result<SlipStatus, Error> {
+        if self.printer == SupportedPrinters::Unknown {
+            return Err(Error::Unsupported);
+        }
+        Ok(SlipStatus::from(self.real_time_status(0x05)?))
+    }
+
+    /// FS a 0 n / FS b - Read the MICR line of a cheque in the slip station
+    ///
+    /// ASCII    FS   a  0  n
+    /// Hex      1c  61 30  n
+    /// Decimal  28  97 48  n
+    ///
+    /// n = 0 reads E-13B, n = 1 reads CMC-7. The result is then requested with
+    /// FS b, which the printer answers with 0x5f, a status byte, the MICR
+    /// characters and a terminating NUL.
+    ///
+    /// Notes:
+    ///   - The cheque stays in the printer (to print the endorsement for
+    ///     example) until [Printer::eject_check]
+    pub fn read_micr(&mut self, font: MicrFont) -> Result<MicrData, Error> {
+        if self.printer == SupportedPrinters::Unknown {
+            return Err(Error::Unsupported);
+        }
+        let n = match font {
+            MicrFont::E13B => 0x00,
+            MicrFont::CMC7 => 0x01,
+        };
+        self.write(&[0x1c, 0x61, 0x30, n])?;
+        self.send(&[0x1c, 0x62, 0x01])?;
+        let mut buffer = [0_u8; 128];
+        let transferred = self.read_status(&mut buffer, MICR_TIMEOUT)?;
+        if transferred < 3 || buffer[0] != 0x5f {
+            return Err(Error::InvalidResponse);
+        }
+        let data = &buffer[2..transferred];
+        let data = match data.iter().position(|b| *b == 0x00) {
+            Some(end) => &data[..end],
+            None => data,
+        };
+        // A failed read only has the header and status
+        if data.is_empty() {
+            return Err(Error::InvalidResponse);
+        }
+        let line = std::str::from_utf8(data).map_err(|_| Error::InvalidResponse)?;
+        Ok(MicrData::parse(line, font)?)
+    }
+
+    pub fn chain_eject_check(&mut self) -> Result<&mut Self, Error> {
+        self.eject_check().map(|_| self)
+    }
+
+    /// FS a 2 - Eject the cheque after [Printer::read_micr]
+    ///
+    /// ASCII    FS   a  2
+    /// Hex      1c  61 32
+    /// Decimal  28  97 50
+    pub fn eject_check(&mut self) -> Result<usize, Error> {
+        self.command("eject_check", String::new, |p| p.write(&[0x1c, 0x61, 0x32]))
+    }
+
+    pub fn chain_code_page(&mut self, n: u8) -> Result<&mut Self, Error> {
+        self.code_page(n).map(|_| self)
+    }
+
+    /// ESC t n - Select character code table
+    ///
+    /// ASCII    ESC   t  n
+    /// Hex      1b   74  n
+    /// Decimal  27  116  n
+    ///
+    /// The page numbers differ between printers, see the printer's code page
+    /// table. Star Line Mode uses ESC GS t n. The selection is re-sent after [Printer::hwinit] and power
+    /// cycles, see [Printer::resync].
+    pub fn code_page(&mut self, n: u8) -> Result<usize, Error> {
+        let state = TextState {
+            code_page: Some(n),
+            ..self.text_state
+        };
+        self.attribute(
+            "code_page",
+            || n.to_string(),
+            Some(state),
+            |p| {
+                let n_bytes = p.write(&code_page_cmd(p.printer, n))?;
+                p.session.code_page = Some(n);
+                Ok(n_bytes)
+            },
+        )
+    }
+
+    pub fn chain_print_density(&mut self, n: i8) -> Result<&mut Self, Error> {
+        self.print_density(n).map(|_| self)
+    }
+
+    /// GS ( K pL pH fn m - Select print density (fn = 49)
+    ///
+    /// ASCII    GS   (   K  pL  pH  fn  m
+    /// Hex      1d  28  4b  02  00  31  m
+    /// Decimal  29  40  75   2   0  49  m
+    /// Range: -6 <= n <= 6, sent as m = 250-255 (-6 to -1) or 0-6
+    ///
+    /// 0 is the printer's standard density, negative values print lighter.
+    pub fn print_density(&mut self, n: i8) -> Result<usize, Error> {
+        if !(-6..=6).contains(&n) {
+            return Err(Error::InvalidArgument);
+        }
+        self.command(
+            "print_density",
+            || n.to_string(),
+            |p| {
+                let n_bytes = p.write(&[0x1d, 0x28, 0x4b, 0x02, 0x00, 0x31, n as u8])?;
+                p.session.density = Some(n);
+                Ok(n_bytes)
+            },
+        )
+    }
+
+    pub fn chain_define_logo(&mut self, image: &Image) -> Result<&mut Self, Error> {
+        self.define_logo(image).map(|_| self)
+    }
+
+    /// GS * x y d1...dk - Define downloaded bit image
+    ///
+    /// ASCII    GS   *  x  y  d1...dk
+    /// Hex      1d  2a  x  y  d1...dk
+    /// Decimal  29  42  x  y  d1...dk
+    /// Range: 1 <= x <= 255, 1 <= y <= 48, x * y <= 1536
+    ///
+    /// x and y are the width and height in units of 8 dots, each data byte is a
+    /// column of 8 vertical dots.
+    ///
+    /// Notes:
+    ///   - The image is kept in RAM until it is redefined, ESC @ is sent or the
+    ///     printer is powered off, so it is re-sent after [Printer::hwinit] and
+    ///     power cycles, see [Printer::resync].
+    pub fn define_logo(&mut self, image: &Image) -> Result<usize, Error> {
+        if self.quirks.raster_only {
+            return Err(Error::Unsupported);
+        }
+        let x = image.width.div_ceil(8);
+        let y = image.height.div_ceil(8);
+        if !(1..=255).contains(&x) || !(1..=48).contains(&y) || x * y > 1536 {
+            return Err(Error::InvalidArgument);
+        }
+        let mut cmd = vec![0x1d, 0x2a, x as u8, y as u8];
+        cmd.extend(column_bytes(image, x * 8, y));
+        self.command(
+            "define_logo",
+            || format!("{}x{}", image.width, image.height),
+            |p| {
+                let n_bytes = p.write(&cmd)?;
+                p.session.logo = Some(cmd);
+                Ok(n_bytes)
+            },
+        )
+    }
+
+    pub fn chain_print_logo(&mut self, mode: Option<&str>) -> Result<&mut Self, Error> {
+        self.print_logo(mode).map(|_| self)
+    }
+
+    /// GS / m - Print downloaded bit image defined with [Printer::define_logo]
+    ///
+    /// ASCII    GS   /  m
+    /// Hex      1d  2f  m
+    /// Decimal  29  47  m
+    ///
+    /// Modes are the same as [Printer::raster]
+    pub fn print_logo(&mut self, mode: Option<&str>) -> Result<usize, Error> {
+        if self.quirks.raster_only {
+            return Err(Error::Unsupported);
+        }
+        self.check_strict(known_image_mode(mode))?;
+        self.command(
+            "print_logo",
+            || format!("{:?}", mode),
+            |p| {
+                let mut buf = [0; 8];
+                let mode_upper = upper(mode.unwrap_or("NORMAL"), &mut buf);
+                let m = match mode_upper {
+                    "DW" => 0x01,
+                    "DH" => 0x02,
+                    "QD" => 0x03,
+                    // "NORMAL" | _ =>
+                    _ => 0x00,
+                };
+                p.write(&[0x1d, 0x2f, m])
+            },
+        )
+    }
+
+    /// Command set [Printer::define_nv_images] and [Printer::print_nv_image]
+    /// use, asked from the printer the first time
+    ///
+    /// SNBC printers are asked for their firmware version with GS I 3, see
+    /// [NvImageCommands::for_firmware]. When they don't answer the legacy
+    /// commands are used, other printers always get GS ( L.
+    pub fn nv_image_commands(&mut self) -> NvImageCommands {
+        if let Some(commands) = self.nv_commands {
+            return commands;
+        }
+        let commands = match self.printer {
+            SupportedPrinters::SNBC => match self.get_rom_version() {
+                Ok(firmware) => NvImageCommands::for_firmware(self.printer, &firmware),
+                Err(e) => {
+                    log::debug!("No firmware version ({}), using FS q NV images", e);
+                    NvImageCommands::Legacy
+                }
+            },
+            _ => NvImageCommands::Graphics,
+        };
+        self.nv_commands = Some(commands);
+        commands
+    }
+
+    /// Overrides the detected NV image command set
+    pub fn set_nv_image_commands(&mut self, commands: NvImageCommands) {
+        self.nv_commands = Some(commands);
+    }
+
+    pub fn chain_define_nv_images(&mut self, images: &[Image]) -> Result<&mut Self, Error> {
+        self.define_nv_images(images).map(|_| self)
+    }
+
+    /// Stores images in the printer's NV memory as images 1 to images.len(),
+    /// replacing all stored images
+    ///
+    /// FS q n [xL xH yL yH d1...dk]1...[xL xH yL yH d1...dk]n - Define NV bit image
+    ///
+    /// ASCII    FS   q  n  [xL  xH  yL  yH  d1...dk]1...
+    /// Hex      1c  71  n  [xL  xH  yL  yH  d1...dk]1...
+    /// Decimal  28 113  n  [xL  xH  yL  yH  d1...dk]1...
+    /// Range: 1 <= n <= 255, 1 <= x <= 1023, 1 <= y <= 288
+    ///
+    /// x and y are the width and height in units of 8 dots, the data is
+    /// columns as in [Printer::define_logo].
+    ///
+    /// Printers with GS ( L instead get GS ( L 65 deleting all images and
+    /// one GS ( L 67 raster definition per image, see
+    /// [Printer::nv_image_commands]:
+    ///
+    /// ASCII    GS   (  L  pL  pH  0  C  0  kc1 kc2 1  xL  xH  yL  yH  1  d1...dk
+    /// Hex      1d  28 4c  pL  pH 30 43 30  kc1 kc2 01 xL  xH  yL  yH 31  d1...dk
+    /// Decimal  29  40 76  pL  pH 48 67 48  kc1 kc2 1  xL  xH  yL  yH 49  d1...dk
+    /// Range: 1 <= x <= 8192, 1 <= y <= 2304 dots, pL + pH * 256 <= 65535
+    ///
+    /// Notes:
+    ///   - NV memory survives power cycles but wears out, store the images
+    ///     once at setup rather than before every job
+    ///   - The printer is busy writing for a while afterwards and ignores
+    ///     real-time commands
+    pub fn define_nv_images(&mut self, images: &[Image]) -> Result<usize, Error> {
+        if images.is_empty() || images.len() > 255 {
+            return Err(Error::InvalidArgument);
+        }
+        let mut cmd = Vec::new();
+        match self.nv_image_commands() {
+            NvImageCommands::Legacy => {
+                cmd.extend_from_slice(&[0x1c, 0x71, images.len() as u8]);
+                for image in images {
+                    let x = image.width.div_ceil(8);
+                    let y = image.height.div_ceil(8);
+                    if !(1..=1023).contains(&x) || !(1..=288).contains(&y) {
+                        return Err(Error::InvalidArgument);
+                    }
+                    cmd.extend_from_slice(&(x as u16).to_le_bytes());
+                    cmd.extend_from_slice(&(y as u16).to_le_bytes());
+                    cmd.extend(column_bytes(image, x * 8, y));
+                }
+            }
+            NvImageCommands::Graphics => {
+                // Delete all, "CLR"
+                cmd.extend_from_slice(&[
+                    0x1d, 0x28, 0x4c, 0x05, 0x00, 0x30, 0x41, 0x43, 0x4c, 0x52,
+                ]);
+                for (i, image) in images.iter().enumerate() {
+                    let data = image.get_raster();
+                    let len = data.len() + 11;
+                    if !(1..=8192).contains(&image.width)
+                        || !(1..=2304).contains(&image.height)
+                        || len > u16::MAX as usize
+                    {
+                        return Err(Error::InvalidArgument);
+                    }
+                    let [kc1, kc2] = nv_key(i as u8 + 1);
+                    cmd.extend_from_slice(&[0x1d, 0x28, 0x4c]);
+                    cmd.extend_from_slice(&(len as u16).to_le_bytes());
+                    cmd.extend_from_slice(&[0x30, 0x43, 0x30, kc1, kc2, 0x01]);
+                    cmd.extend_from_slice(&(image.width as u16).to_le_bytes());
+                    cmd.extend_from_slice(&(image.height as u16).to_le_bytes());
+                    cmd.push(0x31);
+                    cmd.extend_from_slice(&data);
+                }
+            }
+        }
+        self.command(
+            "define_nv_images",
+            || images.len().to_string(),
+            |p| p.write(&cmd),
+        )
+    }
+
+    pub fn chain_print_nv_image(&mut self, n: u8, mode: Option<&str>) -> Result<&mut Self, Error> {
+        self.print_nv_image(n, mode).map(|_| self)
+    }
+
+    /// Prints image n stored with [Printer::define_nv_images]
+    ///
+    /// FS p n m - Print NV bit image
+    ///
+    /// ASCII    FS   p  n  m
+    /// Hex      1c  70  n  m
+    /// Decimal  28 112  n  m
+    ///
+    /// or with GS ( L, see [Printer::nv_image_commands]:
+    ///
+    /// ASCII    GS   (  L  pL  pH  0  E  kc1 kc2 x  y
+    /// Hex      1d  28 4c  06  00 30 45  kc1 kc2 x  y
+    /// Decimal  29  40 76   6   0 48 69  kc1 kc2 x  y
+    ///
+    /// Modes are the same as [Printer::raster]
+    pub fn print_nv_image(&mut self, n: u8, mode: Option<&str>) -> Result<usize, Error> {
+        if n == 0 {
+            return Err(Error::InvalidArgument);
+        }
+        self.check_strict(known_image_mode(mode))?;
+        let commands = self.nv_image_commands();
+        self.command(
+            "print_nv_image",
+            || format!("{} {:?}", n, mode),
+            |p| {
+                let mut buf = [0; 8];
+                let mode_upper = upper(mode.unwrap_or("NORMAL"), &mut buf);
+                let m = match mode_upper {
+                    "DW" => 0x01,
+                    "DH" => 0x02,
+                    "QD" => 0x03,
+                    // "NORMAL" | _ =>
+                    _ => 0x00,
+                };
+                match commands {
+                    NvImageCommands::Legacy => p.write(&[0x1c, 0x70, n, m]),
+                    NvImageCommands::Graphics => {
+                        let [kc1, kc2] = nv_key(n);
+                        let (x, y) = (1 + (m & 1), 1 + (m >> 1));
+                        p.write(&[0x1d, 0x28, 0x4c, 0x06, 0x00, 0x30, 0x45, kc1, kc2, x, y])
+                    }
+                }
+            },
+        )
+    }
+
+    /// GS : - Start/end of macro definition
+    ///
+    /// ASCII    GS   :
+    /// Hex      1d  3a
+    /// Decimal  29  58
+    ///
+    /// Everything `commands` sends to the printer is stored as the macro
+    /// instead of being printed, and can then be replayed with
+    /// [Printer::run_macro]. Useful for a fixed header or footer on slow links.
+    ///
+    /// Notes:
+    ///   - Up to 2048 bytes, longer macros return [Error::InvalidArgument]
+    ///     and aren't stored by the printer
+    ///   - The macro is not cleared by ESC @, only when the printer is powered
+    ///     off, so it is re-sent after power cycles, see [Printer::resync]
+    pub fn define_macro<F>(&mut self, commands: F) -> Result<usize, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<(), Error>,
+    {
+        self.command("define_macro", String::new, |p| {
+            p.header_in_macro = false;
+            let mut n_bytes = p.write(&[0x1d, 0x3a])?;
+            p.recording = Some(Vec::new());
+            let res = commands(p);
+            let data = p.recording.take().unwrap_or_default();
+            n_bytes += data.len();
+            // Always end the definition so the printer doesn't swallow
+            // whatever is sent next
+            n_bytes += p.write(&[0x1d, 0x3a])?;
+            p.session.macro_data = None;
+            res?;
+            if data.len() > MACRO_MAX_LEN {
+                return Err(Error::InvalidArgument);
+            }
+            p.session.macro_data = Some(data);
+            Ok(n_bytes)
+        })
+    }
+
+    pub fn chain_run_macro(
+        &mut self,
+        times: u8,
+        interval: u8,
+        wait_for_button: bool,
+    ) -> Result<&mut Self, Error> {
+        self.run_macro(times, interval, wait_for_button)
+            .map(|_| self)
+    }
+
+    /// GS ^ r t m - Execute macro
+    ///
+    /// ASCII    GS   ^  r  t  m
+    /// Hex      1d  5e  r  t  m
+    /// Decimal  29  94  r  t  m
+    /// Range: 0 <= r <= 255, 0 <= t <= 255, 0 <= m <= 1
+    ///
+    /// Runs the macro defined with [Printer::define_macro] `times` times,
+    /// waiting `interval` * 100 ms between runs. With `wait_for_button` the
+    /// printer waits for the FEED button instead, blinking the error LED.
+    pub fn run_macro(
+        &mut self,
+        times: u8,
+        interval: u8,
+        wait_for_button: bool,
+    ) -> Result<usize, Error> {
+        self.command(
+            "run_macro",
+            || format!("{}, {}, {}", times, interval, wait_for_button),
+            |p| {
+                p.text_state = TextState::default();
+                p.write(&[0x1d, 0x5e, times, interval, wait_for_button as u8])
+            },
+        )
+    }
+
+    /// Re-sends the code page, print density, RAM logo and macro set during
+    /// this session
+    ///
+    /// This happens automatically before the next command after
+    /// [Printer::hwinit], after [Printer::check_power_cycle] detects a power
+    /// cycle, and after a status query sees the printer come back online
+    /// (which is how a power cycle looks from the status alone).
+    pub fn resync(&mut self) -> Result<usize, Error> {
+        self.session.needs_resync = false;
+        // The text attributes were lost along with the session
+        self.text_state = TextState::default();
+        self.command("resync", String::new, |p| {
+            let session = p.session.clone();
+            let mut n_bytes = 0;
+            if let Some(n) = session.code_page {
+                n_bytes += p.write(&code_page_cmd(p.printer, n))?;
+            }
+            if let Some(n) = session.density {
+                n_bytes += p.write(&[0x1d, 0x28, 0x4b, 0x02, 0x00, 0x31, n as u8])?;
+            }
+            if let Some(logo) = session.logo {
+                n_bytes += p.write(&logo)?;
+            }
+            if let Some(data) = session.macro_data {
+                n_bytes += p.write(&[0x1d, 0x3a])?;
+                n_bytes += p.write(&data)?;
+                n_bytes += p.write(&[0x1d, 0x3a])?;
+            }
+            Ok(n_bytes)
+        })
+    }
+
+    pub fn chain_bit_image(
+        &mut self,
+        image: &Image,
+        density: Option<BitImageDensity>,
+    ) -> Result<&mut Self, Error> {
+        self.bit_image(image, density).map(|_| self)
+    }
+
+    /// ESC * m nL nH d1...dk - Select bit-image mode
+    ///
+    /// Prints image as stripes of density. Without a density it's picked by
+    /// [BitImageDensity::auto] from the image height, [Profile::dpi] and
+    /// [Printer::set_image_quality].
+    pub fn bit_image(
+        &mut self,
+        image: &Image,
+        density: Option<BitImageDensity>,
+    ) -> Result<usize, Error> {
+        let density = density.unwrap_or_else(|| {
+            let dpi = self.profile.dpi.unwrap_or(203);
+            BitImageDensity::auto(image.height, dpi, self.image_quality)
+        });
+        self.command(
+            "bit_image",
+            || format!("{}x{}, {:?}", image.width, image.height, density),
+            |p| {
+                if p.quirks.raster_only {
+                    return p.raster(image, None);
+                }
+                let header = density.header();
+                let n = density.dots() / 8;
+                let mut n_bytes = 0;
+                n_bytes += p.line_space(0)?;
+                for line in image.bitimage_lines(density.dots()) {
+                    n_bytes += p.write(header)?;
+                    n_bytes += p.write_u16le((line.len() / n as usize) as u16)?;
+                    n_bytes += p.write(line.as_ref())?;
+                    n_bytes += p.feed(1)?;
+                    p.pace(line.len())?;
+                }
+                Ok(n_bytes)
+            },
+        )
+    }
+
+    pub fn chain_raster(&mut self, image: &Image, mode: Option<&str>) -> Result<&mut Self, Error> {
+        self.raster(image, mode).map(|_| self)
+    }
+    pub fn raster(&mut self, image: &Image, mode: Option<&str>) -> Result<usize, Error> {
+        self.check_strict(known_image_mode(mode))?;
+        self.command(
+            "raster",
+            || format!("{}x{}, {:?}", image.width, image.height, mode),
+            |p| {
+                if p.printer == SupportedPrinters::Star {
+                    return p.star_raster(image);
+                }
+                let mut buf = [0; 8];
+                let mode_upper = upper(mode.unwrap_or("NORMAL"), &mut buf);
+                let header = match mode_upper {
+                    // Double Wide
+                    "DW" => &[0x1d, 0x76, 0x30, 0x01],
+                    // Double Height
+                    "DH" => &[0x1d, 0x76, 0x30, 0x02],
+                    // Quadruple
+                    "QD" => &[0x1d, 0x76, 0x30, 0x03],
+                    // "NORMAL" | _ =>
+                    _ => &[0x1d, 0x76, 0x30, 0x00],
+                };
+                let width_bytes = image.width.div_ceil(8).max(1) as usize;
+                let raster = image.get_raster();
+                // When pacing, the image is sent as a band of rows per command
+                let band_rows = match p.flow_control {
+                    FlowControl::StatusPaced { chunk_size, .. } => {
+                        (chunk_size / width_bytes).max(1)
+                    }
+                    FlowControl::None => image.height as usize,
+                };
+                let mut n_bytes = 0;
+                for band in raster.chunks(band_rows * width_bytes) {
+                    n_bytes += p.write(header)?;
+                    n_bytes += p.write_u16le(width_bytes as u16)?;
+                    n_bytes += p.write_u16le((band.len() / width_bytes) as u16)?;
+                    n_bytes += p.write(band)?;
+                    p.pace(band.len())?;
+                }
+                Ok(n_bytes)
+            },
+        )
+    }
+
+    /// ESC * r A, b n1 n2 d1...dk, ESC * r B - Star Line Mode raster graphics
+    ///
+    /// Each row is sent as its own `b` raster line transfer, the print mode
+    /// can't be scaled.
+    fn star_raster(&mut self, image: &Image) -> Result<usize, Error> {
+        let width_bytes = image.width.div_ceil(8).max(1) as usize;
+        let raster = image.get_raster();
+        let mut n_bytes = self.write(consts::STAR_RASTER_START)?;
+        for row in raster.chunks(width_bytes) {
+            n_bytes += self.write(&[0x62])?;
+            n_bytes += self.write_u16le(row.len() as u16)?;
+            n_bytes += self.write(row)?;
+            self.pace(row.len())?;
+        }
+        n_bytes += self.write(consts::STAR_RASTER_END)?;
+        Ok(n_bytes)
+    }
+
+    /// Paces large transmissions with flow_control
+    ///
+    /// [FlowControl::StatusPaced] waits on GS r 1, which Star Line Mode
+    /// doesn't have, and returns [Error::Unsupported] for it and for unknown
+    /// printers.
+    pub fn set_flow_control(&mut self, flow_control: FlowControl) -> Result<(), Error> {
+        if let FlowControl::StatusPaced { .. } = flow_control {
+            if matches!(
+                self.printer,
+                SupportedPrinters::Star | SupportedPrinters::Unknown
+            ) {
+                return Err(Error::Unsupported);
+            }
+        }
+        self.flow_control = flow_control;
+        self.unpaced_bytes = 0;
+        Ok(())
+    }
+
+    /// Accounts for n_bytes that were just sent and waits for the printer to
+    /// catch up once a full chunk is outstanding
+    fn pace(&mut self, n_bytes: usize) -> Result<(), Error> {
+        self.poll_monitor();
+        if self
+            .cancel
+            .as_ref()
+            .is_some_and(|token| token.is_canceled())
+        {
+            return Err(self.abort_transfer());
+        }
+        if let FlowControl::StatusPaced {
+            chunk_size,
+            timeout,
+        } = self.flow_control
+        {
+            self.unpaced_bytes += n_bytes;
+            if self.unpaced_bytes >= chunk_size {
+                self.wait_for_drain(timeout)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// GS r 1 - Transmit paper sensor status, used to wait for the printer to
+    /// process everything sent so far
+    ///
+    /// Unlike DLE EOT, GS r is queued behind the data already in the receive
+    /// buffer, so the answer only arrives once the buffer has drained up to
+    /// this point.
+    ///
+    /// With a [Printer::set_status_monitor] the wait is cut into monitor
+    /// intervals and the status is polled between them.
+    fn wait_for_drain(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.send(&[0x1d, 0x72, 0x01])?;
+        self.drain_pending = true;
+        let res = self.read_drain_reply(timeout);
+        self.drain_pending = false;
+        self.drain_reply = None;
+        res?;
+        self.unpaced_bytes = 0;
+        Ok(())
+    }
+
+    fn read_drain_reply(&mut self, timeout: Duration) -> Result<(), Error> {
+        let deadline = Instant::now() + timeout;
+        let mut buffer = [0_u8; 1];
+        // Read by a status poll already
+        while self.drain_reply.is_none() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(Error::Timeout);
+            }
+            let wait = match &self.monitor {
+                Some((interval, _)) => left.min(*interval),
+                None => left,
+            };
+            match self.read_status(&mut buffer, wait) {
+                Ok(0) => return Err(Error::Timeout),
+                // A late DLE EOT answer is dropped
+                Ok(_) => {
+                    let _ = self.route_reply(buffer[0]);
+                }
+                Err(Error::Usb(rusb::Error::Timeout)) if self.monitor.is_some() => {
+                    self.poll_monitor()
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls monitor with the status polled every interval while jobs and
+    /// images are sent, e.g. to show the paper running out during a long
+    /// image, replacing any monitor set before
+    ///
+    /// The status is read with DLE EOT, which the printer answers ahead of
+    /// the data in its receive buffer. Answers to the GS r sent by
+    /// [FlowControl::StatusPaced] are told apart from them, so neither is
+    /// taken for the other. Like any status query, a poll sends the bytes
+    /// batched so far first. The first poll is one interval from now.
+    pub fn set_status_monitor<M: StatusMonitor + 'static>(
+        &mut self,
+        interval: Duration,
+        monitor: M,
+    ) {
+        self.monitor = Some((interval, Box::new(monitor)));
+        self.last_poll = Some(Instant::now());
+    }
+
+    pub fn clear_status_monitor(&mut self) {
+        self.monitor = None;
+    }
+
+    /// Polls the status for the monitor once its interval passed
+    fn poll_monitor(&mut self) {
+        let Some((interval, _)) = &self.monitor else {
+            return;
+        };
+        if self.last_poll.is_some_and(|t| t.elapsed() < *interval) {
+            return;
+        }
+        self.last_poll = Some(Instant::now());
+        let status = self.live_status();
+        if let Some((_, monitor)) = &mut self.monitor {
+            monitor.status(&status);
+        }
+    }
+
+    /// DLE EOT 1 and 4 - Online and paper state, safe to call while a job is
+    /// being sent
+    pub fn live_status(&mut self) -> LiveStatus {
+        let mut status = LiveStatus::default();
+        if let Ok(online) = self.real_time_status(0x01) {
+            let online = ((online >> RT_OFFLINE_BIT) & 1) == 0;
+            self.track_online(online);
+            status.online = Some(online);
+        }
+        status.paper = self
+            .real_time_status(0x04)
+            .ok()
+            .map(PaperState::from_roll_status);
+        status.bytes_sent = self.bytes_sent;
+        status
+    }
+
+    /// Sorts a byte read from the status endpoint, returning DLE EOT answers
+    ///
+    /// The answer to a pending GS r is kept for [Printer::wait_for_drain],
+    /// anything else is [Error::InvalidResponse].
+    fn route_reply(&mut self, byte: u8) -> Result<Option<u8>, Error> {
+        // Bits 1 and 4 are always set and bits 0 and 7 always cleared in DLE
+        // EOT answers, GS r always clears bit 4
+        if byte & 0x93 == 0x12 {
+            Ok(Some(byte))
+        } else if self.drain_pending && self.drain_reply.is_none() && byte & 0x90 == 0 {
+            self.drain_reply = Some(byte);
+            Ok(None)
+        } else {
+            Err(Error::InvalidResponse)
+        }
+    }
+
+    pub fn get_serial(&mut self) -> Result<String, Error> {
+        match self.printer {
+            SupportedPrinters::P3 => {
+                let mut buffer = [0_u8; 16];
+                let n = self.query(&[0x1c, 0xea, 0x52], &mut buffer)?;
+                parse_text(&buffer[..n])
+            }
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    /// GS 0xE2 - Cuts made over the printer's life
+    pub fn get_cut_count(&mut self) -> Result<u32, Error> {
+        // Ten digits hold any u32, the rest is room for padding
+        let mut buffer = [0_u8; 16];
+        let n = self.query(&[0x1d, 0xe2], &mut buffer)?;
+        parse_counter(&buffer[..n])
+    }
+
+    pub fn get_rom_version(&mut self) -> Result<String, Error> {
+        let mut buffer = [0_u8; 4];
+        let n = self.query(&[0x1d, 0x49, 0x03], &mut buffer)?;
+        parse_text(&buffer[..n])
+    }
+
+    pub fn get_firmware_checksum(&mut self) -> Result<String, Error> {
+        match self.printer {
+            SupportedPrinters::Epic => {
+                match self.write(&[0x1b, 0x7e, 0x5a]) {
+                    Ok(_) => {
+                        let mut buffer = [0_u8; 4];
+                        let _ = self.read_status(&mut buffer, self.timeout)?;
+                        // Truncate the first two command bytes and read the remaining two as bits
+                        let value = format!("{:b}{:b}", &buffer[2], &buffer[3]);
+                        Ok(value.to_string())
+                    }
+                    e => {
+                        println!("Error ecountered getting firmware_checksum: {:?}", e);
+                        Err(Error::Timeout)
+                    }
+                }
+            }
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    pub fn get_firmware_id(&mut self) -> Result<String, Error> {
+        match self.printer {
+            SupportedPrinters::Epic => {
+                match self.write(&[0x1b, 0x7e, 0x46]) {
+                    Ok(_) => {
+                        let mut buffer = [0_u8; 14];
+                        let _ = self.read_status(&mut buffer, self.timeout)?;
+                        // Truncate the first two command bytes and terminator
+                        let firmware_id = &buffer[2..13];
+                        let value = std::str::from_utf8(firmware_id).unwrap();
+                        Ok(value.to_string())
+                    }
+                    e => {
+                        println!("Error encountered getting firmware_id: {:?}", e);
+                        Err(Error::Timeout)
+                    }
+                }
+            }
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    /// GS 0xE5 - Times the printer was powered on
+    pub fn get_power_count(&mut self) -> Result<u32, Error> {
+        let mut buffer = [0_u8; 8];
+        let n = self.query(&[0x1d, 0xe5], &mut buffer)?;
+        parse_counter(&buffer[..n])
+    }
+
+    /// Compares the printer's power-on counter with the one seen by the
+    /// previous call and returns true when the printer has been power cycled
+    /// in between. The session state is re-sent before the next command when
+    /// that happens.
+    ///
+    /// The first call only records the counter and returns false.
+    pub fn check_power_cycle(&mut self) -> Result<bool, Error> {
+        let count = self.get_power_count()?;
+        let cycled = matches!(self.session.power_count, Some(last) if last != count);
+        self.session.power_count = Some(count);
+        if cycled {
+            self.session.needs_resync |= !self.session.is_empty();
+            self.text_state = TextState::default();
+        }
+        Ok(cycled)
+    }
+
+    /// Records the online state from a status query, forgetting the text
+    /// attributes when the printer may have been power cycled
+    fn track_online(&mut self, online: bool) {
+        if self.session.track_online(online) {
+            self.text_state = TextState::default();
+        }
+    }
+
+    /// GS 0xE3 - Paper printed over the printer's life, counted in meters
+    pub fn get_printed_length(&mut self) -> Result<PaperLength, Error> {
+        let mut buffer = [0_u8; 8];
+        let n = self.query(&[0x1d, 0xe3], &mut buffer)?;
+        Ok(PaperLength {
+            value: parse_counter(&buffer[..n])?,
+            unit: LengthUnit::Meters,
+        })
+    }
+
+    /// GS 0xE1 - Paper left on the roll, counted in centimeters like the
+    /// limit of [Printer::set_paper_end_limit]
+    pub fn get_remaining_paper(&mut self) -> Result<PaperLength, Error> {
+        let mut buffer = [0_u8; 8];
+        let n = self.query(&[0x1d, 0xe1], &mut buffer)?;
+        Ok(PaperLength {
+            value: parse_counter(&buffer[..n])?,
+            unit: LengthUnit::Centimeters,
+        })
+    }
+
+    /// starting with a value in centimeters, calculate nH and nL as follows:
+    /// nH = <cm> / 256
+    /// nL = <cm> - (nH * 256)
+    ///
+    /// So if we wanted to calculated based on 15 meters:
+    /// 15m = 1500cm
+    /// nH = 1500 / 256 = 5
+    /// nL = 1500 - (nH * 256) = 1500 - (5 * 256) = 220
+    ///
+    /// Then convert to hex:
+    /// 5 = 0x05
+    /// 220 = 0xdc
+    pub fn set_paper_end_limit(&mut self) -> Result<(), Error> {
+        // TODO: what should we pass in, length in meters and then calculate?
+        let n_l: u8 = 0x00;
+        let n_h: u8 = 0x00;
+        self.write(&[0x1d, 0xe6, n_h, n_l]).unwrap();
+        Ok(())
+    }
+
+    pub fn paper_loaded(&mut self) -> Result<bool, Error> {
+        self.write(&[0x1d, 0x72, 0x01]).unwrap();
+        let mut buffer = [0_u8; 1];
+        let _ = self.read_status(&mut buffer, self.timeout)?;
+        Ok(buffer[0] == 0x00_u8)
+    }
+
+    // TODO: Flesh this out more
+    // So `0x10, 0x04, n` can get a few different status results:
+    // | n    | Type |
+    // |------|------|
+    // | 0x01 | device status |
+    // | 0x02 | off-line status |
+    // | 0x03 | error status |
+    // | 0x04 | paper roll sensor status |
+    // | 0x11 | print status |
+    // | 0x14 | full status |
+    // | 0x15 | device id |
+    //
+    // We should probably evaluate what we want to get and implement it here
+    // Below is an example using off-line status to get state of paper door
+    pub fn get_status(&mut self) -> Result<(), Vec<StatusError>> {
+        let mut errors: Vec<StatusError> = Vec::new();
+        let mut buffer = [0_u8; 16];
+
+        match self.printer {
+            SupportedPrinters::SNBC => {
+                if self.printer == SupportedPrinters::SNBC {
+                    match self.read(&mut buffer) {
+                        Ok(_) => (),
+                        Err(_) => {
+                            errors.push(StatusError::Communication);
+                            return Err(errors);
+                        }
+                    }
+
+                    // First Byte
+                    if ((buffer[0] >> OFFLINE_BIT) & 1) == 1 {
+                        errors.push(StatusError::Offline);
+                    } else {
+                        errors.push(StatusError::Online);
+                    }
+                    if ((buffer[0] >> DOOR_STATUS_BIT) & 1) == 1 {
+                        errors.push(StatusError::DoorOpen);
+                    }
+                    if ((buffer[0] >> PAPER_FEED_BIT) & 1) == 1 {
+                        errors.push(StatusError::PaperFeed);
+                    }
+
+                    // Second Byte
+                    if ((buffer[1] >> AUTO_CUTTER_BIT) & 1) == 1 {
+                        errors.push(StatusError::AutoCutter);
+                    }
+                    if ((buffer[1] >> RECOVERABLE_BIT) & 1) == 1 {
+                        errors.push(StatusError::Recoverable);
+                    }
+                    if ((buffer[1] >> AUTOMATIC_RECOVERABLE_BIT) & 1) == 1 {
+                        errors.push(StatusError::AutomaticallyRecoverable);
+                    }
+
+                    // Third Byte
+                    if ((buffer[2] >> PAPER_NEAR_END_BIT) & 0b11) == 0b11 {
+                        errors.push(StatusError::PaperNearEnd);
+                    }
+                    if ((buffer[2] >> PAPER_BIT) & 0b11) == 0b11 {
+                        errors.push(StatusError::PaperEnd);
+                    }
+                    // Fourth byte seems to be unused
+                }
+            }
+            SupportedPrinters::Epic => {
+                let mut data_in = [0_u8; 16];
+                let mut i: i32 = 0;
+                while i < 4 {
+                    let cmd = [0x1B_u8, 0x40, 0x10, 0x04, (i + 1) as u8];
+                    match self.send(&cmd) {
+                        Ok(_) => (),
+                        Err(_) => errors.push(StatusError::Communication),
+                    }
+                    match self.read_status(&mut data_in[(i as usize)..], self.timeout) {
+                        Ok(transferred) => {
+                            if transferred != 1 {
+                                errors.push(StatusError::Communication);
+                            }
+                        }
+                        Err(_) => errors.push(StatusError::Communication),
+                    }
+                    i += 1;
+                }
+                if data_in[EPIC_STATUS_BYTE_0] >> EPIC_STATUS_OFFLINE_BIT == 1 {
+                    errors.push(StatusError::Offline)
+                };
+                if data_in[EPIC_STATUS_BYTE_1] >> EPIC_STATUS_COVER_OPEN_BIT == 1 {
+                    errors.push(StatusError::DoorOpen)
+                };
+                if data_in[EPIC_STATUS_BYTE_1] >> EPIC_STATUS_PAPER_END_BIT == 1 {
+                    errors.push(StatusError::PaperEnd)
+                };
+                if data_in[EPIC_STATUS_BYTE_2] >> EPIC_STATUS_AUTO_CUTTER_BIT == 1 {
+                    errors.push(StatusError::AutoCutter)
+                };
+            }
+            SupportedPrinters::Bixolon | SupportedPrinters::Citizen => {
+                // These don't send ASB packets unasked, the real-time status is
+                // queried instead
+                let status = match self.real_time_status(0x01) {
+                    Ok(status) => status,
+                    Err(_) => {
+                        errors.push(StatusError::Communication);
+                        return Err(errors);
+                    }
+                };
+                if ((status >> RT_OFFLINE_BIT) & 1) == 1 {
+                    errors.push(StatusError::Offline);
+                } else {
+                    errors.push(StatusError::Online);
+                }
+                if let Ok(status) = self.real_time_status(0x02) {
+                    if ((status >> RT_COVER_OPEN_BIT) & 1) == 1 {
+                        errors.push(StatusError::DoorOpen);
+                    }
+                    if ((status >> RT_PAPER_FEED_BIT) & 1) == 1 {
+                        errors.push(StatusError::PaperFeed);
+                    }
+                }
+                if let Ok(status) = self.real_time_status(0x03) {
+                    if ((status >> RT_AUTO_CUTTER_BIT) & 1) == 1 {
+                        errors.push(StatusError::AutoCutter);
+                    }
+                }
+                if let Ok(status) = self.real_time_status(0x04) {
+                    if status & RT_PAPER_END_BITS == RT_PAPER_END_BITS {
+                        errors.push(StatusError::PaperEnd);
+                    } else if status & RT_PAPER_NEAR_END_BITS == RT_PAPER_NEAR_END_BITS {
+                        errors.push(StatusError::PaperNearEnd);
+                    }
+                }
+            }
+            SupportedPrinters::P3 => (),
+            SupportedPrinters::Star | SupportedPrinters::StarEscPos => (),
+            SupportedPrinters::Generic => (),
+            SupportedPrinters::Unknown => (),
+        }
+
+        if !errors.contains(&StatusError::Communication) {
+            self.track_online(!errors.contains(&StatusError::Offline));
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(())
+    }
+
+    /// Prints a [Document] with the commands of this printer's dialect, see
+    /// [Printer::submit]
+    pub fn print_document(&mut self, doc: &Document) -> Result<usize, Error> {
+        self.submit(&Job::from(doc))
+    }
+
+    /// Starts a [JobBuilder], which builds a job in one expression and
+    /// submits it at once
+    ///
+    /// ```no_run
+    /// # use posify::printer::{Printer, SupportedPrinters};
+    /// use posify::document::Alignment;
+    ///
+    /// let mut printer = Printer::new(None, None, SupportedPrinters::SNBC, 0x0483, 0x5743)?;
+    /// printer
+    ///     .builder()
+    ///     .align(Alignment::Center)?
+    ///     .line("Thank you")?
+    ///     .feed(3)?
+    ///     .partial_cut()?
+    ///     .finish()?;
+    /// # Ok::<(), posify::printer::Error>(())
+    /// ```
+    pub fn builder(&mut self) -> JobBuilder<'_> {
+        JobBuilder::new(self)
+    }
+
+    /// Encoder for this printer's dialect, quirks and text encoding
+    pub(crate) fn encoder(&self) -> Encoder {
+        Encoder {
+            printer: self.printer,
+            quirks: self.quirks,
+            codec: self.codec,
+            trap: self.trap,
+            glyphs: self.glyphs.clone(),
+            cut_feed: self.profile.cut_feed,
+            dpi: self.profile.dpi.unwrap_or(203),
+        }
+    }
+
+    /// Prints header before every job sent with [Printer::submit] and
+    /// [Printer::print_document], for the logo and store details
+    ///
+    /// When the profile has [Capabilities::macros](crate::profile::Capabilities::macros)
+    /// and the encoded header fits, it's stored in the printer's macro once
+    /// and each job only sends the 5 bytes running it. This replaces a macro
+    /// set with [Printer::define_macro].
+    pub fn set_header(&mut self, header: Job) -> Result<usize, Error> {
+        let mut n = 0;
+        self.header_in_macro = false;
+        if self.profile.capabilities.macros {
+            let bytes = self.encoder().encode(&header)?;
+            if bytes.len() <= MACRO_MAX_LEN {
+                n = self.define_macro(|p| p.write(&bytes).map(|_| ()))?;
+                self.header_in_macro = true;
+            }
+        }
+        self.header = Some(header);
+        Ok(n)
+    }
+
+    pub fn clear_header(&mut self) {
+        self.header = None;
+        self.header_in_macro = false;
+    }
+
+    /// Prints footer at the end of every job sent with [Printer::submit]
+    /// and [Printer::print_document], before the job's final feeds and cut
+    pub fn set_footer(&mut self, footer: Job) {
+        self.footer = Some(footer);
+    }
+
+    pub fn clear_footer(&mut self) {
+        self.footer = None;
+    }
+
+    /// Runs middleware on every command of the jobs sent with
+    /// [Printer::submit] and [Printer::print_document], headers and footers
+    /// included, after the middleware added before it
+    ///
+    /// It gets the bytes the command encodes to and what it leaves in them
+    /// is sent instead: clearing them drops the command, adding to them
+    /// injects vendor commands. Cuts left as they are still go out the
+    /// printer's way, with the profile's cut feed. Images are split into
+    /// bands first, the middleware sees each band as a [Command::Raster] of
+    /// its own.
+    ///
+    /// Only jobs go through it, the commands sent with the printer's own
+    /// methods ([Printer::print], [Printer::cut]...) don't.
+    ///
+    /// ```no_run
+    /// # use posify::job::Command;
+    /// # use posify::printer::Printer;
+    /// # fn setup(p: &mut Printer) {
+    /// // Store policy, the paper mustn't fall off the printer
+    /// p.add_job_middleware(|command: &Command, bytes: &mut Vec<u8>| {
+    ///     if let Command::Cut { partial: false } = command {
+    ///         *bytes = vec![0x1d, 0x56, 0x42, 0x00];
+    ///     }
+    /// });
+    /// # }
+    /// ```
+    pub fn add_job_middleware<M: JobMiddleware + 'static>(&mut self, middleware: M) {
+        self.middleware.push(Box::new(middleware));
+    }
+
+    pub fn clear_job_middleware(&mut self) {
+        self.middleware.clear();
     }
 
-    // --------------------------------------------------
+    /// Runs the middleware on the encoded command, returning whether it
+    /// left the bytes as they were
+    fn run_middleware(&mut self, command: &Command, bytes: &mut Vec<u8>) -> bool {
+        let original = bytes.clone();
+        let mut middleware = std::mem::take(&mut self.middleware);
+        for m in &mut middleware {
+            m.process(command, bytes);
+        }
+        self.middleware = middleware;
+        *bytes == original
+    }
 
-    fn encode(&mut self, content: &str) -> io::Result<Vec<u8>> {
-        self.codec
-            .encode(content, self.trap)
-            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+    /// Encodes a [Job] for this printer's dialect and sends it, between the
+    /// [Printer::set_header] header and [Printer::set_footer] footer
+    ///
+    /// Cuts go through [Printer::cut] so profile overrides and the Epic cut
+    /// delay still apply. With [FlowControl::StatusPaced] rasters are sent
+    /// in bands of at most one chunk.
+    pub fn submit(&mut self, job: &Job) -> Result<usize, Error> {
+        self.submit_job(job, None)
     }
 
-    pub fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
-        let n_bytes = self.handle.write_bulk(self.cmd_ep, buf, self.timeout)?;
-        if n_bytes != buf.len() {
-            return Err(Error::Timeout);
+    /// [Printer::submit] that stops when token is canceled, so a big image
+    /// on a slow link or a stuck printer doesn't hold up checkout
+    ///
+    /// The token is checked every kilobyte transferred and while waiting
+    /// for the printer with [FlowControl::StatusPaced]. Once canceled
+    /// nothing more of the job is sent, the printer's buffers are cleared
+    /// with DLE DC4 8 where the dialect has it, see
+    /// [Printer::clear_buffers], and [Error::Canceled] is returned. A token
+    /// canceled before the call sends nothing.
+    ///
+    /// Inside a [Printer::batch] or [Printer::capture] nothing is transferred
+    /// until the call returns, so there's nothing to cancel and
+    /// [Error::Unsupported] is returned.
+    ///
+    /// ```no_run
+    /// # use posify::job::Job;
+    /// # use posify::printer::{CancellationToken, Error, Printer};
+    /// # fn receipt(p: &mut Printer, job: &Job) -> Result<(), Error> {
+    /// let token = CancellationToken::new();
+    /// let cancel = token.clone();
+    /// // Give up on the print after ten seconds
+    /// std::thread::spawn(move || {
+    ///     std::thread::sleep(std::time::Duration::from_secs(10));
+    ///     cancel.cancel();
+    /// });
+    /// match p.submit_cancellable(job, &token) {
+    ///     Err(Error::Canceled) => println!("Print canceled"),
+    ///     res => {
+    ///         res?;
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn submit_cancellable(
+        &mut self,
+        job: &Job,
+        token: &CancellationToken,
+    ) -> Result<usize, Error> {
+        if token.is_canceled() {
+            return Err(Error::Canceled);
         }
+        if self.in_batch || self.capturing {
+            return Err(Error::Unsupported);
+        }
+        let previous = self.cancel.replace(token.clone());
+        let res = self.submit_job(job, None);
+        self.cancel = previous;
+        res
+    }
 
-        Ok(n_bytes)
+    /// [Printer::submit] calling progress as the job is transferred, e.g. for
+    /// a kiosk showing how far a photo print got
+    ///
+    /// Progress is reported after every raster band and every few kilobytes
+    /// otherwise, the bytes so far are transferred first instead of the job
+    /// going out in one transfer. Inside a [Printer::batch] they are counted
+    /// as sent once batched. The last call has sent equal to total.
+    pub fn submit_with_progress<F: FnMut(&Progress)>(
+        &mut self,
+        job: &Job,
+        mut progress: F,
+    ) -> Result<usize, Error> {
+        self.submit_job(job, Some(&mut progress))
     }
-    // Old file based write
-    // fn write2(&mut self, buf: &[u8]) -> io::Result<usize> {
-    //     self.file.write(buf)
-    // }
 
-    pub fn chain_write_u8(&mut self, n: u8) -> Result<&mut Self, Error> {
-        self.write_u8(n).map(|_| self)
+    fn submit_job(
+        &mut self,
+        job: &Job,
+        progress: Option<&mut dyn FnMut(&Progress)>,
+    ) -> Result<usize, Error> {
+        #[cfg(feature = "metrics")]
+        let (start, bytes_before) = (Instant::now(), self.bytes_sent);
+        let res = self.send_job(job, progress);
+        if res.is_ok() {
+            self.printed(job);
+        }
+        #[cfg(feature = "metrics")]
+        self.emit(Event::Job {
+            commands: job.commands.len(),
+            bytes: self.bytes_sent - bytes_before,
+            duration: start.elapsed(),
+            ok: res.is_ok(),
+        });
+        res
     }
-    pub fn write_u8(&mut self, n: u8) -> Result<usize, Error> {
-        self.write(vec![n].as_slice())
+
+    /// Counts the paper job used with the header and footer, and archives it
+    fn printed(&mut self, job: &Job) {
+        self.paper_used_mm += [self.header.as_ref(), Some(job), self.footer.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|job| job.estimate_length_mm(&self.profile))
+            .sum::<f32>();
+        self.archive(job);
     }
 
-    fn write_u16le(&mut self, n: u16) -> Result<usize, Error> {
-        let mut wtr = vec![];
-        wtr.write_u16::<LittleEndian>(n)?;
-        self.write(wtr.as_slice())
+    /// Sends job in its encoding, selecting the code page for it before and
+    /// the printer's again after, also when sending fails
+    fn send_job(
+        &mut self,
+        job: &Job,
+        mut progress: Option<&mut dyn FnMut(&Progress)>,
+    ) -> Result<usize, Error> {
+        self.command(
+            "submit",
+            || format!("{} commands", job.commands.len()),
+            |p| {
+                let mut encoder = p.encoder();
+                // The header and footer are in the printer's encoding
+                let mut frame = p.encoder();
+                // The job's code page, and the one to go back to after
+                let mut pages = None;
+                if let Some(codec) = job.codec()? {
+                    if codec.name() != p.codec.name() {
+                        match p.profile.code_page(codec.name()) {
+                            Some(page) => pages = Some((page, p.session.code_page.unwrap_or(0))),
+                            None => log::debug!("No code page for {}", codec.name()),
+                        }
+                    }
+                    encoder.codec = codec;
+                }
+                if let Some(trap) = job.trap {
+                    encoder.trap = trap.into();
+                }
+                if job.cut_feed.is_some() {
+                    encoder.cut_feed = job.cut_feed;
+                    frame.cut_feed = job.cut_feed;
+                }
+                let res = p.send_framed(job, &encoder, &frame, pages, progress.as_deref_mut());
+                let restored = match pages {
+                    Some((page, restore)) if p.session.code_page == Some(page) => {
+                        // Known before it's sent, so a canceled transfer
+                        // still selects it, see Printer::abort_transfer
+                        p.session.code_page = Some(restore);
+                        p.write(&code_page_cmd(p.printer, restore))
+                    }
+                    _ => Ok(0),
+                };
+                // Jobs set attributes without going through the tracked
+                // commands
+                p.text_state = TextState::default();
+                let (sent, section) = res?;
+                let n = sent + restored?;
+                if let Some(progress) = progress.as_mut() {
+                    p.report_progress(progress, n, n, section)?;
+                }
+                Ok(n)
+            },
+        )
     }
 
-    // Useful when using a file handler, probably not needed now
-    pub fn flush(&mut self) -> Result<(), Error> {
-        // self.file.flush()
+    /// Sends the commands of job with encoder between the header and footer
+    /// encoded with frame, returning the bytes sent and the section it ended
+    /// in
+    ///
+    /// pages are the job's code page, selected for its commands, and the one
+    /// selected again for the footer.
+    fn send_framed(
+        &mut self,
+        job: &Job,
+        encoder: &Encoder,
+        frame: &Encoder,
+        pages: Option<(u8, u8)>,
+        mut progress: Option<&mut (dyn FnMut(&Progress) + '_)>,
+    ) -> Result<(usize, Section), Error> {
+        let mut n = 0;
+        let mut header = self.header.clone();
+        let header_in_macro = header.is_some() && self.header_in_macro;
+        if header_in_macro {
+            n += self.run_macro(1, 0, false)?;
+            header = None;
+        }
+        let footer = self.footer.clone();
+        let mut bytes = Vec::new();
+        let mut total = n;
+        if progress.is_some() {
+            for (from, command) in framed(header.as_ref(), job, footer.as_ref()) {
+                let encoder = if from == Section::Body {
+                    encoder
+                } else {
+                    frame
+                };
+                bytes.clear();
+                encoder.encode_command(command, &mut bytes)?;
+                total += bytes.len();
+            }
+        }
+        let mut reported = 0;
+        // Whether the job's code page is selected
+        let mut in_page = false;
+        let mut commands = framed(header.as_ref(), job, footer.as_ref()).peekable();
+        // The macro running the header is part of it
+        let mut section = match commands.peek() {
+            _ if header_in_macro => Section::Header,
+            Some((section, _)) => *section,
+            None => Section::Body,
+        };
+        for (from, command) in commands {
+            if let Some(progress) = progress.as_mut() {
+                if from != section || n - reported >= PROGRESS_STEP {
+                    self.report_progress(progress, n, total.max(n), section)?;
+                    reported = n;
+                }
+            }
+            if let Some((page, restore)) = pages {
+                if (from == Section::Body) != in_page {
+                    in_page = !in_page;
+                    n += self.code_page(if in_page { page } else { restore })?;
+                }
+            }
+            section = from;
+            let encoder = if from == Section::Body {
+                encoder
+            } else {
+                frame
+            };
+            if let Command::QrCode { .. } = command {
+                if !self.profile.capabilities.qr_code {
+                    return Err(Error::Unsupported);
+                }
+            }
+            // Middleware sees the bands of a raster, below
+            let banded = matches!(
+                command,
+                Command::Raster { width_bytes, height, .. } if *width_bytes > 0 && *height > 0
+            );
+            if !self.middleware.is_empty() && !banded {
+                bytes.clear();
+                let cut = matches!(command, Command::Cut { .. });
+                // A cut the encoder can't make is left to the
+                // printer, which may feed instead
+                let encoded = match encoder.encode_command(command, &mut bytes) {
+                    Err(_) if cut => false,
+                    res => res.map(|_| true)?,
+                };
+                if encoded {
+                    let unchanged = self.run_middleware(command, &mut bytes);
+                    if !(unchanged && cut) {
+                        n += self.write(&bytes)?;
+                        self.pace(bytes.len())?;
+                        continue;
+                    }
+                }
+            }
+            match command {
+                Command::Cut { partial } => {
+                    let mode = if *partial {
+                        CutMode::Partial
+                    } else {
+                        CutMode::Full
+                    };
+                    n += match encoder.cut_feed {
+                        Some(feed) => self.cut_fed(mode, feed)?,
+                        None => self.cut(mode, None)?,
+                    };
+                }
+                Command::Raster {
+                    width_bytes,
+                    height,
+                    data,
+                } if *width_bytes > 0 && *height > 0 => {
+                    let width = *width_bytes as usize;
+                    let band_rows = match self.flow_control {
+                        FlowControl::StatusPaced { chunk_size, .. } => (chunk_size / width).max(1),
+                        FlowControl::None => *height as usize,
+                    };
+                    for band in data.chunks(band_rows * width) {
+                        bytes.clear();
+                        let band = Command::Raster {
+                            width_bytes: *width_bytes,
+                            height: (band.len() / width) as u16,
+                            data: band.to_vec(),
+                        };
+                        encoder.encode_command(&band, &mut bytes)?;
+                        if !self.middleware.is_empty() {
+                            self.run_middleware(&band, &mut bytes);
+                        }
+                        n += self.write(&bytes)?;
+                        self.pace(bytes.len())?;
+                        if let Some(progress) = progress.as_mut() {
+                            self.report_progress(progress, n, total.max(n), section)?;
+                            reported = n;
+                        }
+                    }
+                }
+                command => {
+                    bytes.clear();
+                    encoder.encode_command(command, &mut bytes)?;
+                    n += self.write(&bytes)?;
+                    self.pace(bytes.len())?;
+                }
+            }
+        }
+        Ok((n, section))
+    }
+
+    /// Transfers what was sent so far, unless a [Printer::batch] holds it,
+    /// and calls progress
+    fn report_progress(
+        &mut self,
+        progress: &mut dyn FnMut(&Progress),
+        sent: usize,
+        total: usize,
+        section: Section,
+    ) -> Result<(), Error> {
+        if self.batch_owned {
+            self.flush_batch()?;
+        }
+        progress(&Progress {
+            sent,
+            total,
+            section,
+        });
         Ok(())
     }
 
-    /// ESC @ - Initialize printer, clear data in print buffer and set print mode
-    /// to the default mode when powered on.
+    pub fn chain_reprint(&mut self, job: &Job, mark: CopyMark) -> Result<&mut Self, Error> {
+        self.reprint(job, mark).map(|_| self)
+    }
+
+    /// Prints a stored job again marked as a copy, e.g. a duplicate fiscal
+    /// receipt loaded with [Job::load] or from an [ArchivedJob]
     ///
-    /// Seems to be the same for SNBC and P3 printers
+    /// The mark goes at the top, after the job's initialization, and again
+    /// before its final feeds and cut, so each end of the paper shows it.
+    pub fn reprint(&mut self, job: &Job, mark: CopyMark) -> Result<usize, Error> {
+        let columns = self.profile.chars_per_line(Font::FontA, 1);
+        let dots = self.profile.dots_per_line.unwrap_or(576) as u32;
+        let mark = copy_mark(mark, columns, dots);
+        let top = job
+            .commands
+            .iter()
+            .position(|c| *c != Command::Init)
+            .unwrap_or(job.commands.len());
+        let bottom = footer_position(&job.commands).max(top);
+        let mut copy = job.clone();
+        copy.commands.splice(bottom..bottom, mark.iter().cloned());
+        copy.commands.splice(top..top, mark);
+        self.submit(&copy)
+    }
+
+    /// Hands a copy of every job printed by [Printer::submit] to archive,
+    /// rendered as options asks for, replacing any archive set before
     ///
-    /// ASCII    ESC   @
-    /// Hex      1b   40
-    /// Decimal  27   64
-    /// Notes:
-    ///   - The data in the receive buffer is not cleared
-    ///   - The macro definition is not cleared
-    ///   - The NV bitmap data is not cleared (SNBC, not sure about P3)
-    pub fn hwinit(&mut self) -> Result<usize, Error> {
-        self.write(&[0x1b, 0x40])
+    /// The copies are as wide as [Profile::dots_per_line], or 576 dots.
+    pub fn set_archive<A: Archive + 'static>(&mut self, options: ArchiveOptions, archive: A) {
+        self.archive = Some((options, Box::new(archive)));
     }
-    pub fn chain_hwinit(&mut self) -> Result<&mut Self, Error> {
-        self.hwinit().map(|_| self)
+
+    pub fn clear_archive(&mut self) {
+        self.archive = None;
     }
 
-    /// ESC = n - Enable/Disable Printer
-    /// Docs describe this as "Select printer to which host computer sends data"
-    ///
-    /// SNBC:
+    /// Passes job, with the header and footer it was printed with, to the
+    /// archive
+    fn archive(&mut self, job: &Job) {
+        let Some((options, archive)) = &mut self.archive else {
+            return;
+        };
+        let printed = Job {
+            commands: framed(self.header.as_ref(), job, self.footer.as_ref())
+                .map(|(_, command)| command.clone())
+                .collect(),
+            ..job.clone()
+        };
+        archive.archive(&ArchivedJob::new(printed, *options, &self.profile));
+    }
+
+    /// Encodes job the way [Printer::submit] sends it, leaving the
+    /// `{{name}}` placeholders of its text to [Printer::print_template]
     ///
-    /// ASCII    ESC   =  n
-    /// Hex      1b   3d  n
-    /// Decimal  27   61  n
-    /// Range: 0 <= n <= 1
+    /// Nothing is sent, archived, recorded in the history or metrics or
+    /// counted as paper used. The values are encoded with the job's or the
+    /// printer's encoding, without [Printer::set_glyphs] pictures.
+    pub fn compile(&mut self, job: &Job) -> Result<Template, Error> {
+        let (text_state, session) = (self.text_state, self.session.clone());
+        let bytes_sent = self.bytes_sent;
+        let history = self.history.take();
+        #[cfg(feature = "metrics")]
+        let metrics = self.metrics.take();
+        // What resyncing would send belongs to the next command, not the job
+        self.session.needs_resync = false;
+        let bytes = self.capture(|p| p.send_job(job, None));
+        self.text_state = text_state;
+        self.session = session;
+        self.bytes_sent = bytes_sent;
+        self.history = history;
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics = metrics;
+        }
+        let codec = job.codec()?.unwrap_or(self.codec);
+        let trap = job.trap.map_or(self.trap, EncoderTrap::from);
+        Template::split(&bytes?, job, codec, trap)
+    }
+
+    /// Prints a job compiled with [Printer::compile], values giving the
+    /// text of each placeholder
     ///
-    /// Meaning of n is as follows:
+    /// Like [Printer::submit] the job is archived and counted as paper used,
+    /// with the values filled in.
+    pub fn print_template(
+        &mut self,
+        template: &Template,
+        values: &[(&str, &str)],
+    ) -> Result<usize, Error> {
+        let bytes = template.render(values)?;
+        #[cfg(feature = "metrics")]
+        let (start, bytes_before) = (Instant::now(), self.bytes_sent);
+        let res = self.command(
+            "print_template",
+            || format!("{:?}", values),
+            |p| {
+                p.text_state = TextState::default();
+                p.write(&bytes)
+            },
+        );
+        let job = template.job(values);
+        if res.is_ok() {
+            self.printed(&job);
+        }
+        #[cfg(feature = "metrics")]
+        self.emit(Event::Job {
+            commands: job.commands.len(),
+            bytes: self.bytes_sent - bytes_before,
+            duration: start.elapsed(),
+            ok: res.is_ok(),
+        });
+        res
+    }
+
+    /// Customer display connected through the printer, see [Display]
+    pub fn display(&mut self) -> Result<Display<'_>, Error> {
+        Display::new(self)
+    }
+
+    /// Ruled line printing of SNBC printers, see [RuledLines]
+    pub fn ruled_lines(&mut self) -> Result<RuledLines<'_>, Error> {
+        RuledLines::new(self)
+    }
+
+    /// Overrides the drawer polarity of the printer profile, see
+    /// [SupportedPrinters::drawer_polarity]
+    pub fn set_drawer_polarity(&mut self, polarity: DrawerPolarity) {
+        self.drawer_polarity = polarity;
+    }
+
+    /// Overrides the quirks of the printer profile, see
+    /// [SupportedPrinters::quirks]
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Switches to a profile, for example one loaded with [Profile::load],
+    /// replacing the dialect and quirks the printer was created with
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.printer = profile.base;
+        self.quirks = profile.quirks();
+        self.profile = profile;
+        self.throttled_bytes = 0;
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// Printable width in dots, when the profile knows it
+    pub fn dots_per_line(&self) -> Option<u16> {
+        self.profile.dots_per_line
+    }
+
+    /// GS I 67 - Transmit printer model name
     ///
-    /// | Bit | 1/0 | Hex | Decimal | Function         |
-    /// |-----|-----|-----|---------|------------------|
-    /// |  0  |  0  |  00 |    0    | Printer disabled |
-    /// |  0  |  1  |  01 |    1    | Printer enabled  |
-    /// | 1-7 |     |     |         | Undefined        |
+    /// ASCII    GS  I  n
+    /// Hex      1d  49 43
+    /// Decimal  29  73 67
     ///
-    /// Notes:
-    /// When the printer is disabled, it ignores all commands except for
-    /// real-time commands (DLE EOT, DLE ENQ, DLE DC4) until it is enabled by
-    /// this command.
+    /// The answer is a `_` header, the name and NUL.
+    pub fn model_name(&mut self) -> Result<String, Error> {
+        let mut buffer = [0_u8; 32];
+        let n = self.query(MODEL_NAME, &mut buffer)?;
+        parse_model_name(&buffer[..n]).ok_or(Error::InvalidResponse)
+    }
+
+    /// GS ( E fn = 1, 5, 2 - Writes settings to the printer's NV memory
     ///
-    /// Default: n = 1
+    /// ASCII    GS   (   E  pL  pH  fn  [a  n1  n2]...
+    /// Hex      1d  28  45  pL  pH  05  [a  n1  n2]...
+    /// Decimal  29  40  69  pL  pH   5  [a  n1  n2]...
     ///
-    /// P3:
-    /// Select the device to which the host computer sends data, using n as follows:
+    /// The printer is put into user setting mode, given the settings and
+    /// restarts when the mode ends, which also resets what this session set,
+    /// see [Printer::resync]. NV memory wears out, so this is for setting up
+    /// a printer once, not for every job. Star Line Mode and the P3 have no
+    /// user setting mode.
+    pub fn write_config(&mut self, settings: &[ConfigSetting]) -> Result<usize, Error> {
+        if matches!(
+            self.printer,
+            SupportedPrinters::Star | SupportedPrinters::P3 | SupportedPrinters::Unknown
+        ) {
+            return Err(Error::Unsupported);
+        }
+        let cmd = config_cmd(settings)?;
+        self.user_setting_mode()?;
+        let mut n = self.write(&cmd)?;
+        n += self.end_user_setting_mode()?;
+        Ok(n)
+    }
+
+    /// GS ( E fn = 6 - Reads the paper width and print density back from
+    /// NV memory, to check [Printer::write_config]
     ///
-    /// |      n       | Function        |
-    /// |--------------|-----------------|
-    /// |  0x01, 0x03  | Device enabled  |
-    /// |     0x02     | Device disabled |
+    /// ASCII    GS   (   E  pL  pH  fn  a
+    /// Hex      1d  28  45  02  00  06  a
+    /// Decimal  29  40  69   2   0   6  a
     ///
-    /// Default: n = 0x01
+    /// The customized value numbers in custom are read back as well, as
+    /// [ConfigSetting::Custom]. A paper width other than 58 or 80 mm is
+    /// [Error::InvalidResponse]. Like [Printer::write_config] this restarts
+    /// the printer.
+    pub fn read_config(&mut self, custom: &[u8]) -> Result<Vec<ConfigSetting>, Error> {
+        if matches!(
+            self.printer,
+            SupportedPrinters::Star | SupportedPrinters::P3 | SupportedPrinters::Unknown
+        ) {
+            return Err(Error::Unsupported);
+        }
+        self.user_setting_mode()?;
+        let mut buffer = [0_u8; 16];
+        let mut read = |p: &mut Printer, number| {
+            let n = p.query(&[0x1d, 0x28, 0x45, 0x02, 0x00, 0x06, number], &mut buffer)?;
+            parse_config_value(&buffer[..n]).ok_or(Error::InvalidResponse)
+        };
+        let mut config = || {
+            let width = paper_width_mm(read(self, 3)?).ok_or(Error::InvalidResponse)?;
+            let mut config = vec![
+                ConfigSetting::PaperWidth(width),
+                ConfigSetting::Density(read(self, 5)? as i16 as i8),
+            ];
+            for &number in custom {
+                let value = read(self, number)?;
+                config.push(ConfigSetting::Custom { number, value });
+            }
+            Ok(config)
+        };
+        let config = config();
+        // Leave user setting mode even when the printer didn't answer
+        self.end_user_setting_mode()?;
+        config
+    }
 
-    pub fn enable(&mut self) -> Result<usize, Error> {
-        match self.printer {
-            SupportedPrinters::SNBC => self.write(&[0x1b, 0x3d, 0x01]),
-            SupportedPrinters::P3 => self.write(&[0x1b, 0x3d, 0x01]),
-            _ => Err(Error::Unsupported),
+    /// GS ( E fn = 1 - Change into user setting mode, answered with `7 20 00`
+    fn user_setting_mode(&mut self) -> Result<(), Error> {
+        let mut buffer = [0_u8; 3];
+        let n = self.query(
+            &[0x1d, 0x28, 0x45, 0x03, 0x00, 0x01, 0x49, 0x4e],
+            &mut buffer,
+        )?;
+        match buffer[..n].starts_with(b"7") {
+            true => Ok(()),
+            false => Err(Error::InvalidResponse),
         }
     }
-    pub fn chain_enable(&mut self) -> Result<&mut Self, Error> {
-        self.enable().map(|_| self)
+
+    /// GS ( E fn = 2 - End user setting mode, the printer restarts
+    fn end_user_setting_mode(&mut self) -> Result<usize, Error> {
+        let n = self.write(&[0x1d, 0x28, 0x45, 0x04, 0x00, 0x02, 0x4f, 0x55, 0x54])?;
+        // The restart clears the macro as well
+        self.session.needs_resync = !self.session.is_empty();
+        self.text_state = TextState::default();
+        Ok(n)
     }
 
-    pub fn disable(&mut self) -> Result<usize, Error> {
-        match self.printer {
-            SupportedPrinters::SNBC => self.write(&[0x1b, 0x3d, 0x00]),
-            SupportedPrinters::P3 => self.write(&[0x1b, 0x3d, 0x02]),
-            _ => Err(Error::Unsupported),
+    /// Asks the printer for its model and drops the code pages it doesn't
+    /// have from the profile, see [Profile::restrict_code_pages]
+    ///
+    /// Done on connecting unless turned off with
+    /// [Printer::set_auto_code_pages]. Returns the model name. [Printer::select_encoding] then refuses the
+    /// dropped encodings instead of printing garbage.
+    pub fn negotiate_code_pages(&mut self) -> Result<String, Error> {
+        let model = self.model_name()?;
+        if !self.profile.restrict_code_pages(&model) {
+            log::debug!("No code page list for {}, keeping the profile's", model);
         }
+        Ok(model)
     }
-    pub fn chain_disable(&mut self) -> Result<&mut Self, Error> {
-        self.disable().map(|_| self)
+
+    /// Selects the printer code page for encoding from the profile's
+    /// [Profile::code_pages] and encodes text with it from now on
+    ///
+    /// Returns [Error::Unsupported] when the profile doesn't list the encoding.
+    pub fn select_encoding(&mut self, encoding: EncodingRef) -> Result<usize, Error> {
+        let n = self
+            .profile
+            .code_page(encoding.name())
+            .ok_or(Error::Unsupported)?;
+        let n_bytes = self.code_page(n)?;
+        self.codec = encoding;
+        Ok(n_bytes)
     }
 
-    // TODO: There doesn't seem to be a hwreset command for snbc
-    // pub fn hwreset(&mut self) -> io::Result<usize> {
-    //     self.write(consts::HW_RESET)
-    // }
-    // pub fn chain_hwreset(&mut self) -> io::Result<&mut Self> {
-    //     self.hwreset().map(|_| self)
-    // }
+    /// DLE EOT 1 - Transmit printer status, used to check whether the cash
+    /// drawer is open
+    ///
+    /// ASCII    DLE  EOT  n
+    /// Hex      10   04   01
+    /// Decimal  16   4    1
+    ///
+    /// Bit 2 of the response is the level of the drawer kick-out connector pin
+    /// 3, which is mapped to open/closed using the drawer polarity.
+    ///
+    /// Notes:
+    ///   - DLE EOT is a real-time command so it is processed even when the
+    ///     printer is offline or the receive buffer is full
+    pub fn drawer_open(&mut self) -> Result<bool, Error> {
+        if self.printer == SupportedPrinters::Unknown {
+            return Err(Error::Unsupported);
+        }
+        let status = self.real_time_status(0x01)?;
+        let pin_high = ((status >> DRAWER_STATUS_BIT) & 1) == 1;
+        Ok(pin_high == (self.drawer_polarity == DrawerPolarity::OpenHigh))
+    }
 
-    pub fn print(&mut self, content: &str) -> Result<usize, Error> {
-        // let rv = self.encode(content);
-        let rv = self.encode(content)?;
-        self.write(rv.as_slice())
+    /// Collects online, paper, cover and error state plus the firmware version
+    /// and serial number into one [HealthReport]
+    ///
+    /// The state comes from the real-time DLE EOT 1-4 queries, so this also
+    /// works while the printer is offline. Queries that fail are left out of
+    /// the report instead of failing the whole check.
+    pub fn health_check(&mut self) -> Result<HealthReport, Error> {
+        if self.printer == SupportedPrinters::Unknown {
+            return Err(Error::Unsupported);
+        }
+        let mut report = HealthReport::default();
+
+        if let Ok(status) = self.real_time_status(0x01) {
+            let offline = ((status >> RT_OFFLINE_BIT) & 1) == 1;
+            if offline {
+                report.errors.push(StatusError::Offline);
+            }
+            self.track_online(!offline);
+            report.online = Some(!offline);
+        }
+        if let Ok(status) = self.status_offline() {
+            if status.cover_open {
+                report.errors.push(StatusError::DoorOpen);
+            }
+            if status.feed_button_pressed {
+                report.errors.push(StatusError::PaperFeed);
+            }
+            report.cover_open = Some(status.cover_open);
+        }
+        if let Ok(status) = self.real_time_status(0x03) {
+            if ((status >> RT_AUTO_CUTTER_BIT) & 1) == 1 {
+                report.errors.push(StatusError::AutoCutter);
+            }
+            if ((status >> RT_RECOVERABLE_BIT) & 1) == 1 {
+                report.errors.push(StatusError::Recoverable);
+            }
+            if ((status >> RT_AUTOMATIC_RECOVERABLE_BIT) & 1) == 1 {
+                report.errors.push(StatusError::AutomaticallyRecoverable);
+            }
+        }
+        if let Ok(status) = self.real_time_status(0x04) {
+            let paper = PaperState::from_roll_status(status);
+            match paper {
+                PaperState::End => report.errors.push(StatusError::PaperEnd),
+                PaperState::NearEnd => report.errors.push(StatusError::PaperNearEnd),
+                PaperState::Present => (),
+            }
+            report.paper = Some(paper);
+        }
+
+        report.firmware = match self.printer {
+            SupportedPrinters::Epic => self.get_firmware_id().ok(),
+            _ => self.get_rom_version().ok(),
+        };
+        report.serial = self.get_serial().ok();
+        if self.station == Station::Slip {
+            report.slip = self.slip_status().ok();
+        }
+        if let Ok(presenter) = self.presenter_status() {
+            if presenter.ticket_present {
+                report.errors.push(StatusError::TicketPresent);
+            }
+        }
+
+        Ok(report)
     }
-    pub fn chain_print(&mut self, content: &str) -> Result<&mut Self, Error> {
-        self.print(content).map(|_| self)
+
+    /// Starts counting the paper used again, call it when a new roll is
+    /// loaded
+    pub fn new_roll(&mut self) {
+        self.paper_used_mm = 0.0;
+        self.roll_start_mm = None;
     }
 
-    pub fn println(&mut self, content: &str) -> Result<usize, Error> {
-        self.print(format!("{}{}", content, "\n").as_ref())
+    /// Paper used since [Printer::new_roll], estimated from the jobs sent
+    /// with [Printer::submit]
+    pub fn paper_used_mm(&self) -> f32 {
+        self.paper_used_mm
     }
-    pub fn chain_println(&mut self, content: &str) -> Result<&mut Self, Error> {
-        self.println(content).map(|_| self)
+
+    /// Paper left of a roll roll_length_mm long, for a "roll low" warning
+    /// on printers without a near end sensor
+    ///
+    /// Only jobs sent with [Printer::submit] are counted, unless
+    /// [Printer::sync_paper_used] is called now and then.
+    pub fn estimated_remaining_mm(&self, roll_length_mm: u32) -> u32 {
+        (roll_length_mm as f32 - self.paper_used_mm).max(0.0) as u32
+    }
+
+    /// Reads the printer's printed length counter and takes the paper it
+    /// counted since the roll was changed when that's more than the
+    /// estimate, e.g. after printing that didn't go through
+    /// [Printer::submit]
+    ///
+    /// The first call after [Printer::new_roll] only takes the counter as
+    /// the start of the roll. It counts whole meters, so it corrects the
+    /// estimate over a roll rather than a receipt.
+    pub fn sync_paper_used(&mut self) -> Result<f32, Error> {
+        let printed = self.get_printed_length()?.millimeters();
+        match self.roll_start_mm {
+            Some(start) => {
+                let counted = printed.saturating_sub(start) as f32;
+                self.paper_used_mm = self.paper_used_mm.max(counted);
+            }
+            None => {
+                self.roll_start_mm = Some(printed.saturating_sub(self.paper_used_mm as u64));
+            }
+        }
+        Ok(self.paper_used_mm)
+    }
+
+    /// Collects the serial number, ROM version, cut and power-on counts and
+    /// the printed and remaining paper into one [PrinterInventory]
+    ///
+    /// Like [Printer::health_check], queries that fail are left out instead
+    /// of failing the whole report. Only the SNBC has the GS 0xE1 to 0xE5
+    /// counters and Star Line Mode has no GS I, the queries a dialect doesn't
+    /// have aren't sent.
+    pub fn inventory(&mut self) -> Result<PrinterInventory, Error> {
+        if self.printer == SupportedPrinters::Unknown {
+            return Err(Error::Unsupported);
+        }
+        let mut inventory = PrinterInventory {
+            serial: self.get_serial().ok(),
+            ..PrinterInventory::default()
+        };
+        if self.printer != SupportedPrinters::Star {
+            inventory.rom_version = self.get_rom_version().ok();
+        }
+        if self.printer == SupportedPrinters::SNBC {
+            inventory.cut_count = self.get_cut_count().ok();
+            inventory.power_count = self.get_power_count().ok();
+            inventory.printed_length = self.get_printed_length().ok();
+            inventory.remaining_paper = self.get_remaining_paper().ok();
+        }
+        Ok(inventory)
     }
 
-    // TODO: This seems useless? just use print/println?
-    pub fn text(&mut self, content: &str) -> Result<usize, Error> {
-        self.println(content)
+    /// DLE EOT 2 - Transmit off-line status
+    ///
+    /// ASCII    DLE  EOT  n
+    /// Hex      10   04   02
+    /// Decimal  16   4    2
+    ///
+    /// | Bit | Meaning when set                         |
+    /// |-----|------------------------------------------|
+    /// |  2  | Cover is open                            |
+    /// |  3  | Paper is being fed by the feed button    |
+    /// |  5  | Printing stopped due to paper end        |
+    /// |  6  | Error occurred                           |
+    pub fn status_offline(&mut self) -> Result<OfflineStatus, Error> {
+        if self.printer == SupportedPrinters::Unknown {
+            return Err(Error::Unsupported);
+        }
+        Ok(OfflineStatus::from(self.real_time_status(0x02)?))
     }
-    pub fn chain_text(&mut self, content: &str) -> Result<&mut Self, Error> {
-        self.text(content).map(|_| self)
+
+    pub fn chain_real_time_pulse(
+        &mut self,
+        pin: DrawerPin,
+        on_time_ms: u16,
+    ) -> Result<&mut Self, Error> {
+        self.real_time_pulse(pin, on_time_ms).map(|_| self)
     }
 
-    pub fn underline_mode(&mut self, mode: Option<&str>) -> Result<usize, Error> {
-        let mode = mode.unwrap_or("OFF");
-        let mode_upper = mode.to_uppercase();
-        match mode_upper.as_ref() {
-            "OFF" => Ok(self.write(&[0x1b, 0x2d, 0x00])?),
-            "ON" => Ok(self.write(&[0x1b, 0x2d, 0x01])?),
-            "THICK" => Ok(self.write(&[0x1b, 0x2d, 0x02])?),
-            _ => Ok(self.write(&[0x1b, 0x2d, 0x00])?),
+    /// DLE DC4 fn m t - Generate pulse in real-time (fn = 1)
+    ///
+    /// ASCII    DLE  DC4  fn  m  t
+    /// Hex      10   14   01  m  t
+    /// Decimal  16   20    1  m  t
+    /// Range: m = 0 (pin 2), 1 (pin 5), 1 <= t <= 8
+    ///
+    /// The pulse is on for t x 100 ms, so the on time is rounded up to a
+    /// multiple of 100 ms.
+    ///
+    /// Range: 1 <= on_time_ms <= 800
+    ///
+    /// Notes:
+    ///   - Works while the printer is offline or waiting for paper, unlike
+    ///     [Printer::cashdraw]
+    ///   - Ignored while the printer is already generating a pulse
+    pub fn real_time_pulse(&mut self, pin: DrawerPin, on_time_ms: u16) -> Result<usize, Error> {
+        if !(1..=800).contains(&on_time_ms) {
+            return Err(Error::InvalidArgument);
         }
+        let m = match pin {
+            DrawerPin::Pin2 => 0x00,
+            DrawerPin::Pin5 => 0x01,
+        };
+        let t = on_time_ms.div_ceil(100) as u8;
+        self.real_time(
+            "real_time_pulse",
+            || format!("{:?}, {}", pin, on_time_ms),
+            &[0x10, 0x14, 0x01, m, t],
+        )
     }
-    pub fn chain_underline_mode(&mut self, mode: Option<&str>) -> Result<&mut Self, Error> {
-        self.underline_mode(mode).map(|_| self)
+
+    pub fn chain_power_off(&mut self) -> Result<&mut Self, Error> {
+        self.power_off().map(|_| self)
     }
 
-    pub fn chain_hr(&mut self, width: usize) -> Result<&mut Self, Error> {
-        self.hr(width).map(|_| self)
+    /// DLE DC4 fn a b - Execute power-off sequence (fn = 2)
+    ///
+    /// ASCII    DLE  DC4  fn  a  b
+    /// Hex      10   14   02  01 08
+    /// Decimal  16   20    2   1  8
+    ///
+    /// The printer stops printing, stores its maintenance counters and gets
+    /// ready for the power to be switched off. It accepts nothing but real-time
+    /// commands until it is powered off.
+    pub fn power_off(&mut self) -> Result<usize, Error> {
+        self.real_time("power_off", String::new, &[0x10, 0x14, 0x02, 0x01, 0x08])
     }
 
-    /// hr generates a line of width <width>
-    pub fn hr(&mut self, width: usize) -> Result<usize, Error> {
-        let mut n_bytes = 0;
-        let width = if width < 1 { 1 } else { width }; // 0 would be invalid, so set to 1
-        let line = vec![0xc4; width];
-        n_bytes += self.write(&line)?;
-        n_bytes += self.write("\n".as_ref())?;
-        Ok(n_bytes)
+    pub fn chain_real_time_buzzer(
+        &mut self,
+        pattern: u8,
+        times: u8,
+        on_time: u8,
+        off_time: u8,
+    ) -> Result<&mut Self, Error> {
+        self.real_time_buzzer(pattern, times, on_time, off_time)
+            .map(|_| self)
     }
 
-    pub fn chain_char_size(&mut self, height: u8) -> Result<&mut Self, Error> {
-        self.char_size(height).map(|_| self)
+    /// DLE DC4 fn a n r t1 t2 - Sound buzzer in real-time (fn = 3)
+    ///
+    /// ASCII    DLE  DC4  fn  a  n  r  t1  t2
+    /// Hex      10   14   03  a  01 r  t1  t2
+    /// Decimal  16   20    3  a   1 r  t1  t2
+    /// Range: 0 <= a <= 7, 0 <= r <= 63, 0 <= t1 <= 50, 0 <= t2 <= 50
+    ///
+    /// Sounds buzzer pattern a r times, on for t1 x 100 ms and off for
+    /// t2 x 100 ms. Pattern 0 stops the buzzer. The patterns differ between
+    /// printers and only printers fitted with a buzzer support it.
+    pub fn real_time_buzzer(
+        &mut self,
+        pattern: u8,
+        times: u8,
+        on_time: u8,
+        off_time: u8,
+    ) -> Result<usize, Error> {
+        if pattern > 7 || times > 63 || on_time > 50 || off_time > 50 {
+            return Err(Error::InvalidArgument);
+        }
+        self.real_time(
+            "real_time_buzzer",
+            || format!("{}, {}, {}, {}", pattern, times, on_time, off_time),
+            &[0x10, 0x14, 0x03, pattern, 0x01, times, on_time, off_time],
+        )
     }
 
-    pub fn char_size(&mut self, height: u8) -> Result<usize, Error> {
-        self.write(&[0x1d, 0x21, height])
+    pub fn chain_clear_buffers(&mut self) -> Result<&mut Self, Error> {
+        self.clear_buffers().map(|_| self)
     }
 
-    /// ESC 2/ESC 3 n - Set line spacing
+    /// DLE DC4 fn d1...d7 - Clear buffer(s) (fn = 8)
     ///
-    /// ESC 2 (0x1b, 0x32) Sets line spacing to default
-    /// ESC 3 (0x1b, 0x33, n) Specifies a specific line spacing
+    /// ASCII    DLE  DC4  fn  d1 d2 d3 d4 d5 d6 d7
+    /// Hex      10   14   08  01 03 14 01 06 02 08
+    /// Decimal  16   20    8   1  3 20  1  6  2  8
     ///
-    /// ASCII    ESC   2
-    /// Hex      1b   32
-    /// Decimal  27   50
+    /// Throws away everything in the receive and print buffers, for example to
+    /// abandon a receipt that's stuck behind a paper end. Settings such as the
+    /// code page are kept.
     ///
-    /// ASCII    ESC   3  n
-    /// Hex      1b   33  n
-    /// Decimal  27   51  n
-    /// Range: 0 <= n <= 255
+    /// Star Line Mode and the P3 have no such command, [Error::Unsupported].
+    pub fn clear_buffers(&mut self) -> Result<usize, Error> {
+        let clear = clear_buffers_cmd(self.printer).ok_or(Error::Unsupported)?;
+        let n_bytes = self.real_time("clear_buffers", String::new, clear)?;
+        self.unpaced_bytes = 0;
+        Ok(n_bytes)
+    }
+
+    /// Sends a real-time command, which the printer runs as soon as it is
+    /// received, even while offline
     ///
-    /// Notes:
-    ///   - The line spacing can be set independently in standard mode and in
-    ///     page mode.
-    ///   - The horizontal and vertical motion units are specified by GS P.
-    ///     Changing the horizontal or vertical motion unit does not affect the
-    ///     current line spacing.
-    ///   - In standard mode, the vertical motion unit (y) is used.
-    ///   - In page mode, this command functions as follows, depending on the
-    ///     direction and starting position of the printable area:
-    ///     1) When the starting position is set to the upper left or lower right
-    ///        of the printable area by ESC T, the vertical motion unit (y) is
-    ///        used.
-    ///     2) When the starting position is set to the upper right or lower left
-    ///        of the printable area by ESC T, the horizontal motion unit (x) is
-    ///        used.
-    ///   - The maximum paper feed amount is 1016 mm (40 inches). Even if a paper
-    ///     feed amount of more than 1016 mm (40 inches) is set, the printer
-    ///     feeds the paper only 1016 mm (40 inches).
+    /// Skips [Printer::resync], the resent state would only queue up behind
+    /// whatever is keeping the printer offline. It isn't held back by a
+    /// [Printer::batch] either, it's transferred right away.
+    fn real_time<P>(
+        &mut self,
+        name: &'static str,
+        parameters: P,
+        cmd: &[u8],
+    ) -> Result<usize, Error>
+    where
+        P: FnOnce() -> String,
+    {
+        let needs_resync = std::mem::take(&mut self.session.needs_resync);
+        let res = self.command(name, parameters, |p| {
+            if !p.in_batch || p.capturing || p.recording.is_some() {
+                return p.send(cmd);
+            }
+            p.bytes_sent += cmd.len();
+            p.write_bulk(cmd)
+        });
+        self.session.needs_resync |= needs_resync;
+        res
+    }
+
+    /// DLE EOT n - Transmit real-time status
     ///
-    /// Default: The default line spacing is approximately 4.23mm (1/6 inches).
-    pub fn line_space(&mut self, n: i32) -> Result<usize, Error> {
-        if (0..=255).contains(&n) {
-            Ok(self.write(&[0x1b, 0x33, n as u8])?)
-        } else {
-            self.write(&[0x1b, 0x32])
+    /// | n | Status               |
+    /// |---|----------------------|
+    /// | 1 | printer status       |
+    /// | 2 | off-line status      |
+    /// | 3 | error status         |
+    /// | 4 | paper roll sensor    |
+    fn real_time_status(&mut self, n: u8) -> Result<u8, Error> {
+        // Star Line Mode reports status through ENQ and automatic status
+        // instead
+        if self.printer == SupportedPrinters::Star {
+            return Err(Error::Unsupported);
+        }
+        let mut buffer = [0_u8; 1];
+        self.query(&[0x10, 0x04, n], &mut buffer)?;
+        // Anything else is not a DLE EOT response (an ASB packet for
+        // example), unless it answers the GS r sent before
+        if let Some(status) = self.route_reply(buffer[0])? {
+            return Ok(status);
+        }
+        if self.read_status(&mut buffer, self.timeout)? == 0 {
+            return Err(Error::Timeout);
         }
+        self.route_reply(buffer[0])?.ok_or(Error::InvalidResponse)
     }
-    pub fn chain_line_space(&mut self, n: i32) -> Result<&mut Self, Error> {
-        self.line_space(n).map(|_| self)
+
+    /// Sends a command that the printer answers and reads the answer into buf
+    fn query(&mut self, cmd: &[u8], buf: &mut [u8]) -> Result<usize, Error> {
+        self.send(cmd)?;
+        let transferred = self.read_status(buf, self.timeout)?;
+        if transferred == 0 {
+            return Err(Error::Timeout);
+        }
+        Ok(transferred)
     }
 
-    pub fn feed(&mut self, n: usize) -> Result<usize, Error> {
-        let n = if n < 1 { 1 } else { n };
-        self.write("\n".repeat(n).as_ref())
+    pub fn read(&mut self, buf: &mut [u8; 16]) -> Result<usize, Error> {
+        let transferred = self.read_status(buf, self.timeout)?;
+        Ok(transferred)
     }
-    pub fn chain_feed(&mut self, n: usize) -> Result<&mut Self, Error> {
-        self.feed(n).map(|_| self)
+
+    pub fn has_asb_capability(&self) -> bool {
+        matches!(self.printer, SupportedPrinters::SNBC)
     }
+}
 
-    pub fn chain_control(&mut self, ctrl: &str) -> Result<&mut Self, Error> {
-        self.control(ctrl).map(|_| self)
+/// Number in a counter answer, ASCII digits padded with spaces or NULs
+fn parse_counter(answer: &[u8]) -> Result<u32, Error> {
+    parse_text(answer)?
+        .parse()
+        .map_err(|_| Error::InvalidResponse)
+}
+
+/// Text of an answer without its padding, spaces or NULs
+fn parse_text(answer: &[u8]) -> Result<String, Error> {
+    let text = std::str::from_utf8(answer).map_err(|_| Error::InvalidResponse)?;
+    let text = text.trim_matches(|c: char| c == '\0' || c.is_ascii_whitespace());
+    Ok(text.to_string())
+}
+
+/// Whether mode is one of the image modes of [Printer::raster], None is
+/// normal
+fn known_image_mode(mode: Option<&str>) -> bool {
+    let mut buf = [0; 8];
+    matches!(
+        upper(mode.unwrap_or("NORMAL"), &mut buf),
+        "NORMAL" | "DW" | "DH" | "QD"
+    )
+}
+
+/// ASCII upper case copy of a keyword argument ("lt", "b", "d24"...) made
+/// without allocating. Anything longer than buf comes back empty, which no
+/// keyword matches.
+fn upper<'a>(s: &str, buf: &'a mut [u8; 8]) -> &'a str {
+    let Some(buf) = buf.get_mut(..s.len()) else {
+        return "";
+    };
+    buf.copy_from_slice(s.as_bytes());
+    buf.make_ascii_uppercase();
+    std::str::from_utf8(buf).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, assert_hex};
+    use encoding::all::ASCII;
+
+    /// A printer of the dialect that isn't opened, for [Printer::capture]
+    fn unopened(printer: SupportedPrinters) -> Printer {
+        Printer::lazy(None, None, printer, 0, 0)
     }
-    pub fn control(&mut self, ctrl: &str) -> Result<usize, Error> {
-        let ctrl_upper = ctrl.to_uppercase();
-        let ctrl_value = match ctrl_upper.as_ref() {
-            "LF" => consts::CTL_LF,
-            "FF" => consts::CTL_FF,
-            "CR" => consts::CTL_CR,
-            "HT" => consts::CTL_HT,
-            "VT" => consts::CTL_VT,
-            _ => return Err(Error::Unsupported),
+
+    #[test]
+    fn offline_status_from_byte() {
+        let status = OfflineStatus::from(0x12);
+        assert_eq!(status, OfflineStatus::default());
+        assert_eq!(status.to_string(), "ok");
+
+        let status = OfflineStatus::from(0x12 | 0x04 | 0x20);
+        assert!(status.cover_open);
+        assert!(!status.feed_button_pressed);
+        assert!(status.paper_end);
+        assert!(!status.error);
+        assert_eq!(status.to_string(), "cover open, paper end");
+    }
+
+    #[test]
+    fn parses_paper_lengths() {
+        assert_eq!(parse_counter(b"  1234\0\0").unwrap(), 1234);
+        assert!(matches!(
+            parse_counter(b"12a4"),
+            Err(Error::InvalidResponse)
+        ));
+        assert!(matches!(
+            parse_counter(b"\0\0"),
+            Err(Error::InvalidResponse)
+        ));
+        assert_eq!(parse_text(b"V1.2\0\0").unwrap(), "V1.2");
+
+        let printed = PaperLength {
+            value: 3,
+            unit: LengthUnit::Meters,
+        };
+        assert_eq!(printed.centimeters(), 300);
+        let remaining = PaperLength {
+            value: 1250,
+            unit: LengthUnit::Centimeters,
         };
-        self.write(ctrl_value)
+        assert_eq!(remaining.millimeters(), 12500);
+        assert_eq!(remaining.meters(), 12.5);
     }
 
-    pub fn chain_align(&mut self, alignment: &str) -> Result<&mut Self, Error> {
-        self.align(alignment).map(|_| self)
+    #[test]
+    fn takes_inventory() {
+        // Star Line Mode has none of the queries, nothing is sent
+        let mut p = unopened(SupportedPrinters::Star);
+        assert_eq!(p.inventory().unwrap(), PrinterInventory::default());
+        assert!(!p.is_connected());
+        let mut p = unopened(SupportedPrinters::Unknown);
+        assert!(matches!(p.inventory(), Err(Error::Unsupported)));
     }
-    pub fn align(&mut self, alignment: &str) -> Result<usize, Error> {
-        let align_upper = alignment.to_uppercase();
-        let align_value = match align_upper.as_ref() {
-            "LT" => consts::TXT_ALIGN_LT,
-            "CT" => consts::TXT_ALIGN_CT,
-            "RT" => consts::TXT_ALIGN_RT,
-            _ => return Err(Error::InvalidArgument),
+
+    #[cfg(feature = "json_profiles")]
+    #[test]
+    fn inventory_as_json() {
+        let inventory = PrinterInventory {
+            rom_version: Some("V1.2".to_string()),
+            cut_count: Some(42),
+            printed_length: Some(PaperLength {
+                value: 3,
+                unit: LengthUnit::Meters,
+            }),
+            ..PrinterInventory::default()
         };
-        self.write(align_value)
+        let json = serde_json::to_value(&inventory).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "serial": null,
+                "rom_version": "V1.2",
+                "cut_count": 42,
+                "power_count": null,
+                "printed_length": {"value": 3, "unit": "Meters"},
+                "remaining_paper": null,
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<PrinterInventory>(json).unwrap(),
+            inventory
+        );
     }
 
-    pub fn chain_font(&mut self, family: &str) -> Result<&mut Self, Error> {
-        self.font(family).map(|_| self)
+    #[test]
+    fn counts_paper_used() {
+        let mut p = unopened(SupportedPrinters::SNBC);
+        let mut job = Job::new();
+        job.push(Command::Text("Total 12.50".to_string()))
+            .push(Command::NewLine)
+            .push(Command::Feed(5));
+        p.capture(|p| p.submit(&job)).unwrap();
+        let used = p.paper_used_mm();
+        assert!(used > 0.0);
+        assert_eq!(used, job.estimate_length_mm(&p.profile));
+        assert_eq!(p.estimated_remaining_mm(1000), (1000.0 - used) as u32);
+
+        p.set_footer(job.clone());
+        p.capture(|p| p.submit(&job)).unwrap();
+        assert_eq!(p.paper_used_mm(), 3.0 * used);
+        assert_eq!(p.estimated_remaining_mm(1), 0);
+        p.new_roll();
+        assert_eq!(p.estimated_remaining_mm(1000), 1000);
     }
-    pub fn font(&mut self, family: &str) -> Result<usize, Error> {
-        let family_upper = family.to_uppercase();
-        let family_value = match family_upper.as_ref() {
-            "A" => consts::TXT_FONT_A,
-            "B" => consts::TXT_FONT_B,
-            "C" => consts::TXT_FONT_C,
-            _ => return Err(Error::InvalidArgument),
+
+    #[test]
+    fn traps_per_call() {
+        let mut p = Printer::lazy(Some(ASCII), None, SupportedPrinters::SNBC, 0, 0);
+        let bytes = p
+            .capture(|p| {
+                let mut n = p.print("1€")?;
+                assert!(p.with_trap(EncoderTrap::Strict, |p| p.print("2€")).is_err());
+                n += p.with_trap(EncoderTrap::Ignore, |p| p.print("3€"))?;
+                Ok(n + p.print("4€")?)
+            })
+            .unwrap();
+        assert_eq!(bytes, b"1?34?");
+    }
+
+    #[test]
+    fn paces_only_answering_printers() {
+        let paced = FlowControl::StatusPaced {
+            chunk_size: 4096,
+            timeout: Duration::from_secs(5),
         };
-        self.write(family_value)
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Star, 0, 0);
+        assert!(matches!(p.set_flow_control(paced), Err(Error::Unsupported)));
+        assert_eq!(p.flow_control, FlowControl::None);
+        p.set_flow_control(FlowControl::None).unwrap();
+
+        let mut p = Printer::lazy(None, None, SupportedPrinters::SNBC, 0, 0);
+        p.set_flow_control(paced).unwrap();
+        assert_eq!(p.flow_control, paced);
     }
 
-    pub fn chain_style(&mut self, kind: &str) -> Result<&mut Self, Error> {
-        self.style(kind).map(|_| self)
+    #[test]
+    fn cancels_before_sending() {
+        let mut p = unopened(SupportedPrinters::SNBC);
+        let mut job = Job::new();
+        job.push(Command::Text("Total 9.99".to_string()));
+        let token = CancellationToken::new();
+        // Captured or batched, the job isn't transferred until later
+        assert!(matches!(
+            p.capture(|p| p.submit_cancellable(&job, &token)),
+            Err(Error::Unsupported)
+        ));
+        assert!(p.cancel.is_none());
+
+        token.clone().cancel();
+        assert!(token.is_canceled());
+        let bytes = p
+            .capture(|p| {
+                let res = p.submit_cancellable(&job, &token);
+                assert!(matches!(res, Err(Error::Canceled)));
+                Ok(0)
+            })
+            .unwrap();
+        assert!(bytes.is_empty() && p.cancel.is_none());
     }
-    pub fn style(&mut self, kind: &str) -> Result<usize, Error> {
-        let kind_upper = kind.to_uppercase();
-        match kind_upper.as_ref() {
-            "B" => Ok(self.write(consts::TXT_UNDERL_OFF)? + self.write(consts::TXT_BOLD_ON)?),
-            "U" => Ok(self.write(consts::TXT_BOLD_OFF)? + self.write(consts::TXT_UNDERL_ON)?),
-            "U2" => Ok(self.write(consts::TXT_BOLD_OFF)? + self.write(consts::TXT_UNDERL2_ON)?),
-            "BU" => Ok(self.write(consts::TXT_BOLD_ON)? + self.write(consts::TXT_UNDERL_ON)?),
-            "BU2" => Ok(self.write(consts::TXT_BOLD_ON)? + self.write(consts::TXT_UNDERL2_ON)?),
-            // "NORMAL" | _ =>
-            _ => Ok(self.write(consts::TXT_BOLD_OFF)? + self.write(consts::TXT_UNDERL_OFF)?),
-        }
+
+    #[test]
+    fn runs_middleware() {
+        let mut p = unopened(SupportedPrinters::SNBC);
+        p.add_job_middleware(|command: &Command, bytes: &mut Vec<u8>| match command {
+            Command::Cut { partial: false } => *bytes = vec![0x1d, 0x56, 0x42, 0x00],
+            Command::Raw(_) => bytes.clear(),
+            _ => (),
+        });
+        p.add_job_middleware(|command: &Command, bytes: &mut Vec<u8>| {
+            if let Command::Text(_) = command {
+                bytes.insert(0, b'>');
+            }
+        });
+        let mut job = Job::new();
+        job.push(Command::Text("Hi".to_string()))
+            .push(Command::Raw(vec![0x1b, 0x70, 0x00]))
+            .push(Command::Cut { partial: false });
+        let bytes = p.capture(|p| p.submit(&job)).unwrap();
+        assert_hex(&bytes, "3e 48 69  1d 56 42 00");
+
+        // Left as they are cuts still get the feed
+        p.clear_job_middleware();
+        p.add_job_middleware(|_: &Command, _: &mut Vec<u8>| ());
+        let mut job = Job::new();
+        job.push(Command::Cut { partial: true }).cut_feed = Some(CutFeed {
+            lines_before: 2,
+            mm_after: 1,
+        });
+        let bytes = p.capture(|p| p.submit(&job)).unwrap();
+        assert_hex(&bytes, "0a 0a  1d 56 42 08");
+
+        // Images are banded before the middleware sees them
+        p.clear_job_middleware();
+        p.add_job_middleware(|command: &Command, bytes: &mut Vec<u8>| {
+            if let Command::Raster { height, .. } = command {
+                assert_eq!(*height, 2);
+                // Double width
+                bytes[3] = 1;
+            }
+        });
+        let mut job = Job::new();
+        job.push(Command::Raster {
+            width_bytes: 1,
+            height: 2,
+            data: vec![0xff, 0x00],
+        });
+        let bytes = p.capture(|p| p.submit(&job)).unwrap();
+        assert_hex(&bytes, "1d 76 30 01 01 00 02 00 ff 00");
     }
 
-    pub fn chain_size(&mut self, width: usize, height: usize) -> Result<&mut Self, Error> {
-        self.size(width, height).map(|_| self)
+    #[test]
+    #[allow(deprecated)]
+    fn generic_profile_quirks() {
+        let quirks = SupportedPrinters::Generic.quirks();
+        assert!(quirks.barcode_nul_terminator && quirks.no_code93 && quirks.raster_only);
+        assert_eq!(SupportedPrinters::SNBC.quirks(), Quirks::default());
+
+        // GS V cuts, with and without feed
+        let bytes = testing::capture(SupportedPrinters::Generic, |p| {
+            Ok(p.cut(CutMode::Full, None)? + p.cut(CutMode::Partial, Some(2))?)
+        })
+        .unwrap();
+        assert_hex(&bytes, "1d 56 41 00  1d 56 42 10");
+        let bytes = testing::capture(SupportedPrinters::Generic, |p| {
+            Ok(p.full_cut()? + p.partial_cut()?)
+        })
+        .unwrap();
+        assert_hex(&bytes, "0a 0a 0a  1d 56 00  0a 0a 0a  1d 56 01");
     }
-    pub fn size(&mut self, width: usize, height: usize) -> Result<usize, Error> {
-        let mut n = self.write(consts::TXT_NORMAL)?;
-        if width == 2 {
-            n += self.write(consts::TXT_2WIDTH)?;
+
+    #[test]
+    fn footer_before_final_cut() {
+        let text = Command::Text("Thanks".to_string());
+        let commands = [
+            text.clone(),
+            Command::Feed(3),
+            Command::Cut { partial: true },
+        ];
+        assert_eq!(footer_position(&commands), 1);
+        assert_eq!(footer_position(&commands[..1]), 1);
+        assert_eq!(footer_position(&[Command::Cut { partial: false }]), 0);
+    }
+
+    #[test]
+    fn archives_printed_jobs() {
+        use std::sync::{Arc, Mutex};
+
+        let mut p = Printer::lazy(None, None, SupportedPrinters::SNBC, 0, 0);
+        p.batching = true;
+        let copies = Arc::new(Mutex::new(Vec::new()));
+        let archived = copies.clone();
+        let options = ArchiveOptions {
+            text: true,
+            png: false,
+        };
+        p.set_archive(options, move |copy: &ArchivedJob| {
+            archived.lock().unwrap().push(copy.text.clone().unwrap())
+        });
+        let mut footer = Job::new();
+        footer.push(Command::Text("Thanks".to_string()));
+        p.set_footer(footer);
+        let mut job = Job::new();
+        job.push(Command::Text("Total 9.99".to_string()))
+            .push(Command::NewLine)
+            .push(Command::Cut { partial: true });
+        p.submit(&job).unwrap();
+        assert_eq!(
+            *copies.lock().unwrap(),
+            [format!("Total 9.99\nThanks\n{}\n", "-".repeat(48))]
+        );
+    }
+
+    #[test]
+    fn cuts_per_profile() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::SNBC, 0, 0);
+        p.batching = true;
+        p.cut(CutMode::Partial, Some(5)).unwrap();
+        assert_eq!(p.batch, [0x1d, 0x56, 0x42, 40]);
+        // Without a feed the profile's line feeds and feed
+        p.profile.cut_feed = Some(CutFeed {
+            lines_before: 1,
+            mm_after: 2,
+        });
+        p.batch.clear();
+        p.cut(CutMode::Partial, None).unwrap();
+        assert_eq!(p.batch, [0x0a, 0x1d, 0x56, 0x42, 16]);
+        p.batch.clear();
+        p.finish_sale(SaleFinish::default()).unwrap();
+        assert_eq!(
+            p.batch,
+            [0x1b, 0x70, 0, 50, 100, 0x0a, 0x1d, 0x56, 0x42, 16]
+        );
+        p.profile.cut_feed = None;
+        // Converted with the profile's resolution, never clamped
+        p.profile.dpi = Some(300);
+        p.batch.clear();
+        p.cut(CutMode::Partial, Some(5)).unwrap();
+        assert_eq!(p.batch, [0x1d, 0x56, 0x42, 59]);
+        assert!(matches!(
+            p.cut(CutMode::Partial, Some(22)),
+            Err(Error::InvalidArgument)
+        ));
+        let finish = SaleFinish {
+            drawer: Some(DrawerPin::Pin2),
+            feed_mm: Some(40),
+            ..SaleFinish::default()
+        };
+        p.batch.clear();
+        assert!(matches!(p.finish_sale(finish), Err(Error::InvalidArgument)));
+        assert!(p.batch.is_empty());
+
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Bixolon, 0, 0);
+        p.batching = true;
+        p.cut(CutMode::Full, None).unwrap();
+        assert_eq!(p.batch, [0x1d, 0x56, 0x42, 0]);
+        p.set_degradation(Degradation::Fail);
+        assert!(matches!(
+            p.cut(CutMode::Full, None),
+            Err(Error::Unsupported)
+        ));
+
+        p.profile.capabilities.cutter = false;
+        p.batch.clear();
+        p.set_degradation(Degradation::Substitute);
+        p.cut(CutMode::Full, Some(2)).unwrap();
+        assert_eq!(p.batch, [0x0a, 0x0a, 0x0a, 0x1b, 0x4a, 16]);
+        // The profile's line feeds replace the three
+        p.profile.cut_feed = Some(CutFeed {
+            lines_before: 1,
+            mm_after: 0,
+        });
+        p.batch.clear();
+        p.cut(CutMode::Full, None).unwrap();
+        assert_eq!(p.batch, [0x0a]);
+
+        let mut p = Printer::lazy(None, None, SupportedPrinters::P3, 0, 0);
+        p.batching = true;
+        p.cut(CutMode::Partial, None).unwrap();
+        assert_eq!(p.batch, [0x0a, 0x0a, 0x0a, 0x1b, 0x6d]);
+        let mut job = Job::new();
+        job.push(Command::Cut { partial: true }).cut_feed = Some(CutFeed {
+            lines_before: 1,
+            mm_after: 2,
+        });
+        p.batch.clear();
+        p.submit(&job).unwrap();
+        assert_eq!(p.batch, [0x0a, 0x1b, 0x4a, 16, 0x1b, 0x6d]);
+        let mut encoder = p.encoder();
+        encoder.cut_feed = job.cut_feed;
+        assert_eq!(encoder.encode(&job).unwrap(), p.batch);
+    }
+
+    #[test]
+    fn commits_pages() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::SNBC, 0, 0);
+        p.batching = true;
+        p.style("B").unwrap();
+        let area = PageArea {
+            x: 0,
+            y: 0,
+            width: 512,
+            height: 300,
+        };
+        p.begin_page(area).unwrap();
+        assert!(p
+            .batch
+            .ends_with(&[0x1b, 0x4c, 0x1b, 0x57, 0, 0, 0, 0, 0, 2, 44, 1]));
+        assert!(matches!(p.begin_page(area), Err(Error::InvalidArgument)));
+        p.style("U").unwrap();
+        p.batch.clear();
+        p.commit_page().unwrap();
+        // FF, not ESC FF which stays in page mode, then bold again
+        assert_eq!(p.batch, [0x0c, 0x1b, 0x2d, 0x00, 0x1b, 0x45, 0x01]);
+        assert_eq!(p.text_state.bold, Some(true));
+        assert_eq!(p.text_state.underline, Some(0));
+        assert!(matches!(p.commit_page(), Err(Error::InvalidArgument)));
+        let tall = PageArea { y: 1600, ..area };
+        assert!(matches!(p.begin_page(tall), Err(Error::InvalidArgument)));
+
+        // Cancelled when the paper got narrower
+        p.begin_page(area).unwrap();
+        p.profile.dots_per_line = Some(384);
+        p.batch.clear();
+        assert!(matches!(p.commit_page(), Err(Error::InvalidArgument)));
+        assert_eq!(p.batch, [0x18, 0x1b, 0x53]);
+        assert!(p.page.is_none());
+
+        // Cancelled when the text runs past the page height
+        p.profile.dots_per_line = None;
+        p.profile.page_height = Some(400);
+        let low = PageArea { y: 100, ..area };
+        p.begin_page(low).unwrap();
+        for _ in 0..5 {
+            p.println("Total 9.99").unwrap();
         }
-        if height == 2 {
-            n += self.write(consts::TXT_2HEIGHT)?;
+        p.batch.clear();
+        p.commit_page().unwrap();
+        assert_eq!(p.batch[0], 0x0c);
+        p.begin_page(low).unwrap();
+        for _ in 0..20 {
+            p.println("Total 9.99").unwrap();
         }
-        Ok(n)
+        assert!(matches!(p.commit_page(), Err(Error::InvalidArgument)));
+        assert!(p.page_content.is_empty());
     }
 
-    pub fn chain_barcode(
-        &mut self,
-        code: &str,
-        kind: BarcodeType,
-        position: TextPosition,
-        font: Font,
-        width: u8,
-        height: u8,
-    ) -> Result<&mut Self, Error> {
-        self.barcode(code, kind, position, font, width, height)
-            .map(|_| self)
+    #[test]
+    #[allow(deprecated)]
+    fn feeds_around_cuts() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::SNBC, 0, 0);
+        p.batching = true;
+        p.partial_cut().unwrap();
+        assert_eq!(p.batch, [0x0a, 0x0a, 0x0a, 0x1d, 0x56, 0x01]);
+
+        p.profile.cut_feed = Some(CutFeed {
+            lines_before: 0,
+            mm_after: 2,
+        });
+        p.batch.clear();
+        p.full_cut().unwrap();
+        assert_eq!(p.batch, [0x1d, 0x56, 0x41, 16]);
+
+        let mut job = Job::new();
+        job.push(Command::Cut { partial: true });
+        p.batch.clear();
+        p.submit(&job).unwrap();
+        assert!(p.batch.ends_with(&[0x1d, 0x56, 0x42, 16]));
+
+        job.cut_feed = Some(CutFeed {
+            lines_before: 1,
+            mm_after: 0,
+        });
+        p.batch.clear();
+        p.submit(&job).unwrap();
+        assert!(p.batch.ends_with(&[0x0a, 0x1d, 0x56, 0x42, 0]));
     }
-    pub fn barcode(
-        &mut self,
-        code: &str,
-        kind: BarcodeType,
-        position: TextPosition,
-        font: Font,
-        width: u8,
-        height: u8,
-    ) -> Result<usize, Error> {
-        let mut n = 0;
-        let mut bc = Barcode {
-            printer: self.printer,
-            width,
-            height,
-            position,
-            font,
-            kind,
+
+    #[test]
+    fn finishes_sale_in_order() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::SNBC, 0, 0);
+        p.batching = true;
+        let finish = SaleFinish {
+            buzzer: 2,
+            ..SaleFinish::default()
         };
+        p.finish_sale(finish).unwrap();
+        assert_eq!(
+            p.batch,
+            [
+                0x1b, 0x70, 0, 50, 100, // drawer
+                0x1d, 0x56, 0x42, 0, // cut
+                0x10, 0x14, 0x03, 1, 1, 2, 3, 2, // buzzer
+            ]
+        );
 
-        // Code128 requires the Code Set to be sent before the barcode text
-        //
-        // Currently we just default to Code B, but we might want to think about
-        // allowing the selection of the code set
-        //
-        // 128A (Code Set A) – ASCII characters 00 to 95 (0–9, A–Z and control codes), special characters, and FNC 1–4
-        // 128B (Code Set B) – ASCII characters 32 to 127 (0–9, A–Z, a–z), special characters, and FNC 1–4
-        // 128C (Code Set C) – 00–99 (encodes two digits with a single code point) and FNC1
-        // SNBC Also requires sending the number of bytes in the Code128 receipt
-        if kind == BarcodeType::Code128 && self.printer == SupportedPrinters::SNBC {
-            n += self.write(&bc.set_width()?)?;
-            n += self.write(&bc.set_height())?;
-            n += self.write(&bc.set_text_position())?;
-            n += self.write(&bc.set_font())?;
-            n += self.write(&bc.set_barcode_type())?;
-            let mut code128_bytes: Vec<u8> = vec![0x7b]; // Next byte will set the code set
-            if code.len() % 2 == 0 && code.chars().all(|x| x.is_ascii_digit()) {
-                // even number of chars and they are all numbers, we can use Code Set C
-                code128_bytes.push(0x43); // Codeset C
-                let mut converted: Vec<u8> = Barcode::to_codeset_c(code.to_string()).unwrap();
-                code128_bytes.append(&mut converted);
-            } else {
-                // otherwise we just push the characters which match up with Code Set B
-                code128_bytes.push(0x42); // Codeset B
-                for byte in code.as_bytes().iter() {
-                    code128_bytes.push(*byte);
-                }
-            }
+        // A drawer that opens only once the receipt is cut
+        p.profile.sale_order = Some(vec![SaleStep::Cut]);
+        p.batch.clear();
+        p.finish_sale(finish).unwrap();
+        assert_eq!(
+            p.batch,
+            [
+                0x1d, 0x56, 0x42, 0, // cut
+                0x1b, 0x70, 0, 50, 100, // drawer
+                0x10, 0x14, 0x03, 1, 1, 2, 3, 2, // buzzer
+            ]
+        );
 
-            let count = code128_bytes.len();
-            code128_bytes.insert(0, count as u8);
-            n += self.write(&code128_bytes)?;
-            return Ok(n);
-        } else if self.printer == SupportedPrinters::Epic {
-            n += self.write(&[
-                0x1D,
-                0x48,
-                0x02,
-                0x1D,
-                0x77,
-                0x02,
-                0x1D,
-                0x6B,
-                0x49,
-                code.len() as u8,
-            ])?;
-        } else {
-            return Err(Error::Unsupported);
-        }
+        // Too many buzzes fail before the drawer opens
+        p.batch.clear();
+        let noisy = SaleFinish {
+            buzzer: 64,
+            ..finish
+        };
+        assert!(matches!(p.finish_sale(noisy), Err(Error::InvalidArgument)));
+        assert!(p.batch.is_empty());
 
-        n += self.write(code.as_bytes())?;
-        n += self.write(&[0x00_u8])?; // Need to send NULL to finish
+        // Without a buzzer it's left out
+        p.profile.sale_order = None;
+        p.profile.capabilities.buzzer = false;
+        p.finish_sale(finish).unwrap();
+        assert_eq!(p.batch, [0x1b, 0x70, 0, 50, 100, 0x1d, 0x56, 0x42, 0]);
 
-        Ok(n)
+        p.set_degradation(Degradation::Fail);
+        assert!(matches!(p.finish_sale(finish), Err(Error::Unsupported)));
+        p.profile.capabilities.buzzer = true;
+        p.profile.capabilities.drawer = false;
+        assert!(matches!(p.finish_sale(finish), Err(Error::Unsupported)));
     }
 
-    #[cfg(feature = "qrcode")]
-    pub fn chain_qrimage(&mut self) -> Result<&mut Self, Error> {
-        self.qrimage().map(|_| self)
+    #[test]
+    fn picks_bit_image_density() {
+        use BitImageDensity::*;
+        assert_eq!(BitImageDensity::auto(100, 203, ImageQuality::Fine), D24);
+        assert_eq!(BitImageDensity::auto(100, 203, ImageQuality::Draft), S24);
+        assert_eq!(BitImageDensity::auto(16, 203, ImageQuality::Fine), D8);
+        assert_eq!(BitImageDensity::auto(100, 80, ImageQuality::Draft), S8);
+
+        let mut p = Printer::lazy(None, None, SupportedPrinters::SNBC, 0, 0);
+        p.batching = true;
+        let image = Image::from(image::DynamicImage::new_luma8(8, 16));
+        p.bit_image(&image, None).unwrap();
+        // Two 8 dot stripes after ESC 3 0
+        assert_eq!(&p.batch[3..6], consts::BITMAP_D8);
+        assert_eq!(p.batch.len(), 3 + 2 * (3 + 2 + 8 + 1));
     }
-    #[cfg(feature = "qrcode")]
-    pub fn qrimage(&mut self) -> Result<usize, Error> {
-        Ok(0)
+
+    #[test]
+    fn prints_itf14() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Generic, 0, 0);
+        p.batching = true;
+        assert!(matches!(p.itf14("123"), Err(Error::InvalidArgument)));
+        p.itf14("1540014128876").unwrap();
+        // Centered, narrow width 4 and 255 dots high
+        assert!(p.batch.starts_with(consts::TXT_ALIGN_CT));
+        let barcode = &p.batch[3..];
+        assert_eq!(barcode[5..10], [4, 0x1d, 0x68, 255, 0x1d]);
+        assert_eq!(&barcode[13..27], b"15400141288763");
     }
 
-    #[cfg(feature = "qrcode")]
-    pub fn chain_qrcode(
-        &mut self,
-        code: &str,
-        version: Option<i32>,
-        level: &str,
-        size: Option<i32>,
-    ) -> Result<&mut Self, Error> {
-        self.qrcode(code, version, level, size).map(|_| self)
+    #[test]
+    fn routes_status_replies() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::SNBC, 0, 0);
+        assert_eq!(p.route_reply(0x16).unwrap(), Some(0x16));
+        assert!(matches!(p.route_reply(0x03), Err(Error::InvalidResponse)));
+
+        // A GS r answer read while polling is kept for the drain wait
+        p.drain_pending = true;
+        assert_eq!(p.route_reply(0x03).unwrap(), None);
+        assert_eq!(p.drain_reply, Some(0x03));
+        assert_eq!(p.route_reply(0x12).unwrap(), Some(0x12));
+        assert!(p.route_reply(0x00).is_err());
+        assert_eq!(
+            PaperState::from_roll_status(0x12 | RT_PAPER_NEAR_END_BITS),
+            PaperState::NearEnd
+        );
+
+        // Not polled before the interval passed
+        p.batching = true;
+        let interval = Duration::from_secs(3600);
+        p.set_status_monitor(interval, |_: &LiveStatus| panic!("polled"));
+        let set = p.last_poll;
+        let mut job = Job::new();
+        job.push(Command::Raster {
+            width_bytes: 1,
+            height: 8,
+            data: vec![0xFF; 8],
+        });
+        p.submit(&job).unwrap();
+        assert_eq!(p.last_poll, set);
+    }
+
+    #[test]
+    fn reports_progress() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Generic, 0, 0);
+        p.batching = true;
+        let mut header = Job::new();
+        header.push(Command::Text("Store".to_string()));
+        p.set_header(header).unwrap();
+        let mut job = Job::new();
+        job.push(Command::Text("Photo".to_string()))
+            .push(Command::Raster {
+                width_bytes: 72,
+                height: 100,
+                data: vec![0; 7200],
+            })
+            .push(Command::Cut { partial: true });
+
+        let mut reports = Vec::new();
+        let n = p
+            .submit_with_progress(&job, |progress: &Progress| reports.push(*progress))
+            .unwrap();
+        let sections: Vec<_> = reports.iter().map(|r| r.section).collect();
+        assert_eq!(sections, [Section::Header, Section::Body, Section::Body]);
+        assert!(reports.windows(2).all(|r| r[0].sent <= r[1].sent));
+        // The raster band, estimated within the cut's bytes
+        assert!(reports[1].sent > 7200);
+        assert!(reports[1].total.abs_diff(n) < 8);
+        let last = reports.last().unwrap();
+        assert_eq!((last.sent, last.total), (n, n));
+    }
+
+    #[test]
+    fn reprints_marked_copies() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Generic, 0, 0);
+        p.batching = true;
+        let mut job = Job::new();
+        job.push(Command::Init)
+            .push(Command::Text("Total 9.99".to_string()))
+            .push(Command::NewLine)
+            .push(Command::Cut { partial: true });
+        p.reprint(&job, CopyMark::Inverse).unwrap();
+        let banner = format!("{:^24}", "COPY");
+        let marks = p
+            .batch
+            .windows(banner.len())
+            .filter(|w| *w == banner.as_bytes())
+            .count();
+        assert_eq!(marks, 2);
+        // After ESC @, centered and reversed
+        assert!(p.batch.starts_with(b"\x1b@\x1ba\x01\x1dB\x01"));
+
+        p.batch.clear();
+        p.reprint(&job, CopyMark::Diagonal).unwrap();
+        let raster = p.batch.windows(4).position(|w| w == b"\x1dv0\x00").unwrap();
+        // 72 bytes wide and 7 * 4 + 4 * 12 rows high
+        assert_eq!(p.batch[raster + 4..raster + 8], [72, 0, 76, 0]);
     }
+
     #[cfg(feature = "qrcode")]
-    pub fn qrcode(
-        &mut self,
-        code: &str,
-        version: Option<i32>,
-        level: &str,
-        size: Option<i32>,
-    ) -> Result<usize, Error> {
-        let level = level.to_uppercase();
-        let level_value = match level.as_ref() {
-            "M" => consts::QR_LEVEL_M,
-            "Q" => consts::QR_LEVEL_Q,
-            "H" => consts::QR_LEVEL_H,
-            // "L" | _ =>
-            _ => consts::QR_LEVEL_L,
-        };
-        let mut n = 0;
-        n += self.write(consts::TYPE_QR)?;
-        n += self.write(consts::CODE2D)?;
-        n += self.write_u8(version.unwrap_or(3) as u8)?;
-        n += self.write(level_value)?;
-        n += self.write_u8(size.unwrap_or(3) as u8)?;
-        n += self.write_u16le(code.len() as u16)?;
-        n += self.write(code.as_bytes())?;
-        Ok(n)
+    #[test]
+    fn prints_qrcode_with_text() {
+        assert_eq!(
+            wrap_code("https://example.com/r/12345", 12),
+            ["https://", "example.com/", "r/12345"]
+        );
+        assert_eq!(wrap_code("ABCDEFGHIJ", 4), ["ABCD", "EFGH", "IJ"]);
+
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Generic, 0, 0);
+        p.batching = true;
+        p.qrcode_with_text("https://example.com/r/12345", "example.com/r/12345", None)
+            .unwrap();
+        let text = p
+            .batch
+            .windows(20)
+            .position(|w| w == b"example.com/r/12345\n")
+            .unwrap();
+        let font_b = p
+            .batch
+            .windows(3)
+            .position(|w| w == consts::TXT_FONT_B)
+            .unwrap();
+        assert!(font_b < text);
+        assert!(p
+            .batch
+            .ends_with(&[consts::TXT_FONT_A, consts::TXT_ALIGN_LT].concat()));
+    }
+
+    #[test]
+    fn prints_job_encoding() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Generic, 0, 0);
+        p.batching = true;
+        p.profile.code_pages.insert("ibm866".to_string(), 17);
+        let mut job = Job::new();
+        job.push(Command::Text("Купон".to_string()))
+            .set_encoding(encoding::all::IBM866, job::Trap::Strict);
+        p.submit(&job).unwrap();
+        let mut expected = code_page_cmd(p.printer, 17);
+        expected.extend_from_slice(&[0x8a, 0xe3, 0xaf, 0xae, 0xad]);
+        expected.extend_from_slice(&code_page_cmd(p.printer, 0));
+        assert_eq!(p.batch, expected);
+
+        // Strict fails on characters the encoding doesn't have, the
+        // printer's code page is selected again all the same
+        p.batch.clear();
+        job.commands = vec![
+            Command::Text("Купон".to_string()),
+            Command::Text("€".to_string()),
+        ];
+        assert!(p.submit(&job).is_err());
+        assert!(p.batch.ends_with(&code_page_cmd(p.printer, 0)));
+        assert_eq!(p.session.code_page, Some(0));
+        job.encoding = Some("klingon".to_string());
+        assert!(matches!(p.submit(&job), Err(Error::InvalidArgument)));
+
+        // The header and footer are in the printer's encoding and code page
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Generic, 0, 0);
+        p.profile.code_pages.insert("ibm866".to_string(), 17);
+        p.profile.capabilities.macros = false;
+        let mut frame = Job::new();
+        frame.push(Command::Text("Café".to_string()));
+        p.set_header(frame.clone()).unwrap();
+        p.set_footer(frame);
+        let mut job = Job::new();
+        job.push(Command::Text("Купон".to_string()))
+            .set_encoding(encoding::all::IBM866, job::Trap::Strict);
+        let bytes = p.capture(|p| p.submit(&job)).unwrap();
+        let mut expected = "Café".as_bytes().to_vec();
+        expected.extend_from_slice(&code_page_cmd(p.printer, 17));
+        expected.extend_from_slice(&[0x8a, 0xe3, 0xaf, 0xae, 0xad]);
+        expected.extend_from_slice(&code_page_cmd(p.printer, 0));
+        expected.extend_from_slice("Café".as_bytes());
+        assert_eq!(bytes, expected);
+        assert_eq!(p.session.code_page, Some(0));
+    }
+
+    #[test]
+    fn prints_cyrillic() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Generic, 0, 0);
+        p.batching = true;
+        p.select_encoding(encoding::all::WINDOWS_1251).unwrap();
+        p.print("Привет, Київ").unwrap();
+        assert_eq!(
+            p.batch,
+            [
+                0x1b, 0x74, 46, // ESC t 46
+                0xcf, 0xf0, 0xe8, 0xe2, 0xe5, 0xf2, 0x2c, 0x20, 0xca, 0xe8, 0xbf, 0xe2,
+            ]
+        );
+
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Star, 0, 0);
+        p.batching = true;
+        p.select_encoding(encoding::all::IBM866).unwrap();
+        p.print("Привет").unwrap();
+        assert_eq!(
+            p.batch,
+            [
+                0x1b, 0x1d, 0x74, 10, // ESC GS t 10
+                0x8f, 0xe0, 0xa8, 0xa2, 0xa5, 0xe2,
+            ]
+        );
+
+        p.profile.capabilities.cyrillic = false;
+        assert!(matches!(
+            p.select_encoding(encoding::all::WINDOWS_1251),
+            Err(Error::Unsupported)
+        ));
+    }
+
+    #[test]
+    fn prints_thai() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Generic, 0, 0);
+        p.batching = true;
+        assert!(matches!(p.thai_println("ดี"), Err(Error::Unsupported)));
+        p.profile.code_pages.insert("windows-874".to_string(), 21);
+        p.thai_println("ดี").unwrap();
+        assert_eq!(
+            p.batch,
+            [
+                0x1b, 0x74, 21, // ESC t 21
+                0x1b, 0x33, 24, // ESC 3 24
+                0xd5, 0x0a, 0xb4, 0x0a, // sara ii over do dek
+                0x1b, 0x32, // ESC 2
+                0x1b, 0x74, 0,
+            ]
+        );
+
+        // Spacing and page are restored when a line can't be encoded
+        p.batch.clear();
+        p.set_trap(EncoderTrap::Strict);
+        assert!(p.thai_println("ดี ☕").is_err());
+        assert!(p.batch.ends_with(&[0x1b, 0x32, 0x1b, 0x74, 0]));
+        assert_eq!(p.session.code_page, Some(0));
+    }
+
+    #[test]
+    fn prints_halfwidth_kana() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Generic, 0, 0);
+        p.batching = true;
+        p.set_halfwidth_kana(true);
+        p.println("ｶﾞ 1").unwrap();
+        p.println("total").unwrap();
+        assert_eq!(
+            p.batch,
+            [
+                0x1b, 0x74, 1, // ESC t 1
+                0xb6, 0xde, b' ', b'1', // ka and the voiced sound mark
+                0x1b, 0x74, 0, 0x0a, // back to page 0 before the line feed
+                b't', b'o', b't', b'a', b'l', 0x0a,
+            ]
+        );
+        p.batch.clear();
+        p.println("がっこう").unwrap();
+        assert_eq!(&p.batch[3..8], [0xb6, 0xde, 0xaf, 0xba, 0xb3]);
+
+        // The page is restored when the kanji can't be encoded
+        let mut p = Printer::lazy(
+            Some(ASCII),
+            Some(EncoderTrap::Strict),
+            SupportedPrinters::Generic,
+            0,
+            0,
+        );
+        p.batching = true;
+        p.set_halfwidth_kana(true);
+        assert!(p.print("ｶ 合").is_err());
+        assert_eq!(p.batch, [0x1b, 0x74, 1, 0x1b, 0x74, 0]);
+        assert_eq!(p.session.code_page, Some(0));
+    }
+
+    #[test]
+    fn encodes_config() {
+        let settings = [ConfigSetting::PaperWidth(58), ConfigSetting::Density(-2)];
+        assert_eq!(
+            config_cmd(&settings).unwrap(),
+            [0x1d, 0x28, 0x45, 0x07, 0x00, 0x05, 3, 6, 0, 5, 0xfe, 0xff]
+        );
+        assert!(matches!(
+            config_cmd(&[ConfigSetting::PaperWidth(76)]),
+            Err(Error::InvalidArgument)
+        ));
+        assert_eq!(parse_config_value(b"7\x27\x05\x1f65534\x00"), Some(65534));
+        assert_eq!(parse_config_value(b"_TM-T20\x00"), None);
+        assert_eq!(paper_width_mm(6), Some(58));
+        assert_eq!(paper_width_mm(8), Some(80));
+        assert_eq!(paper_width_mm(7), None);
+
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Star, 0, 0);
+        assert!(matches!(p.write_config(&settings), Err(Error::Unsupported)));
+        assert!(matches!(p.read_config(&[9]), Err(Error::Unsupported)));
+    }
+
+    #[test]
+    fn rejects_fallbacks_when_strict() {
+        let mut p = unopened(SupportedPrinters::Generic);
+        p.capture(|p| Ok(p.style("bold")? + p.size(3, 1)?)).unwrap();
+        p.set_strict(true);
+        p.capture(|p| {
+            assert!(matches!(p.style("bold"), Err(Error::InvalidArgument)));
+            assert!(matches!(p.size(3, 1), Err(Error::InvalidArgument)));
+            assert!(matches!(
+                p.underline_mode(Some("dotted")),
+                Err(Error::InvalidArgument)
+            ));
+            assert!(matches!(
+                p.barcode(
+                    "1234",
+                    BarcodeType::EAN13,
+                    TextPosition::Below,
+                    Font::FontA,
+                    2,
+                    50
+                ),
+                Err(Error::InvalidArgument)
+            ));
+            Ok(p.style("normal")? + p.underline_mode(None)?)
+        })
+        .unwrap();
     }
 
-    pub fn chain_cashdraw(&mut self, pin: i32) -> Result<&mut Self, Error> {
-        self.cashdraw(pin).map(|_| self)
+    #[test]
+    fn fills_in_templates() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let mut job = Job::new();
+        job.push(Command::Text("Order {{order_no}}".to_string()))
+            .push(Command::NewLine)
+            .push(Command::Text("{{total}} {{x}".to_string()))
+            .push(Command::Cut { partial: true });
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Generic, 0, 0);
+        let archived = Arc::new(AtomicUsize::new(0));
+        let copies = archived.clone();
+        p.set_archive(ArchiveOptions::default(), move |_: &ArchivedJob| {
+            copies.fetch_add(1, Ordering::SeqCst);
+        });
+        p.enable_history(8);
+        let template = p.compile(&job).unwrap();
+        assert_eq!(template.fields(), ["order_no", "total"]);
+        // Compiling prints nothing
+        assert_eq!(archived.load(Ordering::SeqCst), 0);
+        assert_eq!(p.paper_used_mm(), 0.0);
+        assert_eq!(p.history().count(), 0);
+
+        let values = [("total", "9.99"), ("order_no", "A-17")];
+        let printed = p.capture(|p| p.print_template(&template, &values)).unwrap();
+        // Printing the template is printing a job
+        assert_eq!(archived.load(Ordering::SeqCst), 1);
+        let used = p.paper_used_mm();
+        assert!(used > 0.0);
+        job.commands[0] = Command::Text("Order A-17".to_string());
+        job.commands[2] = Command::Text("9.99 {{x}".to_string());
+        assert_eq!(template.job(&values), job);
+        assert_eq!(printed, p.capture(|p| p.submit(&job)).unwrap());
+        assert_eq!(p.paper_used_mm(), 2.0 * used);
+        assert!(matches!(
+            template.render(&values[..1]),
+            Err(Error::InvalidArgument)
+        ));
     }
-    pub fn cashdraw(&mut self, pin: i32) -> Result<usize, Error> {
-        let pin_value = if pin == 5 {
-            consts::CD_KICK_5
-        } else {
-            consts::CD_KICK_2
+
+    #[test]
+    fn restores_sections() {
+        let mut p = unopened(SupportedPrinters::Generic);
+        p.capture(|p| p.align("CT")).unwrap();
+        let bytes = p
+            .capture(|p| {
+                p.section(|p| {
+                    p.section(|p| p.style("B"))?;
+                    p.align("CT")?;
+                    p.size(2, 1)
+                })
+            })
+            .unwrap();
+        // Bold and no underline, ended by the inner section, then double
+        // width ended by the outer one, still centered
+        assert_hex(
+            &bytes,
+            "1b 2d 00  1b 45 01  1b 45 00  1b 2d 00  1b 21 00  1b 21 20  1b 21 00",
+        );
+    }
+
+    #[test]
+    fn prints_code39_options() {
+        let mut p = unopened(SupportedPrinters::Star);
+        let options = Code39Options {
+            check_digit: true,
+            start_stop: false,
+            ratio: Some(Code39Ratio::TwoAndAHalf),
         };
-        self.write(pin_value)
+        let bytes = p.capture(|p| p.code39("*CODE39*", &options)).unwrap();
+        let defaults = p.profile.barcode_defaults();
+        let mode = defaults.width.clamp(1, 3) + 3;
+        let mut expected = vec![0x1b, 0x62, 0x04, 0x02, mode, defaults.height];
+        expected.extend_from_slice(b"CODE39W\x1e");
+        assert_hex(&bytes, &testing::hex(&expected));
+
+        p.printer = SupportedPrinters::SNBC;
+        assert!(matches!(
+            p.capture(|p| p.code39("CODE39", &options)),
+            Err(Error::Unsupported)
+        ));
+        assert!(matches!(
+            p.capture(|p| p.code39("code39", &Code39Options::default())),
+            Err(Error::Barcode(BarcodeError::InvalidCharacter('c')))
+        ));
     }
 
-    pub fn chain_full_cut(&mut self) -> Result<&mut Self, Error> {
-        self.full_cut().map(|_| self)
+    #[test]
+    fn prints_code32() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::Generic, 0, 0);
+        p.batching = true;
+        assert!(matches!(p.code32("0123456"), Err(Error::InvalidArgument)));
+        p.code32("01234567").unwrap();
+        let data = p.batch.windows(6).position(|w| w == b"0CSSBD");
+        assert!(data.is_some());
+        // Alignment is restored after the human readable text
+        assert!(p.batch.ends_with(b"A012345676\n\x1ba\x00"));
     }
 
-    pub fn full_cut(&mut self) -> Result<usize, Error> {
-        match self.printer {
-            SupportedPrinters::SNBC | SupportedPrinters::Epic => {
-                self.write(&[0x0a, 0x0a, 0x0a, 0x1d, 0x56, 0x00])
-            }
-            // p3 seems to only support partial cut
-            _ => Err(Error::Unsupported),
-        }
+    #[test]
+    fn picks_nv_image_commands() {
+        use NvImageCommands::*;
+        let snbc = SupportedPrinters::SNBC;
+        assert_eq!(NvImageCommands::for_firmware(snbc, "1.05"), Legacy);
+        assert_eq!(NvImageCommands::for_firmware(snbc, "V2.1"), Graphics);
+        assert_eq!(NvImageCommands::for_firmware(snbc, "\0\0\0\0"), Legacy);
+        assert_eq!(
+            NvImageCommands::for_firmware(SupportedPrinters::P3, ""),
+            Graphics
+        );
+
+        let image = Image::from(image::DynamicImage::new_luma8(8, 16));
+        let mut p = Printer::lazy(None, None, snbc, 0, 0);
+        p.batching = true;
+        p.set_nv_image_commands(Legacy);
+        p.define_nv_images(std::slice::from_ref(&image)).unwrap();
+        assert_eq!(p.batch[..7], [0x1c, 0x71, 1, 1, 0, 2, 0]);
+        assert_eq!(p.batch.len(), 7 + 16);
+        p.batch.clear();
+        p.print_nv_image(1, Some("dw")).unwrap();
+        assert_eq!(p.batch, [0x1c, 0x70, 1, 1]);
+
+        p.batch.clear();
+        p.set_nv_image_commands(Graphics);
+        p.define_nv_images(&[image]).unwrap();
+        // Delete all, then 11 parameter bytes and a byte per row
+        assert_eq!(p.batch[10..15], [0x1d, 0x28, 0x4c, 11 + 16, 0]);
+        assert_eq!(p.batch.len(), 10 + 5 + 11 + 16);
+        p.batch.clear();
+        p.print_nv_image(1, None).unwrap();
+        assert_eq!(p.batch[7..], [0x20, 0x21, 1, 1]);
+        assert!(matches!(
+            p.print_nv_image(0, None),
+            Err(Error::InvalidArgument)
+        ));
     }
 
-    pub fn chain_partial_cut(&mut self) -> Result<&mut Self, Error> {
-        self.partial_cut().map(|_| self)
+    #[test]
+    fn skips_unchanged_attributes() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::SNBC, 0, 0);
+        // Collect the bytes instead of sending them
+        p.batching = true;
+        assert_eq!(p.align("ct").unwrap(), 3);
+        assert_eq!(p.align("CT").unwrap(), 0);
+        assert_eq!(p.style("b").unwrap(), 6);
+        p.size(2, 1).unwrap();
+        assert_eq!(p.style("b").unwrap(), 6);
+
+        assert!(p.align("middle").is_err());
+        assert_eq!(p.align("CT").unwrap(), 3);
+        p.write(&[0x1b, 0x61, 0x00]).unwrap();
+        assert_eq!(p.align("CT").unwrap(), 3);
+        assert_eq!(p.batch.len(), 3 + 6 + 6 + 6 + 3 + 3 + 3);
     }
 
-    pub fn partial_cut(&mut self) -> Result<usize, Error> {
-        let res = match self.printer {
-            SupportedPrinters::SNBC | SupportedPrinters::Epic => {
-                self.write(&[0x0a, 0x0a, 0x0a, 0x1d, 0x56, 0x01])
-            }
-            SupportedPrinters::P3 => self.write(&[0x0a, 0x0a, 0x0a, 0x1b, 0x6d]),
-            _ => Err(Error::Unsupported),
-        };
-        if self.printer == SupportedPrinters::Epic {
-            std::thread::sleep(std::time::Duration::new(3, 0));
-        }
-        res
+    #[test]
+    fn restores_alignment() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::SNBC, 0, 0);
+        p.batching = true;
+        p.align("RT").unwrap();
+        let n = p
+            .with_alignment(Alignment::Center, |p| p.text("centered"))
+            .unwrap();
+        assert_eq!(n, 9);
+        assert_eq!(p.text_state.align, Some(Alignment::Right));
+        assert!(p.batch.ends_with(consts::TXT_ALIGN_RT));
+
+        // Restored after an error too
+        assert!(p
+            .with_alignment(Alignment::Center, |p| p.align("middle"))
+            .is_err());
+        assert!(p.batch.ends_with(consts::TXT_ALIGN_RT));
     }
 
-    pub fn chain_bit_image(
-        &mut self,
-        image: &Image,
-        density: Option<&str>,
-    ) -> Result<&mut Self, Error> {
-        self.bit_image(image, density).map(|_| self)
+    #[test]
+    fn validates_raw() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::SNBC, 0, 0);
+        p.batching = true;
+        p.set_raw_validation(true);
+        assert_eq!(p.raw_hex("1b 40 0x1d 0x56 00").unwrap(), 5);
+        assert!(matches!(p.raw_hex("1b 4g"), Err(Error::InvalidArgument)));
+        assert!(matches!(p.raw(b"Hi\x1b\x74"), Err(Error::Malformed(2))));
+        p.set_raw_validation(false);
+        assert_eq!(p.raw(b"\x1b\x74").unwrap(), 2);
+        assert_eq!(p.batch.len(), 7);
     }
-    pub fn bit_image(&mut self, image: &Image, density: Option<&str>) -> Result<usize, Error> {
-        let density = density.unwrap_or("d24");
-        let density_upper = density.to_uppercase();
-        let header = match density_upper.as_ref() {
-            "S8" => consts::BITMAP_S8,
-            "D8" => consts::BITMAP_D8,
-            "S24" => consts::BITMAP_S24,
-            // "D24" | _ =>
-            _ => consts::BITMAP_D24,
-        };
-        let n = if density == "s8" || density == "d8" {
-            1
-        } else {
-            3
-        };
-        let mut n_bytes = 0;
-        n_bytes += self.line_space(0)?;
-        for line in image.bitimage_lines(n * 8) {
-            n_bytes += self.write(header)?;
-            n_bytes += self.write_u16le((line.len() / n as usize) as u16)?;
-            n_bytes += self.write(line.as_ref())?;
-            n_bytes += self.feed(1)?;
-        }
-        Ok(n_bytes)
+
+    #[test]
+    fn model_names() {
+        assert_eq!(
+            parse_model_name(b"_TM-T20II\0"),
+            Some("TM-T20II".to_string())
+        );
+        assert_eq!(parse_model_name(b"BTP-R180"), Some("BTP-R180".to_string()));
+        assert_eq!(parse_model_name(b"_\0"), None);
     }
 
-    pub fn chain_raster(&mut self, image: &Image, mode: Option<&str>) -> Result<&mut Self, Error> {
-        self.raster(image, mode).map(|_| self)
+    #[test]
+    fn upper_keywords() {
+        let mut buf = [0; 8];
+        assert_eq!(upper("d24", &mut buf), "D24");
+        assert_eq!(upper("normal", &mut buf), "NORMAL");
+        assert_eq!(upper("much too long", &mut buf), "");
     }
-    pub fn raster(&mut self, image: &Image, mode: Option<&str>) -> Result<usize, Error> {
-        let mode_upper = mode.unwrap_or("NORMAL").to_uppercase();
-        let header = match mode_upper.as_ref() {
-            // Double Wide
-            "DW" => &[0x1d, 0x76, 0x30, 0x01],
-            // Double Height
-            "DH" => &[0x1d, 0x76, 0x30, 0x02],
-            // Quadruple
-            "QD" => &[0x1d, 0x76, 0x30, 0x03],
-            // "NORMAL" | _ =>
-            _ => &[0x1d, 0x76, 0x30, 0x00],
-        };
-        let mut n_bytes = 0;
-        n_bytes += self.write(header)?;
-        n_bytes += self.write_u16le(((image.width + 7) / 8) as u16)?;
-        n_bytes += self.write_u16le(image.height as u16)?;
-        n_bytes += self.write(image.get_raster().as_ref())?;
-        Ok(n_bytes)
+
+    #[test]
+    fn tracks_power_cycles() {
+        let mut session = Session::default();
+        session.track_online(false);
+        session.track_online(true);
+        // Nothing to send again yet
+        assert!(!session.needs_resync);
+
+        session.code_page = Some(17);
+        session.track_online(true);
+        assert!(!session.needs_resync);
+        // Coming back online looks like a power cycle
+        session.track_online(false);
+        session.track_online(true);
+        assert!(session.needs_resync);
     }
 
-    pub fn get_serial(&mut self) -> Result<String, Error> {
-        match self.printer {
-            SupportedPrinters::P3 => {
-                self.write(&[0x1c, 0xea, 0x52])?;
-                let mut buffer = [0_u8; 16];
-                let _ = self
-                    .handle
-                    .read_bulk(self.stat_ep, &mut buffer, self.timeout)?;
-                let value = std::str::from_utf8(&buffer).unwrap();
-                Ok(value.to_string())
+    #[test]
+    fn reads_slip_status() {
+        let status = SlipStatus::from(0b0010_0100);
+        assert!(status.waiting_for_insertion);
+        assert!(!status.top_sensor_paper);
+        assert!(status.bottom_sensor_paper);
+        assert_eq!(
+            SlipStatus::from(0),
+            SlipStatus {
+                waiting_for_insertion: false,
+                top_sensor_paper: true,
+                bottom_sensor_paper: true,
             }
-            _ => Err(Error::Unsupported),
-        }
+        );
     }
 
-    pub fn get_cut_count(&mut self) -> Result<String, Error> {
-        self.write(&[0x1d, 0xe2]).unwrap();
-        let mut buffer = [0_u8; 16]; // TODO: This is more than enough now... but what about as
-                                     // cuts increase?
-        let _ = self
-            .handle
-            .read_bulk(self.stat_ep, &mut buffer, self.timeout)?;
-        let value = std::str::from_utf8(&buffer).unwrap(); // This seems to trim the padding
-        Ok(value.to_string())
+    #[test]
+    fn reads_ticket_taken() {
+        let status = PresenterStatus::from(0b0010_0100);
+        assert!(status.ticket_present);
+        assert!(status.paper_present);
+        assert!(status.paper_near_end);
+        let status = PresenterStatus::from(0b0000_0001);
+        assert!(!status.ticket_present);
+        assert!(!status.paper_present);
     }
 
-    pub fn get_rom_version(&mut self) -> Result<String, Error> {
-        self.write(&[0x1d, 0x49, 0x03]).unwrap();
-        let mut buffer = [0_u8; 4];
-        let _ = self
-            .handle
-            .read_bulk(self.stat_ep, &mut buffer, self.timeout)?;
-        let value = std::str::from_utf8(&buffer).unwrap();
-        Ok(value.to_string())
+    #[test]
+    fn selects_star_code_pages() {
+        assert_eq!(
+            code_page_cmd(SupportedPrinters::Star, 10),
+            [0x1b, 0x1d, 0x74, 10]
+        );
+        assert_eq!(code_page_cmd(SupportedPrinters::SNBC, 17), [0x1b, 0x74, 17]);
     }
 
-    pub fn get_firmware_checksum(&mut self) -> Result<String, Error> {
-        match self.printer {
-            SupportedPrinters::Epic => {
-                match self.write(&[0x1b, 0x7e, 0x5a]) {
-                    Ok(_) => {
-                        let mut buffer = [0_u8; 4];
-                        let _ = self
-                            .handle
-                            .read_bulk(self.stat_ep, &mut buffer, self.timeout)?;
-                        // Truncate the first two command bytes and read the remaining two as bits
-                        let value = format!("{:b}{:b}", &buffer[2], &buffer[3]);
-                        Ok(value.to_string())
-                    },
-                    e => {
-                        println!("Error ecountered getting firmware_checksum: {:?}", e);
-                        Err(Error::Timeout)
-                    },
-                }
-            },
-            _ => Err(Error::Unsupported),
-        }
+    #[test]
+    fn throttles_transfers() {
+        let delay = Throttle::DelayAfter {
+            bytes: 512,
+            delay_ms: 50,
+        };
+        // What's left of the 512 bytes, then the pause
+        assert_eq!(throttle_part(delay, 100, 1000), 412);
+        assert_eq!(throttle_part(delay, 0, 1000), 512);
+        assert_eq!(throttle_part(delay, 0, 76), 76);
+        assert_eq!(throttle_part(delay, 600, 76), 1);
+        // 9600 baud sends a tenth of a second at a time
+        assert_eq!(throttle_part(Throttle::BytesPerSecond(960), 0, 1000), 96);
+        assert_eq!(throttle_part(Throttle::BytesPerSecond(5), 0, 1000), 1);
     }
 
-    pub fn get_firmware_id(&mut self) -> Result<String, Error> {
-        match self.printer {
-            SupportedPrinters::Epic => {
-                match self.write(&[0x1b, 0x7e, 0x46]) {
-                    Ok(_) => {
-                        let mut buffer = [0_u8; 14];
-                        let _ = self
-                            .handle
-                            .read_bulk(self.stat_ep, &mut buffer, self.timeout)?;
-                        // Truncate the first two command bytes and terminator
-                        let firmware_id = &buffer[2..13]; 
-                        let value = std::str::from_utf8(&firmware_id).unwrap();
-                        Ok(value.to_string())
-                    },
-                    e => {
-                        println!("Error encountered getting firmware_id: {:?}", e);
-                        Err(Error::Timeout)
-                    }
-                }
-            },
-            _ => Err(Error::Unsupported),
+    #[test]
+    fn connects_lazily() {
+        let mut p = Printer::lazy(None, None, SupportedPrinters::SNBC, 0x154f, 0x154f);
+        assert!(!p.is_connected());
+        // Batched bytes stay in the process until they are flushed
+        p.batching = true;
+        p.hwinit().unwrap();
+        p.print("Hi").unwrap();
+        assert_eq!(p.batch, b"\x1b\x40Hi");
+        assert!(!p.is_connected());
+        p.release().unwrap();
+        assert!(!p.is_connected());
+    }
+
+    #[test]
+    fn resyncs_after_reset() {
+        let mut p = unopened(SupportedPrinters::SNBC);
+        p.capture(|p| Ok(p.code_page(17)? + p.print_density(-2)?))
+            .unwrap();
+        let bytes = p.capture(|p| Ok(p.hwinit()? + p.print("Hi")?)).unwrap();
+        // ESC @ resets the page and density, they're sent again before the
+        // next command
+        assert_hex(&bytes, "1b 40  1b 74 11  1d 28 4b 02 00 31 fe  48 69");
+        assert!(!p.session.needs_resync);
+
+        // Coming back online looks like a power cycle
+        p.session.track_online(false);
+        p.session.track_online(true);
+        let bytes = p.capture(|p| p.print("Hi")).unwrap();
+        assert_hex(&bytes, "1b 74 11  1d 28 4b 02 00 31 fe  48 69");
+        let bytes = p.capture(|p| p.resync()).unwrap();
+        assert_hex(&bytes, "1b 74 11  1d 28 4b 02 00 31 fe");
+    }
+
+    #[test]
+    fn forgets_text_state_after_power_cycle() {
+        let mut p = unopened(SupportedPrinters::SNBC);
+        let bytes = p.capture(|p| Ok(p.style("b")? + p.style("b")?)).unwrap();
+        assert_hex(&bytes, "1b 2d 00 1b 45 01");
+
+        // The printer came back with its attributes reset, even with no
+        // session state to send again
+        p.track_online(false);
+        p.track_online(true);
+        assert!(!p.session.needs_resync);
+        let bytes = p.capture(|p| p.style("b")).unwrap();
+        assert_hex(&bytes, "1b 2d 00 1b 45 01");
+
+        p.capture(|p| p.code_page(17)).unwrap();
+        p.track_online(false);
+        p.track_online(true);
+        let bytes = p.capture(|p| p.style("b")).unwrap();
+        assert_hex(&bytes, "1b 74 11  1b 2d 00 1b 45 01");
+    }
+
+    #[test]
+    fn pulses_drawer() {
+        let mut p = unopened(SupportedPrinters::SNBC);
+        let bytes = p
+            .capture(|p| {
+                Ok(p.cashdraw(DrawerPin::Pin2, 100, 200)? + p.cashdraw(DrawerPin::Pin5, 3, 0)?)
+            })
+            .unwrap();
+        // 2 ms units, rounded up
+        assert_hex(&bytes, "1b 70 00 32 64  1b 70 01 02 00");
+        for (on, off) in [(0, 0), (1, 200), (511, 200), (100, 511)] {
+            assert!(matches!(
+                p.capture(|p| p.cashdraw(DrawerPin::Pin2, on, off)),
+                Err(Error::InvalidArgument)
+            ));
         }
+        let bytes = p
+            .capture(|p| p.cashdraw(DrawerPin::Pin2, 510, 510))
+            .unwrap();
+        assert_hex(&bytes, "1b 70 00 ff ff");
+
+        let bytes = testing::capture(SupportedPrinters::Star, |p| {
+            p.cashdraw(DrawerPin::Pin5, 100, 205)
+        })
+        .unwrap();
+        // ESC BEL in 10 ms units, then SUB
+        assert_hex(&bytes, "1b 07 0a 15  1a");
+
+        p.profile.capabilities.drawer = false;
+        assert!(matches!(
+            p.capture(|p| p.cashdraw(DrawerPin::Pin2, 100, 200)),
+            Err(Error::Unsupported)
+        ));
     }
 
-    pub fn get_power_count(&mut self) -> Result<String, Error> {
-        self.write(&[0x1d, 0xe5]).unwrap();
-        let mut buffer = [0_u8; 8];
-        let _ = self
-            .handle
-            .read_bulk(self.stat_ep, &mut buffer, self.timeout)?;
-        let value = std::str::from_utf8(&buffer).unwrap();
-        Ok(value.to_string())
+    #[test]
+    fn configures_panel_and_sensors() {
+        let bytes = testing::capture(SupportedPrinters::SNBC, |p| {
+            Ok(p.panel_buttons(false)?
+                + p.panel_buttons(true)?
+                + p.paper_end_signal_sensors(true, false)?
+                + p.paper_end_signal_sensors(true, true)?
+                + p.stop_printing_sensors(true)?
+                + p.stop_printing_sensors(false)?)
+        })
+        .unwrap();
+        assert_hex(
+            &bytes,
+            "1b 63 35 01  1b 63 35 00
+             1b 63 33 03  1b 63 33 0f
+             1b 63 34 03  1b 63 34 00",
+        );
     }
 
-    pub fn get_printed_length(&mut self) -> Result<String, Error> {
-        self.write(&[0x1d, 0xe3]).unwrap();
-        let mut buffer = [0_u8; 8];
-        let _ = self
-            .handle
-            .read_bulk(self.stat_ep, &mut buffer, self.timeout)?;
-        let value = std::str::from_utf8(&buffer).unwrap();
-        Ok(value.to_string())
+    #[test]
+    fn feeds_in_motion_units() {
+        let bytes = testing::capture(SupportedPrinters::SNBC, |p| {
+            Ok(p.set_motion_units(180, 180)?
+                + p.feed_units(24)?
+                + p.reverse_feed_units(12)?
+                + p.feed_lines(3)?)
+        })
+        .unwrap();
+        assert_hex(&bytes, "1d 50 b4 b4  1b 4a 18  1b 4b 0c  1b 64 03");
+
+        // ESC d cuts in Star Line Mode
+        let bytes = testing::capture(SupportedPrinters::Star, |p| p.feed_lines(3)).unwrap();
+        assert_hex(&bytes, "1b 61 03");
     }
 
-    pub fn get_remaining_paper(&mut self) -> Result<String, Error> {
-        self.write(&[0x1d, 0xe1]).unwrap();
-        let mut buffer = [0_u8; 8];
-        let _ = self
-            .handle
-            .read_bulk(self.stat_ep, &mut buffer, self.timeout)?;
-        let value = std::str::from_utf8(&buffer).unwrap();
-        Ok(value.to_string())
+    #[test]
+    fn cuts_after_feed() {
+        let mut p = unopened(SupportedPrinters::SNBC);
+        let bytes = p.capture(|p| p.cut_after_feed(5)).unwrap();
+        assert_hex(&bytes, "1d 56 42 05");
+
+        for printer in [SupportedPrinters::P3, SupportedPrinters::Star] {
+            assert!(matches!(
+                testing::capture(printer, |p| p.cut_after_feed(5)),
+                Err(Error::Unsupported)
+            ));
+        }
+
+        p.profile.capabilities.cutter = false;
+        assert!(matches!(
+            p.capture(|p| p.cut_after_feed(5)),
+            Err(Error::Unsupported)
+        ));
     }
 
-    /// starting with a value in centimeters, calculate nH and nL as follows:
-    /// nH = <cm> / 256
-    /// nL = <cm> - (nH * 256)
-    ///
-    /// So if we wanted to calculated based on 15 meters:
-    /// 15m = 1500cm
-    /// nH = 1500 / 256 = 5
-    /// nL = 1500 - (nH * 256) = 1500 - (5 * 256) = 220
-    ///
-    /// Then convert to hex:
-    /// 5 = 0x05
-    /// 220 = 0xdc
-    pub fn set_paper_end_limit(&mut self) -> Result<(), Error> {
-        // TODO: what should we pass in, length in meters and then calculate?
-        let n_l: u8 = 0x00;
-        let n_h: u8 = 0x00;
-        self.write(&[0x1d, 0xe6, n_h, n_l]).unwrap();
-        Ok(())
+    #[test]
+    fn sizes_lines_to_the_paper() {
+        let mut p = unopened(SupportedPrinters::Generic);
+        p.profile.dots_per_line = Some(384);
+        let bytes = p.capture(|p| p.hr(0)).unwrap();
+        assert_eq!(bytes, [vec![0xc4; 32], vec![b'\n']].concat());
+        let bytes = p.capture(|p| p.columns("Tea", "1.00")).unwrap();
+        assert_eq!(bytes, format!("Tea{}1.00\n", " ".repeat(25)).as_bytes());
     }
 
-    pub fn paper_loaded(&mut self) -> Result<bool, Error> {
-        self.write(&[0x1d, 0x72, 0x01]).unwrap();
-        let mut buffer = [0_u8; 1];
-        let _ = self
-            .handle
-            .read_bulk(self.stat_ep, &mut buffer, self.timeout)?;
-        Ok(buffer[0] == 0x00_u8)
+    #[test]
+    fn drives_presenter() {
+        let bytes = testing::capture(SupportedPrinters::P3, |p| {
+            Ok(p.set_retract_timeout(30)?
+                + p.present_ticket(40)?
+                + p.retract_ticket()?
+                + p.eject_ticket()?)
+        })
+        .unwrap();
+        assert_hex(&bytes, "1d 65 12 1e  1d 65 03 28  1d 65 05 00  1d 65 0c 00");
+
+        // Refused before asking a printer that doesn't answer DLE EOT 0x14
+        let mut p = unopened(SupportedPrinters::SNBC);
+        assert!(matches!(p.present_ticket(40), Err(Error::Unsupported)));
+        assert!(matches!(p.presenter_status(), Err(Error::Unsupported)));
+        assert!(matches!(
+            p.wait_for_ticket_taken(Duration::from_secs(1)),
+            Err(Error::Unsupported)
+        ));
     }
 
-    // TODO: Flesh this out more
-    // So `0x10, 0x04, n` can get a few different status results:
-    // | n    | Type |
-    // |------|------|
-    // | 0x01 | device status |
-    // | 0x02 | off-line status |
-    // | 0x03 | error status |
-    // | 0x04 | paper roll sensor status |
-    // | 0x11 | print status |
-    // | 0x14 | full status |
-    // | 0x15 | device id |
-    //
-    // We should probably evaluate what we want to get and implement it here
-    // Below is an example using off-line status to get state of paper door
-    pub fn get_status(&mut self) -> Result<(), Vec<StatusError>> {
-        let mut errors: Vec<StatusError> = Vec::new();
-        let mut buffer = [0_u8; 16];
+    #[test]
+    fn prints_on_slip() {
+        let mut p = unopened(SupportedPrinters::SNBC);
+        assert!(matches!(
+            p.capture(|p| p.eject_slip()),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(
+            p.capture(|p| p.wait_for_slip(65, 0)),
+            Err(Error::InvalidArgument)
+        ));
+        let bytes = p
+            .capture(|p| {
+                Ok(p.select_station(Station::Slip)?
+                    + p.wait_for_slip(2, 5)?
+                    + p.print("Hi")?
+                    + p.eject_slip()?
+                    + p.select_station(Station::Receipt)?)
+            })
+            .unwrap();
+        assert_hex(&bytes, "1b 63 30 04  1b 66 02 05  48 69  0c  1b 63 30 02");
+    }
 
-        match self.printer {
-            SupportedPrinters::SNBC => {
-                if self.printer == SupportedPrinters::SNBC {
-                    match self.read(&mut buffer) {
-                        Ok(_) => (),
-                        Err(_) => {
-                            errors.push(StatusError::Communication);
-                            return Err(errors);
-                        }
-                    }
+    #[test]
+    fn defines_and_runs_macro() {
+        let mut p = unopened(SupportedPrinters::SNBC);
+        let bytes = p
+            .capture(|p| {
+                Ok(p.define_macro(|p| p.print("Hi").map(|_| ()))? + p.run_macro(2, 5, true)?)
+            })
+            .unwrap();
+        assert_hex(&bytes, "1d 3a 48 69 1d 3a  1d 5e 02 05 01");
+        // Lost on power cycles, so resent with the session state
+        let bytes = p.capture(|p| p.resync()).unwrap();
+        assert_hex(&bytes, "1d 3a 48 69 1d 3a");
 
-                    // First Byte
-                    if ((buffer[0] >> OFFLINE_BIT) & 1) == 1 {
-                        errors.push(StatusError::Offline);
-                    } else {
-                        errors.push(StatusError::Online);
-                    }
-                    if ((buffer[0] >> DOOR_STATUS_BIT) & 1) == 1 {
-                        errors.push(StatusError::DoorOpen);
-                    }
-                    if ((buffer[0] >> PAPER_FEED_BIT) & 1) == 1 {
-                        errors.push(StatusError::PaperFeed);
-                    }
+        // The definition is still ended, but the printer drops it
+        let long = "x".repeat(MACRO_MAX_LEN + 1);
+        p.capture(|p| {
+            assert!(matches!(
+                p.define_macro(|p| p.print(&long).map(|_| ())),
+                Err(Error::InvalidArgument)
+            ));
+            Ok(0)
+        })
+        .unwrap();
+        assert_eq!(p.session.macro_data, None);
+    }
 
-                    // Second Byte
-                    if ((buffer[1] >> AUTO_CUTTER_BIT) & 1) == 1 {
-                        errors.push(StatusError::AutoCutter);
-                    }
-                    if ((buffer[1] >> RECOVERABLE_BIT) & 1) == 1 {
-                        errors.push(StatusError::Recoverable);
-                    }
-                    if ((buffer[1] >> AUTOMATIC_RECOVERABLE_BIT) & 1) == 1 {
-                        errors.push(StatusError::AutomaticallyRecoverable);
-                    }
+    #[test]
+    fn sends_real_time_functions() {
+        let bytes = testing::capture(SupportedPrinters::SNBC, |p| {
+            Ok(p.real_time_pulse(DrawerPin::Pin5, 250)?
+                + p.real_time_buzzer(1, 3, 5, 2)?
+                + p.clear_buffers()?
+                + p.power_off()?)
+        })
+        .unwrap();
+        assert_hex(
+            &bytes,
+            "10 14 01 01 03
+             10 14 03 01 01 03 05 02
+             10 14 08 01 03 14 01 06 02 08
+             10 14 02 01 08",
+        );
 
-                    // Third Byte
-                    if ((buffer[2] >> PAPER_NEAR_END_BIT) & 0b11) == 0b11 {
-                        errors.push(StatusError::PaperNearEnd);
-                    }
-                    if ((buffer[2] >> PAPER_BIT) & 0b11) == 0b11 {
-                        errors.push(StatusError::PaperEnd);
-                    }
-                    // Fourth byte seems to be unused
-                }
-            }
-            SupportedPrinters::Epic => {
-                let mut data_in = [0_u8; 16];
-                let mut i: i32 = 0;
-                while i < 4 {
-                    let cmd = [0x1B_u8, 0x40, 0x10, 0x04, (i + 1) as u8];
-                    match self.handle.write_bulk(self.cmd_ep, &cmd, self.timeout) {
-                        Ok(_) => (),
-                        Err(_) => errors.push(StatusError::Communication),
-                    }
-                    match self.handle.read_bulk(
-                        self.stat_ep,
-                        &mut data_in[(i as usize)..],
-                        self.timeout,
-                    ) {
-                        Ok(transferred) => {
-                            if transferred != 1 {
-                                errors.push(StatusError::Communication);
-                            }
-                        }
-                        Err(_) => errors.push(StatusError::Communication),
-                    }
-                    i += 1;
-                }
-                if data_in[EPIC_STATUS_BYTE_0] >> EPIC_STATUS_OFFLINE_BIT == 1 {
-                    errors.push(StatusError::Offline)
-                };
-                if data_in[EPIC_STATUS_BYTE_1] >> EPIC_STATUS_COVER_OPEN_BIT == 1 {
-                    errors.push(StatusError::DoorOpen)
-                };
-                if data_in[EPIC_STATUS_BYTE_1] >> EPIC_STATUS_PAPER_END_BIT == 1 {
-                    errors.push(StatusError::PaperEnd)
-                };
-                if data_in[EPIC_STATUS_BYTE_2] >> EPIC_STATUS_AUTO_CUTTER_BIT == 1 {
-                    errors.push(StatusError::AutoCutter)
-                };
-            }
-            SupportedPrinters::P3 => (),
-            SupportedPrinters::Unknown => (),
+        let mut p = unopened(SupportedPrinters::SNBC);
+        for on_time_ms in [0, 801] {
+            assert!(matches!(
+                p.capture(|p| p.real_time_pulse(DrawerPin::Pin2, on_time_ms)),
+                Err(Error::InvalidArgument)
+            ));
+        }
+        for (pattern, times, on_time, off_time) in [(8, 1, 1, 1), (1, 64, 1, 1), (1, 1, 51, 1)] {
+            assert!(matches!(
+                p.capture(|p| p.real_time_buzzer(pattern, times, on_time, off_time)),
+                Err(Error::InvalidArgument)
+            ));
         }
 
-        if !errors.is_empty() {
-            return Err(errors);
+        // Skips the resync, which would queue up behind the offline printer
+        p.capture(|p| p.code_page(17)).unwrap();
+        p.session.needs_resync = true;
+        let bytes = p.capture(|p| p.clear_buffers()).unwrap();
+        assert_hex(&bytes, "10 14 08 01 03 14 01 06 02 08");
+        assert!(p.session.needs_resync);
+        for printer in [SupportedPrinters::Star, SupportedPrinters::P3] {
+            let mut p = unopened(printer);
+            assert!(matches!(
+                p.capture(|p| p.clear_buffers()),
+                Err(Error::Unsupported)
+            ));
         }
-        Ok(())
+
+        // Inside a Printer::batch it goes to the device right away instead
+        // of waiting in the batch, which fails (or panics, without libusb)
+        // here
+        let mut p = unopened(SupportedPrinters::SNBC);
+        (p.batching, p.in_batch) = (true, true);
+        let sent = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| p.clear_buffers()));
+        assert!(!matches!(sent, Ok(Ok(_))));
+        assert!(p.batch.is_empty());
     }
 
-    pub fn read(&mut self, buf: &mut [u8; 16]) -> Result<usize, Error> {
-        let transferred = self.handle.read_bulk(self.stat_ep, buf, self.timeout)?;
-        Ok(transferred)
+    #[test]
+    fn speaks_star_line_mode() {
+        let mut p = unopened(SupportedPrinters::Star);
+        let bytes = p
+            .capture(|p| {
+                Ok(p.align("ct")?
+                    + p.font("b")?
+                    + p.style("b")?
+                    + p.reverse(true)?
+                    + p.size(2, 2)?
+                    + p.cut(CutMode::Partial, Some(5))?
+                    + p.cut(CutMode::Full, None)?)
+            })
+            .unwrap();
+        assert_hex(
+            &bytes,
+            "1b 1d 61 01  1b 1e 46 01  1b 2d 00 1b 45  1b 34  1b 69 01 01
+             1b 4a 14 1b 64 03  1b 64 02",
+        );
+
+        // One b n1 n2 line per raster row, black is printed
+        let image = Image::from(image::DynamicImage::new_luma8(8, 2));
+        let bytes = p.capture(|p| p.raster(&image, None)).unwrap();
+        assert_hex(&bytes, "1b 2a 72 41  62 01 00 ff  62 01 00 ff  1b 2a 72 42");
+
+        // ESC i takes 0 to 5
+        let bytes = p.capture(|p| p.char_size(0x52)).unwrap();
+        assert_hex(&bytes, "1b 69 02 05");
+        assert!(matches!(p.char_size(0x06), Err(Error::InvalidArgument)));
+        assert!(matches!(p.char_size(0x60), Err(Error::InvalidArgument)));
     }
 
-    pub fn has_asb_capability(&self) -> bool {
-        matches!(self.printer, SupportedPrinters::SNBC)
+    #[test]
+    fn speaks_bixolon_and_citizen() {
+        assert_eq!(
+            match_manufacturer(0x1504, 0x0006, Some("BIXOLON")),
+            Some(SupportedPrinters::Bixolon)
+        );
+        assert_eq!(
+            match_manufacturer(0x1d90, 0x2060, Some("CITIZEN")),
+            Some(SupportedPrinters::Citizen)
+        );
+        // A full cut, which the SRP-350 can't do
+        let bytes =
+            testing::capture(SupportedPrinters::Citizen, |p| p.cut(CutMode::Full, None)).unwrap();
+        assert_hex(&bytes, "1d 56 41 00");
+
+        #[cfg(feature = "qrcode")]
+        {
+            let bytes = testing::capture(SupportedPrinters::Bixolon, |p| {
+                p.qrcode("AB", None, "M", Some(4))
+            })
+            .unwrap();
+            assert_hex(&bytes, "1d 5a 02  1b 5a 03 4d 04 02 00 41 42");
+            // GS ( k instead
+            let bytes = testing::capture(SupportedPrinters::Citizen, |p| {
+                p.qrcode("AB", None, "M", Some(4))
+            })
+            .unwrap();
+            assert_hex(
+                &bytes,
+                "1d 28 6b 04 00 31 41 32 00
+                 1d 28 6b 03 00 31 43 04
+                 1d 28 6b 03 00 31 45 31
+                 1d 28 6b 05 00 31 50 30 41 42
+                 1d 28 6b 03 00 31 51 30",
+            );
+        }
+    }
+
+    #[test]
+    fn batches_commands() {
+        let mut p = unopened(SupportedPrinters::SNBC);
+        p.batch(|p| {
+            let n = p.style("bu")? + p.size(2, 1)? + p.print("Hi")?;
+            // Nothing was transferred, everything waits in one batch
+            assert_hex(&p.batch, "1b 45 01 1b 2d 01  1b 21 00 1b 21 20  48 69");
+            assert!(!p.is_connected());
+            p.batch.clear();
+            Ok(n)
+        })
+        .unwrap();
+        assert!(!p.batching);
+
+        // A nested batch is part of the open one
+        let bytes = p
+            .capture(|p| p.batch(|p| Ok(p.print("A")? + p.batch(|p| p.print("B"))?)))
+            .unwrap();
+        assert_hex(&bytes, "41 42");
+        assert!(!p.batching);
     }
 }
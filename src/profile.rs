@@ -0,0 +1,686 @@
+//! Printer profiles describing how a model deviates from its base
+//! [SupportedPrinters] dialect, see
+//! [Printer::set_profile](crate::printer::Printer::set_profile)
+//!
+//! Profiles can be loaded at runtime so a new model can be supported without
+//! a new release, from JSON with the `json_profiles` feature or TOML with the
+//! `toml_profiles` feature:
+//!
+//! ```toml
+//! name = "Acme TP-80"
+//! base = "Generic"
+//! dots_per_line = 576
+//! sale_order = ["cut", "drawer", "buzzer"]
+//!
+//! [code_pages]
+//! ibm437 = 0
+//! windows-1252 = 16
+//!
+//! [quirks]
+//! raster_only = true
+//!
+//! [capabilities]
+//! qr_code = false
+//!
+//! [barcode]
+//! height = 80
+//! position = "Off"
+//!
+//! [commands]
+//! full_cut = "1b 69"
+//! partial_cut = "1b 6d"
+//!
+//! [throttle]
+//! bytes_per_second = 9600
+//!
+//! [cut_feed]
+//! lines_before = 0
+//! mm_after = 2
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::barcode::{Font, TextPosition};
+use crate::printer::{Quirks, SupportedPrinters};
+
+/// Commands whose bytes a profile can replace, by [Printer](crate::printer::Printer)
+/// method name. Only commands without arguments can be replaced.
+pub const OVERRIDABLE_COMMANDS: &[&str] = &[
+    "hwinit",
+    "enable",
+    "disable",
+    "full_cut",
+    "partial_cut",
+    "retract_ticket",
+    "eject_ticket",
+    "eject_slip",
+    "eject_check",
+    "power_off",
+    "clear_buffers",
+];
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProfileError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "json_profiles")]
+    #[error("Invalid JSON profile: {0}")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "toml_profiles")]
+    #[error("Invalid TOML profile: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Unsupported profile format: {0}")]
+    UnknownFormat(String),
+    #[error("Command {0} can't be overridden")]
+    UnknownCommand(String),
+    #[error("Invalid hex bytes for command {0}")]
+    InvalidHex(String),
+    #[error("Throttle limits must not be zero")]
+    InvalidThrottle,
+}
+
+/// Barcode settings of [Printer::barcode_simple]
+///
+/// [Printer::barcode_simple]: crate::printer::Printer::barcode_simple
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct BarcodeDefaults {
+    /// Module width in dots
+    pub width: u8,
+    /// Height in dots
+    pub height: u8,
+    /// Where the human readable text goes
+    pub position: TextPosition,
+    pub font: Font,
+}
+
+impl Default for BarcodeDefaults {
+    fn default() -> Self {
+        BarcodeDefaults::from(SupportedPrinters::Generic)
+    }
+}
+
+impl From<SupportedPrinters> for BarcodeDefaults {
+    fn from(printer: SupportedPrinters) -> Self {
+        BarcodeDefaults {
+            // The default module width each model documents, a wider Epic
+            // barcode runs off the paper
+            width: match printer {
+                SupportedPrinters::P3 => 3,
+                SupportedPrinters::Epic => 1,
+                _ => 2,
+            },
+            // 20.25 mm at 8 dots per mm
+            height: 162,
+            position: TextPosition::Below,
+            font: Font::FontA,
+        }
+    }
+}
+
+/// Optional hardware, a profile turns these off for models without it
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Capabilities {
+    pub cutter: bool,
+    pub drawer: bool,
+    pub qr_code: bool,
+    /// GS : macros, used to keep the [Printer::set_header] header on the
+    /// printer
+    ///
+    /// [Printer::set_header]: crate::printer::Printer::set_header
+    pub macros: bool,
+    /// Whether the printer has the Cyrillic code pages of its dialect, see
+    /// [cyrillic_code_page]
+    pub cyrillic: bool,
+    /// A buzzer for [Printer::finish_sale] to sound
+    ///
+    /// [Printer::finish_sale]: crate::printer::Printer::finish_sale
+    pub buzzer: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            cutter: true,
+            drawer: true,
+            qr_code: true,
+            macros: true,
+            cyrillic: true,
+            buzzer: true,
+        }
+    }
+}
+
+/// ESC t page number of `ibm866` and `windows-1251` in the dialects that
+/// have them, used by [Profile::code_page] for profiles that don't list them
+///
+/// The numbers are the ones Epson assigns (PC866 is 17, WPC1251 is 46),
+/// which the ESC/POS clones share, and Star's for Star Line Mode. Ukrainian
+/// needs `windows-1251`, PC866 has no `і`.
+pub fn cyrillic_code_page(printer: SupportedPrinters, encoding: &str) -> Option<u8> {
+    match (printer, encoding) {
+        (SupportedPrinters::P3 | SupportedPrinters::Unknown, _) => None,
+        (SupportedPrinters::Star, "ibm866") => Some(10),
+        (SupportedPrinters::Star, "windows-1251") => Some(34),
+        (_, "ibm866") => Some(17),
+        (_, "windows-1251") => Some(46),
+        _ => None,
+    }
+}
+
+/// ESC t page number of the JIS X 0201 katakana page, [kana::ENCODING],
+/// used by [Profile::code_page] for profiles that don't list it
+///
+/// Katakana is page 1 in the Epson table and page 2 in Star's. Nearly every
+/// model has it, so unlike the Cyrillic and Thai pages no capability turns
+/// it off.
+///
+/// [kana::ENCODING]: crate::kana::ENCODING
+pub fn katakana_code_page(printer: SupportedPrinters, encoding: &str) -> Option<u8> {
+    match (printer, encoding) {
+        (SupportedPrinters::P3 | SupportedPrinters::Unknown, _) => None,
+        (SupportedPrinters::Star, crate::kana::ENCODING) => Some(2),
+        (_, crate::kana::ENCODING) => Some(1),
+        _ => None,
+    }
+}
+
+/// Limits how fast data is sent, for Bluetooth and serial printers that
+/// silently drop data when flooded
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Throttle {
+    /// Average rate cap, for example the baud rate divided by 10
+    BytesPerSecond(u32),
+    /// Pause for `delay_ms` after every `bytes` bytes
+    DelayAfter { bytes: usize, delay_ms: u64 },
+}
+
+/// A step of [Printer::finish_sale], see [Profile::sale_order]
+///
+/// [Printer::finish_sale]: crate::printer::Printer::finish_sale
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SaleStep {
+    Drawer,
+    /// The cut, or the feed when the receipt isn't cut
+    Cut,
+    Buzzer,
+}
+
+/// Order of the steps of a sale when the profile doesn't change it
+pub const DEFAULT_SALE_ORDER: [SaleStep; 3] = [SaleStep::Drawer, SaleStep::Cut, SaleStep::Buzzer];
+
+/// Paper fed around a cut, see [Profile::cut_feed]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct CutFeed {
+    /// Line feeds before the cut
+    pub lines_before: u8,
+    /// Millimetres fed past the cutting position before cutting, at most 31
+    /// at 203 dpi, see [Profile::dpi]
+    pub mm_after: u8,
+}
+
+impl CutFeed {
+    /// The three line feeds [Printer::full_cut] and [Printer::partial_cut]
+    /// always sent before profiles could change them
+    ///
+    /// [Printer::full_cut]: crate::printer::Printer::full_cut
+    /// [Printer::partial_cut]: crate::printer::Printer::partial_cut
+    pub const LEGACY: CutFeed = CutFeed {
+        lines_before: 3,
+        mm_after: 0,
+    };
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Profile {
+    pub name: String,
+    /// Built-in dialect used for everything the profile doesn't change
+    pub base: SupportedPrinters,
+    /// Printable width in dots
+    pub dots_per_line: Option<u16>,
+    /// Print resolution in dots per inch, 203 when unset. Impact printers
+    /// are below 100.
+    pub dpi: Option<u16>,
+    /// ESC t page number of each encoding the printer has, by encoding name
+    /// (`ibm437`, `windows-1252`...)
+    pub code_pages: BTreeMap<String, u8>,
+    /// Replaces the quirks of the base dialect, see [Profile::quirks]
+    pub quirks: Option<Quirks>,
+    pub capabilities: Capabilities,
+    /// Replacement bytes as hex by command name, see [OVERRIDABLE_COMMANDS]
+    pub commands: BTreeMap<String, String>,
+    /// Pacing for printers that can't keep up with the transport
+    pub throttle: Option<Throttle>,
+    /// Encodings each model has, by model name prefix, checked before
+    /// [MODEL_ENCODINGS], see [Profile::restrict_code_pages]
+    pub model_encodings: BTreeMap<String, Vec<String>>,
+    /// Replaces the barcode settings of the base dialect, see
+    /// [Profile::barcode_defaults]
+    pub barcode: Option<BarcodeDefaults>,
+    /// Paper fed around cuts, [CutFeed::LEGACY] for
+    /// [Printer::full_cut] and [Printer::partial_cut] and none for the cuts
+    /// of jobs when unset. A job's own [Job::cut_feed] replaces it.
+    ///
+    /// [Printer::full_cut]: crate::printer::Printer::full_cut
+    /// [Printer::partial_cut]: crate::printer::Printer::partial_cut
+    /// [Job::cut_feed]: crate::job::Job::cut_feed
+    pub cut_feed: Option<CutFeed>,
+    /// Tallest page mode area in dots, [DEFAULT_PAGE_HEIGHT] when unset,
+    /// see [Printer::begin_page](crate::printer::Printer::begin_page)
+    pub page_height: Option<u16>,
+    /// Order of the steps of
+    /// [Printer::finish_sale](crate::printer::Printer::finish_sale),
+    /// [DEFAULT_SALE_ORDER] when unset, see [Profile::sale_order]
+    pub sale_order: Option<Vec<SaleStep>>,
+}
+
+/// Page mode height of most 80 mm printers, about 208 mm at 203 dpi
+pub const DEFAULT_PAGE_HEIGHT: u16 = 1662;
+
+/// Encodings of models with fewer code pages than their siblings, by the
+/// prefix of the model name they report with GS I
+///
+/// From the ESC t character code table of each model's manual: the Epson
+/// TM-T20 and TM-T20II Technical Reference Guides, the TM-U220 and TM-P20
+/// Technical Reference Guides and the SNBC BTP-R180 Programming Manual.
+pub const MODEL_ENCODINGS: &[(&str, &[&str])] = &[
+    (
+        "TM-T20",
+        &[
+            "ibm437",
+            crate::kana::ENCODING,
+            "ibm850",
+            "ibm860",
+            "ibm863",
+            "ibm865",
+            "windows-1252",
+            "ibm866",
+            "ibm852",
+            "ibm858",
+        ],
+    ),
+    ("TM-U220", &["ibm437", "ibm850", "ibm866"]),
+    ("TM-P20", &["ibm437", "ibm850", "windows-1252"]),
+    ("BTP-R180", &["ibm437", "ibm866", "windows-1252"]),
+];
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::from(SupportedPrinters::Generic)
+    }
+}
+
+impl From<SupportedPrinters> for Profile {
+    fn from(printer: SupportedPrinters) -> Self {
+        Profile {
+            name: format!("{:?}", printer),
+            base: printer,
+            dots_per_line: None,
+            dpi: None,
+            code_pages: BTreeMap::new(),
+            quirks: None,
+            capabilities: Capabilities {
+                // Star Line Mode has no macros
+                macros: printer != SupportedPrinters::Star,
+                ..Capabilities::default()
+            },
+            commands: BTreeMap::new(),
+            throttle: None,
+            model_encodings: BTreeMap::new(),
+            barcode: None,
+            cut_feed: None,
+            page_height: None,
+            sale_order: None,
+        }
+    }
+}
+
+impl Profile {
+    /// Steps of a sale in the order the profile gives, followed by the ones
+    /// it leaves out in the default order
+    pub fn sale_order(&self) -> Vec<SaleStep> {
+        let mut order = self.sale_order.clone().unwrap_or_default();
+        for step in DEFAULT_SALE_ORDER {
+            if !order.contains(&step) {
+                order.push(step);
+            }
+        }
+        order
+    }
+
+    /// Loads a profile, picking the format from the file extension
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Profile, ProfileError> {
+        let path = path.as_ref();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            #[cfg(feature = "json_profiles")]
+            Some("json") => Profile::from_json(&std::fs::read_to_string(path)?),
+            #[cfg(feature = "toml_profiles")]
+            Some("toml") => Profile::from_toml(&std::fs::read_to_string(path)?),
+            ext => Err(ProfileError::UnknownFormat(
+                ext.unwrap_or_default().to_string(),
+            )),
+        }
+    }
+
+    #[cfg(feature = "json_profiles")]
+    pub fn from_json(json: &str) -> Result<Profile, ProfileError> {
+        let profile: Profile = serde_json::from_str(json)?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    #[cfg(feature = "toml_profiles")]
+    pub fn from_toml(toml: &str) -> Result<Profile, ProfileError> {
+        let profile: Profile = toml::from_str(toml)?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    /// Quirks of the profile, or of its base dialect when it sets none
+    pub fn quirks(&self) -> Quirks {
+        self.quirks.unwrap_or_else(|| self.base.quirks())
+    }
+
+    /// Characters of font that fit one line at size times the normal width,
+    /// on [Profile::dots_per_line] or 576 dots
+    ///
+    /// Font A and Standard are 12 dots wide, font B and Compressed 9, so 80
+    /// mm paper fits 48 and 64 characters and 512 dots fit 42.
+    pub fn chars_per_line(&self, font: Font, size: u8) -> usize {
+        let cell = match font {
+            Font::FontA | Font::Standard => 12,
+            Font::FontB | Font::Compressed => 9,
+        };
+        let dots = self.dots_per_line.unwrap_or(576) as usize;
+        (dots / (cell * size.max(1) as usize)).max(1)
+    }
+
+    /// Barcode settings of the profile, or of its base dialect when it sets
+    /// none
+    pub fn barcode_defaults(&self) -> BarcodeDefaults {
+        self.barcode
+            .unwrap_or_else(|| BarcodeDefaults::from(self.base))
+    }
+
+    /// Checks that every command override can be used and the throttle
+    /// can be kept
+    pub fn validate(&self) -> Result<(), ProfileError> {
+        if let Some(Throttle::BytesPerSecond(0) | Throttle::DelayAfter { bytes: 0, .. }) =
+            self.throttle
+        {
+            return Err(ProfileError::InvalidThrottle);
+        }
+        for (name, hex) in &self.commands {
+            if !OVERRIDABLE_COMMANDS.contains(&name.as_str()) {
+                return Err(ProfileError::UnknownCommand(name.clone()));
+            }
+            parse_hex(hex).ok_or_else(|| ProfileError::InvalidHex(name.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// Bytes replacing the command, if the profile overrides it
+    pub fn command(&self, name: &str) -> Option<Vec<u8>> {
+        self.commands.get(name).and_then(|hex| parse_hex(hex))
+    }
+
+    /// ESC t page number for the encoding, from [Profile::code_pages] or,
+    /// for Cyrillic and katakana ones, [cyrillic_code_page] and
+    /// [katakana_code_page]
+    ///
+    /// There is no built in page for `windows-874` (Thai): Epson's pages 20
+    /// to 26 are its own Thai Character Code tables (42, 11, 13, 14, 16, 17
+    /// and 18) rather than TIS-620, and the clones number their Thai pages
+    /// differently, so Thai printers list the page in [Profile::code_pages].
+    pub fn code_page(&self, encoding: &str) -> Option<u8> {
+        self.code_pages
+            .get(encoding)
+            .copied()
+            .or_else(|| self.builtin_code_page(encoding))
+    }
+
+    /// The dialect's page for encoding, when the capabilities allow it
+    fn builtin_code_page(&self, encoding: &str) -> Option<u8> {
+        let cyrillic = self.capabilities.cyrillic;
+        cyrillic
+            .then(|| cyrillic_code_page(self.base, encoding))
+            .flatten()
+            .or_else(|| katakana_code_page(self.base, encoding))
+    }
+
+    /// Encodings model has, from [Profile::model_encodings] or else
+    /// [MODEL_ENCODINGS], None for models neither knows
+    pub fn model_encodings(&self, model: &str) -> Option<Vec<String>> {
+        let found = self
+            .model_encodings
+            .iter()
+            .find(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .map(|(_, encodings)| encodings.clone());
+        found.or_else(|| {
+            MODEL_ENCODINGS
+                .iter()
+                .find(|(prefix, _)| model.starts_with(prefix))
+                .map(|(_, encodings)| encodings.iter().map(|e| e.to_string()).collect())
+        })
+    }
+
+    /// Drops the [Profile::code_pages] model doesn't have, so text isn't
+    /// encoded for a page the printer would print as garbage
+    ///
+    /// Returns whether the model is known, unknown models keep every page.
+    /// The Cyrillic pages of a known model are listed in [Profile::code_pages]
+    /// and [Capabilities::cyrillic] turned off, so only the ones it has are
+    /// used.
+    pub fn restrict_code_pages(&mut self, model: &str) -> bool {
+        let Some(encodings) = self.model_encodings(model) else {
+            return false;
+        };
+        for encoding in &encodings {
+            if let Some(n) = self.builtin_code_page(encoding) {
+                self.code_pages.entry(encoding.clone()).or_insert(n);
+            }
+        }
+        self.capabilities.cyrillic = false;
+        self.code_pages
+            .retain(|encoding, _| encodings.iter().any(|e| e == encoding));
+        true
+    }
+}
+
+/// Parses whitespace separated hex bytes, `"1d 56 01"`
+pub(crate) fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.split_whitespace()
+        .map(|byte| u8::from_str_radix(byte.trim_start_matches("0x"), 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_overrides() {
+        let mut profile = Profile::from(SupportedPrinters::Generic);
+        profile
+            .commands
+            .insert("full_cut".to_string(), "1b 69".to_string());
+        assert!(profile.validate().is_ok());
+        assert_eq!(profile.command("full_cut"), Some(vec![0x1b, 0x69]));
+        assert_eq!(profile.command("partial_cut"), None);
+
+        profile
+            .commands
+            .insert("partial_cut".to_string(), "1b zz".to_string());
+        assert!(matches!(
+            profile.validate(),
+            Err(ProfileError::InvalidHex(name)) if name == "partial_cut"
+        ));
+
+        profile.commands.clear();
+        profile
+            .commands
+            .insert("barcode".to_string(), "00".to_string());
+        assert!(matches!(
+            profile.validate(),
+            Err(ProfileError::UnknownCommand(_))
+        ));
+    }
+
+    #[test]
+    fn counts_chars_per_line() {
+        let mut profile = Profile::default();
+        assert_eq!(profile.chars_per_line(Font::FontA, 1), 48);
+        assert_eq!(profile.chars_per_line(Font::FontB, 1), 64);
+        assert_eq!(profile.chars_per_line(Font::Standard, 2), 24);
+        profile.dots_per_line = Some(512);
+        assert_eq!(profile.chars_per_line(Font::FontA, 0), 42);
+        assert_eq!(profile.chars_per_line(Font::FontA, 8), 5);
+    }
+
+    #[test]
+    fn restricts_code_pages_by_model() {
+        let mut profile = Profile::from(SupportedPrinters::Generic);
+        for (n, encoding) in ["ibm437", "ibm866", "windows-1251"].iter().enumerate() {
+            profile.code_pages.insert(encoding.to_string(), n as u8);
+        }
+        assert!(!profile.clone().restrict_code_pages("Acme 80"));
+
+        let mut t20 = profile.clone();
+        assert!(t20.restrict_code_pages("TM-T20II"));
+        assert_eq!(t20.code_page("windows-1251"), None);
+        assert_eq!(t20.code_page("ibm866"), Some(1));
+
+        // The built-in Cyrillic pages of the dialect
+        let mut generic = Profile::from(SupportedPrinters::Generic);
+        assert_eq!(generic.code_page("windows-1251"), Some(46));
+        assert_eq!(
+            Profile::from(SupportedPrinters::Star).code_page("ibm866"),
+            Some(10)
+        );
+        assert!(generic.restrict_code_pages("TM-T20II"));
+        assert_eq!(generic.code_page("ibm866"), Some(17));
+        assert_eq!(generic.code_page("windows-1251"), None);
+        assert_eq!(generic.code_page(crate::kana::ENCODING), Some(1));
+        generic.code_pages.clear();
+        assert_eq!(generic.code_page("ibm866"), None);
+
+        // Thai pages come from the profile only
+        let mut thai = Profile::from(SupportedPrinters::Generic);
+        assert_eq!(thai.code_page("windows-874"), None);
+        thai.code_pages.insert("windows-874".to_string(), 70);
+        assert_eq!(thai.code_page("windows-874"), Some(70));
+
+        profile
+            .model_encodings
+            .insert("TM-T20".to_string(), vec!["windows-1251".to_string()]);
+        assert!(profile.restrict_code_pages("TM-T20II"));
+        assert_eq!(profile.code_pages.len(), 1);
+    }
+
+    #[cfg(feature = "toml_profiles")]
+    #[test]
+    fn from_toml() {
+        let profile = Profile::from_toml(
+            r#"
+            name = "Acme TP-80"
+            base = "Generic"
+            dots_per_line = 576
+            sale_order = ["cut", "drawer"]
+
+            [code_pages]
+            ibm437 = 0
+
+            [quirks]
+            no_code93 = false
+
+            [capabilities]
+            qr_code = false
+
+            [barcode]
+            height = 80
+
+            [commands]
+            full_cut = "1b 69"
+
+            [throttle.delay_after]
+            bytes = 512
+            delay_ms = 50
+
+            [cut_feed]
+            mm_after = 2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(profile.base, SupportedPrinters::Generic);
+        assert_eq!(profile.dots_per_line, Some(576));
+        assert_eq!(profile.code_page("ibm437"), Some(0));
+        assert!(!profile.quirks().no_code93);
+        assert!(!profile.capabilities.qr_code);
+        assert!(profile.capabilities.cutter);
+        assert_eq!(profile.barcode_defaults().height, 80);
+        assert_eq!(profile.barcode_defaults().width, 2);
+        assert_eq!(
+            Profile::from(SupportedPrinters::P3)
+                .barcode_defaults()
+                .width,
+            3
+        );
+        assert_eq!(profile.command("full_cut"), Some(vec![0x1b, 0x69]));
+        assert_eq!(
+            profile.throttle,
+            Some(Throttle::DelayAfter {
+                bytes: 512,
+                delay_ms: 50
+            })
+        );
+        assert_eq!(
+            profile.cut_feed,
+            Some(CutFeed {
+                lines_before: 0,
+                mm_after: 2
+            })
+        );
+        assert_eq!(
+            profile.sale_order(),
+            [SaleStep::Cut, SaleStep::Drawer, SaleStep::Buzzer]
+        );
+    }
+
+    #[cfg(feature = "json_profiles")]
+    #[test]
+    fn from_json() {
+        let profile = Profile::from_json(r#"{"base": "Citizen", "dots_per_line": 576}"#).unwrap();
+        assert_eq!(profile.base, SupportedPrinters::Citizen);
+        assert_eq!(profile.quirks(), SupportedPrinters::Citizen.quirks());
+        assert!(profile.commands.is_empty());
+    }
+}
@@ -0,0 +1,728 @@
+//! Background printing through a job queue
+//!
+//! A [PrintQueue] owns the printer on a worker thread. Jobs are printed by
+//! [Priority], in the order they were pushed within a priority, queued
+//! again with a growing delay when they fail with a
+//! [transient](crate::printer::Error::is_transient) error, and can be
+//! followed or cancelled by their [JobId]. See
+//! [JobOptions] for time limits and deduplication, and
+//! [PrintQueue::with_spool] for keeping jobs across restarts.
+//!
+//! ```no_run
+//! use posify::document::Document;
+//! use posify::job::Job;
+//! use posify::printer::{Printer, SupportedPrinters};
+//! use posify::queue::{JobStatus, PrintQueue};
+//!
+//! let printer = Printer::new(None, None, SupportedPrinters::SNBC, 0x0483, 0x5743)?;
+//! let queue = PrintQueue::new(printer);
+//!
+//! let mut doc = Document::new();
+//! doc.text("Order 42").cut();
+//! let id = queue.push(Job::from(&doc));
+//! assert_eq!(queue.wait(id), Some(JobStatus::Done));
+//! # Ok::<(), posify::printer::Error>(())
+//! ```
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::job::Job;
+use crate::printer::{Error, HealthReport, Printer};
+use crate::spool::Spool;
+
+/// Anything a [PrintQueue] can print on
+pub trait Target: Send + 'static {
+    fn submit(&mut self, job: &Job) -> Result<usize, Error>;
+
+    /// State of the device, [Error::Unsupported] when it can't tell
+    fn health_check(&mut self) -> Result<HealthReport, Error> {
+        Err(Error::Unsupported)
+    }
+}
+
+impl Target for Printer {
+    fn submit(&mut self, job: &Job) -> Result<usize, Error> {
+        Printer::submit(self, job)
+    }
+
+    fn health_check(&mut self) -> Result<HealthReport, Error> {
+        Printer::health_check(self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JobId(u64);
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JobStatus {
+    Queued,
+    Printing,
+    Done,
+    /// Failed with this error, after any retries
+    Failed(String),
+    Cancelled,
+    /// Still queued when its time to live ran out
+    Expired,
+}
+
+impl JobStatus {
+    /// Whether the job won't change status any more
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Done | JobStatus::Failed(_) | JobStatus::Cancelled | JobStatus::Expired
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Priority {
+    /// Reprints and other jobs that can wait
+    Low,
+    #[default]
+    Normal,
+    /// Fiscal receipts and other jobs that must not wait behind others
+    High,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct JobOptions {
+    pub priority: Priority,
+    /// Longest the job may wait in the queue before it's dropped as
+    /// [JobStatus::Expired], a kitchen ticket that prints an hour late is
+    /// worse than none
+    pub ttl: Option<Duration>,
+    /// Idempotency key, pushing a job with the key of a recent job returns
+    /// that job instead of printing twice. Jobs that failed, were cancelled
+    /// or expired can be pushed again.
+    pub key: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct QueueConfig {
+    /// Attempts after the first one for transient errors
+    pub retries: u32,
+    /// Wait before the first retry, doubled for every retry after it
+    pub retry_delay: Duration,
+    /// Longest wait between retries
+    pub max_retry_delay: Duration,
+    /// How long idempotency keys are remembered, see [JobOptions::key]
+    pub key_lifetime: Duration,
+    /// Finished jobs whose status is kept, [PrintQueue::status] of older
+    /// ones is None
+    pub kept_statuses: usize,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            retries: 3,
+            retry_delay: Duration::from_secs(1),
+            max_retry_delay: Duration::from_secs(60),
+            key_lifetime: Duration::from_secs(600),
+            kept_statuses: 1000,
+        }
+    }
+}
+
+struct Pending {
+    id: JobId,
+    job: Job,
+    priority: Priority,
+    expires: Option<Instant>,
+    /// Failed attempts so far
+    attempts: u32,
+    /// Not printed before this, set when a failed job is queued again
+    retry_at: Option<Instant>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    /// Highest priority first
+    pending: VecDeque<Pending>,
+    /// Job and time it was pushed by idempotency key
+    keys: HashMap<String, (JobId, Instant)>,
+    status: HashMap<JobId, JobStatus>,
+    /// Finished jobs, oldest first, and how many of them to keep
+    finished: VecDeque<JobId>,
+    kept_statuses: usize,
+    /// Printing jobs that mustn't be retried
+    cancelled: HashSet<JobId>,
+    shutdown: bool,
+    /// Logs the jobs until they finish, job ids are spool ids
+    spool: Option<Spool>,
+}
+
+impl State {
+    /// Queues pending behind the queued jobs of the same or a higher priority
+    fn enqueue(&mut self, pending: Pending) {
+        let at = self
+            .pending
+            .iter()
+            .position(|queued| queued.priority < pending.priority)
+            .unwrap_or(self.pending.len());
+        self.pending.insert(at, pending);
+    }
+
+    /// Sets the status of a job, forgetting the oldest finished jobs beyond
+    /// [QueueConfig::kept_statuses]
+    fn set_status(&mut self, id: JobId, status: JobStatus) {
+        if status.is_finished() {
+            self.finished.push_back(id);
+            while self.finished.len() > self.kept_statuses {
+                if let Some(old) = self.finished.pop_front() {
+                    self.status.remove(&old);
+                }
+            }
+        }
+        self.status.insert(id, status);
+    }
+
+    /// Drops the finished job from the spool
+    fn unspool(&mut self, id: JobId) {
+        if let Some(spool) = &mut self.spool {
+            if let Err(e) = spool.complete(id.0) {
+                log::error!("Removing job {:?} from the spool failed: {}", id, e);
+            }
+        }
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled on every change to the state
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_status(&self, id: JobId, status: JobStatus) {
+        self.lock().set_status(id, status);
+        self.changed.notify_all();
+    }
+}
+
+pub struct PrintQueue<T: Target = Printer> {
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<T>>,
+    key_lifetime: Duration,
+}
+
+impl<T: Target> PrintQueue<T> {
+    pub fn new(target: T) -> PrintQueue<T> {
+        PrintQueue::with_config(target, QueueConfig::default())
+    }
+
+    pub fn with_config(target: T, config: QueueConfig) -> PrintQueue<T> {
+        PrintQueue::start(target, config, State::default())
+    }
+
+    /// Logs every job pushed to spool until it finishes, and queues the
+    /// jobs spool still had from before
+    ///
+    /// Jobs that still fail with a transient error after the retries stay in
+    /// the spool and are queued again the next time it's opened.
+    ///
+    /// ```no_run
+    /// use posify::printer::{Printer, SupportedPrinters};
+    /// use posify::queue::{PrintQueue, QueueConfig};
+    /// use posify::spool::Spool;
+    ///
+    /// let printer = Printer::new(None, None, SupportedPrinters::SNBC, 0x0483, 0x5743)?;
+    /// let spool = Spool::open("/var/spool/posify.wal")?;
+    /// let queue = PrintQueue::with_spool(printer, QueueConfig::default(), spool);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn with_spool(target: T, config: QueueConfig, spool: Spool) -> PrintQueue<T> {
+        let mut state = State {
+            next_id: spool.next_id(),
+            ..State::default()
+        };
+        for (id, job) in spool.pending() {
+            state.pending.push_back(Pending {
+                id: JobId(id),
+                job: job.clone(),
+                priority: Priority::Normal,
+                expires: None,
+                attempts: 0,
+                retry_at: None,
+            });
+            state.status.insert(JobId(id), JobStatus::Queued);
+        }
+        state.spool = Some(spool);
+        PrintQueue::start(target, config, state)
+    }
+
+    fn start(target: T, config: QueueConfig, mut state: State) -> PrintQueue<T> {
+        state.kept_statuses = config.kept_statuses;
+        let shared = Arc::new(Shared {
+            state: Mutex::new(state),
+            changed: Condvar::new(),
+        });
+        let worker_shared = shared.clone();
+        let worker = thread::spawn(move || work(target, config, &worker_shared));
+        PrintQueue {
+            shared,
+            worker: Some(worker),
+            key_lifetime: config.key_lifetime,
+        }
+    }
+
+    /// Adds job to the end of the queue with the default [JobOptions]
+    pub fn push(&self, job: Job) -> JobId {
+        self.push_with(job, JobOptions::default())
+    }
+
+    /// Adds job behind the queued jobs of the same or a higher priority
+    pub fn push_with(&self, job: Job, options: JobOptions) -> JobId {
+        let now = Instant::now();
+        let mut state = self.shared.lock();
+        let lifetime = self.key_lifetime;
+        state
+            .keys
+            .retain(|_, (_, pushed)| now.duration_since(*pushed) < lifetime);
+        if let Some((id, _)) = options.key.as_ref().and_then(|key| state.keys.get(key)) {
+            let id = *id;
+            match state.status.get(&id) {
+                Some(JobStatus::Failed(_) | JobStatus::Cancelled | JobStatus::Expired) => (),
+                _ => return id,
+            }
+        }
+
+        let id = match &mut state.spool {
+            Some(spool) => match spool.append(&job) {
+                Ok(id) => JobId(id),
+                Err(e) => {
+                    log::error!("Spooling job failed, it won't survive a restart: {}", e);
+                    JobId(spool.skip_id())
+                }
+            },
+            None => JobId(state.next_id),
+        };
+        state.next_id = id.0 + 1;
+        if let Some(key) = options.key {
+            state.keys.insert(key, (id, now));
+        }
+        state.enqueue(Pending {
+            id,
+            job,
+            priority: options.priority,
+            expires: options.ttl.map(|ttl| now + ttl),
+            attempts: 0,
+            retry_at: None,
+        });
+        state.status.insert(id, JobStatus::Queued);
+        drop(state);
+        self.shared.changed.notify_all();
+        id
+    }
+
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        self.shared.lock().status.get(&id).cloned()
+    }
+
+    /// Cancels a job that hasn't started printing. A job that is printing
+    /// isn't retried any more, it is cancelled if the current attempt fails.
+    /// Returns false if the job already finished.
+    pub fn cancel(&self, id: JobId) -> bool {
+        let mut state = self.shared.lock();
+        match state.status.get(&id) {
+            Some(JobStatus::Queued) => {
+                state.pending.retain(|pending| pending.id != id);
+                state.set_status(id, JobStatus::Cancelled);
+                state.unspool(id);
+                drop(state);
+                self.shared.changed.notify_all();
+                true
+            }
+            Some(JobStatus::Printing) => {
+                state.cancelled.insert(id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Blocks until the job is done, failed or cancelled
+    pub fn wait(&self, id: JobId) -> Option<JobStatus> {
+        let mut state = self.shared.lock();
+        loop {
+            match state.status.get(&id) {
+                Some(status) if status.is_finished() => return Some(status.clone()),
+                None => return None,
+                _ => (),
+            }
+            state = self
+                .shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Number of jobs waiting to be printed
+    pub fn len(&self) -> usize {
+        self.shared.lock().pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Prints the jobs still queued, then stops the worker and gives the
+    /// printer back
+    ///
+    /// Jobs waiting to be retried aren't waited for, they fail with the
+    /// error of their last attempt.
+    pub fn shutdown(mut self) -> T {
+        self.stop()
+            .expect("worker is only taken by shutdown or drop")
+    }
+
+    fn stop(&mut self) -> Option<T> {
+        self.shared.lock().shutdown = true;
+        self.shared.changed.notify_all();
+        self.worker.take()?.join().ok()
+    }
+}
+
+impl<T: Target> Drop for PrintQueue<T> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn work<T: Target>(mut target: T, config: QueueConfig, shared: &Shared) -> T {
+    loop {
+        let mut next = {
+            let mut state = shared.lock();
+            loop {
+                let now = Instant::now();
+                if let Some(at) = state
+                    .pending
+                    .iter()
+                    .position(|pending| pending.retry_at.is_none_or(|retry_at| retry_at <= now))
+                {
+                    let next = state.pending.remove(at).expect("position is in the queue");
+                    if next.expires.is_some_and(|expires| expires <= now) {
+                        state.set_status(next.id, JobStatus::Expired);
+                        state.unspool(next.id);
+                        shared.changed.notify_all();
+                        continue;
+                    }
+                    // Under the same lock so it can't be cancelled as Queued
+                    state.status.insert(next.id, JobStatus::Printing);
+                    break next;
+                }
+                if state.shutdown {
+                    // Only jobs waiting for a retry are left
+                    for pending in std::mem::take(&mut state.pending) {
+                        state.set_status(
+                            pending.id,
+                            JobStatus::Failed("not retried before shutdown".to_string()),
+                        );
+                    }
+                    shared.changed.notify_all();
+                    return target;
+                }
+                let retry_at = state
+                    .pending
+                    .iter()
+                    .filter_map(|pending| pending.retry_at)
+                    .min();
+                state = match retry_at {
+                    Some(retry_at) => {
+                        shared
+                            .changed
+                            .wait_timeout(state, retry_at.saturating_duration_since(now))
+                            .unwrap_or_else(|e| e.into_inner())
+                            .0
+                    }
+                    None => shared
+                        .changed
+                        .wait(state)
+                        .unwrap_or_else(|e| e.into_inner()),
+                };
+            }
+        };
+        shared.changed.notify_all();
+        let id = next.id;
+        let mut transient = false;
+        let status = match target.submit(&next.job) {
+            Ok(_) => JobStatus::Done,
+            Err(e) if e.is_transient() && next.attempts < config.retries => {
+                let mut state = shared.lock();
+                if state.cancelled.remove(&id) {
+                    state.unspool(id);
+                    drop(state);
+                    shared.set_status(id, JobStatus::Cancelled);
+                    continue;
+                }
+                // Other jobs print in the meantime, the printer may only be
+                // out of paper
+                let delay = config
+                    .retry_delay
+                    .saturating_mul(1 << next.attempts.min(16))
+                    .min(config.max_retry_delay);
+                log::warn!("Job {:?} failed, retrying in {:?}: {}", id, delay, e);
+                next.attempts += 1;
+                next.retry_at = Some(Instant::now() + delay);
+                state.enqueue(next);
+                state.status.insert(id, JobStatus::Queued);
+                drop(state);
+                shared.changed.notify_all();
+                continue;
+            }
+            Err(e) => {
+                // Kept in the spool to print after a restart
+                transient = e.is_transient();
+                JobStatus::Failed(e.to_string())
+            }
+        };
+        let mut state = shared.lock();
+        let status = match (status, state.cancelled.remove(&id)) {
+            (JobStatus::Failed(_), true) => JobStatus::Cancelled,
+            (status, _) => status,
+        };
+        if !transient || status == JobStatus::Cancelled {
+            state.unspool(id);
+        }
+        drop(state);
+        shared.set_status(id, status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::Command;
+
+    /// Fails with a timeout the first `failures` times
+    struct Flaky {
+        failures: u32,
+        printed: Vec<Job>,
+    }
+
+    impl Target for Flaky {
+        fn submit(&mut self, job: &Job) -> Result<usize, Error> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(Error::Timeout);
+            }
+            if job.commands.is_empty() {
+                return Err(Error::InvalidArgument);
+            }
+            self.printed.push(job.clone());
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let config = QueueConfig {
+            retries: 2,
+            retry_delay: Duration::from_millis(1),
+            ..QueueConfig::default()
+        };
+        let queue = PrintQueue::with_config(
+            Flaky {
+                failures: 2,
+                printed: Vec::new(),
+            },
+            config,
+        );
+        let mut job = Job::new();
+        job.push(Command::Text("Hi".to_string()));
+        let first = queue.push(job.clone());
+        let second = queue.push(Job::new());
+        assert_eq!(queue.wait(first), Some(JobStatus::Done));
+        assert_eq!(
+            queue.wait(second),
+            Some(JobStatus::Failed(Error::InvalidArgument.to_string()))
+        );
+        assert!(!queue.cancel(first));
+        assert_eq!(queue.shutdown().printed, vec![job]);
+    }
+
+    /// Fails the first attempt of the jobs whose text starts with "flaky"
+    #[derive(Default)]
+    struct FailsOnce {
+        failed: HashSet<String>,
+        printed: Vec<String>,
+    }
+
+    impl Target for FailsOnce {
+        fn submit(&mut self, job: &Job) -> Result<usize, Error> {
+            let Some(Command::Text(text)) = job.commands.first() else {
+                return Err(Error::InvalidArgument);
+            };
+            if text.starts_with("flaky") && self.failed.insert(text.clone()) {
+                return Err(Error::Timeout);
+            }
+            self.printed.push(text.clone());
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn requeues_transient_failures() {
+        let config = QueueConfig {
+            retries: 1,
+            retry_delay: Duration::from_millis(200),
+            ..QueueConfig::default()
+        };
+        let queue = PrintQueue::with_config(FailsOnce::default(), config);
+        let job = |text: &str| {
+            let mut job = Job::new();
+            job.push(Command::Text(text.to_string()));
+            job
+        };
+        let flaky = queue.push(job("flaky"));
+        let next = queue.push(job("next"));
+        // The next job doesn't wait for the retry
+        assert_eq!(queue.wait(next), Some(JobStatus::Done));
+        assert_eq!(queue.status(flaky), Some(JobStatus::Queued));
+        assert_eq!(queue.wait(flaky), Some(JobStatus::Done));
+        assert_eq!(queue.shutdown().printed, vec!["next", "flaky"]);
+    }
+
+    #[test]
+    fn retries_io_errors_by_kind() {
+        use std::io::{Error as IoError, ErrorKind};
+        assert!(Error::Io(IoError::from(ErrorKind::BrokenPipe)).is_transient());
+        assert!(Error::Io(IoError::from(ErrorKind::TimedOut)).is_transient());
+        assert!(!Error::Io(IoError::from(ErrorKind::NotFound)).is_transient());
+        assert!(!Error::Io(IoError::from(ErrorKind::PermissionDenied)).is_transient());
+    }
+
+    #[test]
+    fn forgets_old_statuses() {
+        let config = QueueConfig {
+            kept_statuses: 2,
+            ..QueueConfig::default()
+        };
+        let queue = PrintQueue::with_config(FailsOnce::default(), config);
+        let ids: Vec<_> = ["a", "b", "c"]
+            .into_iter()
+            .map(|text| {
+                let mut job = Job::new();
+                job.push(Command::Text(text.to_string()));
+                queue.push(job)
+            })
+            .collect();
+        assert_eq!(queue.wait(ids[2]), Some(JobStatus::Done));
+        assert_eq!(queue.status(ids[0]), None);
+        assert_eq!(queue.status(ids[1]), Some(JobStatus::Done));
+    }
+
+    /// Records the text of each job, waiting for a message before printing
+    struct Gated {
+        gate: std::sync::mpsc::Receiver<()>,
+        printed: Vec<String>,
+    }
+
+    impl Target for Gated {
+        fn submit(&mut self, job: &Job) -> Result<usize, Error> {
+            self.gate.recv().map_err(|_| Error::Timeout)?;
+            if let Some(Command::Text(text)) = job.commands.first() {
+                self.printed.push(text.clone());
+            }
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn priorities_ttl_and_keys() {
+        let (open, gate) = std::sync::mpsc::channel();
+        let queue = PrintQueue::new(Gated {
+            gate,
+            printed: Vec::new(),
+        });
+        let job = |text: &str| {
+            let mut job = Job::new();
+            job.push(Command::Text(text.to_string()));
+            job
+        };
+        let options = |priority, ttl, key: Option<&str>| JobOptions {
+            priority,
+            ttl,
+            key: key.map(str::to_string),
+        };
+
+        // Blocks the worker until the gate opens
+        let first = queue.push(job("first"));
+        while queue.status(first) != Some(JobStatus::Printing) {
+            thread::yield_now();
+        }
+        queue.push_with(job("reprint"), options(Priority::Low, None, None));
+        let late = queue.push_with(
+            job("late"),
+            options(Priority::Normal, Some(Duration::ZERO), None),
+        );
+        let order = queue.push_with(job("order"), options(Priority::Normal, None, Some("o-1")));
+        let again = queue.push_with(job("order"), options(Priority::Normal, None, Some("o-1")));
+        assert_eq!(order, again);
+        queue.push_with(job("fiscal"), options(Priority::High, None, None));
+
+        for _ in 0..4 {
+            open.send(()).unwrap();
+        }
+        assert_eq!(queue.wait(late), Some(JobStatus::Expired));
+        assert_eq!(
+            queue.shutdown().printed,
+            vec!["first", "fiscal", "order", "reprint"]
+        );
+    }
+
+    #[test]
+    fn keeps_failed_jobs_spooled() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let config = QueueConfig {
+            retries: 0,
+            ..QueueConfig::default()
+        };
+        let mut job = Job::new();
+        job.push(Command::Text("Order 42".to_string()));
+
+        let spool = Spool::open(file.path()).unwrap();
+        let offline = Flaky {
+            failures: 1,
+            printed: Vec::new(),
+        };
+        let queue = PrintQueue::with_spool(offline, config, spool);
+        let id = queue.push(job.clone());
+        assert!(matches!(queue.wait(id), Some(JobStatus::Failed(_))));
+        drop(queue);
+
+        let spool = Spool::open(file.path()).unwrap();
+        assert_eq!(spool.pending().count(), 1);
+        let online = Flaky {
+            failures: 0,
+            printed: Vec::new(),
+        };
+        let queue = PrintQueue::with_spool(online, config, spool);
+        assert_eq!(queue.wait(id), Some(JobStatus::Done));
+        assert_eq!(queue.shutdown().printed[0].commands, job.commands);
+        assert_eq!(Spool::open(file.path()).unwrap().pending().count(), 0);
+    }
+}
@@ -0,0 +1,525 @@
+//! Sales receipts computed from their line items
+//!
+//! A [Receipt] holds typed [LineItem]s and works out discounts, tax per
+//! [TaxGroup], rounding and the total itself, then prints exactly the
+//! amounts it computed. Set [Receipt::expect_total] to the total the point
+//! of sale charged and the receipt refuses to print when they disagree.
+//!
+//! Amounts are integers in minor units (cents), rates are in basis points
+//! (1900 is 19%). The labels are looked up in the receipt's
+//! [Locale](crate::locale::Locale), see [Receipt::locale]. The header
+//! lines and the date print above the items.
+//!
+//! ```
+//! use posify::receipt::{Discount, LineItem, Receipt, TaxGroup};
+//!
+//! let mut receipt = Receipt::new(32);
+//! receipt
+//!     .tax_group(TaxGroup::new("A", 1900))
+//!     .tax_group(TaxGroup::new("B", 700))
+//!     .item(LineItem::new("Coffee", 2, 250, "B"))
+//!     .item(LineItem::new("Mug", 1, 1200, "A").discount(Discount::Percent(1000)))
+//!     .expect_total(1580);
+//! assert_eq!(receipt.totals()?.total, 1580);
+//! let doc = receipt.document()?;
+//! # Ok::<(), posify::receipt::ReceiptError>(())
+//! ```
+
+use crate::barcode::Font;
+use crate::document::{Alignment, Direction, Document, TextStyle};
+use crate::format::{self, MoneyFormat};
+use crate::locale::{self, DateStyle, DateTime, Locale};
+use crate::profile::Profile;
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ReceiptError {
+    #[error("Line {0} uses unknown tax group {1}")]
+    UnknownTaxGroup(usize, String),
+    #[error("Discount on line {0} is more than the line amount")]
+    InvalidDiscount(usize),
+    #[error("Computed total {computed} differs from the sale total {expected}")]
+    TotalMismatch { computed: i64, expected: i64 },
+    #[error("Amount overflow")]
+    Overflow,
+}
+
+/// How fractions of a minor unit are rounded
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rounding {
+    /// Halves away from zero, what most tax authorities expect
+    #[default]
+    HalfUp,
+    /// Halves to the even neighbour, banker's rounding
+    HalfEven,
+}
+
+impl Rounding {
+    /// numerator / denominator rounded to an integer, denominator > 0
+    fn divide(&self, numerator: i128, denominator: i128) -> i128 {
+        let quotient = numerator.div_euclid(denominator);
+        let remainder = numerator.rem_euclid(denominator);
+        let up = match (remainder * 2).cmp(&denominator) {
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Equal => match self {
+                // Away from zero, quotient is rounded towards minus infinity
+                Rounding::HalfUp => numerator >= 0,
+                Rounding::HalfEven => quotient % 2 != 0,
+            },
+        };
+        quotient + up as i128
+    }
+}
+
+/// Whether prices already contain the tax
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TaxMode {
+    /// Gross prices, tax is extracted from them (VAT)
+    #[default]
+    Inclusive,
+    /// Net prices, tax is added to the total (US sales tax)
+    Exclusive,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TaxGroup {
+    /// Printed next to each line and in the tax summary, usually a letter
+    pub name: String,
+    /// Rate in basis points
+    pub rate: u32,
+}
+
+impl TaxGroup {
+    pub fn new(name: &str, rate: u32) -> TaxGroup {
+        TaxGroup {
+            name: name.to_string(),
+            rate,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Discount {
+    #[default]
+    None,
+    /// Off the line amount, in minor units
+    Amount(i64),
+    /// Off the line amount, in basis points
+    Percent(u32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineItem {
+    pub name: String,
+    pub quantity: u32,
+    /// Price of one, negative for returns and deposits
+    pub unit_price: i64,
+    pub discount: Discount,
+    /// Name of the line's [TaxGroup]
+    pub tax_group: String,
+}
+
+impl LineItem {
+    pub fn new(name: &str, quantity: u32, unit_price: i64, tax_group: &str) -> LineItem {
+        LineItem {
+            name: name.to_string(),
+            quantity,
+            unit_price,
+            discount: Discount::None,
+            tax_group: tax_group.to_string(),
+        }
+    }
+
+    pub fn discount(mut self, discount: Discount) -> LineItem {
+        self.discount = discount;
+        self
+    }
+}
+
+/// Amounts of one line
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineTotal {
+    /// Quantity times unit price
+    pub gross: i64,
+    pub discount: i64,
+    /// Gross minus discount
+    pub amount: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TaxTotal {
+    pub group: String,
+    pub rate: u32,
+    /// Line amounts of the group, the same prices as the lines
+    pub base: i64,
+    pub tax: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Totals {
+    pub lines: Vec<LineTotal>,
+    /// Sum of the line amounts, after discounts
+    pub subtotal: i64,
+    pub discounts: i64,
+    /// Groups in the order they were added, unused groups are left out
+    pub taxes: Vec<TaxTotal>,
+    /// Cash rounding, added to the total
+    pub rounding: i64,
+    pub total: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Receipt {
+    /// Characters per printed line
+    pub width: usize,
+    pub money: MoneyFormat,
+    /// Labels, English by default
+    pub locale: Locale,
+    pub tax_mode: TaxMode,
+    pub rounding: Rounding,
+    /// Total rounded to a multiple of this, 5 for Swiss francs
+    pub cash_rounding: Option<i64>,
+    /// Right to left puts the amounts on the left
+    pub direction: Direction,
+    header: Vec<String>,
+    date: Option<(DateTime, DateStyle)>,
+    tax_groups: Vec<TaxGroup>,
+    items: Vec<LineItem>,
+    expected_total: Option<i64>,
+}
+
+impl Receipt {
+    /// Receipt as wide as font A fits on the paper of profile, see
+    /// [Profile::chars_per_line]
+    pub fn for_profile(profile: &Profile) -> Receipt {
+        Receipt::new(profile.chars_per_line(Font::FontA, 1))
+    }
+
+    pub fn new(width: usize) -> Receipt {
+        Receipt {
+            width,
+            money: MoneyFormat::default(),
+            locale: Locale::default(),
+            tax_mode: TaxMode::default(),
+            rounding: Rounding::default(),
+            cash_rounding: None,
+            direction: Direction::default(),
+            header: Vec::new(),
+            date: None,
+            tax_groups: Vec::new(),
+            items: Vec::new(),
+            expected_total: None,
+        }
+    }
+
+    /// Prints the labels and amounts of locale
+    pub fn locale(&mut self, locale: Locale) -> &mut Self {
+        self.money = locale.money.clone();
+        self.locale = locale;
+        self
+    }
+
+    /// Adds a centered line above the items, the shop's name or address
+    pub fn header(&mut self, line: &str) -> &mut Self {
+        self.header.push(line.to_string());
+        self
+    }
+
+    /// Date of the sale, shortened to fit the line, see
+    /// [format_datetime](locale::format_datetime)
+    pub fn date(&mut self, date: DateTime, style: DateStyle) -> &mut Self {
+        self.date = Some((date, style));
+        self
+    }
+
+    pub fn tax_group(&mut self, group: TaxGroup) -> &mut Self {
+        self.tax_groups.push(group);
+        self
+    }
+
+    pub fn item(&mut self, item: LineItem) -> &mut Self {
+        self.items.push(item);
+        self
+    }
+
+    pub fn items(&self) -> &[LineItem] {
+        &self.items
+    }
+
+    /// Total the sale was charged, [Receipt::document] fails when the
+    /// receipt computes a different one
+    pub fn expect_total(&mut self, total: i64) -> &mut Self {
+        self.expected_total = Some(total);
+        self
+    }
+
+    pub fn totals(&self) -> Result<Totals, ReceiptError> {
+        let mut lines = Vec::new();
+        let mut bases = vec![0_i128; self.tax_groups.len()];
+        for (n, item) in self.items.iter().enumerate() {
+            let group = self
+                .tax_groups
+                .iter()
+                .position(|group| group.name == item.tax_group)
+                .ok_or_else(|| ReceiptError::UnknownTaxGroup(n, item.tax_group.clone()))?;
+            let gross = item.quantity as i128 * item.unit_price as i128;
+            let discount = match item.discount {
+                Discount::None => 0,
+                Discount::Amount(amount) => amount as i128,
+                Discount::Percent(rate) => self.rounding.divide(gross.abs() * rate as i128, 10_000),
+            };
+            if discount < 0 || discount > gross.abs() {
+                return Err(ReceiptError::InvalidDiscount(n));
+            }
+            // Discounts reduce returns too
+            let amount = gross - discount * gross.signum();
+            bases[group] += amount;
+            lines.push(LineTotal {
+                gross: narrow(gross)?,
+                discount: narrow(discount)?,
+                amount: narrow(amount)?,
+            });
+        }
+
+        let mut taxes = Vec::new();
+        for (group, base) in self.tax_groups.iter().zip(&bases) {
+            if !self.items.iter().any(|item| item.tax_group == group.name) {
+                continue;
+            }
+            let rate = group.rate as i128;
+            let tax = match self.tax_mode {
+                TaxMode::Inclusive => self.rounding.divide(base * rate, 10_000 + rate),
+                TaxMode::Exclusive => self.rounding.divide(base * rate, 10_000),
+            };
+            taxes.push(TaxTotal {
+                group: group.name.clone(),
+                rate: group.rate,
+                base: narrow(*base)?,
+                tax: narrow(tax)?,
+            });
+        }
+
+        let subtotal: i128 = bases.iter().sum();
+        let mut total = subtotal;
+        if self.tax_mode == TaxMode::Exclusive {
+            total += taxes.iter().map(|tax| tax.tax as i128).sum::<i128>();
+        }
+        let rounding = match self.cash_rounding {
+            Some(step) if step > 1 => {
+                let step = step as i128;
+                Rounding::HalfUp.divide(total, step) * step - total
+            }
+            _ => 0,
+        };
+        Ok(Totals {
+            discounts: lines.iter().map(|line| line.gross - line.amount).sum(),
+            lines,
+            subtotal: narrow(subtotal)?,
+            taxes,
+            rounding: narrow(rounding)?,
+            total: narrow(total + rounding)?,
+        })
+    }
+
+    /// Lines, subtotal, tax summary and total as a [Document]
+    pub fn document(&self) -> Result<Document, ReceiptError> {
+        let totals = self.totals()?;
+        if let Some(expected) = self.expected_total {
+            if expected != totals.total {
+                return Err(ReceiptError::TotalMismatch {
+                    computed: totals.total,
+                    expected,
+                });
+            }
+        }
+
+        let money = |amount| self.money.format(amount);
+        let columns = |label: &str, value: &str, width| match self.direction {
+            Direction::Ltr => format::columns(label, value, width),
+            Direction::Rtl => format::columns_rtl(label, value, width),
+        };
+        let line = |label: &str, amount| columns(label, &money(amount), self.width);
+        let label = |key| self.locale.get(key);
+        let mut doc = Document {
+            direction: self.direction,
+            ..Document::default()
+        };
+        for line in &self.header {
+            let style = TextStyle {
+                align: Alignment::Center,
+                ..TextStyle::default()
+            };
+            doc.styled(line, style);
+        }
+        if let Some((date, style)) = &self.date {
+            let date = locale::format_datetime(date, *style, &self.locale, self.width);
+            doc.text(&date);
+        }
+        if !self.header.is_empty() || self.date.is_some() {
+            doc.text(&"-".repeat(self.width));
+        }
+        for (item, total) in self.items.iter().zip(&totals.lines) {
+            let amount = format!("{} {}", money(total.gross), item.tax_group);
+            doc.text(&columns(&item.name, &amount, self.width));
+            if item.quantity != 1 {
+                doc.text(&format!("  {} x {}", item.quantity, money(item.unit_price)));
+            }
+            if total.discount != 0 {
+                let discount = money(total.amount - total.gross);
+                let label = match item.discount {
+                    Discount::Percent(rate) => {
+                        format!("  {} {}", label("discount"), percent(rate, &self.money))
+                    }
+                    _ => format!("  {}", label("discount")),
+                };
+                doc.text(&columns(&label, &discount, self.width));
+            }
+        }
+        doc.text(&"-".repeat(self.width));
+        doc.text(&line(label("subtotal"), totals.subtotal));
+        for tax in &totals.taxes {
+            let key = match self.tax_mode {
+                TaxMode::Inclusive => "tax_included",
+                TaxMode::Exclusive => "tax",
+            };
+            let rate = percent(tax.rate, &self.money);
+            let text = self
+                .locale
+                .text(key, &[("group", &tax.group), ("rate", &rate)]);
+            doc.text(&line(&text, tax.tax));
+        }
+        if totals.rounding != 0 {
+            doc.text(&line(label("rounding"), totals.rounding));
+        }
+        // Double width halves the characters per line
+        doc.styled(
+            &columns(label("total"), &money(totals.total), self.width / 2),
+            TextStyle {
+                align: Alignment::Left,
+                bold: true,
+                width: 2,
+                height: 2,
+                ..TextStyle::default()
+            },
+        );
+        Ok(doc)
+    }
+}
+
+fn narrow(amount: i128) -> Result<i64, ReceiptError> {
+    i64::try_from(amount).map_err(|_| ReceiptError::Overflow)
+}
+
+/// Basis points as a percentage without trailing zeros, `7.5%`, with the
+/// decimal separator of money
+fn percent(rate: u32, money: &MoneyFormat) -> String {
+    let whole = rate / 100;
+    match rate % 100 {
+        0 => format!("{}%", whole),
+        fraction => {
+            let fraction = format!("{:02}", fraction);
+            format!(
+                "{}{}{}%",
+                whole,
+                money.number.decimal_separator,
+                fraction.trim_end_matches('0')
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Element;
+
+    #[test]
+    fn computes_totals() {
+        let mut receipt = Receipt::new(32);
+        receipt
+            .tax_group(TaxGroup::new("A", 1900))
+            .tax_group(TaxGroup::new("B", 750))
+            .item(LineItem::new("Coffee", 3, 333, "A").discount(Discount::Amount(99)))
+            .item(LineItem::new("Bottle return", 1, -25, "B"))
+            .item(LineItem::new("Cake", 1, 450, "B").discount(Discount::Percent(1050)));
+        let totals = receipt.totals().unwrap();
+        assert_eq!(totals.lines[0].amount, 900);
+        // 47.25 rounded half up
+        assert_eq!(totals.lines[2].discount, 47);
+        assert_eq!(totals.subtotal, 1278);
+        assert_eq!(totals.taxes[0].tax, 144);
+        assert_eq!(totals.taxes[1].tax, 26);
+        assert_eq!(totals.total, 1278);
+
+        receipt.tax_mode = TaxMode::Exclusive;
+        receipt.cash_rounding = Some(5);
+        let totals = receipt.totals().unwrap();
+        assert_eq!(totals.taxes[0].tax, 171);
+        // 1278 + 171 + 28 = 1477
+        assert_eq!((totals.rounding, totals.total), (-2, 1475));
+
+        receipt.expect_total(1480);
+        assert!(matches!(
+            receipt.document(),
+            Err(ReceiptError::TotalMismatch {
+                computed: 1475,
+                expected: 1480
+            })
+        ));
+        receipt.expect_total(1475);
+        let doc = receipt.document().unwrap();
+        assert!(matches!(
+            doc.elements.last(),
+            Some(Element::Text { content, .. }) if content == "TOTAL     $14.75"
+        ));
+
+        receipt
+            .locale(Locale::de_de())
+            .header("Café Central")
+            .date(DateTime::new(2026, 10, 15, 9, 5, 0), DateStyle::Long);
+        let doc = receipt.document().unwrap();
+        let text = |i: usize| match &doc.elements[doc.elements.len() - i] {
+            Element::Text { content, .. } => content.clone(),
+            _ => String::new(),
+        };
+        assert_eq!(text(1), "SUMME    14,75 €");
+        assert_eq!(text(4), format::columns("MwSt. A 19%", "1,71 €", 32));
+        assert!(matches!(
+            &doc.elements[1],
+            Element::Text { content, .. } if content == "15. Oktober 2026 09:05"
+        ));
+
+        receipt.item(LineItem::new("Gift card", 1, 500, "C"));
+        assert_eq!(
+            receipt.totals().unwrap_err(),
+            ReceiptError::UnknownTaxGroup(3, "C".to_string())
+        );
+        assert_eq!(percent(750, &MoneyFormat::en_us()), "7.5%");
+        assert_eq!(percent(750, &MoneyFormat::de_de()), "7,5%");
+        assert_eq!(percent(1900, &MoneyFormat::de_de()), "19%");
+    }
+
+    #[cfg(feature = "json_profiles")]
+    #[test]
+    fn receipts_as_json() {
+        let mut receipt = Receipt::new(32);
+        receipt
+            .tax_group(TaxGroup::new("A", 1900))
+            .item(LineItem::new("Coffee", 3, 333, "A"))
+            .header("Café Central");
+        let json = serde_json::to_string(&receipt).unwrap();
+        assert_eq!(serde_json::from_str::<Receipt>(&json).unwrap(), receipt);
+        let totals = receipt.totals().unwrap();
+        let json = serde_json::to_string(&totals).unwrap();
+        assert_eq!(serde_json::from_str::<Totals>(&json).unwrap(), totals);
+    }
+}
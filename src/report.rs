@@ -0,0 +1,222 @@
+//! Cashier shift reports
+//!
+//! The X report a cashier prints during a shift and the Z report that
+//! closes it have the same layout: a title, a few rows about the register
+//! and the shift, then sections of label and value rows with a total
+//! below each. A [Report] lines the values up on the right with
+//! [format::columns], the two column table layout receipts use too, and can
+//! end with a line for a signature.
+//!
+//! ```
+//! use posify::report::Report;
+//!
+//! let mut report = Report::new("Z REPORT", 32);
+//! report
+//!     .row("Register", "2")
+//!     .row("Cashier", "Ana")
+//!     .section("Sales")
+//!     .row("Transactions", "84")
+//!     .amount("Cash", 61250)
+//!     .amount("Card", 49380)
+//!     .total("Total sales", 110630)
+//!     .signature("Cashier");
+//! let doc = report.document();
+//! ```
+
+use crate::barcode::Font;
+use crate::document::{Alignment, Document, TextStyle};
+use crate::format::{self, MoneyFormat};
+use crate::profile::Profile;
+
+/// Rows below a title, see [Report::section]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReportSection {
+    /// Empty for the rows above the first section
+    pub title: String,
+    /// Label and value
+    pub rows: Vec<(String, String)>,
+    /// Label and value printed bold below a line
+    pub total: Option<(String, String)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    /// Characters per printed line
+    pub width: usize,
+    /// Printed double size, so at most half the width
+    pub title: String,
+    /// Formats the amounts added after setting it
+    pub money: MoneyFormat,
+    /// Printed below a line at the end, e.g. "Cashier"
+    pub signature: Option<String>,
+    sections: Vec<ReportSection>,
+}
+
+impl Report {
+    /// Report as wide as font A fits on the paper of profile, see
+    /// [Profile::chars_per_line]
+    pub fn for_profile(title: &str, profile: &Profile) -> Report {
+        Report::new(title, profile.chars_per_line(Font::FontA, 1))
+    }
+
+    pub fn new(title: &str, width: usize) -> Report {
+        Report {
+            width,
+            title: title.to_string(),
+            money: MoneyFormat::default(),
+            signature: None,
+            sections: vec![ReportSection::default()],
+        }
+    }
+
+    /// Starts a section, the following rows and total go into it
+    pub fn section(&mut self, title: &str) -> &mut Self {
+        self.sections.push(ReportSection {
+            title: title.to_string(),
+            ..ReportSection::default()
+        });
+        self
+    }
+
+    pub fn row(&mut self, label: &str, value: &str) -> &mut Self {
+        self.current()
+            .rows
+            .push((label.to_string(), value.to_string()));
+        self
+    }
+
+    /// Row with an amount in minor units, formatted with [Report::money]
+    pub fn amount(&mut self, label: &str, amount: i64) -> &mut Self {
+        let value = self.money.format(amount);
+        self.row(label, &value)
+    }
+
+    /// Total of the current section, replacing an earlier one
+    pub fn total(&mut self, label: &str, amount: i64) -> &mut Self {
+        let value = self.money.format(amount);
+        self.current().total = Some((label.to_string(), value));
+        self
+    }
+
+    pub fn signature(&mut self, label: &str) -> &mut Self {
+        self.signature = Some(label.to_string());
+        self
+    }
+
+    pub fn sections(&self) -> &[ReportSection] {
+        &self.sections
+    }
+
+    /// The last section, the untitled one when a deserialized report has
+    /// none
+    fn current(&mut self) -> &mut ReportSection {
+        if self.sections.is_empty() {
+            self.sections.push(ReportSection::default());
+        }
+        let last = self.sections.len() - 1;
+        &mut self.sections[last]
+    }
+
+    pub fn document(&self) -> Document {
+        let mut doc = Document::new();
+        // Double width halves the characters per line
+        doc.styled(
+            &self.title,
+            TextStyle {
+                align: Alignment::Center,
+                bold: true,
+                width: 2,
+                height: 2,
+                ..TextStyle::default()
+            },
+        );
+        for section in &self.sections {
+            if section.rows.is_empty() && section.total.is_none() && section.title.is_empty() {
+                continue;
+            }
+            doc.text(&"=".repeat(self.width));
+            if !section.title.is_empty() {
+                doc.styled(
+                    &section.title,
+                    TextStyle {
+                        bold: true,
+                        ..TextStyle::default()
+                    },
+                );
+            }
+            for (label, value) in &section.rows {
+                doc.text(&format::columns(label, value, self.width));
+            }
+            if let Some((label, value)) = &section.total {
+                doc.text(&"-".repeat(self.width));
+                doc.styled(
+                    &format::columns(label, value, self.width),
+                    TextStyle {
+                        bold: true,
+                        ..TextStyle::default()
+                    },
+                );
+            }
+        }
+        if let Some(label) = &self.signature {
+            doc.feed(3);
+            doc.text(&format!("X{}", "_".repeat(self.width.saturating_sub(1))));
+            doc.styled(
+                label,
+                TextStyle {
+                    align: Alignment::Center,
+                    ..TextStyle::default()
+                },
+            );
+        }
+        doc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Element;
+
+    #[test]
+    fn lays_out_sections() {
+        let mut report = Report::new("X REPORT", 20);
+        report.money = MoneyFormat::de_de();
+        report
+            .row("Shift", "Morning")
+            .section("Payments")
+            .amount("Cash", 1250)
+            .total("Total", 1250)
+            .signature("Manager");
+        assert_eq!(report.sections().len(), 2);
+
+        let doc = report.document();
+        let lines: Vec<String> = doc
+            .elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Text { content, .. } => Some(content.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(lines[2], "Shift        Morning");
+        assert_eq!(lines[4], "Payments");
+        assert_eq!(lines[5], "Cash         12,50 €");
+        assert_eq!(lines[7], "Total        12,50 €");
+        assert_eq!(lines[8], format!("X{}", "_".repeat(19)));
+        assert_eq!(lines.last().unwrap(), "Manager");
+    }
+
+    #[cfg(feature = "json_profiles")]
+    #[test]
+    fn adds_rows_to_reports_without_sections() {
+        let mut json = serde_json::to_value(Report::new("X REPORT", 20)).unwrap();
+        json["sections"] = serde_json::json!([]);
+        let mut report: Report = serde_json::from_value(json).unwrap();
+        report.row("Shift", "Late").total("Total", 0);
+        assert_eq!(report.sections().len(), 1);
+        assert_eq!(report.sections()[0].rows.len(), 1);
+    }
+}
@@ -0,0 +1,183 @@
+//! Printed grid lines for tables on SNBC printers
+//!
+//! SNBC firmware keeps a ruled line pattern, one bit per dot across the
+//! paper, and while ruled line printing is on it prints the pattern on every
+//! dot line it feeds. Setting the pattern to the column edges of a table
+//! draws its vertical lines through all the rows, however tall they are;
+//! [RuledLines::horizontal] draws the lines between rows. The lines are
+//! solid, unlike lines of `|` and `-` characters.
+//!
+//! ```no_run
+//! use posify::printer::{Printer, SupportedPrinters};
+//! use posify::ruled_lines::RuledPattern;
+//!
+//! let mut printer = Printer::new(None, None, SupportedPrinters::SNBC, 0x154f, 0x0517)?;
+//! // Columns of 20, 8 and 10 characters
+//! let pattern = RuledPattern::for_columns(&[20, 8, 10], 12, 576);
+//! {
+//!     let mut lines = printer.ruled_lines()?;
+//!     lines.set_pattern(&pattern)?;
+//!     lines.horizontal(2)?;
+//!     lines.start()?;
+//! }
+//! // RuledLines borrows the printer, print the rows in between
+//! printer.text(" Item                Qty     Price")?;
+//! let mut lines = printer.ruled_lines()?;
+//! lines.stop()?;
+//! lines.horizontal(2)?;
+//! # Ok::<(), posify::printer::Error>(())
+//! ```
+
+use crate::job::Command;
+use crate::printer::{Error, Printer, SupportedPrinters};
+
+/// Dots where the ruled lines print, left to right
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RuledPattern {
+    /// Paper width in dots
+    pub dots: u32,
+    /// One bit per dot, the most significant bit on the left
+    pub bits: Vec<u8>,
+}
+
+impl RuledPattern {
+    /// No lines on paper dots wide
+    pub fn new(dots: u32) -> RuledPattern {
+        RuledPattern {
+            dots,
+            bits: vec![0; dots.div_ceil(8) as usize],
+        }
+    }
+
+    /// Adds a vertical line width dots wide starting at dot x, cut off at
+    /// the paper edge
+    pub fn line(&mut self, x: u32, width: u32) -> &mut Self {
+        for dot in x..(x + width).min(self.dots) {
+            self.bits[(dot / 8) as usize] |= 0x80 >> (dot % 8);
+        }
+        self
+    }
+
+    /// Two dot lines at both edges and between columns of the given widths
+    /// in characters cell dots wide, on paper dots wide
+    ///
+    /// Each line takes the first two dots of the column after it, so with
+    /// 12 dot characters leave a space at the start of every column.
+    pub fn for_columns(columns: &[u32], cell: u32, dots: u32) -> RuledPattern {
+        let mut pattern = RuledPattern::new(dots);
+        let mut x = 0;
+        pattern.line(0, 2);
+        for width in columns {
+            x += width * cell;
+            // The last edge stays on the paper
+            pattern.line(x.min(dots.saturating_sub(2)), 2);
+        }
+        pattern
+    }
+}
+
+/// The ruled line functions of an SNBC printer
+pub struct RuledLines<'a> {
+    printer: &'a mut Printer,
+}
+
+impl<'a> RuledLines<'a> {
+    /// Only SNBC firmware has ruled lines
+    pub fn new(printer: &'a mut Printer) -> Result<RuledLines<'a>, Error> {
+        match printer.printer {
+            SupportedPrinters::SNBC => Ok(RuledLines { printer }),
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    /// DC3 D nL nH d1...dk - Set the ruled line pattern
+    ///
+    /// ASCII    DC3  D  nL  nH  d1...dk
+    /// Hex      13  44  nL  nH  d1...dk
+    /// Decimal  19  68  nL  nH  d1...dk
+    ///
+    /// k = nL + nH * 256 bytes, the most significant bit of d1 is the left
+    /// most dot.
+    pub fn set_pattern(&mut self, pattern: &RuledPattern) -> Result<usize, Error> {
+        let len = pattern.bits.len().min(u16::MAX as usize);
+        let mut cmd = vec![0x13, 0x44];
+        cmd.extend_from_slice(&(len as u16).to_le_bytes());
+        cmd.extend_from_slice(&pattern.bits[..len]);
+        self.printer.command(
+            "ruled_pattern",
+            || format!("{} dots", pattern.dots),
+            |p| p.send(&cmd),
+        )
+    }
+
+    /// DC3 + - Start printing the pattern on every dot line fed
+    ///
+    /// ASCII    DC3  +
+    /// Hex      13  2b
+    /// Decimal  19  43
+    pub fn start(&mut self) -> Result<usize, Error> {
+        self.printer
+            .command("ruled_lines_start", String::new, |p| p.send(&[0x13, 0x2b]))
+    }
+
+    /// DC3 - - Stop printing the pattern
+    ///
+    /// ASCII    DC3  -
+    /// Hex      13  2d
+    /// Decimal  19  45
+    pub fn stop(&mut self) -> Result<usize, Error> {
+        self.printer
+            .command("ruled_lines_stop", String::new, |p| p.send(&[0x13, 0x2d]))
+    }
+
+    /// A line across the paper thickness dots high, as a raster image
+    pub fn horizontal(&mut self, thickness: u8) -> Result<usize, Error> {
+        let width_bytes = self.printer.dots_per_line().unwrap_or(576).div_ceil(8);
+        let height = thickness.max(1) as u16;
+        let line = Command::Raster {
+            width_bytes,
+            height,
+            data: vec![0xFF; width_bytes as usize * height as usize],
+        };
+        let mut buf = Vec::new();
+        self.printer.encoder().encode_command(&line, &mut buf)?;
+        self.printer.command(
+            "ruled_horizontal",
+            || thickness.to_string(),
+            |p| p.send(&buf),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_column_pattern() {
+        let pattern = RuledPattern::for_columns(&[2, 3], 12, 64);
+        assert_eq!(pattern.bits.len(), 8);
+        // Dots 0, 24 and 60 start two dot lines
+        assert_eq!(pattern.bits[0], 0b1100_0000);
+        assert_eq!(pattern.bits[3], 0b1100_0000);
+        assert_eq!(pattern.bits[7], 0b0000_1100);
+        assert_eq!(pattern.bits.iter().map(|b| b.count_ones()).sum::<u32>(), 6);
+
+        // Ruled lines don't touch the text attributes, the second style
+        // isn't sent again
+        let bytes = crate::testing::capture(SupportedPrinters::SNBC, |p| {
+            Ok(p.style("b")? + p.ruled_lines()?.start()? + p.style("b")?)
+        })
+        .unwrap();
+        let mut style = crate::testing::capture(SupportedPrinters::SNBC, |p| p.style("b")).unwrap();
+        style.extend([0x13, 0x2b]);
+        assert_eq!(bytes, style);
+
+        let mut printer = Printer::lazy(None, None, SupportedPrinters::Generic, 0, 0);
+        assert!(matches!(
+            RuledLines::new(&mut printer).err(),
+            Some(Error::Unsupported)
+        ));
+    }
+}
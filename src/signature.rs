@@ -0,0 +1,122 @@
+//! Printing signatures captured on a pad or touch screen
+//!
+//! A [Signature] is the list of strokes the pen drew, each a polyline in
+//! whatever coordinates the capture device uses. It's scaled to the paper
+//! width and drawn into an [Image], which prints like any other image.
+//!
+//! ```
+//! use posify::document::Document;
+//! use posify::signature::Signature;
+//!
+//! let signature = Signature::new(vec![
+//!     vec![(10.0, 40.0), (30.0, 10.0), (50.0, 40.0)],
+//!     vec![(20.0, 28.0), (40.0, 28.0)],
+//! ]);
+//! let mut doc = Document::new();
+//! doc.image(signature.to_image(384, 3)).text("X______________________");
+//! ```
+
+use image::{DynamicImage, GrayImage, Luma};
+
+use crate::img::Image;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Signature {
+    /// Points of every pen stroke, y grows downwards
+    pub strokes: Vec<Vec<(f32, f32)>>,
+}
+
+impl Signature {
+    pub fn new(strokes: Vec<Vec<(f32, f32)>>) -> Signature {
+        Signature { strokes }
+    }
+
+    /// Smallest and largest x and y of all points, None without points
+    pub fn bounds(&self) -> Option<((f32, f32), (f32, f32))> {
+        let mut points = self.strokes.iter().flatten();
+        let &(x, y) = points.next()?;
+        Some(
+            points.fold(((x, y), (x, y)), |((x0, y0), (x1, y1)), &(x, y)| {
+                ((x0.min(x), y0.min(y)), (x1.max(x), y1.max(y)))
+            }),
+        )
+    }
+
+    /// Draws the strokes scaled to width dots, keeping the aspect ratio,
+    /// with lines pen dots thick
+    pub fn to_image(&self, width: u32, pen: u32) -> Image {
+        let pen = pen.max(1);
+        let width = width.max(pen);
+        let Some(((x0, y0), (x1, y1))) = self.bounds() else {
+            return Image::from(DynamicImage::ImageLuma8(GrayImage::from_pixel(
+                width,
+                1,
+                Luma([0xFF]),
+            )));
+        };
+        // Room for half the pen around the strokes, and never taller than
+        // wide
+        let inner = (width - pen) as f32;
+        let scale = match (x1 - x0).max(y1 - y0) {
+            extent if extent > 0.0 => inner / extent,
+            _ => 1.0,
+        };
+        let height = ((y1 - y0) * scale) as u32 + pen;
+        let mut out = GrayImage::from_pixel(width, height, Luma([0xFF]));
+        let offset = pen as f32 / 2.0;
+        let to_dots = |&(x, y): &(f32, f32)| ((x - x0) * scale + offset, (y - y0) * scale + offset);
+
+        for stroke in &self.strokes {
+            let points: Vec<_> = stroke.iter().map(to_dots).collect();
+            if let [point] = points[..] {
+                dot(&mut out, point, pen);
+            }
+            for segment in points.windows(2) {
+                let ((ax, ay), (bx, by)) = (segment[0], segment[1]);
+                let steps = (bx - ax).abs().max((by - ay).abs()).ceil().max(1.0) as u32;
+                for step in 0..=steps {
+                    let t = step as f32 / steps as f32;
+                    dot(&mut out, (ax + (bx - ax) * t, ay + (by - ay) * t), pen);
+                }
+            }
+        }
+        Image::from(DynamicImage::ImageLuma8(out))
+    }
+}
+
+/// Black square pen dots wide centered on point
+fn dot(image: &mut GrayImage, (x, y): (f32, f32), pen: u32) {
+    let left = (x - pen as f32 / 2.0).round().max(0.0) as u32;
+    let top = (y - pen as f32 / 2.0).round().max(0.0) as u32;
+    for py in top..(top + pen).min(image.height()) {
+        for px in left..(left + pen).min(image.width()) {
+            image.put_pixel(px, py, Luma([0]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_strokes_to_width() {
+        let signature = Signature::new(vec![
+            vec![(100.0, 50.0), (300.0, 50.0)],
+            vec![(200.0, 90.0)],
+        ]);
+        let image = signature.to_image(202, 2);
+        // 200 units wide in 200 dots, plus the pen
+        assert_eq!((image.width, image.height), (202, 42));
+        let raster = image.get_raster();
+        let row = |y: usize| &raster[y * 26..(y + 1) * 26];
+        assert!(row(0)[..25].iter().all(|b| *b == 0xFF));
+        assert_eq!(row(0)[25], 0b1100_0000);
+        assert!(row(20).iter().all(|b| *b == 0));
+        // The single point stroke
+        assert_eq!(row(40)[12], 0b0000_1100);
+
+        assert_eq!(Signature::default().to_image(100, 3).height, 1);
+    }
+}
@@ -0,0 +1,318 @@
+//! Keeping accepted jobs on disk until they are printed
+//!
+//! A [Spool] is a write-ahead log: every accepted job is appended and
+//! synced before it's queued, and a record marking it done is appended once
+//! it printed. After a crash or restart [Spool::open] replays the log and
+//! the jobs that weren't done are printed again. The log is rewritten
+//! without the done jobs when it's opened and emptied whenever nothing is
+//! pending, so it doesn't grow. Ids keep counting up across both, so a job
+//! id is never given out twice.
+//!
+//! [PrintQueue::with_spool](crate::queue::PrintQueue::with_spool) spools
+//! every job pushed to the queue.
+//!
+//! The log is `POSWAL` and version 2, followed by records, numbers are
+//! little endian:
+//!
+//! | Field    | Format                                                       |
+//! |----------|--------------------------------------------------------------|
+//! | tag      | 1 for an accepted job, 2 for a done one, 3 for the next id   |
+//! | id       | u64, the next id to give out for tag 3                       |
+//! | job      | only for tag 1, u32 length and the [Job] file format         |
+//! | checksum | u32 CRC-32 of the fields before                              |
+//!
+//! A next id record starts the log whenever it's rewritten or emptied.
+//! Version 1 logs, without them, are read as well.
+//!
+//! A record cut short by a crash, or any record after a bad checksum, is
+//! dropped.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::job::Job;
+
+/// Start of the log, the last byte is the format version
+const WAL_MAGIC: &[u8; 7] = b"POSWAL\x02";
+const TAG_JOB: u8 = 1;
+const TAG_DONE: u8 = 2;
+const TAG_NEXT_ID: u8 = 3;
+
+pub struct Spool {
+    path: PathBuf,
+    file: File,
+    /// Jobs not done yet by id
+    pending: BTreeMap<u64, Job>,
+    next_id: u64,
+}
+
+impl Spool {
+    /// Opens the log at path, creating it when missing, and keeps the jobs
+    /// that weren't done
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Spool> {
+        let path = path.as_ref().to_path_buf();
+        let (pending, next_id) = match File::open(&path) {
+            Ok(file) => replay(&mut io::BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (BTreeMap::new(), 0),
+            Err(e) => return Err(e),
+        };
+
+        // Only the pending jobs are kept, in a new file so a crash while
+        // writing it leaves the old one
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let mut file = io::BufWriter::new(File::create(&temp)?);
+        file.write_all(WAL_MAGIC)?;
+        write_next_id(&mut file, next_id)?;
+        for (id, job) in &pending {
+            write_job(&mut file, *id, job)?;
+        }
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&temp, &path)?;
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+        Ok(Spool {
+            path,
+            file,
+            pending,
+            next_id,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Logs job and returns its id, the job is on disk when this returns
+    ///
+    /// When logging fails the log is cut back to where it was, a torn
+    /// record would drop the jobs appended after it on replay.
+    pub fn append(&mut self, job: &Job) -> io::Result<u64> {
+        let id = self.next_id;
+        let mut record = Vec::new();
+        write_job(&mut record, id, job)?;
+        let len = self.file.metadata()?.len();
+        let written = self
+            .file
+            .write_all(&record)
+            .and_then(|_| self.file.sync_data());
+        if let Err(e) = written {
+            if let Err(e) = self.file.set_len(len) {
+                log::error!("Cutting a torn record from the spool failed: {}", e);
+            }
+            return Err(e);
+        }
+        self.next_id += 1;
+        self.pending.insert(id, job.clone());
+        Ok(id)
+    }
+
+    /// Logs the job as done, the log is emptied when no job is left
+    pub fn complete(&mut self, id: u64) -> io::Result<()> {
+        if self.pending.remove(&id).is_none() {
+            return Ok(());
+        }
+        if self.pending.is_empty() {
+            // Appends go to the new end, the next id is kept so ids aren't
+            // given out again
+            self.file.set_len(WAL_MAGIC.len() as u64)?;
+            write_next_id(&mut self.file, self.next_id)?;
+        } else {
+            let mut record = vec![TAG_DONE];
+            record.write_u64::<LittleEndian>(id)?;
+            let checksum = crc32(&record);
+            record.write_u32::<LittleEndian>(checksum)?;
+            self.file.write_all(&record)?;
+        }
+        self.file.sync_data()
+    }
+
+    /// Jobs not done yet, oldest first
+    pub fn pending(&self) -> impl Iterator<Item = (u64, &Job)> {
+        self.pending.iter().map(|(id, job)| (*id, job))
+    }
+
+    /// Id the next job is logged with, higher than any in the log
+    pub fn next_id(&self) -> u64 {
+        self.next_id
+    }
+
+    /// Uses up an id for a job that isn't logged, e.g. because
+    /// [Spool::append] failed, so no logged job gets the same one
+    pub fn skip_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+}
+
+fn write_job<W: Write>(w: &mut W, id: u64, job: &Job) -> io::Result<()> {
+    let mut record = vec![TAG_JOB];
+    record.write_u64::<LittleEndian>(id)?;
+    let mut encoded = Vec::new();
+    job.write_to(&mut encoded)?;
+    record.write_u32::<LittleEndian>(encoded.len() as u32)?;
+    record.extend_from_slice(&encoded);
+    let checksum = crc32(&record);
+    record.write_u32::<LittleEndian>(checksum)?;
+    w.write_all(&record)
+}
+
+fn write_next_id<W: Write>(w: &mut W, next_id: u64) -> io::Result<()> {
+    let mut record = vec![TAG_NEXT_ID];
+    record.write_u64::<LittleEndian>(next_id)?;
+    let checksum = crc32(&record);
+    record.write_u32::<LittleEndian>(checksum)?;
+    w.write_all(&record)
+}
+
+/// Pending jobs and the next free id from the records in r
+fn replay<R: Read>(r: &mut R) -> io::Result<(BTreeMap<u64, Job>, u64)> {
+    let mut pending = BTreeMap::new();
+    let mut next_id = 0;
+    let mut magic = [0; 7];
+    match r.read_exact(&mut magic) {
+        Ok(()) if magic[..6] == WAL_MAGIC[..6] && (1..=WAL_MAGIC[6]).contains(&magic[6]) => (),
+        Ok(()) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a spool file",
+            ))
+        }
+        // Crashed before the header was written
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok((pending, next_id)),
+        Err(e) => return Err(e),
+    }
+    while let Some((tag, id, job)) = read_record(r)? {
+        match (tag, job) {
+            (TAG_NEXT_ID, _) => {
+                next_id = next_id.max(id);
+                continue;
+            }
+            (TAG_JOB, Some(job)) => {
+                pending.insert(id, job);
+            }
+            _ => {
+                pending.remove(&id);
+            }
+        }
+        next_id = next_id.max(id + 1);
+    }
+    Ok((pending, next_id))
+}
+
+/// The next record, None at the end of the log or at a damaged record
+fn read_record<R: Read>(r: &mut R) -> io::Result<Option<(u8, u64, Option<Job>)>> {
+    let mut record = vec![0; 9];
+    match r.read_exact(&mut record) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let tag = record[0];
+    if tag == TAG_JOB {
+        let mut len = [0; 4];
+        if r.read_exact(&mut len).is_err() {
+            return Ok(None);
+        }
+        record.extend_from_slice(&len);
+        let len = u32::from_le_bytes(len) as u64;
+        if r.take(len).read_to_end(&mut record)? as u64 != len {
+            return Ok(None);
+        }
+    } else if tag != TAG_DONE && tag != TAG_NEXT_ID {
+        return Ok(None);
+    }
+    let Ok(checksum) = r.read_u32::<LittleEndian>() else {
+        return Ok(None);
+    };
+    if checksum != crc32(&record) {
+        log::warn!("Damaged spool record, dropping the rest of the log");
+        return Ok(None);
+    }
+    let id = u64::from_le_bytes(record[1..9].try_into().expect("9 bytes were read"));
+    let job = match tag {
+        TAG_JOB => Some(Job::read_from(&mut &record[13..])?),
+        _ => None,
+    };
+    Ok(Some((tag, id, job)))
+}
+
+/// CRC-32 as used by zip and PNG
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::Command;
+
+    #[test]
+    fn replays_pending_jobs() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let mut job = Job::new();
+        job.push(Command::Text("Order 42".to_string()));
+        let mut spool = Spool::open(&path).unwrap();
+        let first = spool.append(&job).unwrap();
+        let second = spool.append(&Job::new()).unwrap();
+        spool.complete(first).unwrap();
+        drop(spool);
+
+        // A record cut short by a crash is dropped
+        let mut torn = OpenOptions::new().append(true).open(&path).unwrap();
+        torn.write_all(&[TAG_JOB, 7, 0]).unwrap();
+        drop(torn);
+
+        let mut spool = Spool::open(&path).unwrap();
+        assert_eq!(
+            spool.pending().map(|(id, _)| id).collect::<Vec<_>>(),
+            [second]
+        );
+        assert_eq!(spool.next_id(), 2);
+        spool.complete(second).unwrap();
+        // The magic and the next id
+        assert_eq!(fs::metadata(&path).unwrap().len(), 7 + 13);
+        let spool = Spool::open(&path).unwrap();
+        assert_eq!(spool.pending().count(), 0);
+        // Ids aren't given out again after the log was emptied
+        assert_eq!(spool.next_id(), 2);
+
+        // Version 1 logs are still read
+        fs::write(&path, b"POSWAL\x01").unwrap();
+        assert_eq!(Spool::open(&path).unwrap().next_id(), 0);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn skips_ids_of_unlogged_jobs() {
+        let mut spool = Spool {
+            path: PathBuf::from("/dev/full"),
+            file: OpenOptions::new().append(true).open("/dev/full").unwrap(),
+            pending: BTreeMap::new(),
+            next_id: 3,
+        };
+        assert!(spool.append(&Job::new()).is_err());
+        assert_eq!(spool.pending().count(), 0);
+        assert_eq!(spool.skip_id(), 3);
+        assert_eq!(spool.next_id(), 4);
+    }
+}
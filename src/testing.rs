@@ -0,0 +1,162 @@
+//! Golden tests of the bytes sent to the printer
+//!
+//! [capture] runs printer calls without a printer and returns what they
+//! would have sent, [assert_hex] compares that with the expected bytes
+//! written as hex. When they differ the panic message is a line diff of
+//! both, one command per line named by the [parser], so a receipt layout
+//! can be locked against regressions and a failure says which command
+//! changed.
+//!
+//! ```
+//! use posify::printer::SupportedPrinters;
+//! use posify::testing;
+//!
+//! let bytes = testing::capture(SupportedPrinters::SNBC, |p| {
+//!     Ok(p.align("CT")? + p.println("Hi")?)
+//! })?;
+//! testing::assert_hex(&bytes, "1b 61 01  48 69 0a");
+//! # Ok::<(), posify::printer::Error>(())
+//! ```
+
+use crate::parser::{self, Token};
+use crate::printer::{Error, Printer, SupportedPrinters};
+use crate::profile;
+
+/// Bytes f sends to a printer of the dialect, which isn't opened, see
+/// [Printer::capture]
+pub fn capture<F>(printer: SupportedPrinters, f: F) -> Result<Vec<u8>, Error>
+where
+    F: FnOnce(&mut Printer) -> Result<usize, Error>,
+{
+    Printer::lazy(None, None, printer, 0, 0).capture(f)
+}
+
+/// Space separated hex bytes, `1b 40`
+pub fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// One line per command with its bytes and what the parser decodes them as
+pub fn annotate(bytes: &[u8]) -> String {
+    annotated_lines(bytes).join("\n")
+}
+
+fn annotated_lines(bytes: &[u8]) -> Vec<String> {
+    let spans = parser::parse_spans(bytes);
+    let end = spans.last().map_or(0, |(span, _)| span.end);
+    let mut lines: Vec<String> = spans
+        .into_iter()
+        .map(|(span, token)| {
+            let name = match token {
+                Token::Command(command) => format!("{:?}", command),
+                Token::Other { name, .. } => name.to_string(),
+                Token::Unknown { .. } => "?".to_string(),
+            };
+            format!("{:<23} {}", hex(&bytes[span]), name)
+        })
+        .collect();
+    if end < bytes.len() {
+        lines.push(format!("{:<23} incomplete", hex(&bytes[end..])));
+    }
+    lines
+}
+
+/// Line diff of the annotated bytes, None when they're equal
+///
+/// Lines only in expected start with `-`, lines only in actual with `+`.
+pub fn diff(expected: &[u8], actual: &[u8]) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    let offset = expected
+        .iter()
+        .zip(actual)
+        .position(|(e, a)| e != a)
+        .unwrap_or(expected.len().min(actual.len()));
+    let expected = annotated_lines(expected);
+    let actual = annotated_lines(actual);
+
+    // Longest common subsequence of the lines, from the end
+    let mut common = vec![vec![0_usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut out = format!("bytes differ at offset {}\n", offset);
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            out.push_str(&format!("  {}\n", expected[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if j == actual.len() || (i < expected.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            out.push_str(&format!("- {}\n", expected[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+ {}\n", actual[j]));
+            j += 1;
+        }
+    }
+    Some(out)
+}
+
+/// Panics with a [diff] unless actual is the bytes written as hex in
+/// expected
+///
+/// Whitespace in expected is ignored between bytes, so it can be grouped
+/// by command.
+#[track_caller]
+pub fn assert_hex(actual: &[u8], expected: &str) {
+    let expected = profile::parse_hex(expected).expect("expected bytes aren't hex");
+    if let Some(diff) = diff(&expected, actual) {
+        panic!("{}", diff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::CutMode;
+
+    #[test]
+    fn diffs_commands() {
+        let expected = b"\x1b\x40\x1b\x61\x01Hi\x0a";
+        let actual = b"\x1b\x40\x1b\x61\x02Hi\x0a\x1b";
+        let diff = diff(expected, actual).unwrap();
+        assert_eq!(
+            diff.lines().collect::<Vec<_>>(),
+            [
+                "bytes differ at offset 4",
+                "  1b 40                   Init",
+                "- 1b 61 01                Align(Center)",
+                "+ 1b 61 02                Align(Right)",
+                "  48 69                   Text(\"Hi\")",
+                "  0a                      NewLine",
+                "+ 1b                      ?",
+            ]
+        );
+        assert_eq!(super::diff(expected, expected), None);
+    }
+
+    #[test]
+    fn keeps_flushed_bytes() {
+        // The Epic sends the batch to wait after a cut, flush sends it too
+        let bytes = capture(SupportedPrinters::Epic, |p| {
+            let n = p.cut(CutMode::Full, None)?;
+            p.flush()?;
+            Ok(n + p.println("Hi")?)
+        })
+        .unwrap();
+        assert!(bytes.ends_with(b"Hi\n"));
+        assert!(bytes.len() > 3);
+    }
+}
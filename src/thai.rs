@@ -0,0 +1,163 @@
+//! Thai text printed in three passes
+//!
+//! Thai writes vowels and tone marks above and below the consonant they
+//! belong to. Printer fonts give each of them a character cell of its own,
+//! so sent as they are they print next to the consonant instead of on it.
+//! [three_pass] splits a line into the marks above, the base characters and
+//! the marks below, each lined up under its consonant, and
+//! [Printer::thai_println](crate::printer::Printer::thai_println) prints the
+//! lines close together in the `windows-874` (TIS-620) code page. A tone
+//! mark over an upper vowel goes on a fourth line on top, the way it is
+//! stacked in print.
+//!
+//! ```
+//! use posify::thai;
+//!
+//! // "Thai language"
+//! let lines = thai::three_pass("ภาษาไทย");
+//! assert_eq!(lines.base, "ภาษาไทย");
+//! // "Water", the tone mark over the nikhahit of sara am
+//! let lines = thai::three_pass("น้ำ");
+//! assert_eq!(lines.tones, "้");
+//! assert_eq!(lines.above, "ํ");
+//! assert_eq!(lines.base, "นา");
+//! ```
+
+/// Lines of a [three_pass] split, lined up character by character
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ThreePass {
+    /// Tone marks over an upper vowel, spaces elsewhere
+    pub tones: String,
+    /// Vowels and tone marks above the line, spaces elsewhere
+    pub above: String,
+    pub base: String,
+    /// Vowels below the line, spaces elsewhere
+    pub below: String,
+}
+
+impl ThreePass {
+    /// The lines that print anything, top to bottom
+    pub fn lines(&self) -> Vec<&str> {
+        [&self.tones, &self.above, &self.base, &self.below]
+            .into_iter()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.as_str())
+            .collect()
+    }
+}
+
+/// Sara am, a nikhahit above the consonant before it followed by sara aa
+const SARA_AM: char = '\u{0e33}';
+const NIKHAHIT: char = '\u{0e4d}';
+const SARA_AA: char = '\u{0e32}';
+
+/// Mai han-akat, the upper vowels and the tone and other marks
+pub fn is_above(c: char) -> bool {
+    matches!(c, '\u{0e31}' | '\u{0e34}'..='\u{0e37}' | '\u{0e47}'..='\u{0e4e}')
+}
+
+/// Sara u, sara uu and phinthu
+pub fn is_below(c: char) -> bool {
+    matches!(c, '\u{0e38}'..='\u{0e3a}')
+}
+
+/// Splits text into the lines of a three pass print
+///
+/// A consonant with both an upper vowel (or the nikhahit of sara am) and a
+/// tone mark gets the vowel on the line above and the tone mark on
+/// [ThreePass::tones] over it. Marks without a character before them sit
+/// over a space.
+pub fn three_pass(text: &str) -> ThreePass {
+    // Upper vowel and tone mark of each base character
+    let mut above: Vec<(char, char)> = Vec::new();
+    let mut base = Vec::new();
+    let mut below = Vec::new();
+    for c in text.chars() {
+        match c {
+            SARA_AM => {
+                mark(&mut above, &mut base, NIKHAHIT);
+                base.push(SARA_AA);
+            }
+            c if is_above(c) => mark(&mut above, &mut base, c),
+            c if is_below(c) => mark(&mut below, &mut base, c),
+            c => base.push(c),
+        }
+    }
+    above.resize(base.len(), (' ', ' '));
+    below.resize(base.len(), (' ', ' '));
+    // A lone tone mark sits right above the consonant
+    let line = |cells: &[(char, char)], pick: fn(&(char, char)) -> char| {
+        cells
+            .iter()
+            .map(pick)
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    };
+    ThreePass {
+        tones: line(
+            &above,
+            |&(vowel, tone)| if vowel == ' ' { ' ' } else { tone },
+        ),
+        above: line(
+            &above,
+            |&(vowel, tone)| if vowel == ' ' { tone } else { vowel },
+        ),
+        base: base.into_iter().collect(),
+        below: line(&below, |&(vowel, _)| vowel),
+    }
+}
+
+/// Puts c over or under the last base character, a tone mark in the second
+/// place of its cell
+fn mark(line: &mut Vec<(char, char)>, base: &mut Vec<char>, c: char) {
+    if base.is_empty() {
+        base.push(' ');
+    }
+    line.resize(base.len(), (' ', ' '));
+    let (vowel, tone) = &mut line[base.len() - 1];
+    let place = if is_tone(c) { tone } else { vowel };
+    if *place == ' ' {
+        *place = c;
+    }
+}
+
+/// Mai ek to mai chattawa
+fn is_tone(c: char) -> bool {
+    matches!(c, '\u{0e48}'..='\u{0e4b}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stacks_marks() {
+        // "Hello"
+        let lines = three_pass("สวัสดี");
+        assert_eq!(lines.above, " ั ี");
+        assert_eq!(lines.base, "สวสด");
+        assert!(lines.below.is_empty());
+        assert_eq!(lines.lines().len(), 2);
+
+        // Sara ii and mai ek over the same consonant, the tone mark on top
+        let lines = three_pass("ที่สุด");
+        assert_eq!(lines.tones, "่");
+        assert_eq!(lines.above, "ี");
+        assert_eq!(lines.base, "ทสด");
+        assert_eq!(lines.below, " ุ");
+        assert_eq!(lines.lines().len(), 4);
+        assert_eq!(three_pass("ุ").base, " ");
+
+        // A tone mark alone sits on the line above
+        let lines = three_pass("ไม่");
+        assert!(lines.tones.is_empty());
+        assert_eq!(lines.above, " ่");
+
+        // The tone mark comes before sara am, its nikhahit goes under it
+        let lines = three_pass("น้ำ");
+        assert_eq!(lines.tones, "้");
+        assert_eq!(lines.above, "ํ");
+        assert_eq!(lines.base, "นา");
+    }
+}
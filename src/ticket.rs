@@ -0,0 +1,206 @@
+//! "Now serving" queue number tickets
+//!
+//! A [QueueTicket] prints the number a customer waits for as large as the
+//! paper allows. The printer font stops at 8 times its size, so when the
+//! digits can be drawn larger they are sent as a raster image instead. An
+//! optional QR code links to a page showing the queue.
+//!
+//! ```
+//! use posify::ticket::{Digits, QueueTicket};
+//!
+//! let mut ticket = QueueTicket::new(42, 576);
+//! ticket
+//!     .title("Please wait")
+//!     .link("https://example.com/queue/042");
+//! assert_eq!(ticket.number, "042");
+//! assert!(matches!(ticket.digits(), Digits::Raster { .. }));
+//! let job = ticket.job();
+//! ```
+
+use crate::document::Alignment;
+use crate::job::{Command, Job};
+use crate::preview::glyph;
+
+/// Width of a printer font character in dots
+const FONT_WIDTH: u32 = 12;
+/// Largest character magnification of GS !
+const MAX_SIZE: u32 = 8;
+/// A raster digit is 5 dots wide plus one dot of space, times the scale
+const GLYPH_WIDTH: u32 = 6;
+const GLYPH_HEIGHT: u32 = 7;
+
+/// How the number is printed
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Digits {
+    /// Printer font at size times the normal size
+    Text { size: u8 },
+    /// Digits drawn as an image, every font dot scale x scale dots
+    Raster { scale: u32 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QueueTicket {
+    /// At least three digits, padded with zeros
+    pub number: String,
+    /// Printable width in dots
+    pub dots: u32,
+    /// Printed above the number
+    pub title: Option<String>,
+    /// Printed below the number, e.g. the time it was taken
+    pub message: Option<String>,
+    /// Encoded in a QR code below the number
+    pub link: Option<String>,
+    /// QR code module size in dots
+    pub qr_size: u8,
+}
+
+impl QueueTicket {
+    pub fn new(number: u32, dots: u32) -> QueueTicket {
+        QueueTicket {
+            number: format!("{:03}", number),
+            dots,
+            title: None,
+            message: None,
+            link: None,
+            qr_size: 6,
+        }
+    }
+
+    pub fn title(&mut self, title: &str) -> &mut Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn message(&mut self, message: &str) -> &mut Self {
+        self.message = Some(message.to_string());
+        self
+    }
+
+    pub fn link(&mut self, link: &str) -> &mut Self {
+        self.link = Some(link.to_string());
+        self
+    }
+
+    /// The larger of the printer font and raster digits that fit the paper
+    pub fn digits(&self) -> Digits {
+        let len = self.number.chars().count().max(1) as u32;
+        let size = (self.dots / (FONT_WIDTH * len)).clamp(1, MAX_SIZE);
+        let scale = self.dots / (GLYPH_WIDTH * len);
+        if GLYPH_WIDTH * scale > FONT_WIDTH * size {
+            Digits::Raster { scale }
+        } else {
+            Digits::Text { size: size as u8 }
+        }
+    }
+
+    pub fn job(&self) -> Job {
+        let mut job = Job::new();
+        job.push(Command::Init)
+            .push(Command::Align(Alignment::Center));
+        if let Some(title) = &self.title {
+            job.push(Command::Bold(true))
+                .push(Command::Text(title.clone()))
+                .push(Command::NewLine)
+                .push(Command::Bold(false))
+                .push(Command::Feed(1));
+        }
+
+        match self.digits() {
+            Digits::Text { size } => {
+                job.push(Command::Size {
+                    width: size,
+                    height: size,
+                })
+                .push(Command::Text(self.number.clone()))
+                .push(Command::NewLine)
+                .push(Command::Size {
+                    width: 1,
+                    height: 1,
+                });
+            }
+            Digits::Raster { scale } => {
+                job.push(self.raster(scale)).push(Command::Feed(1));
+            }
+        }
+
+        if let Some(message) = &self.message {
+            job.push(Command::Text(message.clone()))
+                .push(Command::NewLine);
+        }
+        if let Some(link) = &self.link {
+            job.push(Command::QrCode {
+                data: link.clone(),
+                size: self.qr_size,
+            });
+        }
+        job.push(Command::Feed(3))
+            .push(Command::Cut { partial: true });
+        job
+    }
+
+    /// The number drawn with the preview font, centered on the paper
+    fn raster(&self, scale: u32) -> Command {
+        let width_bytes = self.dots.div_ceil(8);
+        let height = GLYPH_HEIGHT * scale;
+        let mut data = vec![0u8; (width_bytes * height) as usize];
+        let len = self.number.chars().count() as u32;
+        // The last digit needs no space after it
+        let used = GLYPH_WIDTH * scale * len - scale;
+        let left = (self.dots - used.min(self.dots)) / 2;
+        for (i, c) in self.number.chars().enumerate() {
+            let x0 = left + i as u32 * GLYPH_WIDTH * scale;
+            for (col, bits) in glyph(c).iter().enumerate() {
+                for row in 0..GLYPH_HEIGHT {
+                    if bits & (1 << row) == 0 {
+                        continue;
+                    }
+                    for y in row * scale..(row + 1) * scale {
+                        for x in x0 + col as u32 * scale..x0 + (col as u32 + 1) * scale {
+                            let byte = (y * width_bytes + x / 8) as usize;
+                            data[byte] |= 0x80 >> (x % 8);
+                        }
+                    }
+                }
+            }
+        }
+        Command::Raster {
+            width_bytes: width_bytes as u16,
+            height: height as u16,
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_largest_digits() {
+        let ticket = QueueTicket::new(7, 576);
+        assert_eq!(ticket.number, "007");
+        assert_eq!(ticket.digits(), Digits::Raster { scale: 32 });
+        match ticket.job().commands[2] {
+            Command::Raster {
+                width_bytes,
+                height,
+                ref data,
+            } => {
+                assert_eq!((width_bytes, height), (72, 224));
+                // The top row of the first zero starts one font dot in
+                let left = (576 - (6 * 32 * 3 - 32)) / 2 + 32;
+                assert_eq!(data[left / 8] & (0x80 >> (left % 8)), 0x80 >> (left % 8));
+                assert_eq!(data[left / 8 - 1], 0);
+            }
+            ref other => panic!("expected a raster, got {:?}", other),
+        }
+
+        // Too narrow for the raster digits to beat the font
+        assert_eq!(
+            QueueTicket::new(1234, 96).digits(),
+            Digits::Text { size: 2 }
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! TSPL/TSPL2 output for TSC and compatible desktop label printers
+//!
+//! Renders a [Document] like [zpl](crate::zpl) does: elements are stacked top
+//! to bottom and every [Element::Cut] prints the label and starts the next
+//! one. Barcode data is checked with [BarcodeType::validate] before anything
+//! is generated.
+//!
+//! ```
+//! use posify::document::Document;
+//! use posify::tspl::{LabelSize, TsplPrinter};
+//!
+//! let mut doc = Document::new();
+//! doc.text("FRAGILE").cut();
+//!
+//! let mut printer = TsplPrinter::new(Vec::new(), LabelSize::default());
+//! printer.print_document(&doc)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io::{self, Write};
+
+use crate::barcode::BarcodeType;
+use crate::document::{Alignment, Document, Element, TextStyle};
+use crate::img::Image;
+
+/// Height of font "3" (16 x 24 dots)
+const FONT_HEIGHT: u32 = 24;
+/// Space between elements in dots
+const LINE_GAP: u32 = 8;
+/// Left and right margin in dots
+const MARGIN: u32 = 16;
+
+/// Label stock, sent as SIZE and GAP
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LabelSize {
+    pub width_mm: u32,
+    pub height_mm: u32,
+    /// Gap between labels, 0 for continuous stock
+    pub gap_mm: u32,
+    /// Dots per mm, 8 for 203 dpi and 12 for 300 dpi
+    pub dots_per_mm: u32,
+}
+
+impl Default for LabelSize {
+    /// 4" x 6" shipping labels at 203 dpi
+    fn default() -> Self {
+        LabelSize {
+            width_mm: 102,
+            height_mm: 152,
+            gap_mm: 3,
+            dots_per_mm: 8,
+        }
+    }
+}
+
+pub struct TsplPrinter<W> {
+    device: W,
+    size: LabelSize,
+}
+
+impl<W: io::Write> TsplPrinter<W> {
+    pub fn new(device: W, size: LabelSize) -> TsplPrinter<W> {
+        TsplPrinter { device, size }
+    }
+
+    pub fn print_document(&mut self, doc: &Document) -> io::Result<usize> {
+        let tspl = render(doc, &self.size)?;
+        self.device.write_all(&tspl)?;
+        self.device.flush()?;
+        Ok(tspl.len())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.device
+    }
+}
+
+/// Renders doc as TSPL, printing one label per [Element::Cut]
+pub fn render(doc: &Document, size: &LabelSize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    write!(
+        out,
+        "SIZE {} mm,{} mm\r\nGAP {} mm,0 mm\r\nDIRECTION 1\r\nCLS\r\n",
+        size.width_mm, size.height_mm, size.gap_mm
+    )?;
+    let width = size.width_mm * size.dots_per_mm;
+    let mut y = MARGIN;
+    let mut pending = false;
+    for element in &doc.elements {
+        pending = true;
+        match element {
+            Element::Text { content, style } => {
+                let style = TextStyle {
+                    align: doc.direction.align(style.align),
+                    ..*style
+                };
+                y = text(&mut out, width, y, content, &style)?
+            }
+            Element::Barcode { code, kind, height } => {
+                let name = barcode_name(*kind)?;
+                kind.validate(code)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                write!(
+                    out,
+                    "BARCODE {},{},\"{}\",{},1,0,2,4,\"{}\"\r\n",
+                    MARGIN,
+                    y,
+                    name,
+                    height,
+                    escape(code)
+                )?;
+                y += *height as u32 + FONT_HEIGHT + LINE_GAP;
+            }
+            Element::QrCode { data, size } => {
+                let size = (*size).clamp(1, 10);
+                write!(
+                    out,
+                    "QRCODE {},{},M,{},A,0,\"{}\"\r\n",
+                    MARGIN,
+                    y,
+                    size,
+                    escape(data)
+                )?;
+                let modules = 21 + 4 * (data.len() as u32 / 20);
+                y += modules * size as u32 + LINE_GAP;
+            }
+            Element::Image(image) => y = bitmap(&mut out, y, image)?,
+            Element::Feed(lines) => y += *lines as u32 * (FONT_HEIGHT + LINE_GAP),
+            Element::Cut => {
+                out.extend_from_slice(b"PRINT 1\r\nCLS\r\n");
+                y = MARGIN;
+                pending = false;
+            }
+        }
+    }
+    if pending {
+        out.extend_from_slice(b"PRINT 1\r\n");
+    }
+    Ok(out)
+}
+
+/// TEXT x,y,"font",rotation,x-mul,y-mul,alignment,"content"
+fn text(
+    out: &mut Vec<u8>,
+    width: u32,
+    y: u32,
+    content: &str,
+    style: &TextStyle,
+) -> io::Result<u32> {
+    let (x, alignment) = match style.align {
+        Alignment::Left => (MARGIN, 1),
+        Alignment::Center => (width / 2, 2),
+        Alignment::Right => (width.saturating_sub(MARGIN), 3),
+    };
+    let x_mul = style.width.max(1);
+    let y_mul = style.height.max(1);
+    // No bold font, the text is printed twice one dot apart instead
+    let passes = if style.bold { 2 } else { 1 };
+    for pass in 0..passes {
+        write!(
+            out,
+            "TEXT {},{},\"3\",0,{},{},{},\"{}\"\r\n",
+            x + pass,
+            y,
+            x_mul,
+            y_mul,
+            alignment,
+            escape(content)
+        )?;
+    }
+    let height = FONT_HEIGHT * y_mul as u32;
+    if style.underline {
+        write!(
+            out,
+            "BAR {},{},{},2\r\n",
+            MARGIN,
+            y + height,
+            width.saturating_sub(2 * MARGIN)
+        )?;
+    }
+    Ok(y + height + LINE_GAP)
+}
+
+/// BITMAP x,y,width,height,mode,data where a 0 bit prints, the opposite of
+/// the ESC/POS raster
+fn bitmap(out: &mut Vec<u8>, y: u32, image: &Image) -> io::Result<u32> {
+    let width_bytes = image.width.div_ceil(8).max(1);
+    write!(
+        out,
+        "BITMAP {},{},{},{},0,",
+        MARGIN, y, width_bytes, image.height
+    )?;
+    out.extend(image.get_raster().iter().map(|byte| !byte));
+    out.extend_from_slice(b"\r\n");
+    Ok(y + image.height + LINE_GAP)
+}
+
+fn barcode_name(kind: BarcodeType) -> io::Result<&'static str> {
+    Ok(match kind {
+        BarcodeType::UPCA => "UPCA",
+        BarcodeType::UPCE => "UPCE",
+        BarcodeType::EAN13 => "EAN13",
+        BarcodeType::EAN8 => "EAN8",
+        BarcodeType::CODE39 => "39",
+        BarcodeType::ITF => "25",
+        BarcodeType::Code93 => "93",
+        BarcodeType::Codabar => "CODA",
+        BarcodeType::Code128 => "128",
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{:?} barcodes are not supported by TSPL output", kind),
+            ))
+        }
+    })
+}
+
+/// Quotes in strings are sent as \["]
+fn escape(data: &str) -> String {
+    data.replace('"', "\\[\"]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_labels() {
+        let mut doc = Document::new();
+        doc.text("Say \"hi\"")
+            .barcode("590123412345", BarcodeType::EAN13, 80)
+            .cut();
+        let tspl = String::from_utf8(render(&doc, &LabelSize::default()).unwrap()).unwrap();
+        assert!(tspl.starts_with("SIZE 102 mm,152 mm\r\nGAP 3 mm,0 mm\r\n"));
+        assert!(tspl.contains("TEXT 16,16,\"3\",0,1,1,1,\"Say \\[\"]hi\\[\"]\"\r\n"));
+        assert!(tspl.contains("BARCODE 16,48,\"EAN13\",80,1,0,2,4,\"590123412345\"\r\n"));
+        assert!(tspl.ends_with("PRINT 1\r\nCLS\r\n"));
+
+        let mut doc = Document::new();
+        doc.barcode("12AB", BarcodeType::EAN13, 80);
+        let err = render(&doc, &LabelSize::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
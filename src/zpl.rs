@@ -0,0 +1,252 @@
+//! ZPL II output for Zebra label printers
+//!
+//! Renders the same [Document] as the ESC/POS [Printer](crate::printer::Printer)
+//! so receipt and label printers can share one code path. Every
+//! [Element::Cut] ends a label, elements are stacked top to bottom and the
+//! label length is set to fit them.
+//!
+//! ```
+//! use posify::document::Document;
+//! use posify::zpl::ZplPrinter;
+//!
+//! let mut doc = Document::new();
+//! doc.text("Shelf A-12").qr("https://example.com/a12", 4);
+//!
+//! let mut printer = ZplPrinter::new(Vec::new(), 812);
+//! printer.print_document(&doc)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::fmt::Write as _;
+use std::io;
+
+use crate::barcode::BarcodeType;
+use crate::document::{Alignment, Document, Element, TextStyle};
+use crate::img::Image;
+
+/// Height of unscaled text in dots, about 3.75mm at 203 dpi
+const FONT_HEIGHT: u32 = 30;
+/// Space between elements in dots
+const LINE_GAP: u32 = 8;
+/// Left and right margin in dots
+const MARGIN: u32 = 16;
+
+pub struct ZplPrinter<W> {
+    device: W,
+    /// Label width in dots (^PW)
+    width: u32,
+}
+
+impl<W: io::Write> ZplPrinter<W> {
+    /// width is the printable label width in dots, 812 for a 4" label at
+    /// 203 dpi
+    pub fn new(device: W, width: u32) -> ZplPrinter<W> {
+        ZplPrinter { device, width }
+    }
+
+    pub fn print_document(&mut self, doc: &Document) -> io::Result<usize> {
+        let zpl = render(doc, self.width)?;
+        self.device.write_all(zpl.as_bytes())?;
+        self.device.flush()?;
+        Ok(zpl.len())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.device
+    }
+}
+
+/// Renders doc as one ZPL label per [Element::Cut]
+pub fn render(doc: &Document, width: u32) -> io::Result<String> {
+    let mut zpl = String::new();
+    let mut label = Label::new(width);
+    for element in &doc.elements {
+        match element {
+            Element::Cut => {
+                label.finish(&mut zpl);
+                label = Label::new(width);
+            }
+            Element::Text { content, style } => label.text(
+                content,
+                &TextStyle {
+                    align: doc.direction.align(style.align),
+                    ..*style
+                },
+            ),
+            element => label.add(element)?,
+        }
+    }
+    if !label.body.is_empty() {
+        label.finish(&mut zpl);
+    }
+    Ok(zpl)
+}
+
+struct Label {
+    width: u32,
+    y: u32,
+    body: String,
+}
+
+impl Label {
+    fn new(width: u32) -> Label {
+        Label {
+            width,
+            y: MARGIN,
+            body: String::new(),
+        }
+    }
+
+    fn add(&mut self, element: &Element) -> io::Result<()> {
+        match element {
+            Element::Text { content, style } => self.text(content, style),
+            Element::Barcode { code, kind, height } => self.barcode(code, *kind, *height)?,
+            Element::QrCode { data, size } => {
+                let size = (*size).clamp(1, 10);
+                let _ = write!(
+                    self.body,
+                    "^FO{},{}^BQN,2,{}^FH_^FDQA,{}^FS",
+                    MARGIN,
+                    self.y,
+                    size,
+                    escape(data)
+                );
+                // Model 2 QR codes are at least 21 modules, guess from the data
+                let modules = 21 + 4 * (data.len() as u32 / 20);
+                self.y += modules * size as u32 + LINE_GAP;
+            }
+            Element::Image(image) => self.image(image),
+            Element::Feed(lines) => self.y += *lines as u32 * (FONT_HEIGHT + LINE_GAP),
+            Element::Cut => (),
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, content: &str, style: &TextStyle) {
+        let height = FONT_HEIGHT * style.height.max(1) as u32;
+        let width = FONT_HEIGHT * style.width.max(1) as u32;
+        let justify = match style.align {
+            Alignment::Left => 'L',
+            Alignment::Center => 'C',
+            Alignment::Right => 'R',
+        };
+        // ZPL has no bold, the field is printed twice one dot apart instead
+        let passes = if style.bold { 2 } else { 1 };
+        for pass in 0..passes {
+            let _ = write!(
+                self.body,
+                "^FO{},{}^A0N,{},{}^FB{},1,0,{}^FH_^FD{}^FS",
+                MARGIN + pass,
+                self.y,
+                height,
+                width,
+                self.width.saturating_sub(2 * MARGIN),
+                justify,
+                escape(content)
+            );
+        }
+        if style.underline {
+            let _ = write!(
+                self.body,
+                "^FO{},{}^GB{},2,2^FS",
+                MARGIN,
+                self.y + height,
+                self.width.saturating_sub(2 * MARGIN)
+            );
+        }
+        self.y += height + LINE_GAP;
+    }
+
+    fn barcode(&mut self, code: &str, kind: BarcodeType, height: u8) -> io::Result<()> {
+        let command = match kind {
+            BarcodeType::UPCA => "^BUN,{h},Y,N,Y",
+            BarcodeType::UPCE => "^B9N,{h},Y,N,Y",
+            BarcodeType::EAN13 => "^BEN,{h},Y,N",
+            BarcodeType::EAN8 => "^B8N,{h},Y,N",
+            BarcodeType::CODE39 => "^B3N,N,{h},Y,N",
+            BarcodeType::ITF => "^B2N,{h},Y,N,N",
+            BarcodeType::Code93 => "^BAN,{h},Y,N,N",
+            BarcodeType::Codabar => "^BKN,N,{h},Y,N,A,A",
+            BarcodeType::Code128 => "^BCN,{h},Y,N,N",
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("{:?} barcodes are not supported by ZPL output", kind),
+                ))
+            }
+        };
+        kind.validate(code)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let _ = write!(
+            self.body,
+            "^FO{},{}^BY2{}^FH_^FD{}^FS",
+            MARGIN,
+            self.y,
+            command.replace("{h}", &height.to_string()),
+            escape(code)
+        );
+        // Human readable line below the bars
+        self.y += height as u32 + FONT_HEIGHT + LINE_GAP;
+        Ok(())
+    }
+
+    /// ^GFA with the raster as hex, one byte per 8 horizontal dots
+    fn image(&mut self, image: &Image) {
+        let width_bytes = image.width.div_ceil(8).max(1);
+        let raster = image.get_raster();
+        let mut hex = String::with_capacity(raster.len() * 2);
+        for byte in raster.iter() {
+            let _ = write!(hex, "{:02X}", byte);
+        }
+        let _ = write!(
+            self.body,
+            "^FO{},{}^GFA,{},{},{},{}^FS",
+            MARGIN,
+            self.y,
+            raster.len(),
+            raster.len(),
+            width_bytes,
+            hex
+        );
+        self.y += image.height + LINE_GAP;
+    }
+
+    fn finish(&self, zpl: &mut String) {
+        let _ = writeln!(
+            zpl,
+            "^XA^CI28^PW{}^LL{}{}^XZ",
+            self.width,
+            self.y + MARGIN,
+            self.body
+        );
+    }
+}
+
+/// Escapes the ZPL control characters for a ^FH_ field
+fn escape(data: &str) -> String {
+    data.replace('_', "_5F")
+        .replace('^', "_5E")
+        .replace('~', "_7E")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_labels() {
+        let mut doc = Document::new();
+        doc.text("Price ^ 2")
+            .barcode("12345678", BarcodeType::Code128, 50)
+            .cut()
+            .text("Second");
+        let zpl = render(&doc, 812).unwrap();
+        let labels: Vec<_> = zpl.lines().collect();
+        assert_eq!(labels.len(), 2);
+        assert!(labels[0].starts_with("^XA^CI28^PW812^LL"));
+        assert!(labels[0].contains("^FDPrice _5E 2^FS"));
+        assert!(labels[0].contains("^BCN,50,Y,N,N^FH_^FD12345678^FS"));
+        assert!(labels[1].contains("^FDSecond^FS"));
+        assert!(labels[1].ends_with("^XZ"));
+    }
+}
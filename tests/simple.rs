@@ -3,7 +3,7 @@ extern crate tempfile;
 extern crate posify;
 
 use posify::barcode::{BarcodeType, Font, TextPosition};
-use posify::printer::{Printer, SupportedPrinters};
+use posify::printer::{CutMode, Printer, SupportedPrinters};
 
 #[test]
 fn simple() {
@@ -36,6 +36,6 @@ fn simple() {
         .unwrap()
         .chain_feed(5)
         .unwrap()
-        .chain_partial_cut()
+        .chain_cut(CutMode::Partial, None)
         .unwrap();
 }